@@ -4,7 +4,7 @@
 //! interface can only be 1/0.
 use crate::prog::Prog;
 use crate::target::Target;
-use fots::types::{FnInfo, Group, GroupId, PtrDir, TypeId, TypeInfo};
+use fots::types::{FnInfo, Group, GroupId, TypeId};
 use ndarray::{Array2, Axis};
 use std::collections::HashMap;
 use std::fmt::{Display, Error, Formatter};
@@ -127,25 +127,8 @@ struct Use {
 }
 
 fn res_use(index: usize, f: &FnInfo, t: &Target, uses: &mut HashMap<TypeId, Use>) {
-    if f.has_params() {
-        for p in f.iter_param() {
-            let mut id = p.tid;
-            let mut in_ = true;
-            if let TypeInfo::Ptr { tid, dir, depth } = t.type_of(id) {
-                assert!(*depth == 1, "Multi-level pointer not supported");
-                id = *tid;
-                in_ = *dir == PtrDir::In;
-            }
-            if t.is_res(id) {
-                record_use(uses, id, index, in_);
-            }
-        }
-    }
-
-    if let Some(tid) = f.r_tid {
-        if t.is_res(tid) {
-            record_use(uses, tid, index, false);
-        }
+    for (res, in_) in t.res_uses(f) {
+        record_use(uses, res, index, in_);
     }
 }
 
@@ -164,20 +147,122 @@ fn record_use(uses: &mut HashMap<TypeId, Use>, res: TypeId, fn_index: usize, in_
 /// If A is before B in a prog, then B has impact on A.
 /// Thr prog must be minimized befor being used.
 pub fn prog_analyze(g: &Group, r: &mut RTable, p: &Prog) {
+    for rel in CoverageDelta.learn(g, p) {
+        r[rel] = Relation::Some;
+    }
+}
+
+/// Strategy of learning relations from progs during fuzzing, see `RelationLearner`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LearnerKind {
+    CoverageDelta,
+    CoOccurrence,
+}
+
+impl LearnerKind {
+    pub fn learner(self) -> &'static dyn RelationLearner {
+        match self {
+            LearnerKind::CoverageDelta => &CoverageDelta,
+            LearnerKind::CoOccurrence => &CoOccurrence,
+        }
+    }
+}
+
+/// Learn relations between calls of a group from progs that covered something new.
+pub trait RelationLearner: Sync {
+    /// Relations `p` suggests, `(a, b)` means b has impact on a, both indices of calls in `g`.
+    fn learn(&self, g: &Group, p: &Prog) -> Vec<(usize, usize)>;
+}
+
+/// Each call has impact on the call following it. Progs are minimized to calls needed
+/// for their new coverage, so adjacent calls likely depend on each other.
+pub struct CoverageDelta;
+
+/// Each call has impact on every call after it in the same prog, more relations from each
+/// prog than `CoverageDelta`, at the cost of more unrelated ones.
+pub struct CoOccurrence;
+
+fn indices(g: &Group, p: &Prog) -> Vec<usize> {
     assert!(!p.is_empty());
-    let mut id_index = Vec::new();
+    p.calls
+        .iter()
+        .map(|c| {
+            g.index_by_id(c.fid)
+                .unwrap_or_else(|| panic!("fn{} out of group{}", c.fid, g.id))
+        })
+        .collect()
+}
 
-    for c in &p.calls {
-        if let Some(index) = g.index_by_id(c.fid) {
-            id_index.push(index);
-        } else {
-            panic!("fn{} out of group{}", c.fid, g.id);
+impl RelationLearner for CoverageDelta {
+    fn learn(&self, g: &Group, p: &Prog) -> Vec<(usize, usize)> {
+        indices(g, p).windows(2).map(|w| (w[1], w[0])).collect()
+    }
+}
+
+impl RelationLearner for CoOccurrence {
+    fn learn(&self, g: &Group, p: &Prog) -> Vec<(usize, usize)> {
+        let ids = indices(g, p);
+        let mut rels = Vec::new();
+        for (i, a) in ids.iter().enumerate() {
+            rels.extend(ids[..i].iter().filter(|b| *b != a).map(|b| (*a, *b)));
         }
+        rels
     }
+}
 
-    for i in (0..id_index.len()).rev() {
-        if i != 0 {
-            r[(id_index[i], id_index[i - 1])] = Relation::Some;
+/// Learn relations `p` suggests to each of `learners` into `r`. Return how many relations
+/// each of them learned, i.e. suggested and unknown before `p`, a relation suggested by
+/// several learners counts for each of them.
+pub fn learn(learners: &[LearnerKind], g: &Group, r: &mut RTable, p: &Prog) -> Vec<usize> {
+    let suggested = learners
+        .iter()
+        .map(|l| l.learner().learn(g, p))
+        .collect::<Vec<_>>();
+    let learned = suggested
+        .iter()
+        .map(|rels| {
+            let mut rels = rels.clone();
+            rels.sort_unstable();
+            rels.dedup();
+            rels.iter().filter(|rel| r[**rel] == Relation::None).count()
+        })
+        .collect();
+    for rel in suggested.into_iter().flatten() {
+        r[rel] = Relation::Some;
+    }
+    learned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prog::Call;
+
+    #[test]
+    fn learn_with_each_learner() {
+        let descs = "group T {\n fn a(n i32)\n fn b(n i32)\n fn c(n i32)\n}\n";
+        let t = Target::from(fots::parse_items(descs).unwrap());
+        let g = t.iter_group().next().unwrap();
+        let mut p = Prog::new(g.id);
+        for name in &["a", "b", "c", "a"] {
+            p.add_call(Call::new(t.fn_by_name(name).unwrap().id));
         }
+        let idx = |name| g.index_by_name(name).unwrap();
+        let (a, b, c) = (idx("a"), idx("b"), idx("c"));
+
+        let mut delta = CoverageDelta.learn(g, &p);
+        delta.sort_unstable();
+        let mut expected = vec![(b, a), (c, b), (a, c)];
+        expected.sort_unstable();
+        assert_eq!(delta, expected);
+        // a call doesn't impact itself.
+        assert_eq!(CoOccurrence.learn(g, &p).len(), 5);
+
+        let mut r = RTable::new(g.fn_num());
+        let kinds = [LearnerKind::CoverageDelta, LearnerKind::CoOccurrence];
+        assert_eq!(learn(&kinds, g, &mut r, &p), vec![3, 5]);
+        assert_eq!(learn(&kinds, g, &mut r, &p), vec![0, 0]);
+        assert_eq!(r[(c, a)], Relation::Some);
     }
 }
@@ -0,0 +1,43 @@
+//! Inputs of benchmarks and tests, built with `bench` feature or for tests only.
+//!
+//! Call sequences of synthetic progs come from caller's seeded rng, so benchmarks run
+//! the same calls on every machine; values of calls are still generated as usual.
+use crate::analyze::{static_analyze, RTable};
+use crate::gen::{gen_seq, Config};
+use crate::prog::Prog;
+use crate::target::Target;
+use fots::types::GroupId;
+use rand::prelude::*;
+use std::collections::HashMap;
+
+/// Source of descriptions shipped with healer.
+pub fn descriptions() -> String {
+    format!(
+        "{}\n{}",
+        include_str!("../descriptions/types.fots"),
+        include_str!("../descriptions/sys.fots")
+    )
+}
+
+/// Target of descriptions shipped with healer.
+pub fn target() -> (Target, HashMap<GroupId, RTable>) {
+    let items = fots::parse_items(&descriptions()).unwrap();
+    let t = Target::from(items);
+    let rt = static_analyze(&t);
+    (t, rt)
+}
+
+impl Prog {
+    /// Prog of `len` calls of the largest group of `t`, picked by `rng`.
+    pub fn synthetic<R: Rng>(t: &Target, len: usize, rng: &mut R) -> Prog {
+        let g = t
+            .groups
+            .values()
+            .max_by_key(|g| (g.fns.len(), g.id))
+            .unwrap();
+        let seq = (0..len)
+            .map(|_| rng.gen_range(0, g.fns.len()))
+            .collect::<Vec<_>>();
+        gen_seq(&seq, g.id, t, &Config::default())
+    }
+}
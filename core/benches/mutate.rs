@@ -0,0 +1,25 @@
+use core::gen::Config;
+use core::prog::Prog;
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::prelude::*;
+use std::collections::HashSet;
+
+fn mutate(c: &mut Criterion) {
+    let (t, rt) = core::bench::target();
+    let mut rng = StdRng::seed_from_u64(650);
+    let p = Prog::synthetic(&t, 20, &mut rng);
+    let corpus = (0..1024)
+        .map(|_| Prog::synthetic(&t, 20, &mut rng))
+        .collect::<HashSet<_>>();
+    let rt = &rt[&p.gid];
+    let conf = Config::default();
+
+    let mut group = c.benchmark_group("mutate");
+    for (name, method) in core::mutate::methods().iter() {
+        group.bench_function(*name, |b| b.iter(|| method(&p, &t, rt, &corpus, &conf)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, mutate);
+criterion_main!(benches);
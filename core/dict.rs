@@ -0,0 +1,228 @@
+//! Dictionaries of tokens, e.g. keywords and magic values of a protocol, that string and
+//! byte buffer args are drawn from.
+//!
+//! Files are in AFL's dict format: each line is `"value"` or `name="value"`, the name may
+//! carry a level as in `name@1="value"`, which is ignored, and `#` starts a comment line.
+//! Values are quoted byte strings with `\\`, `\"` and `\xNN` escapes, other bytes must be
+//! printable. Each token keeps its source, file and entry name, to tell where a value of
+//! a prog came from.
+use crate::prog::Prog;
+use crate::value::{NumValue, Value};
+use rand::prelude::*;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub bytes: Vec<u8>,
+    /// File and entry name, e.g. `http.dict:header_host`, or file and line of unnamed ones
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Dict {
+    tokens: Vec<Token>,
+    /// Indices of tokens usable as str value, utf-8 without nul
+    strs: Vec<usize>,
+    known: HashSet<Vec<u8>>,
+}
+
+impl Dict {
+    /// Add tokens of dict `text` read from `file`, a token already known keeps its first
+    /// source. Return malformed lines as `file:line: reason`, they are skipped.
+    pub fn add(&mut self, file: &str, text: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let (name, bytes) = match parse_line(line) {
+                Ok(Some(entry)) => entry,
+                Ok(None) => continue,
+                Err(e) => {
+                    errors.push(format!("{}:{}: {}", file, n + 1, e));
+                    continue;
+                }
+            };
+            if !self.known.insert(bytes.clone()) {
+                continue;
+            }
+            let source = match name {
+                Some(name) => format!("{}:{}", file, name),
+                None => format!("{}:{}", file, n + 1),
+            };
+            if !bytes.contains(&0) && std::str::from_utf8(&bytes).is_ok() {
+                self.strs.push(self.tokens.len());
+            }
+            self.tokens.push(Token { bytes, source });
+        }
+        errors
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Random token usable as str value.
+    pub fn choose_str(&self) -> Option<String> {
+        let i = self.strs.choose(&mut thread_rng())?;
+        Some(String::from_utf8(self.tokens[*i].bytes.clone()).unwrap())
+    }
+
+    /// Random token fitting length bounds `l` and `h` of a slice, see `gen_slice_len`.
+    pub fn choose_bytes(&self, l: isize, h: isize) -> Option<&[u8]> {
+        let fits = |len: usize| match (l, h) {
+            (-1, -1) => len != 0,
+            (l, -1) => len == l as usize,
+            (l, h) => (l as usize..h as usize).contains(&len),
+        };
+        self.tokens
+            .iter()
+            .filter(|t| fits(t.bytes.len()))
+            .choose(&mut thread_rng())
+            .map(|t| &t.bytes[..])
+    }
+
+    /// Whether any str or byte buffer arg of `p` is a token.
+    pub fn contains_token(&self, p: &Prog) -> bool {
+        p.calls
+            .iter()
+            .flat_map(|c| c.args.iter())
+            .any(|a| self.in_val(&a.val))
+    }
+
+    fn in_val(&self, val: &Value) -> bool {
+        match val {
+            Value::Str(s) => self.known.contains(s.as_bytes()),
+            Value::Group(vals) => {
+                let bytes = vals
+                    .iter()
+                    .map(|v| match v {
+                        Value::Num(NumValue::Signed(n)) if (-128..=127).contains(n) => {
+                            Some(*n as u8)
+                        }
+                        Value::Num(NumValue::Unsigned(n)) if *n <= 0xff => Some(*n as u8),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>();
+                match bytes {
+                    Some(bytes) if !bytes.is_empty() => self.known.contains(&bytes),
+                    _ => vals.iter().any(|v| self.in_val(v)),
+                }
+            }
+            Value::Opt { val, .. } => self.in_val(val),
+            _ => false,
+        }
+    }
+}
+
+/// Optional name and value of a dict entry.
+type Entry<'a> = (Option<&'a str>, Vec<u8>);
+
+/// Entry of `line`, None for blank and comment lines.
+fn parse_line(line: &str) -> Result<Option<Entry<'_>>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let (name, value) = match line.find('"') {
+        Some(0) => (None, line),
+        Some(i) => {
+            let name = line[..i].trim_end();
+            let name = name
+                .strip_suffix('=')
+                .ok_or_else(|| String::from("expect name=\"value\""))?
+                .trim_end();
+            let name = name.split('@').next().unwrap();
+            let valid =
+                !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_');
+            if !valid {
+                return Err(format!("bad name: {}", name));
+            }
+            (Some(name), line[i..].trim_start())
+        }
+        None => return Err(String::from("no quoted value")),
+    };
+    unquote(value).map(|v| Some((name, v)))
+}
+
+fn unquote(value: &str) -> Result<Vec<u8>, String> {
+    let inner = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .filter(|_| value.len() >= 2)
+        .ok_or_else(|| String::from("value must be enclosed in quotes"))?;
+    let mut bytes = Vec::new();
+    let mut iter = inner.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'\\' => match iter.next() {
+                Some(b'\\') => bytes.push(b'\\'),
+                Some(b'"') => bytes.push(b'"'),
+                Some(b'x') => {
+                    let hex = [iter.next(), iter.next()];
+                    let digits = hex
+                        .iter()
+                        .map(|d| d.and_then(|d| (d as char).to_digit(16)))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| String::from("bad \\x escape"))?;
+                    bytes.push((digits[0] * 16 + digits[1]) as u8);
+                }
+                _ => return Err(String::from("bad escape")),
+            },
+            b'"' => return Err(String::from("unescaped quote in value")),
+            0x20..=0x7e => bytes.push(b),
+            _ => return Err(String::from("non-printable byte in value")),
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_afl_dict() {
+        let text = r#"# http
+header_host="Host:"
+kw@2="GET"
+"\x00\x01\\\"end"
+
+bad="unterminated
+"GET"
+bad-name = "y"
+raw="tab	here"
+"#;
+        let mut dict = Dict::default();
+        let errors = dict.add("http.dict", text);
+        assert_eq!(
+            errors,
+            vec![
+                "http.dict:6: value must be enclosed in quotes".to_string(),
+                "http.dict:8: bad name: bad-name".to_string(),
+                "http.dict:9: non-printable byte in value".to_string(),
+            ]
+        );
+        let tokens = dict.tokens();
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0].bytes, b"Host:");
+        assert_eq!(tokens[0].source, "http.dict:header_host");
+        assert_eq!(tokens[1].source, "http.dict:kw");
+        assert_eq!(tokens[2].bytes, b"\x00\x01\\\"end");
+        assert_eq!(tokens[2].source, "http.dict:4");
+
+        // binary token isn't a str, but fits byte buffers.
+        for _ in 0..32 {
+            let s = dict.choose_str().unwrap();
+            assert!(s == "Host:" || s == "GET");
+        }
+        assert_eq!(dict.choose_bytes(3, -1), Some(&b"GET"[..]));
+        assert_eq!(dict.choose_bytes(6, 8), Some(&b"\x00\x01\\\"end"[..]));
+        assert_eq!(dict.choose_bytes(1, 2), None);
+    }
+}
@@ -0,0 +1,333 @@
+//! Diff of two targets, e.g. before and after updating descriptions.
+//!
+//! Calls and resources are matched by name, since ids are reassigned on every
+//! compilation. Layout of a call is compared by hashing its param and return type
+//! trees, so progs generated with old target can be kept if their calls are unchanged.
+//! Type trees of unchanged calls are walked along each other to pair old type ids with
+//! new ones, so args of migrated progs refer to types of new target.
+use crate::prog::Prog;
+use crate::target::Target;
+use fots::types::{FnId, TypeId, TypeInfo};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, Default)]
+pub struct TargetDiff {
+    /// Calls only in new target
+    pub added: BTreeSet<String>,
+    /// Calls only in old target
+    pub removed: BTreeSet<String>,
+    /// Calls in both targets with different layout
+    pub changed: BTreeSet<String>,
+    /// Resources only in new target
+    pub res_added: BTreeSet<String>,
+    /// Resources only in old target
+    pub res_removed: BTreeSet<String>,
+    /// Old id to new id of calls whose layout is unchanged
+    fid_map: HashMap<FnId, FnId>,
+    /// Old id to new id of types reachable from those calls
+    tid_map: HashMap<TypeId, TypeId>,
+}
+
+pub fn diff(old: &Target, new: &Target) -> TargetDiff {
+    let old_layouts = layouts(old);
+    let new_layouts = layouts(new);
+    let mut diff = TargetDiff::default();
+
+    for (name, (old_fid, old_hash)) in old_layouts.iter() {
+        match new_layouts.get(name) {
+            None => {
+                diff.removed.insert(name.clone());
+            }
+            Some((_, new_hash)) if new_hash != old_hash => {
+                diff.changed.insert(name.clone());
+            }
+            Some((new_fid, _)) => {
+                diff.fid_map.insert(*old_fid, *new_fid);
+                map_fn_types(old, new, *old_fid, *new_fid, &mut diff.tid_map);
+            }
+        }
+    }
+    diff.added = new_layouts
+        .keys()
+        .filter(|name| !old_layouts.contains_key(*name))
+        .cloned()
+        .collect();
+
+    let old_res = res_names(old);
+    let new_res = res_names(new);
+    diff.res_added = new_res.difference(&old_res).cloned().collect();
+    diff.res_removed = old_res.difference(&new_res).cloned().collect();
+    diff
+}
+
+impl TargetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.res_added.is_empty()
+            && self.res_removed.is_empty()
+    }
+
+    /// Whether call of old target is removed or changed in new target.
+    pub fn is_stale(&self, name: &str) -> bool {
+        self.removed.contains(name) || self.changed.contains(name)
+    }
+
+    /// Rewrite prog generated with old target for new target.
+    /// Return None if any call of prog is stale or type of any arg can't be mapped.
+    pub fn migrate(&self, p: &Prog, new: &Target) -> Option<Prog> {
+        let mut p = p.clone();
+        for c in p.calls.iter_mut() {
+            c.fid = *self.fid_map.get(&c.fid)?;
+            for a in c.args.iter_mut().chain(c.ret.iter_mut()) {
+                a.tid = *self.tid_map.get(&a.tid)?;
+            }
+        }
+        if let Some(c) = p.calls.first() {
+            p.gid = new.fn_of(c.fid).gid;
+            if p.calls.iter().any(|c| new.fn_of(c.fid).gid != p.gid) {
+                return None;
+            }
+        }
+        Some(p)
+    }
+}
+
+impl fmt::Display for TargetDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            ("Added calls", &self.added),
+            ("Removed calls", &self.removed),
+            ("Changed calls", &self.changed),
+            ("Added resources", &self.res_added),
+            ("Removed resources", &self.res_removed),
+        ];
+        for (title, names) in sections.iter() {
+            writeln!(f, "{}: {}", title, names.len())?;
+            for name in names.iter() {
+                writeln!(f, "    {}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Id and layout hash of each call, keyed by name.
+fn layouts(t: &Target) -> HashMap<String, (FnId, u64)> {
+    t.groups
+        .values()
+        .flat_map(|g| g.iter_fn())
+        .map(|f| {
+            let mut hasher = DefaultHasher::new();
+            if f.has_params() {
+                for p in f.iter_param() {
+                    p.ident.hash(&mut hasher);
+                    hash_type(t, p.tid, &mut hasher, &mut Vec::new());
+                }
+            }
+            if let Some(tid) = f.r_tid {
+                hash_type(t, tid, &mut hasher, &mut Vec::new());
+            }
+            (f.dec_name.clone(), (f.id, hasher.finish()))
+        })
+        .collect()
+}
+
+/// Hash type tree of `tid` by structure, ids are not hashed.
+/// Types being visited are hashed by name only, to stop at recursive types.
+fn hash_type(t: &Target, tid: TypeId, h: &mut DefaultHasher, visiting: &mut Vec<TypeId>) {
+    let info = t.type_of(tid);
+    std::mem::discriminant(info).hash(h);
+    if visiting.contains(&tid) {
+        info.ident().hash(h);
+        return;
+    }
+    visiting.push(tid);
+    match info {
+        TypeInfo::Num(num) => num.hash(h),
+        TypeInfo::Ptr { dir, depth, tid } => {
+            dir.hash(h);
+            depth.hash(h);
+            hash_type(t, *tid, h, visiting);
+        }
+        TypeInfo::Slice { tid, l, h: high } => {
+            l.hash(h);
+            high.hash(h);
+            hash_type(t, *tid, h, visiting);
+        }
+        TypeInfo::Str { str_type, vals } => {
+            str_type.hash(h);
+            vals.hash(h);
+        }
+        TypeInfo::Struct { ident, fields } | TypeInfo::Union { ident, fields } => {
+            ident.hash(h);
+            for field in fields.iter() {
+                field.ident.hash(h);
+                hash_type(t, field.tid, h, visiting);
+            }
+        }
+        TypeInfo::Flag { ident, flags } => {
+            ident.hash(h);
+            flags.hash(h);
+        }
+        TypeInfo::Alias { ident, tid } => {
+            ident.hash(h);
+            hash_type(t, *tid, h, visiting);
+        }
+        TypeInfo::Res { tid } => hash_type(t, *tid, h, visiting),
+        TypeInfo::Len {
+            tid,
+            path,
+            is_param,
+        } => {
+            path.hash(h);
+            is_param.hash(h);
+            hash_type(t, *tid, h, visiting);
+        }
+    }
+    visiting.pop();
+}
+
+/// Pair type ids of call `old_fid` of `old` with those of call `new_fid` of `new`, both
+/// of the same layout.
+fn map_fn_types(
+    old: &Target,
+    new: &Target,
+    old_fid: FnId,
+    new_fid: FnId,
+    map: &mut HashMap<TypeId, TypeId>,
+) {
+    let (old_f, new_f) = (old.fn_of(old_fid), new.fn_of(new_fid));
+    if old_f.has_params() {
+        for (o, n) in old_f.iter_param().zip(new_f.iter_param()) {
+            map_type(old, new, o.tid, n.tid, map);
+        }
+    }
+    if let (Some(o), Some(n)) = (old_f.r_tid, new_f.r_tid) {
+        map_type(old, new, o, n, map);
+    }
+}
+
+/// Walk type trees of `old_tid` and `new_tid` along each other, pairing their ids.
+/// Types already paired are not walked again, which stops at recursive types.
+fn map_type(
+    old: &Target,
+    new: &Target,
+    old_tid: TypeId,
+    new_tid: TypeId,
+    map: &mut HashMap<TypeId, TypeId>,
+) {
+    if map.insert(old_tid, new_tid).is_some() {
+        return;
+    }
+    match (old.type_of(old_tid), new.type_of(new_tid)) {
+        (TypeInfo::Ptr { tid: o, .. }, TypeInfo::Ptr { tid: n, .. })
+        | (TypeInfo::Slice { tid: o, .. }, TypeInfo::Slice { tid: n, .. })
+        | (TypeInfo::Alias { tid: o, .. }, TypeInfo::Alias { tid: n, .. })
+        | (TypeInfo::Res { tid: o }, TypeInfo::Res { tid: n })
+        | (TypeInfo::Len { tid: o, .. }, TypeInfo::Len { tid: n, .. }) => {
+            map_type(old, new, *o, *n, map)
+        }
+        (TypeInfo::Struct { fields: o, .. }, TypeInfo::Struct { fields: n, .. })
+        | (TypeInfo::Union { fields: o, .. }, TypeInfo::Union { fields: n, .. }) => {
+            for (o, n) in o.iter().zip(n.iter()) {
+                map_type(old, new, o.tid, n.tid, map);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn res_names(t: &Target) -> BTreeSet<String> {
+    t.types
+        .iter()
+        .filter(|(tid, _)| t.is_res(**tid))
+        .filter_map(|(_, info)| info.ident().map(String::from))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bench::descriptions;
+    use crate::c::to_prog;
+    use crate::diff::diff;
+    use crate::gen::{gen_seq, Config};
+    use crate::prog::{Call, Prog};
+    use crate::target::Target;
+    use fots::types::{TypeId, TypeInfo};
+
+    #[test]
+    fn diff_targets() {
+        let descs = descriptions();
+        let old = Target::from(fots::parse_items(&descs).unwrap());
+        let descs = descs
+            .replace("    fn sync()\n", "    fn sync_new()\n")
+            .replace(
+                "fn read(fd fd_t, buf *Out [i8], count len<i32, buf>)",
+                "fn read(fd fd_t, buf *Out [i16], count len<i32, buf>)",
+            );
+        let new = Target::from(fots::parse_items(&descs).unwrap());
+
+        assert!(diff(&old, &old).is_empty());
+        let d = diff(&old, &new);
+        assert_eq!(d.added.iter().collect::<Vec<_>>(), vec!["sync_new"]);
+        assert_eq!(d.removed.iter().collect::<Vec<_>>(), vec!["sync"]);
+        assert_eq!(d.changed.iter().collect::<Vec<_>>(), vec!["read"]);
+        assert!(d.res_added.is_empty() && d.res_removed.is_empty());
+
+        let close = old.fn_by_name("close").unwrap();
+        let mut p = Prog::new(close.gid);
+        p.add_call(Call::new(close.id));
+        let migrated = d.migrate(&p, &new).unwrap();
+        assert_eq!(new.fn_of(migrated.calls[0].fid).dec_name, "close");
+
+        let read = old.fn_by_name("read").unwrap();
+        p.add_call(Call::new(read.id));
+        assert!(d.migrate(&p, &new).is_none());
+    }
+
+    #[test]
+    fn migrate_arg_types() {
+        let descs = descriptions();
+        let old = Target::from(fots::parse_items(&descs).unwrap());
+        // a type declared first shifts ids of all others.
+        let descs = format!("struct diff_pad {{\n    a i32\n}}\n{}", descs);
+        let new = Target::from(fots::parse_items(&descs).unwrap());
+        let d = diff(&old, &new);
+        assert!(d.is_empty());
+
+        let sendmsg = old.fn_by_name("sendmsg").unwrap();
+        let g = &old.groups[&sendmsg.gid];
+        let i = g.fns.iter().position(|f| f.id == sendmsg.id).unwrap();
+        for _ in 0..32 {
+            let p = gen_seq(&[i], sendmsg.gid, &old, &Config::default());
+            let migrated = d.migrate(&p, &new).unwrap();
+            assert_eq!(new.validate(&migrated), Ok(()));
+            // includes are unordered.
+            let body = |c: String| {
+                c.lines()
+                    .filter(|l| !l.starts_with("#include"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            assert_eq!(body(to_prog(&migrated, &new)), body(to_prog(&p, &old)));
+            let c = migrated.calls.last().unwrap();
+            assert_eq!(new.fn_of(c.fid).dec_name, "sendmsg");
+            // msg is a pointer to struct msghdr, whose id changed.
+            assert_ne!(c.args[1].tid, p.calls.last().unwrap().args[1].tid);
+            assert!(matches!(
+                new.type_of(c.args[1].tid),
+                TypeInfo::Ptr { tid, .. } if new.type_of(*tid).ident() == Some("msghdr")
+            ));
+        }
+
+        // an arg of a type no unchanged call has can't be mapped.
+        let mut p = gen_seq(&[i], sendmsg.gid, &old, &Config::default());
+        p.calls[0].args[0].tid = TypeId::MAX;
+        assert!(d.migrate(&p, &new).is_none());
+    }
+}
@@ -11,8 +11,9 @@
 //! samply by number of random input. In this case, we need add
 //! some other interfaces that modify that external/global state
 //! which means generating sequence of target not single call.
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use ndarray::Axis;
 use rand::distributions::Alphanumeric;
@@ -20,10 +21,11 @@ use rand::prelude::*;
 use rand::{random, thread_rng, Rng};
 
 use fots::types::{
-    Field, Flag, FnInfo, GroupId, NumInfo, NumLimit, PtrDir, StrType, TypeId, TypeInfo,
+    Field, Flag, FnId, FnInfo, GroupId, NumInfo, NumLimit, PtrDir, StrType, TypeId, TypeInfo,
 };
 
 use crate::analyze::{RTable, Relation};
+use crate::dict::Dict;
 use crate::prog::{Arg, ArgIndex, ArgPos, Call, Prog};
 use crate::target::Target;
 use crate::value::{NumValue, Value};
@@ -36,6 +38,18 @@ pub struct Config {
     pub str_max_len: usize,
     pub path_max_depth: usize,
     pub sp_delta: f64,
+    /// Each generated prog contains one of these calls, producers of its resources
+    /// are generated before it. Empty means no call is required.
+    pub required_calls: HashSet<FnId>,
+    /// Calls never chosen, e.g. ones disabled during a run. Shared, so configs of all
+    /// jobs refer to one set instead of copying it on each change.
+    pub disabled_calls: Arc<HashSet<FnId>>,
+    /// Distribution of prog length, clamped to `[prog_min_len, prog_max_len]`.
+    pub len_curve: LenCurve,
+    /// Tokens str and byte buffer values are drawn from, shared like `disabled_calls`.
+    pub dict: Arc<Dict>,
+    /// Chance of drawing such a value from `dict`.
+    pub dict_prob: f64,
 }
 
 impl Default for Config {
@@ -47,10 +61,115 @@ impl Default for Config {
             str_max_len: 32,
             path_max_depth: 4,
             sp_delta: 0.4,
+            required_calls: HashSet::new(),
+            disabled_calls: Arc::new(HashSet::new()),
+            len_curve: LenCurve::default(),
+            dict: Arc::new(Dict::default()),
+            dict_prob: 0.25,
         }
     }
 }
 
+/// Distribution length of generated progs is drawn from.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LenCurve {
+    /// Chance of stopping grows with length until max len, how progs were always generated
+    #[default]
+    Progressive,
+    /// Geometric of `mean`, mostly short progs with a long tail
+    Geometric { mean: f64 },
+    /// Triangular rising from `min` to `mode` and falling to `max`
+    Triangular { min: usize, mode: usize, max: usize },
+}
+
+/// Named length curves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LenPreset {
+    /// Geometric of mean 3
+    Short,
+    /// Triangular of 1, 6 and 16
+    Balanced,
+    /// Triangular of 8, 16 and 32, beyond default max len
+    Deep,
+}
+
+impl From<LenPreset> for LenCurve {
+    fn from(preset: LenPreset) -> Self {
+        match preset {
+            LenPreset::Short => LenCurve::Geometric { mean: 3.0 },
+            LenPreset::Balanced => LenCurve::Triangular {
+                min: 1,
+                mode: 6,
+                max: 16,
+            },
+            LenPreset::Deep => LenCurve::Triangular {
+                min: 8,
+                mode: 16,
+                max: 32,
+            },
+        }
+    }
+}
+
+impl LenCurve {
+    pub fn check(&self) -> Result<(), String> {
+        match *self {
+            LenCurve::Geometric { mean } if !mean.is_finite() || mean < 1.0 => Err(format!(
+                "mean of geometric curve must be at least 1, got {}",
+                mean
+            )),
+            LenCurve::Triangular { min, mode, max } if min == 0 || min > mode || mode > max => {
+                Err(format!(
+                    "triangular curve must have 0 < min <= mode <= max, got {}, {}, {}",
+                    min, mode, max
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Mean length of curve before clamping, None for progressive one.
+    pub fn mean(&self) -> Option<f64> {
+        match *self {
+            LenCurve::Progressive => None,
+            LenCurve::Geometric { mean } => Some(mean),
+            LenCurve::Triangular { min, mode, max } => Some((min + mode + max) as f64 / 3.0),
+        }
+    }
+
+    /// Draw a length in `[min_len, max_len]`, None for progressive curve, whose length
+    /// is decided while the sequence grows.
+    pub fn sample<R: Rng>(&self, min_len: usize, max_len: usize, rng: &mut R) -> Option<usize> {
+        let len = match *self {
+            LenCurve::Progressive => return None,
+            LenCurve::Geometric { mean } => {
+                // trials until first success of p = 1 / mean, by inversion
+                let p = 1.0 / mean;
+                if p >= 1.0 {
+                    1
+                } else {
+                    let u = 1.0 - rng.gen::<f64>();
+                    1 + (u.ln() / (1.0 - p).ln()).floor() as usize
+                }
+            }
+            LenCurve::Triangular { min, mode, max } => {
+                let (a, c, b) = (min as f64, mode as f64, max as f64);
+                let u = rng.gen::<f64>();
+                let x = if b == a {
+                    a
+                } else if u < (c - a) / (b - a) {
+                    a + (u * (b - a) * (c - a)).sqrt()
+                } else {
+                    b - ((1.0 - u) * (b - a) * (b - c)).sqrt()
+                };
+                x.round() as usize
+            }
+        };
+        Some(len.clamp(min_len, max_len))
+    }
+}
+
 pub fn gen<S: std::hash::BuildHasher>(
     t: &Target,
     rs: &HashMap<GroupId, RTable, S>,
@@ -60,14 +179,45 @@ pub fn gen<S: std::hash::BuildHasher>(
     assert_eq!(t.groups.len(), rs.len());
 
     let mut rng = thread_rng();
-    // choose group
-    let gid = rs.keys().choose(&mut rng).unwrap();
+    // choose group, one containing an enabled required call if any
+    let enabled = |f: &&FnInfo| !conf.disabled_calls.contains(&f.id);
+    let gid = if conf.required_calls.is_empty() {
+        rs.keys()
+            .filter(|gid| t.groups[gid].fns.iter().any(|f| enabled(&f)))
+            .choose(&mut rng)
+    } else {
+        rs.keys()
+            .filter(|gid| {
+                t.groups[gid]
+                    .fns
+                    .iter()
+                    .filter(enabled)
+                    .any(|f| conf.required_calls.contains(&f.id))
+            })
+            .choose(&mut rng)
+    }
+    .expect("required calls are in no group or all calls are disabled");
     gen_prog(*gid, &rs[gid], t, conf)
 }
 
 pub fn gen_prog(gid: GroupId, r: &RTable, t: &Target, conf: &Config) -> Prog {
+    let fns = &t.groups[&gid].fns;
+    // required calls of this group are anchors of sequence
+    let anchors = fns
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| conf.required_calls.contains(&f.id))
+        .filter(|(_, f)| !conf.disabled_calls.contains(&f.id))
+        .map(|(i, _)| i);
+    let anchor = anchors.choose(&mut thread_rng());
+    let disabled = fns
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| conf.disabled_calls.contains(&f.id))
+        .map(|(i, _)| i);
+    let disabled = disabled.collect::<Vec<_>>();
     // choose sequence
-    let seq = choose_seq(r, conf);
+    let seq = choose_seq(r, conf, anchor, &disabled);
     assert!(!seq.is_empty());
 
     gen_seq(&seq, gid, t, conf)
@@ -86,7 +236,28 @@ pub fn gen_seq(seq: &[usize], gid: GroupId, t: &Target, conf: &Config) -> Prog {
     s.prog
 }
 
-fn adjust_size_param(p: &mut Prog, t: &Target) {
+/// Generate value of `tid` for an arg of call `call` of `p`, reusing resources produced by
+/// earlier calls. Resources the value produces itself are not recorded, so are not reused.
+pub(crate) fn gen_arg_val(p: &Prog, call: usize, tid: TypeId, t: &Target, conf: &Config) -> Value {
+    let mut s = State::new(Prog::new(p.gid), conf);
+    s.frozen = true;
+    for (cid, c) in p.calls[..call].iter().enumerate() {
+        if let Some(ret) = c.ret.as_ref().filter(|r| t.is_res(r.tid)) {
+            s.res.entry(ret.tid).or_default().push((cid, ArgPos::Ret));
+        }
+        for (i, arg) in c.args.iter().enumerate() {
+            if let TypeInfo::Ptr { dir, tid, .. } = t.type_of(arg.tid) {
+                if *dir != PtrDir::In && t.is_res(*tid) {
+                    s.res.entry(*tid).or_default().push((cid, ArgPos::Arg(i)));
+                }
+            }
+        }
+    }
+    gen_value(tid, t, &mut s)
+}
+
+/// Fill len args and fields of `p` with lengths of what they measure.
+pub(crate) fn adjust_size_param(p: &mut Prog, t: &Target) {
     for c in &mut p.calls.iter_mut() {
         let f = t.fn_of(c.fid);
         if f.has_params() {
@@ -184,6 +355,8 @@ struct State<'a> {
     strs: HashMap<StrType, Vec<String>>,
     prog: Prog,
     conf: &'a Config,
+    /// Value of an existing prog is generated, see `gen_arg_val`
+    frozen: bool,
 }
 
 impl<'a> State<'a> {
@@ -193,13 +366,17 @@ impl<'a> State<'a> {
             strs: hashmap! {StrType::FileName => Vec::new()},
             prog,
             conf,
+            frozen: false,
         }
     }
 
     pub fn record_res(&mut self, tid: TypeId, is_ret: bool) {
+        if self.frozen {
+            return;
+        }
         let cid = self.prog.len() - 1;
 
-        let idx = self.res.entry(tid).or_insert_with(Default::default);
+        let idx = self.res.entry(tid).or_default();
         if is_ret {
             idx.push((cid, ArgPos::Ret))
         } else {
@@ -209,7 +386,7 @@ impl<'a> State<'a> {
     }
 
     pub fn record_str(&mut self, t: StrType, val: &str) {
-        let vals = self.strs.entry(t).or_insert_with(Default::default);
+        let vals = self.strs.entry(t).or_default();
         vals.push(val.into())
     }
 
@@ -380,6 +557,12 @@ fn gen_str(str_type: &StrType, vals: &Option<Vec<String>>, s: &mut State) -> Val
             return Value::Str(vals.choose(&mut rng).unwrap().clone());
         }
     }
+    let dict = &s.conf.dict;
+    if *str_type != StrType::FileName && !dict.is_empty() && rng.gen::<f64>() < s.conf.dict_prob {
+        if let Some(val) = dict.choose_str() {
+            return Value::Str(val);
+        }
+    }
     if let Some(s) = s.try_reuse_str(str_type.clone()) {
         return s;
     }
@@ -422,6 +605,25 @@ fn gen_str(str_type: &StrType, vals: &Option<Vec<String>>, s: &mut State) -> Val
 }
 
 fn gen_slice(tid: TypeId, l: isize, h: isize, t: &Target, s: &mut State) -> Value {
+    let dict = &s.conf.dict;
+    if !dict.is_empty() && random::<f64>() < s.conf.dict_prob {
+        let bytes = match t.type_of(tid) {
+            TypeInfo::Num(NumInfo::I8(NumLimit::None)) => dict.choose_bytes(l, h).map(|b| {
+                b.iter()
+                    .map(|b| NumValue::Signed(*b as i8 as i64))
+                    .collect::<Vec<_>>()
+            }),
+            TypeInfo::Num(NumInfo::U8(NumLimit::None)) => dict.choose_bytes(l, h).map(|b| {
+                b.iter()
+                    .map(|b| NumValue::Unsigned(*b as u64))
+                    .collect::<Vec<_>>()
+            }),
+            _ => None,
+        };
+        if let Some(bytes) = bytes {
+            return Value::Group(bytes.into_iter().map(Value::Num).collect());
+        }
+    }
     let len: usize = gen_slice_len(l, h);
     let mut vals = Vec::new();
 
@@ -536,19 +738,32 @@ fn gen_num(type_info: &NumInfo) -> Value {
     }
 }
 
-fn choose_seq(rs: &RTable, conf: &Config) -> Vec<usize> {
+/// Choose sequence of calls, starting from `anchor` if given. Sequence is built backward,
+/// calls pushed after one are ones it depends on, so `anchor` is the last call.
+fn choose_seq(rs: &RTable, conf: &Config, anchor: Option<usize>, disabled: &[usize]) -> Vec<usize> {
     assert!(!rs.is_empty());
 
-    // selection prability list
-    let mut sps = std::iter::repeat(1.0).take(rs.len()).collect::<Vec<_>>();
+    // selection prability list, disabled calls are never selected
+    let mut sps = vec![1.0; rs.len()];
+    for &i in disabled {
+        sps[i] = 0.0;
+    }
+    let len = conf
+        .len_curve
+        .sample(conf.prog_min_len, conf.prog_max_len, &mut thread_rng());
     let mut seq = Vec::new();
+    if let Some(anchor) = anchor {
+        sps[anchor] *= conf.sp_delta;
+        seq.push(anchor);
+        push_deps(rs, &mut seq, 0, &mut sps, conf, len);
+    }
     let mut i;
-    while !should_stop(seq.len(), &conf) {
+    while !should_stop(seq.len(), conf, len) {
         let index = choose_call(&sps);
         sps[index] *= conf.sp_delta;
         seq.push(index);
         i = seq.len() - 1;
-        push_deps(rs, &mut seq, i, &mut sps, conf);
+        push_deps(rs, &mut seq, i, &mut sps, conf, len);
     }
 
     seq.shrink_to_fit();
@@ -557,7 +772,11 @@ fn choose_seq(rs: &RTable, conf: &Config) -> Vec<usize> {
     seq
 }
 
-fn should_stop(prog_len: usize, conf: &Config) -> bool {
+/// Whether sequence of `prog_len` calls is long enough, `len` is drawn from length curve.
+fn should_stop(prog_len: usize, conf: &Config, len: Option<usize>) -> bool {
+    if let Some(len) = len {
+        return prog_len >= len;
+    }
     let crt_progress = (prog_len as f64) / (conf.prog_max_len as f64);
     !(prog_len < conf.prog_min_len
         || (prog_len < conf.prog_max_len && random::<f64>() > crt_progress))
@@ -565,7 +784,7 @@ fn should_stop(prog_len: usize, conf: &Config) -> bool {
 
 fn choose_call(sps: &[f64]) -> usize {
     let mut rng = thread_rng();
-    let mut cum_sum = std::iter::repeat(0.0).take(sps.len()).collect::<Vec<_>>();
+    let mut cum_sum = vec![0.0; sps.len()];
     let mut pre = 0.0;
 
     for (i, sum) in cum_sum.iter_mut().enumerate() {
@@ -583,10 +802,17 @@ fn choose_call(sps: &[f64]) -> usize {
 }
 
 #[allow(clippy::collapsible_if)]
-fn push_deps(rs: &RTable, seq: &mut Vec<usize>, mut i: usize, sps: &mut [f64], conf: &Config) {
+fn push_deps(
+    rs: &RTable,
+    seq: &mut Vec<usize>,
+    mut i: usize,
+    sps: &mut [f64],
+    conf: &Config,
+    len: Option<usize>,
+) {
     let mut call_index;
 
-    while !should_stop(seq.len(), &conf) && i < seq.len() {
+    while !should_stop(seq.len(), conf, len) && i < seq.len() {
         call_index = seq[i];
         for (j, r) in rs.index_axis(Axis(0), call_index).iter().enumerate() {
             if call_index != j && random::<f64>() < sps[j] {
@@ -599,3 +825,105 @@ fn push_deps(rs: &RTable, seq: &mut Vec<usize>, mut i: usize, sps: &mut [f64], c
         i += 1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::bench::target;
+    use crate::c::to_prog;
+    use crate::dict::Dict;
+    use crate::gen::{gen, Config, LenCurve, LenPreset};
+    use rand::prelude::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn gen_smoke() {
+        let (t, rt) = target();
+        let conf = Default::default();
+        for _ in 0..1000 {
+            let p = gen(&t, &rt, &conf);
+            assert!(!p.is_empty());
+            assert_eq!(t.validate(&p), Ok(()));
+            assert!(!to_prog(&p, &t).is_empty());
+        }
+    }
+
+    #[test]
+    fn gen_required_calls() {
+        let (t, rt) = target();
+        let required = t.fn_by_name("write").unwrap().id;
+        let mut conf = Config::default();
+        conf.required_calls.insert(required);
+        for _ in 0..1000 {
+            let p = gen(&t, &rt, &conf);
+            assert!(p.calls.iter().any(|c| c.fid == required));
+        }
+    }
+
+    #[test]
+    fn gen_disabled_calls() {
+        let (t, rt) = target();
+        let mut conf = Config::default();
+        for name in &["read", "write"] {
+            Arc::make_mut(&mut conf.disabled_calls).insert(t.fn_by_name(name).unwrap().id);
+        }
+        for _ in 0..1000 {
+            let p = gen(&t, &rt, &conf);
+            assert!(!p.is_empty());
+            assert!(p
+                .calls
+                .iter()
+                .all(|c| !conf.disabled_calls.contains(&c.fid)));
+        }
+    }
+
+    #[test]
+    fn gen_from_dict() {
+        let (t, rt) = target();
+        let mut dict = Dict::default();
+        assert!(dict.add("t.dict", "magic=\"user.magic\"").is_empty());
+        let conf = Config {
+            dict: Arc::new(dict),
+            dict_prob: 1.0,
+            ..Config::default()
+        };
+        let mut with_token = 0;
+        for _ in 0..1000 {
+            let p = gen(&t, &rt, &conf);
+            assert_eq!(t.validate(&p), Ok(()));
+            if conf.dict.contains_token(&p) {
+                with_token += 1;
+            }
+        }
+        assert_ne!(with_token, 0);
+    }
+
+    #[test]
+    fn len_curve_mean() {
+        let mut rng = StdRng::seed_from_u64(653);
+        let curves = [
+            LenCurve::Geometric { mean: 5.0 },
+            LenCurve::from(LenPreset::Balanced),
+            LenCurve::from(LenPreset::Deep),
+        ];
+        for curve in curves.iter() {
+            curve.check().unwrap();
+            let n = 10_000;
+            let sum = (0..n)
+                .map(|_| curve.sample(1, 1024, &mut rng).unwrap())
+                .sum::<usize>();
+            let mean = sum as f64 / n as f64;
+            let expected = curve.mean().unwrap();
+            assert!(
+                (mean - expected).abs() < expected * 0.05,
+                "{:?}: mean {}, expected {}",
+                curve,
+                mean,
+                expected
+            );
+        }
+        assert_eq!(LenCurve::Progressive.sample(1, 16, &mut rng), None);
+        for mean in [0.5, f64::NAN, f64::INFINITY] {
+            assert!(LenCurve::Geometric { mean }.check().is_err());
+        }
+    }
+}
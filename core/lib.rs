@@ -6,10 +6,18 @@ extern crate serde;
 extern crate lazy_static;
 
 pub mod analyze;
+#[cfg(any(test, feature = "bench"))]
+pub mod bench;
 pub mod c;
+pub mod dict;
+pub mod diff;
 pub mod gen;
 pub mod minimize;
 pub mod mutate;
 pub mod prog;
+pub mod subsystem;
+pub mod sweep;
+pub mod syz;
 pub mod target;
+pub mod validate;
 pub mod value;
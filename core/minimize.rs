@@ -25,10 +25,9 @@ pub fn remove(p: &mut Prog, i: usize) -> bool {
     if calls.is_empty() {
         return false;
     }
-    // adjust ref arg
-    let last_call = p.len() - 1;
+    // adjust ref arg, of last call too, which is never removed
     for (j, call) in p.calls.iter_mut().enumerate().skip(i + 1) {
-        if !calls.contains(&j) && j != last_call {
+        if !calls.contains(&j) {
             for arg in call.args.iter_mut() {
                 for_each_ref_mut(&mut arg.val, |(ref mut cid, _)| {
                     let count = calls
@@ -126,3 +125,23 @@ fn for_each_ref_mut<F: FnMut(&mut ArgIndex)>(val: &mut Value, f: F) {
 
     do_for_each_ref_mut(val, &mut f.f)
 }
+#[cfg(test)]
+mod tests {
+    use crate::bench::target;
+    use crate::gen::{gen, Config};
+    use crate::minimize::remove;
+
+    #[test]
+    fn remove_keeps_refs_valid() {
+        let (t, rt) = target();
+        for _ in 0..100 {
+            let p = gen(&t, &rt, &Config::default());
+            for i in 0..p.len() - 1 {
+                let mut p = p.clone();
+                if remove(&mut p, i) {
+                    assert_eq!(t.validate(&p), Ok(()));
+                }
+            }
+        }
+    }
+}
@@ -1,14 +1,28 @@
 use crate::analyze::RTable;
-use crate::gen::{gen_seq, Config};
+use crate::gen::{adjust_size_param, gen_arg_val, gen_seq, Config};
 use crate::prog::Prog;
 use crate::target::Target;
-use fots::types::GroupId;
+use crate::value::Value;
+use fots::types::{GroupId, PtrDir, TypeId, TypeInfo};
 use rand::prelude::*;
 use std::collections::{HashMap, HashSet};
 
-#[allow(clippy::type_complexity)]
-const MUTATE_METHOD: [fn(&Prog, &Target, &RTable, &HashSet<Prog>, &Config) -> Prog; 2] =
-    [seq_reuse, merge_seq /*remove_call*/];
+type Method = fn(&Prog, &Target, &RTable, &HashSet<Prog>, &Config) -> Prog;
+
+const MUTATE_METHOD: [Method; 3] = [seq_reuse, merge_seq, mutate_arg /*remove_call*/];
+
+/// Levels of structs, unions and slices `mutate_arg` descends into.
+const MAX_DEPTH: usize = 4;
+
+/// Mutation methods by name, so that each can be benchmarked on its own.
+#[cfg(feature = "bench")]
+pub fn methods() -> [(&'static str, Method); 3] {
+    [
+        ("seq_reuse", seq_reuse),
+        ("merge_seq", merge_seq),
+        ("mutate_arg", mutate_arg),
+    ]
+}
 
 pub fn mutate(
     corpus: &HashSet<Prog>,
@@ -16,11 +30,23 @@ pub fn mutate(
     rt: &HashMap<GroupId, RTable>,
     conf: &Config,
 ) -> Prog {
-    let mut rng = thread_rng();
-    let p = corpus.iter().choose(&mut rng).unwrap();
+    let p = corpus.iter().choose(&mut thread_rng()).unwrap();
+    mutate_prog(p, corpus, t, rt, conf)
+}
+
+/// Mutate `p` selected from `corpus` by caller.
+pub fn mutate_prog(
+    p: &Prog,
+    corpus: &HashSet<Prog>,
+    t: &Target,
+    rt: &HashMap<GroupId, RTable>,
+    conf: &Config,
+) -> Prog {
     let rt = &rt[&p.gid];
-    let method = MUTATE_METHOD.choose(&mut rng).unwrap();
-    method(p, t, rt, corpus, conf)
+    let method = MUTATE_METHOD.choose(&mut thread_rng()).unwrap();
+    let p = method(p, t, rt, corpus, conf);
+    debug_assert_eq!(t.validate(&p), Ok(()), "mutated prog is invalid");
+    p
 }
 
 fn seq_reuse(p: &Prog, t: &Target, _rt: &RTable, _corpus: &HashSet<Prog>, conf: &Config) -> Prog {
@@ -51,6 +77,140 @@ fn merge_seq(p0: &Prog, t: &Target, _rt: &RTable, corpus: &HashSet<Prog>, conf:
     gen_seq(&s0, p0.gid, t, conf)
 }
 
+/// Mutate one value of an arg of a random call, which may be nested in structs, unions and
+/// slices: a union switches to another field, a slice grows or shrinks by an element, and
+/// anything else is generated again. Lens are adjusted afterwards, so prog stays valid.
+fn mutate_arg(p: &Prog, t: &Target, rt: &RTable, corpus: &HashSet<Prog>, conf: &Config) -> Prog {
+    let mut rng = thread_rng();
+    let mut p = p.clone();
+    let calls = (0..p.len()).filter(|i| !p.calls[*i].args.is_empty());
+    let call = match calls.choose(&mut rng) {
+        Some(call) => call,
+        None => return seq_reuse(&p, t, rt, corpus, conf),
+    };
+    let mut args = std::mem::take(&mut p.calls[call].args);
+    let mut count = usize::MAX;
+    for arg in args.iter_mut() {
+        visit(t, arg.tid, &mut arg.val, 0, &mut count, &mut |_, _| ());
+    }
+    let count = usize::MAX - count;
+    if count == 0 {
+        p.calls[call].args = args;
+        return seq_reuse(&p, t, rt, corpus, conf);
+    }
+
+    let mut nth = rng.gen_range(0, count);
+    let mut mutate = |tid, val: &mut Value| mutate_val(&p, call, tid, val, t, conf);
+    for arg in args.iter_mut() {
+        if visit(t, arg.tid, &mut arg.val, 0, &mut nth, &mut mutate) {
+            break;
+        }
+    }
+    p.calls[call].args = args;
+    adjust_size_param(&mut p, t);
+    p
+}
+
+/// Visit mutable values of `val` of type `tid` in order, `nth` counts down on each, the
+/// one it reaches 0 at is mutated by `f`. Returns whether it's been reached.
+fn visit(
+    t: &Target,
+    tid: TypeId,
+    val: &mut Value,
+    depth: usize,
+    nth: &mut usize,
+    f: &mut dyn FnMut(TypeId, &mut Value),
+) -> bool {
+    let mut hit = |val: &mut Value, nth: &mut usize| {
+        if *nth == 0 {
+            f(tid, val);
+            return true;
+        }
+        *nth -= 1;
+        false
+    };
+    match (t.type_of(tid), val) {
+        (TypeInfo::Len { .. }, _)
+        | (
+            TypeInfo::Ptr {
+                dir: PtrDir::Out, ..
+            },
+            _,
+        ) => false,
+        (TypeInfo::Alias { tid, .. }, val) if !t.is_res(*tid) => visit(t, *tid, val, depth, nth, f),
+        (TypeInfo::Ptr { tid, .. }, val) if *val != Value::None => {
+            visit(t, *tid, val, depth, nth, f)
+        }
+        (TypeInfo::Struct { fields, .. }, Value::Group(vals)) => {
+            depth < MAX_DEPTH
+                && fields
+                    .iter()
+                    .zip(vals.iter_mut())
+                    .any(|(field, val)| visit(t, field.tid, val, depth + 1, nth, f))
+        }
+        (TypeInfo::Union { fields, .. }, val) => {
+            if fields.len() > 1 && hit(val, nth) {
+                return true;
+            }
+            match val {
+                Value::Opt { choice, val } if depth < MAX_DEPTH => {
+                    visit(t, fields[*choice].tid, val, depth + 1, nth, f)
+                }
+                _ => false,
+            }
+        }
+        (TypeInfo::Slice { tid, l, h }, val) => {
+            if slice_bounds(*l, *h).is_some() && hit(val, nth) {
+                return true;
+            }
+            match val {
+                Value::Group(vals) if depth < MAX_DEPTH => vals
+                    .iter_mut()
+                    .any(|val| visit(t, *tid, val, depth + 1, nth, f)),
+                _ => false,
+            }
+        }
+        (_, val) => hit(val, nth),
+    }
+}
+
+/// Mutate `val` of type `tid` in arg of call `call` of `p`.
+fn mutate_val(p: &Prog, call: usize, tid: TypeId, val: &mut Value, t: &Target, conf: &Config) {
+    let mut rng = thread_rng();
+    match (t.type_of(tid), &mut *val) {
+        (TypeInfo::Union { fields, .. }, Value::Opt { choice, .. }) => {
+            let other = (0..fields.len()).filter(|i| i != choice);
+            let choice = other.choose(&mut rng).unwrap();
+            *val = Value::Opt {
+                choice,
+                val: Box::new(gen_arg_val(p, call, fields[choice].tid, t, conf)),
+            };
+        }
+        (TypeInfo::Slice { tid, l, h }, Value::Group(vals)) => {
+            let (min, max) = slice_bounds(*l, *h).unwrap();
+            let grow = vals.len() < min || (vals.len() < max && (vals.len() == min || rng.gen()));
+            if grow {
+                let i = rng.gen_range(0, vals.len() + 1);
+                vals.insert(i, gen_arg_val(p, call, *tid, t, conf));
+            } else {
+                vals.remove(rng.gen_range(0, vals.len()));
+            }
+        }
+        _ => *val = gen_arg_val(p, call, tid, t, conf),
+    }
+}
+
+/// Lengths a slice can be resized to, both included, None if it's fixed. `h` is excluded
+/// as in `gen_slice_len`.
+fn slice_bounds(l: isize, h: isize) -> Option<(usize, usize)> {
+    match (l, h) {
+        (-1, -1) => Some((1, usize::MAX)),
+        (_, -1) => None,
+        (l, h) if l + 1 < h => Some((l as usize, h as usize - 1)),
+        _ => None,
+    }
+}
+
 // fn insert_call(p: &Prog, t: &Target, rt: &RTable, corpus: &[Prog], conf: &Config) -> Prog {
 //     let seq = ex
 //     todo!()
@@ -80,3 +240,77 @@ fn merge_seq(p0: &Prog, t: &Target, _rt: &RTable, corpus: &HashSet<Prog>, conf:
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::static_analyze;
+    use crate::c::to_prog;
+    use crate::gen::gen_seq;
+
+    const DESCS: &str = "
+type fd_t = res<i32>
+union val_u { n i32, w i64 }
+struct inner { v val_u, buf *[i8; (1, 4)] }
+struct msg { size len<u32, items>, items *[inner; (1, 3)] }
+group T {
+    fn make(size i32) fd_t
+    fn send(f fd_t, m *msg, v val_u)
+}
+";
+
+    #[test]
+    fn mutate_nested_args() {
+        let t = Target::from(fots::parse_items(DESCS).unwrap());
+        let rt = static_analyze(&t);
+        let conf = Config::default();
+        let gid = t.fn_by_name("send").unwrap().gid;
+        let g = &t.groups[&gid];
+        let seq = ["make", "send"]
+            .iter()
+            .map(|n| g.fns.iter().position(|f| &f.dec_name == n).unwrap())
+            .collect::<Vec<_>>();
+        let items = |p: &Prog| match &p.calls[1].args[1].val {
+            Value::Group(msg) => match (&msg[0], &msg[1]) {
+                (Value::Num(size), Value::Group(items)) => (size.literal(), items.clone()),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+
+        let (mut switched, mut resized) = (false, false);
+        let mut p = gen_seq(&seq, gid, &t, &conf);
+        for _ in 0..500 {
+            let m = mutate_arg(&p, &t, &rt[&gid], &HashSet::new(), &conf);
+            assert_eq!(t.validate(&m), Ok(()));
+            assert!(!to_prog(&m, &t).is_empty());
+            assert!(!crate::syz::encode(&m, &t).is_empty());
+            assert_eq!(m.len(), p.len());
+
+            let ((_, before), (size, after)) = (items(&p), items(&m));
+            assert_eq!(size, after.len().to_string());
+            // items are `(1, 3)`, generated with 1 or 2 of them.
+            assert!((1..3).contains(&after.len()));
+            resized |= before.len() != after.len();
+            let choice = |v: &Value| match v {
+                Value::Opt { choice, .. } => *choice,
+                _ => unreachable!(),
+            };
+            switched |= choice(&p.calls[1].args[2].val) != choice(&m.calls[1].args[2].val);
+            switched |= before.iter().zip(after.iter()).any(|(b, a)| match (b, a) {
+                (Value::Group(b), Value::Group(a)) => choice(&b[0]) != choice(&a[0]),
+                _ => unreachable!(),
+            });
+            p = m;
+        }
+        assert!(switched && resized);
+    }
+
+    #[test]
+    fn slice_bounds_exclude_high() {
+        assert_eq!(slice_bounds(1, 4), Some((1, 3)));
+        assert_eq!(slice_bounds(1, 2), None);
+        assert_eq!(slice_bounds(2, -1), None);
+        assert_eq!(slice_bounds(-1, -1), Some((1, usize::MAX)));
+    }
+}
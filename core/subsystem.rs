@@ -0,0 +1,572 @@
+//! Heuristic grouping of calls into kernel subsystems.
+//!
+//! Name of a call is split into base name and specialization, e.g. `ioctl@KVM_RUN`
+//! is (`ioctl`, `KVM_RUN`). Both `@` and `$` are accepted as separator. Raw wrapper
+//! `syscall@statx` is treated as `statx`. Specialization is matched against the table
+//! first, since it is more specific than the base name, then the base name is matched.
+//! Calls matching nothing belong to [`OTHER`].
+//!
+//! Crash reports are classified by the first frame of the crash that isn't part of error
+//! reporting itself: function named in the title, `RIP:` line, then call trace. Source path
+//! of the frame is used if the report is symbolized, function name prefix otherwise.
+
+/// Subsystem of calls that don't match any prefix.
+pub const OTHER: &str = "other";
+
+/// Subsystems and lower case name prefixes belonging to them.
+/// Order matters: the first matched entry wins, so narrow subsystems go first.
+pub const SUBSYSTEMS: &[(&str, &[&str])] = &[
+    ("kvm", &["kvm"]),
+    ("bpf", &["bpf"]),
+    ("drm", &["drm", "fb"]),
+    ("dma", &["ion", "dma_buf"]),
+    (
+        "sound",
+        &["snd", "sound", "dsp", "adsp", "audio", "mixer", "seq"],
+    ),
+    ("usb", &["usb", "uhid", "hid"]),
+    ("tty", &["tty", "tioc", "pty", "ptmx"]),
+    ("fuse", &["fuse"]),
+    (
+        "security",
+        &[
+            "smack",
+            "selinux",
+            "apparmor",
+            "keyctl",
+            "add_key",
+            "request_key",
+        ],
+    ),
+    (
+        "net",
+        &[
+            "sock",
+            "bind",
+            "connect",
+            "accept",
+            "listen",
+            "sendto",
+            "sendmsg",
+            "sendmmsg",
+            "recvfrom",
+            "recvmsg",
+            "recvmmsg",
+            "shutdown",
+            "setsockopt",
+            "getsockopt",
+            "getpeername",
+            "getsockname",
+            "siocg",
+            "siocs",
+            "ifreq",
+            "vhost",
+            "vnet",
+            "rdma",
+            "tun",
+            "netlink",
+        ],
+    ),
+    (
+        "mm",
+        &[
+            "mmap",
+            "munmap",
+            "mremap",
+            "mprotect",
+            "madvise",
+            "mlock",
+            "munlock",
+            "msync",
+            "mincore",
+            "brk",
+            "memfd",
+            "mbind",
+            "migrate_pages",
+            "move_pages",
+            "remap",
+            "process_vm",
+            "userfaultfd",
+            "pkey_",
+            "membarrier",
+            "modify_ldt",
+        ],
+    ),
+    (
+        "ipc",
+        &[
+            "msgget", "msgsnd", "msgrcv", "msgctl", "semget", "semop", "semctl", "shmget", "shmat",
+            "shmdt", "shmctl", "mq_", "pipe", "eventfd", "signalfd", "kcmp",
+        ],
+    ),
+    (
+        "fs",
+        &[
+            "open",
+            "creat",
+            "close",
+            "read",
+            "write",
+            "pread",
+            "pwrite",
+            "lseek",
+            "stat",
+            "lstat",
+            "fstat",
+            "mkdir",
+            "rmdir",
+            "link",
+            "unlink",
+            "symlink",
+            "rename",
+            "chmod",
+            "fchmod",
+            "chown",
+            "fchown",
+            "lchown",
+            "truncate",
+            "ftruncate",
+            "fsync",
+            "fdatasync",
+            "sync",
+            "mount",
+            "umount",
+            "dup",
+            "fcntl",
+            "getdents",
+            "utime",
+            "access",
+            "faccessat",
+            "chdir",
+            "fchdir",
+            "getcwd",
+            "chroot",
+            "setxattr",
+            "getxattr",
+            "listxattr",
+            "removexattr",
+            "lsetxattr",
+            "fsetxattr",
+            "flock",
+            "fallocate",
+            "fadvise",
+            "sendfile",
+            "splice",
+            "tee",
+            "vmsplice",
+            "mknod",
+            "inotify",
+            "fanotify",
+            "swapon",
+            "swapoff",
+            "name_to_handle",
+            "copy_file_range",
+            "io_",
+            "epoll",
+            "poll",
+            "ppoll",
+            "select",
+            "pselect",
+            "timerfd",
+            "quotactl",
+            "lookup_dcookie",
+            "lgetxattr",
+            "fgetxattr",
+            "llistxattr",
+            "flistxattr",
+            "lremovexattr",
+            "fremovexattr",
+            "futimesat",
+            "posix_fadvise",
+            "pivot_root",
+            "sysfs",
+            "uselib",
+        ],
+    ),
+    (
+        "kernel",
+        &[
+            "clone",
+            "fork",
+            "exit",
+            "wait",
+            "kill",
+            "tkill",
+            "tgkill",
+            "sched",
+            "setuid",
+            "setgid",
+            "seteuid",
+            "setegid",
+            "setre",
+            "setres",
+            "setfs",
+            "setns",
+            "setsid",
+            "setpgid",
+            "setpgrp",
+            "setpriority",
+            "setgroups",
+            "sethost",
+            "setdomain",
+            "setrlimit",
+            "setitimer",
+            "set_t",
+            "getpid",
+            "getppid",
+            "gettid",
+            "getuid",
+            "getgid",
+            "geteuid",
+            "getegid",
+            "getres",
+            "getpriority",
+            "getgroups",
+            "gethost",
+            "getdomain",
+            "getrlimit",
+            "getrusage",
+            "getitimer",
+            "getrandom",
+            "get_t",
+            "prlimit",
+            "nice",
+            "restart_syscall",
+            "rt_tgsig",
+            "capget",
+            "capset",
+            "prctl",
+            "arch_prctl",
+            "ptrace",
+            "times",
+            "time",
+            "clock",
+            "nanosleep",
+            "timer_",
+            "alarm",
+            "uname",
+            "sysinfo",
+            "syslog",
+            "personality",
+            "init_module",
+            "finit_module",
+            "delete_module",
+            "kexec",
+            "ioprio",
+            "ioperm",
+            "iopl",
+            "acct",
+            "reboot",
+            "rt_sig",
+            "sigaltstack",
+            "futex",
+            "pidfd",
+            "perf_event",
+        ],
+    ),
+];
+
+/// Subsystem of call with declared name `dec_name`.
+pub fn classify(dec_name: &str) -> &'static str {
+    let (base, spec) = split_name(dec_name);
+    spec.and_then(match_prefix)
+        .or_else(|| match_prefix(base))
+        .unwrap_or(OTHER)
+}
+
+/// Source dirs and subsystems of code in them, the first matched entry wins.
+pub const CRASH_PATHS: &[(&str, &str)] = &[
+    ("arch/x86/kvm/", "kvm"),
+    ("virt/kvm/", "kvm"),
+    ("kernel/bpf/", "bpf"),
+    ("drivers/gpu/", "drm"),
+    ("drivers/dma-buf/", "dma"),
+    ("drivers/usb/", "usb"),
+    ("drivers/hid/", "usb"),
+    ("drivers/tty/", "tty"),
+    ("drivers/net/", "net"),
+    ("drivers/block/", "block"),
+    ("drivers/vhost/", "net"),
+    ("fs/fuse/", "fuse"),
+    ("sound/", "sound"),
+    ("security/", "security"),
+    ("net/", "net"),
+    ("fs/", "fs"),
+    ("mm/", "mm"),
+    ("ipc/", "ipc"),
+    ("block/", "block"),
+    ("crypto/", "crypto"),
+    ("drivers/", "drivers"),
+    ("kernel/", "kernel"),
+];
+
+/// Function name prefixes of crash frames and their subsystems, the first matched entry wins.
+pub const CRASH_FNS: &[(&str, &str)] = &[
+    ("kvm_", "kvm"),
+    ("vmx_", "kvm"),
+    ("svm_", "kvm"),
+    ("bpf_", "bpf"),
+    ("drm_", "drm"),
+    ("dma_buf", "dma"),
+    ("usb_", "usb"),
+    ("hid_", "usb"),
+    ("tty_", "tty"),
+    ("n_tty", "tty"),
+    ("snd_", "sound"),
+    ("fuse_", "fuse"),
+    ("selinux_", "security"),
+    ("security_", "security"),
+    ("tcp_", "net"),
+    ("udp_", "net"),
+    ("ip_", "net"),
+    ("ipv6_", "net"),
+    ("inet", "net"),
+    ("sock_", "net"),
+    ("sk_", "net"),
+    ("__sk_", "net"),
+    ("skb_", "net"),
+    ("netlink_", "net"),
+    ("nf_", "net"),
+    ("ext4_", "fs"),
+    ("btrfs_", "fs"),
+    ("xfs_", "fs"),
+    ("vfs_", "fs"),
+    ("do_sys_open", "fs"),
+    ("generic_file", "fs"),
+    ("kmalloc", "mm"),
+    ("__kmalloc", "mm"),
+    ("kfree", "mm"),
+    ("kmem_cache", "mm"),
+    ("__alloc_pages", "mm"),
+    ("handle_mm_fault", "mm"),
+    ("vma_", "mm"),
+    ("blk_", "block"),
+    ("bio_", "block"),
+];
+
+/// Frames of error reporting and sanitizers, they tell nothing about the crash site.
+const REPORT_FNS: &[&str] = &[
+    "dump_stack",
+    "__dump_stack",
+    "show_stack",
+    "print_address_description",
+    "print_report",
+    "kasan_",
+    "__kasan_",
+    "__asan_",
+    "check_memory_region",
+    "kmsan_",
+    "__msan_",
+    "ubsan_",
+    "__ubsan_",
+    "__warn",
+    "warn_slowpath",
+    "report_bug",
+    "handle_bug",
+    "fixup_bug",
+    "panic",
+    "__might_sleep",
+    "___might_sleep",
+    "__might_fault",
+    "lockdep_",
+    "__lock_acquire",
+    "lock_acquire",
+    "do_error_trap",
+    "do_invalid_op",
+    "invalid_op",
+    "exc_invalid_op",
+    "asm_exc_invalid_op",
+];
+
+/// All subsystem names calls and crashes are classified into.
+pub fn names() -> Vec<&'static str> {
+    let mut names = SUBSYSTEMS
+        .iter()
+        .map(|(s, _)| *s)
+        .chain(CRASH_PATHS.iter().map(|(_, s)| *s))
+        .chain(std::iter::once(OTHER))
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+/// Subsystem of crash report `report`, [`OTHER`] if no frame is recognized.
+pub fn classify_crash(report: &str) -> &'static str {
+    crash_frames(report)
+        .filter(|(f, _)| !REPORT_FNS.iter().any(|p| f.starts_with(p)))
+        .find_map(|(f, path)| {
+            path.and_then(|path| {
+                let path = path.trim_start_matches("./");
+                CRASH_PATHS
+                    .iter()
+                    .find(|(dir, _)| path.starts_with(dir))
+                    .map(|(_, s)| *s)
+            })
+            .or_else(|| {
+                CRASH_FNS
+                    .iter()
+                    .find(|(prefix, _)| f.starts_with(prefix))
+                    .map(|(_, s)| *s)
+            })
+        })
+        .unwrap_or(OTHER)
+}
+
+/// Function of the first frame of crash outside error reporting, i.e. where it crashed.
+pub fn crash_site(report: &str) -> Option<&str> {
+    crash_frames(report)
+        .map(|(f, _)| f)
+        .find(|f| !REPORT_FNS.iter().any(|p| f.starts_with(p)))
+}
+
+/// Source files of the first `n` frames of crash outside error reporting that have one,
+/// without line numbers and duplicates.
+pub fn crash_files(report: &str, n: usize) -> Vec<&str> {
+    let mut files = Vec::new();
+    let paths = crash_frames(report)
+        .filter(|(f, _)| !REPORT_FNS.iter().any(|p| f.starts_with(p)))
+        .filter_map(|(_, path)| path)
+        .map(|path| path.split(':').next().unwrap().trim_start_matches("./"));
+    for path in paths {
+        if files.len() == n {
+            break;
+        }
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Function and optional source path of frames of the crash, unreliable frames (`?`) and
+/// stacks of allocation and free of KASAN reports are skipped.
+fn crash_frames(report: &str) -> impl Iterator<Item = (&str, Option<&str>)> {
+    let mut lines = report.lines().skip_while(|l| l.trim().is_empty());
+    let title = lines
+        .next()
+        .and_then(|l| l.rsplit(" in ").next().filter(|_| l.contains(" in ")))
+        .and_then(|f| frame_of(f.split_whitespace()));
+    let rest = lines.collect::<Vec<_>>();
+    let rip = rest
+        .iter()
+        .find_map(|l| l.trim().strip_prefix("RIP: "))
+        .and_then(|l| l.split_once(':').map(|(_, f)| f))
+        .and_then(|f| frame_of(f.split_whitespace()));
+    let trace = rest
+        .into_iter()
+        .skip_while(|l| !l.contains("Call Trace:"))
+        .skip(1)
+        .take_while(|l| {
+            let l = l.trim();
+            !l.is_empty()
+                && !l.starts_with("Allocated by")
+                && !l.starts_with("Freed by")
+                && !l.starts_with("---[ end")
+        })
+        .filter(|l| !l.trim_start().starts_with('?'))
+        .filter_map(|l| frame_of(l.split_whitespace()));
+    title.into_iter().chain(rip).chain(trace)
+}
+
+/// Frame of tokens like `tcp_close+0x12/0x30 net/ipv4/tcp.c:2345`.
+fn frame_of<'a>(tokens: impl Iterator<Item = &'a str>) -> Option<(&'a str, Option<&'a str>)> {
+    let mut tokens = tokens.skip_while(|t| !t.contains("+0x") && !is_fn_name(t));
+    let f = tokens.next()?;
+    let f = f.split('+').next().unwrap();
+    if !is_fn_name(f) {
+        return None;
+    }
+    let path = tokens
+        .next()
+        .filter(|t| t.contains(".c:") || t.contains(".h:"));
+    Some((f, path))
+}
+
+fn is_fn_name(t: &str) -> bool {
+    !t.is_empty()
+        && t.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        && !t.starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn split_name(dec_name: &str) -> (&str, Option<&str>) {
+    let mut parts = dec_name.splitn(2, ['@', '$']);
+    let base = parts.next().unwrap();
+    let spec = parts.next();
+    match (base, spec) {
+        ("syscall", Some(name)) => (name, None),
+        _ => (base, spec),
+    }
+}
+
+fn match_prefix(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    SUBSYSTEMS
+        .iter()
+        .find(|(_, prefixes)| prefixes.iter().any(|p| name.starts_with(p)))
+        .map(|(subsystem, _)| *subsystem)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::subsystem::{classify, classify_crash, crash_files, OTHER};
+
+    #[test]
+    fn classify_names() {
+        assert_eq!(classify("ioctl@KVM_RUN"), "kvm");
+        assert_eq!(classify("open@kvm"), "kvm");
+        assert_eq!(classify("ioctl@SNDCTL_DSP_SPEED"), "sound");
+        assert_eq!(classify("socket$netlink"), "net");
+        assert_eq!(classify("ioctl@sock_kcm_SIOCKCMUNATTACH"), "net");
+        assert_eq!(classify("syscall@statx"), "fs");
+        assert_eq!(classify("syscall@madvise"), "mm");
+        assert_eq!(classify("open@special_file"), "fs");
+        assert_eq!(classify("setuid"), "kernel");
+        assert_eq!(classify("ioctl@void"), OTHER);
+    }
+
+    #[test]
+    fn classify_crashes() {
+        let kasan = "BUG: KASAN: use-after-free in tcp_close+0x12/0x30\n\
+                     Read of size 8 at addr ffff888012345678 by task a.out/123\n\
+                     Call Trace:\n \
+                     dump_stack+0x8b/0xb3\n \
+                     print_address_description+0x6f/0x270\n \
+                     kasan_report+0x134/0x191\n \
+                     tcp_close+0x12/0x30\n\
+                     \n\
+                     Allocated by task 1:\n \
+                     __kmalloc+0x10/0x20\n";
+        assert_eq!(classify_crash(kasan), "net");
+
+        let symbolized = "WARNING: CPU: 0 PID: 1 at fs/fuse/dev.c:12 fuse_request_end+0x1/0x2\n\
+                          RIP: 0010:fuse_request_end+0x1/0x2 fs/fuse/dev.c:12\n";
+        assert_eq!(classify_crash(symbolized), "fuse");
+
+        let unknown = "general protection fault: 0000 [#1] SMP\n\
+                       Call Trace:\n \
+                       ? foo_bar+0x1/0x2\n \
+                       my_driver_ioctl+0x1/0x2\n";
+        assert_eq!(classify_crash(unknown), OTHER);
+        assert_eq!(classify_crash(""), OTHER);
+    }
+
+    #[test]
+    fn files_of_crash() {
+        let report = "BUG: KASAN: use-after-free in tcp_close+0x12/0x30 net/ipv4/tcp.c:2345\n\
+                      Call Trace:\n \
+                      dump_stack+0x8b/0xb3 lib/dump_stack.c:118\n \
+                      kasan_report+0x134/0x191 mm/kasan/report.c:400\n \
+                      tcp_close+0x12/0x30 net/ipv4/tcp.c:2345\n \
+                      inet_release+0x1/0x2 ./net/ipv4/af_inet.c:427\n \
+                      __sock_release+0x1/0x2\n \
+                      sock_close+0x1/0x2 net/socket.c:1271\n";
+        assert_eq!(
+            crash_files(report, 3),
+            vec!["net/ipv4/tcp.c", "net/ipv4/af_inet.c", "net/socket.c"]
+        );
+        assert_eq!(crash_files(report, 1), vec!["net/ipv4/tcp.c"]);
+        assert!(crash_files("BUG: soft lockup\n", 3).is_empty());
+    }
+}
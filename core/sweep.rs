@@ -0,0 +1,120 @@
+//! Deterministic sweep of flag args of a call
+//!
+//! Random generation rarely hits a specific flag or combination of two flags of an arg,
+//! sweep tries each flag alone first, then each pair of flags of the same arg, in
+//! declaration order. Combinations of more flags are left to random generation, so an
+//! arg of n flags gets at most n + n*(n-1)/2 variants.
+use std::collections::HashSet;
+
+use fots::types::{Flag, TypeId, TypeInfo};
+
+use crate::prog::Prog;
+use crate::target::Target;
+use crate::value::{NumValue, Value};
+
+/// Variants of `p` with flag args of call `call` swept, at most `max` of them. Singles of
+/// all args come before pairs, so each flag is tried even if `max` cuts pairs off.
+pub fn sweep_flags(p: &Prog, call: usize, t: &Target, max: usize) -> Vec<Prog> {
+    let args = p.calls[call]
+        .args
+        .iter()
+        .enumerate()
+        .filter_map(|(i, arg)| flags_of(t, arg.tid).map(|flags| (i, flags)))
+        .collect::<Vec<_>>();
+    let mut tried = args
+        .iter()
+        .map(|(i, _)| {
+            let mut vals = HashSet::new();
+            if let Value::Num(NumValue::Signed(val)) = p.calls[call].args[*i].val {
+                vals.insert(val);
+            }
+            vals
+        })
+        .collect::<Vec<_>>();
+
+    let singles = args
+        .iter()
+        .enumerate()
+        .flat_map(|(a, (i, flags))| flags.iter().map(move |f| (a, *i, f.val)));
+    let pairs = args.iter().enumerate().flat_map(|(a, (i, flags))| {
+        (0..flags.len()).flat_map(move |x| {
+            (x + 1..flags.len()).map(move |y| (a, *i, flags[x].val | flags[y].val))
+        })
+    });
+    let mut variants = Vec::new();
+    for (a, i, val) in singles.chain(pairs) {
+        if variants.len() == max {
+            break;
+        }
+        // e.g. a zero flag or-ed with another one is the other one.
+        if !tried[a].insert(val) {
+            continue;
+        }
+        let mut v = p.clone();
+        v.calls[call].args[i].val = Value::Num(NumValue::Signed(val));
+        variants.push(v);
+    }
+    variants
+}
+
+fn flags_of(t: &Target, tid: TypeId) -> Option<&[Flag]> {
+    match t.type_of(tid) {
+        TypeInfo::Flag { flags, .. } if !flags.is_empty() => Some(&flags[..]),
+        TypeInfo::Alias { tid, .. } => flags_of(t, *tid),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bench::target;
+    use crate::gen::{gen, Config};
+    use crate::prog::Prog;
+    use crate::sweep::{flags_of, sweep_flags};
+    use crate::value::{NumValue, Value};
+    use std::collections::HashSet;
+
+    #[test]
+    fn sweep_singles_then_pairs() {
+        let (t, rt) = target();
+        let (p, call, i, flags) = (0..1000)
+            .map(|_| gen(&t, &rt, &Config::default()))
+            .find_map(|p| {
+                p.calls.iter().enumerate().find_map(|(call, c)| {
+                    let flagged = c
+                        .args
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(i, arg)| flags_of(&t, arg.tid).map(|f| (i, f.to_vec())))
+                        .collect::<Vec<_>>();
+                    match &flagged[..] {
+                        [(i, flags)] if flags.len() >= 3 => {
+                            Some((p.clone(), call, *i, flags.clone()))
+                        }
+                        _ => None,
+                    }
+                })
+            })
+            .unwrap();
+        let val_of = |v: &Prog| match v.calls[call].args[i].val {
+            Value::Num(NumValue::Signed(val)) => val,
+            _ => unreachable!(),
+        };
+
+        let variants = sweep_flags(&p, call, &t, usize::MAX);
+        let vals = variants.iter().map(val_of).collect::<Vec<_>>();
+        let mut seen = hashset! {val_of(&p)};
+        let singles = flags
+            .iter()
+            .map(|f| f.val)
+            .filter(|v| seen.insert(*v))
+            .collect::<Vec<_>>();
+        assert_eq!(vals[..singles.len()], singles[..]);
+        assert_eq!(vals.iter().collect::<HashSet<_>>().len(), vals.len());
+        assert!(vals.len() <= flags.len() + flags.len() * (flags.len() - 1) / 2);
+        for v in variants.iter() {
+            assert_eq!(t.validate(v), Ok(()));
+        }
+        assert_eq!(sweep_flags(&p, call, &t, 2).len(), 2);
+    }
+}
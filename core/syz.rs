@@ -0,0 +1,722 @@
+//! Decode progs of syzkaller's text format, e.g. values of a syzkaller corpus.db, and
+//! encode progs in it.
+//!
+//! Calls are matched by name, `$` of syzkaller call variants is read as `@`, and args are
+//! converted along params of the matched call. Trailing args and fields syzkaller omits
+//! get default values, addresses of pointers are dropped since executor lays out memory
+//! itself. Resources are only referable where healer produces them, by return values and
+//! out pointer params, a ref to one defined anywhere else fails decoding. Decoded progs are
+//! validated, so anything syzkaller can express but healer can't, e.g. `ANY` squashed args,
+//! fails decoding instead of producing a broken prog.
+//!
+//! Encoding is the inverse: `@` of call variants is written as `$`, resources are named
+//! where they're produced, and each pointee gets its own range of syzkaller's data area.
+//! Syzkaller takes the prog if it has calls of the same names and shapes.
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use fots::types::{NumInfo, PtrDir, TypeId, TypeInfo};
+
+use crate::prog::{Arg, ArgIndex, ArgPos, Call, Prog};
+use crate::target::Target;
+use crate::value::{NumValue, Value};
+
+/// Value of syzkaller text format.
+#[derive(Debug, Clone, PartialEq)]
+enum Syz {
+    Int(u64),
+    /// `'text'`, `"hex"`, or `""/len` of an out buffer
+    Data(Vec<u8>),
+    /// `&(addr)` with optional `=val`
+    Ptr(Option<Box<Syz>>),
+    Nil,
+    Auto,
+    /// Struct `{..}` or array `[..]`
+    Group(Vec<Syz>),
+    /// `@field` with optional `=val`
+    Union(String, Option<Box<Syz>>),
+    /// `rN`
+    Ref(String),
+    /// `<rN=>val`
+    Def(String, Box<Syz>),
+}
+
+/// Decode syzkaller prog `text` for target `t`.
+pub fn decode(text: &str, t: &Target) -> Result<Prog, String> {
+    let mut d = Decoder {
+        t,
+        prog: None,
+        res: HashMap::new(),
+    };
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        d.decode_call(line)
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+    }
+    let p = d.prog.ok_or("no call")?;
+    t.validate(&p).map_err(|errs| errs[0].to_string())?;
+    Ok(p)
+}
+
+struct Decoder<'a> {
+    t: &'a Target,
+    prog: Option<Prog>,
+    /// Producer of each defined resource, none if healer doesn't track where it's defined
+    res: HashMap<String, Option<ArgIndex>>,
+}
+
+impl Decoder<'_> {
+    fn decode_call(&mut self, line: &str) -> Result<(), String> {
+        let mut s = Parser::new(line);
+        let mut ret = None;
+        let mut name = s.ident();
+        s.ws();
+        if s.eat(b'=') {
+            ret = Some(name);
+            s.ws();
+            name = s.ident();
+        }
+        s.expect(b'(')?;
+        let mut vals = s.list(b')')?;
+        // call props, e.g. `(fail_nth: 1)`, are syzkaller's own.
+
+        let dec_name = name.replace('$', "@");
+        let f = self
+            .t
+            .fn_by_name(&dec_name)
+            .ok_or_else(|| format!("unknown call {}", name))?;
+        let p = self.prog.get_or_insert_with(|| Prog::new(f.gid));
+        let cid = p.len();
+        let mut call = Call::new(f.id);
+        let params = if f.has_params() {
+            f.iter_param().collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
+        if vals.len() > params.len() {
+            return Err(format!(
+                "{}: expect {} arg(s), found {}",
+                name,
+                params.len(),
+                vals.len()
+            ));
+        }
+        vals.resize(params.len(), Syz::Auto);
+        for (i, (param, val)) in params.iter().zip(vals).enumerate() {
+            let val = self.arg_val(param.tid, val, (cid, ArgPos::Arg(i)))?;
+            call.add_arg(Arg {
+                tid: param.tid,
+                val,
+            });
+        }
+        if let Some(tid) = f.r_tid.filter(|tid| self.t.is_res(*tid)) {
+            call.ret = Some(Arg::new(tid));
+            if let Some(r) = ret {
+                self.res.insert(r, Some((cid, ArgPos::Ret)));
+            }
+        } else if let Some(r) = ret {
+            self.res.insert(r, None);
+        }
+        self.prog.as_mut().unwrap().add_call(call);
+        Ok(())
+    }
+
+    /// Value of param at `idx`, an out pointer to a resource produces it there.
+    fn arg_val(&mut self, tid: TypeId, val: Syz, idx: ArgIndex) -> Result<Value, String> {
+        if let TypeInfo::Ptr { dir, tid: res, .. } = self.t.type_of(tid) {
+            if *dir != PtrDir::In && self.t.is_res(*res) {
+                if let Syz::Ptr(Some(v)) = &val {
+                    if let Syz::Def(r, _) = &**v {
+                        self.res.insert(r.clone(), Some(idx));
+                    }
+                }
+                return Ok(Value::default_val(*res, self.t));
+            }
+        }
+        self.val(tid, val)
+    }
+
+    fn val(&mut self, tid: TypeId, val: Syz) -> Result<Value, String> {
+        let t = self.t;
+        let val = match val {
+            Syz::Def(r, v) => {
+                self.res.insert(r, None);
+                *v
+            }
+            Syz::Auto => return Ok(Value::default_val(tid, t)),
+            v => v,
+        };
+        match (t.type_of(tid), val) {
+            (TypeInfo::Alias { .. }, Syz::Ref(r)) | (TypeInfo::Res { .. }, Syz::Ref(r)) => {
+                match self.res.get(&r) {
+                    Some(Some(idx)) => Ok(Value::Ref(idx.clone())),
+                    Some(None) => Err(format!("{} is produced where healer doesn't track it", r)),
+                    None => Err(format!("{} is used before defined", r)),
+                }
+            }
+            (TypeInfo::Alias { tid, .. }, v) | (TypeInfo::Res { tid }, v) => self.val(*tid, v),
+            (TypeInfo::Num(info), Syz::Int(v)) => Ok(num_of(info, v)),
+            (TypeInfo::Flag { .. }, Syz::Int(v)) => Ok(Value::Num(NumValue::Signed(v as i64))),
+            (TypeInfo::Len { .. }, Syz::Int(v)) => Ok(Value::Num(NumValue::Unsigned(v))),
+            (TypeInfo::Ptr { depth, .. }, _) if *depth != 1 => {
+                Err(String::from("multi-level pointer"))
+            }
+            (TypeInfo::Ptr { .. }, Syz::Nil) | (TypeInfo::Ptr { .. }, Syz::Int(0)) => {
+                Ok(Value::None)
+            }
+            (TypeInfo::Ptr { tid, .. }, Syz::Int(_))
+            | (TypeInfo::Ptr { tid, .. }, Syz::Ptr(None)) => Ok(Value::default_val(*tid, t)),
+            (TypeInfo::Ptr { tid, .. }, Syz::Ptr(Some(v))) => match *v {
+                // e.g. vma `&(0x7f0000000000/0x1000)=nil`
+                Syz::Nil => Ok(Value::default_val(*tid, t)),
+                v => self.val(*tid, v),
+            },
+            (TypeInfo::Str { .. }, Syz::Data(mut data)) => {
+                while data.last() == Some(&0) {
+                    data.pop();
+                }
+                String::from_utf8(data)
+                    .map(Value::Str)
+                    .map_err(|_| String::from("non utf-8 str"))
+            }
+            (TypeInfo::Slice { tid, .. }, Syz::Data(data)) => match num_info_of(t, *tid) {
+                Some(info) => Ok(Value::Group(
+                    data.into_iter().map(|b| num_of(info, b as u64)).collect(),
+                )),
+                None => Err(format!("data for slice of {}", t.type_of(*tid))),
+            },
+            (TypeInfo::Slice { tid, .. }, Syz::Group(vals)) => vals
+                .into_iter()
+                .map(|v| self.val(*tid, v))
+                .collect::<Result<_, _>>()
+                .map(Value::Group),
+            (TypeInfo::Struct { fields, ident }, Syz::Group(vals)) => {
+                if vals.len() > fields.len() {
+                    return Err(format!(
+                        "{}: expect {} field(s), found {}",
+                        ident,
+                        fields.len(),
+                        vals.len()
+                    ));
+                }
+                let mut vals = vals.into_iter();
+                fields
+                    .iter()
+                    .map(|f| match vals.next() {
+                        Some(v) => self.val(f.tid, v),
+                        None => Ok(Value::default_val(f.tid, t)),
+                    })
+                    .collect::<Result<_, _>>()
+                    .map(Value::Group)
+            }
+            (TypeInfo::Union { fields, ident }, Syz::Union(name, v)) => {
+                let choice = fields
+                    .iter()
+                    .position(|f| f.ident == name)
+                    .ok_or_else(|| format!("{} has no field {}", ident, name))?;
+                let tid = fields[choice].tid;
+                let val = match v {
+                    Some(v) => self.val(tid, *v)?,
+                    None => Value::default_val(tid, t),
+                };
+                Ok(Value::Opt {
+                    choice,
+                    val: Box::new(val),
+                })
+            }
+            (ty, v) => Err(format!("{:?} doesn't fit {}", v, ty)),
+        }
+    }
+}
+
+/// Start of syzkaller's data area pointees are laid out in.
+const DATA_OFFSET: u64 = 0x2000_0000;
+
+/// Encode `p` of target `t` in syzkaller text format.
+pub fn encode(p: &Prog, t: &Target) -> String {
+    let mut e = Encoder {
+        t,
+        used: HashSet::new(),
+        names: HashMap::new(),
+        addr: DATA_OFFSET,
+    };
+    for call in p.calls.iter() {
+        for arg in call.args.iter() {
+            e.collect_refs(&arg.val);
+        }
+    }
+    let mut text = String::new();
+    for (cid, call) in p.calls.iter().enumerate() {
+        if call.ret.is_some() {
+            if let Some(r) = e.name((cid, ArgPos::Ret)) {
+                write!(text, "{} = ", r).unwrap();
+            }
+        }
+        let name = t.fn_of(call.fid).dec_name.replace('@', "$");
+        let args = call
+            .args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| e.arg(arg.tid, &arg.val, (cid, ArgPos::Arg(i))))
+            .collect::<Vec<_>>();
+        writeln!(text, "{}({})", name, args.join(", ")).unwrap();
+    }
+    text
+}
+
+struct Encoder<'a> {
+    t: &'a Target,
+    /// Args referred to by others
+    used: HashSet<ArgIndex>,
+    /// Names of resources produced so far
+    names: HashMap<ArgIndex, String>,
+    /// Address of next pointee
+    addr: u64,
+}
+
+impl Encoder<'_> {
+    fn collect_refs(&mut self, val: &Value) {
+        match val {
+            Value::Ref(idx) => {
+                self.used.insert(idx.clone());
+            }
+            Value::Group(vals) => vals.iter().for_each(|v| self.collect_refs(v)),
+            Value::Opt { val, .. } => self.collect_refs(val),
+            _ => (),
+        }
+    }
+
+    /// Name of resource produced at `idx`, none if nothing refers to it.
+    fn name(&mut self, idx: ArgIndex) -> Option<String> {
+        if !self.used.contains(&idx) {
+            return None;
+        }
+        let r = format!("r{}", self.names.len());
+        self.names.insert(idx, r.clone());
+        Some(r)
+    }
+
+    fn alloc(&mut self, size: u64) -> u64 {
+        let addr = self.addr;
+        // keep pointees apart and aligned.
+        self.addr += size.max(1).div_ceil(64) * 64;
+        addr
+    }
+
+    fn arg(&mut self, tid: TypeId, val: &Value, idx: ArgIndex) -> String {
+        if let TypeInfo::Ptr { dir, tid: res, .. } = self.t.type_of(tid) {
+            if *dir != PtrDir::In && self.t.is_res(*res) {
+                let addr = self.alloc(8);
+                return match self.name(idx) {
+                    Some(r) => format!("&(0x{:x})=<{}=>0x0", addr, r),
+                    None => format!("&(0x{:x})", addr),
+                };
+            }
+        }
+        self.val(tid, val)
+    }
+
+    fn val(&mut self, tid: TypeId, val: &Value) -> String {
+        let t = self.t;
+        match (t.type_of(tid), val) {
+            (TypeInfo::Ptr { .. }, Value::None) => String::from("0x0"),
+            // a ref of pointer type points to the resource, e.g. `&(0x20000000)=r0`.
+            (TypeInfo::Ptr { tid, .. }, v) => {
+                let addr = self.alloc(size_of(v));
+                format!("&(0x{:x})={}", addr, self.val(*tid, v))
+            }
+            (_, Value::Ref(idx)) => self
+                .names
+                .get(idx)
+                .cloned()
+                .unwrap_or_else(|| String::from("0xffffffffffffffff")),
+            (TypeInfo::Alias { tid, .. }, v) | (TypeInfo::Res { tid }, v) => self.val(*tid, v),
+            (_, Value::Num(NumValue::Signed(n))) => format!("0x{:x}", *n as u64),
+            (_, Value::Num(NumValue::Unsigned(n))) => format!("0x{:x}", n),
+            (TypeInfo::Str { .. }, Value::Str(s)) => {
+                let mut text = String::from("'");
+                for b in s.bytes().chain(std::iter::once(0)) {
+                    match b {
+                        b'\\' | b'\'' => write!(text, "\\x{:02x}", b).unwrap(),
+                        0x20..=0x7e => text.push(b as char),
+                        _ => write!(text, "\\x{:02x}", b).unwrap(),
+                    }
+                }
+                text.push('\'');
+                text
+            }
+            (TypeInfo::Slice { tid, .. }, Value::Group(vals))
+                if matches!(
+                    num_info_of(t, *tid),
+                    Some(NumInfo::I8(_)) | Some(NumInfo::U8(_))
+                ) =>
+            {
+                let mut hex = String::from("\"");
+                for v in vals {
+                    if let Value::Num(n) = v {
+                        let b = match n {
+                            NumValue::Signed(n) => *n as u8,
+                            NumValue::Unsigned(n) => *n as u8,
+                        };
+                        write!(hex, "{:02x}", b).unwrap();
+                    }
+                }
+                hex.push('"');
+                hex
+            }
+            (TypeInfo::Slice { tid, .. }, Value::Group(vals)) => {
+                let vals = vals.iter().map(|v| self.val(*tid, v)).collect::<Vec<_>>();
+                format!("[{}]", vals.join(", "))
+            }
+            (TypeInfo::Struct { fields, .. }, Value::Group(vals)) => {
+                let vals = fields
+                    .iter()
+                    .zip(vals)
+                    .map(|(f, v)| self.val(f.tid, v))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", vals.join(", "))
+            }
+            (TypeInfo::Union { fields, .. }, Value::Opt { choice, val }) => {
+                let f = &fields[*choice];
+                format!("@{}={}", f.ident, self.val(f.tid, val))
+            }
+            _ => String::from("0x0"),
+        }
+    }
+}
+
+/// Bytes `val` takes at most in guest memory, all nums counted as 8 bytes.
+fn size_of(val: &Value) -> u64 {
+    match val {
+        Value::Str(s) => s.len() as u64 + 1,
+        Value::Group(vals) => vals.iter().map(size_of).sum(),
+        Value::Opt { val, .. } => size_of(val),
+        _ => 8,
+    }
+}
+
+fn num_info_of(t: &Target, tid: TypeId) -> Option<&NumInfo> {
+    match t.type_of(tid) {
+        TypeInfo::Num(info) => Some(info),
+        TypeInfo::Alias { tid, .. } => num_info_of(t, *tid),
+        _ => None,
+    }
+}
+
+/// Num value of `info` truncated from `v`, syzkaller stores all ints as u64.
+fn num_of(info: &NumInfo, v: u64) -> Value {
+    use NumValue::*;
+    let v = match info {
+        NumInfo::I8(_) => Signed(v as i8 as i64),
+        NumInfo::I16(_) => Signed(v as i16 as i64),
+        NumInfo::I32(_) => Signed(v as i32 as i64),
+        NumInfo::I64(_) | NumInfo::Isize(_) => Signed(v as i64),
+        NumInfo::U8(_) => Unsigned(v as u8 as u64),
+        NumInfo::U16(_) => Unsigned(v as u16 as u64),
+        NumInfo::U32(_) => Unsigned(v as u32 as u64),
+        NumInfo::U64(_) | NumInfo::Usize(_) => Unsigned(v),
+    };
+    Value::Num(v)
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(s: &'a str) -> Self {
+        Self {
+            s: s.as_bytes(),
+            i: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.i).copied()
+    }
+
+    fn ws(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.i += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.i += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        self.ws();
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expect '{}' at {}", c as char, self.i))
+        }
+    }
+
+    fn ident(&mut self) -> String {
+        let start = self.i;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'$')
+        {
+            self.i += 1;
+        }
+        String::from_utf8_lossy(&self.s[start..self.i]).into_owned()
+    }
+
+    /// Values separated by commas up to `end`.
+    fn list(&mut self, end: u8) -> Result<Vec<Syz>, String> {
+        let mut vals = Vec::new();
+        self.ws();
+        if self.eat(end) {
+            return Ok(vals);
+        }
+        loop {
+            vals.push(self.val()?);
+            self.ws();
+            if self.eat(end) {
+                return Ok(vals);
+            }
+            self.expect(b',')?;
+        }
+    }
+
+    fn val(&mut self) -> Result<Syz, String> {
+        self.ws();
+        match self.peek().ok_or("unexpected end")? {
+            b'0'..=b'9' | b'-' => self.int().map(Syz::Int),
+            b'\'' => {
+                self.i += 1;
+                self.text().map(Syz::Data)
+            }
+            b'"' => {
+                self.i += 1;
+                self.hex().map(Syz::Data)
+            }
+            b'&' => {
+                self.i += 1;
+                if self.ident() != "AUTO" {
+                    self.skip_addr()?;
+                }
+                if self.eat(b'=') {
+                    Ok(Syz::Ptr(Some(Box::new(self.val()?))))
+                } else {
+                    Ok(Syz::Ptr(None))
+                }
+            }
+            b'{' => {
+                self.i += 1;
+                self.list(b'}').map(Syz::Group)
+            }
+            b'[' => {
+                self.i += 1;
+                self.list(b']').map(Syz::Group)
+            }
+            b'@' => {
+                self.i += 1;
+                let name = self.ident();
+                let val = if self.eat(b'=') {
+                    Some(Box::new(self.val()?))
+                } else {
+                    None
+                };
+                Ok(Syz::Union(name, val))
+            }
+            b'<' => {
+                self.i += 1;
+                let name = self.ident();
+                if !self.s[self.i..].starts_with(b"=>") {
+                    return Err(format!("expect '=>' at {}", self.i));
+                }
+                self.i += 2;
+                Ok(Syz::Def(name, Box::new(self.val()?)))
+            }
+            _ => match &self.ident()[..] {
+                "nil" => Ok(Syz::Nil),
+                "AUTO" => Ok(Syz::Auto),
+                r if r.starts_with('r')
+                    && r.len() > 1
+                    && r[1..].bytes().all(|c| c.is_ascii_digit()) =>
+                {
+                    Ok(Syz::Ref(String::from(r)))
+                }
+                "" => Err(format!(
+                    "unexpected '{}' at {}",
+                    self.s[self.i] as char, self.i
+                )),
+                other => Err(format!("unsupported value {}", other)),
+            },
+        }
+    }
+
+    fn int(&mut self) -> Result<u64, String> {
+        let neg = self.eat(b'-');
+        let start = self.i;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric()) {
+            self.i += 1;
+        }
+        let lit = std::str::from_utf8(&self.s[start..self.i]).unwrap();
+        let v = if let Some(hex) = lit.strip_prefix("0x") {
+            u64::from_str_radix(hex, 16)
+        } else {
+            lit.parse::<u64>()
+        }
+        .map_err(|_| format!("invalid int {}", lit))?;
+        Ok(if neg { v.wrapping_neg() } else { v })
+    }
+
+    /// `(addr)` of a pointer, possibly `(addr/size)` of a vma.
+    fn skip_addr(&mut self) -> Result<(), String> {
+        self.expect(b'(')?;
+        while !self.eat(b')') {
+            if self.peek().is_none() {
+                return Err(String::from("unclosed pointer address"));
+            }
+            self.i += 1;
+        }
+        Ok(())
+    }
+
+    /// Escaped text after opening `'`.
+    fn text(&mut self) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        loop {
+            let c = self.peek().ok_or("unclosed str")?;
+            self.i += 1;
+            match c {
+                b'\'' => return Ok(data),
+                b'\\' => {
+                    let e = self.peek().ok_or("unclosed str")?;
+                    self.i += 1;
+                    data.push(match e {
+                        b'x' => {
+                            let hex = self.s.get(self.i..self.i + 2).ok_or("unclosed str")?;
+                            self.i += 2;
+                            u8::from_str_radix(std::str::from_utf8(hex).unwrap_or(""), 16)
+                                .map_err(|_| String::from("invalid escape"))?
+                        }
+                        b'0' => 0,
+                        b'a' => 7,
+                        b'b' => 8,
+                        b'f' => 12,
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'v' => 11,
+                        e => e,
+                    });
+                }
+                c => data.push(c),
+            }
+        }
+    }
+
+    /// Hex data after opening `"`, `""/len` is an out buffer of `len` bytes.
+    fn hex(&mut self) -> Result<Vec<u8>, String> {
+        let start = self.i;
+        while self.peek().is_some_and(|c| c != b'"') {
+            self.i += 1;
+        }
+        let hex = std::str::from_utf8(&self.s[start..self.i]).unwrap();
+        self.expect(b'"')?;
+        if !hex.len().is_multiple_of(2) {
+            return Err(String::from("odd length of hex data"));
+        }
+        let mut data = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| String::from("invalid hex data"))?;
+        if self.eat(b'/') {
+            let len = self.int()?;
+            data.resize(len as usize, 0);
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analyze::static_analyze;
+    use crate::bench::target;
+    use crate::gen::{gen, Config};
+    use crate::prog::ArgPos;
+    use crate::syz::{decode, encode};
+    use crate::value::{NumValue, Value};
+
+    #[test]
+    fn decode_syz_progs() {
+        let (t, _) = target();
+        let p = decode(
+            "# comment\n\
+             r0 = openat(0xffffffffffffff9c, &(0x7f0000000000)='./file0\\x00', 0x42, 0x1ff)\n\
+             write(r0, &(0x7f0000000040)=\"0102\", 0x2) (fail_nth: 1)\n\
+             read(r0, &(0x7f0000000080)=\"\"/3, 0x3)\n\
+             close(r0)\n",
+            &t,
+        )
+        .unwrap();
+        assert_eq!(p.len(), 4);
+        let call = |i: usize| &t.fn_of(p.calls[i].fid).dec_name[..];
+        assert_eq!(call(0), "openat");
+        assert_eq!(p.calls[0].args[1].val, Value::Str(String::from("./file0")));
+        let bytes = |bs: &[i64]| {
+            Value::Group(
+                bs.iter()
+                    .map(|b| Value::Num(NumValue::Signed(*b)))
+                    .collect(),
+            )
+        };
+        assert_eq!(p.calls[1].args[1].val, bytes(&[1, 2]));
+        assert_eq!(p.calls[2].args[1].val, bytes(&[0, 0, 0]));
+        assert_eq!(p.calls[3].args[0].val, Value::Ref((0, ArgPos::Ret)));
+
+        assert!(decode("", &t).is_err());
+        assert!(decode("no_such_call$foo(0x0)", &t).is_err());
+        assert!(decode("close(r0)", &t).is_err());
+        assert!(decode("close(0x1, 0x2)", &t).is_err());
+        assert!(decode(
+            "write(0x1, &(0x7f0000000000)=ANY=[@ANYBLOB=\"00\"], 0x1)",
+            &t
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn encode_syz_progs() {
+        let (t, _) = target();
+        let text = "r0 = openat(0xffffff9c, &(0x20000000)='./file0\\x00', 0x42, 0x1ff)\n\
+                    write(r0, &(0x20000040)=\"0102\", 0x2)\n\
+                    close(r0)\n";
+        let p = decode(text, &t).unwrap();
+        assert_eq!(encode(&p, &t), text);
+
+        let rt = static_analyze(&t);
+        for _ in 0..64 {
+            let p = gen(&t, &rt, &Config::default());
+            // healer has calls of the same name, e.g. on different fds, text can't tell
+            // them apart.
+            let unique = p.calls.iter().all(|c| {
+                let f = t.fn_of(c.fid);
+                t.fn_by_name(&f.dec_name).map(|f| f.id) == Some(c.fid)
+            });
+            if !unique {
+                continue;
+            }
+            let text = encode(&p, &t);
+            let decoded = decode(&text, &t).unwrap_or_else(|e| panic!("{}: {}", e, text));
+            assert_eq!(decoded.len(), p.len());
+            assert_eq!(encode(&decoded, &t), text);
+        }
+    }
+}
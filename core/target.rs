@@ -1,12 +1,29 @@
 use std::collections::HashMap;
 
-use fots::types::{Field, FnId, FnInfo, Group, GroupId, Items, NumInfo, TypeId, TypeInfo};
+use fots::types::{Field, FnId, FnInfo, Group, GroupId, Items, NumInfo, PtrDir, TypeId, TypeInfo};
 use std::ptr::NonNull;
 
+use crate::subsystem;
+
+/// A resource type with calls producing and consuming it.
+#[derive(Debug, Clone)]
+pub struct ResNode<'a> {
+    pub res: TypeId,
+    pub name: &'a str,
+    pub producers: &'a [FnId],
+    pub consumers: &'a [FnId],
+}
+
 pub struct Target {
     pub types: HashMap<TypeId, TypeInfo>,
     pub groups: HashMap<GroupId, Group>,
     pub fns: HashMap<FnId, NonNull<FnInfo>>,
+
+    fn_names: HashMap<String, FnId>,
+    res_names: HashMap<String, TypeId>,
+    producers: HashMap<TypeId, Vec<FnId>>,
+    consumers: HashMap<TypeId, Vec<FnId>>,
+    subsystems: HashMap<FnId, &'static str>,
 }
 
 impl Target {
@@ -29,7 +46,147 @@ impl Target {
             .collect();
         fns.shrink_to_fit();
 
-        Target { groups, types, fns }
+        let mut t = Target {
+            groups,
+            types,
+            fns,
+            fn_names: HashMap::new(),
+            res_names: HashMap::new(),
+            producers: HashMap::new(),
+            consumers: HashMap::new(),
+            subsystems: HashMap::new(),
+        };
+        t.build_index();
+        t
+    }
+
+    fn build_index(&mut self) {
+        let mut fn_names = HashMap::new();
+        let mut producers: HashMap<TypeId, Vec<FnId>> = HashMap::new();
+        let mut consumers: HashMap<TypeId, Vec<FnId>> = HashMap::new();
+        let mut subsystems = HashMap::new();
+        for f in self.groups.values().flat_map(|g| g.iter_fn()) {
+            fn_names.insert(f.dec_name.clone(), f.id);
+            subsystems.insert(f.id, subsystem::classify(&f.dec_name));
+            for (res, in_) in self.res_uses(f) {
+                let uses = if in_ {
+                    consumers.entry(res).or_default()
+                } else {
+                    producers.entry(res).or_default()
+                };
+                if !uses.contains(&f.id) {
+                    uses.push(f.id);
+                }
+            }
+        }
+        for ids in producers.values_mut().chain(consumers.values_mut()) {
+            ids.sort_unstable();
+        }
+        let res_names = self
+            .types
+            .iter()
+            .filter(|(tid, _)| self.is_res(**tid))
+            .filter_map(|(tid, info)| info.ident().map(|ident| (ident.to_string(), *tid)))
+            .collect();
+
+        self.fn_names = fn_names;
+        self.res_names = res_names;
+        self.producers = producers;
+        self.consumers = consumers;
+        self.subsystems = subsystems;
+    }
+
+    /// Resources used by `f`, true means the resource is consumed.
+    pub(crate) fn res_uses(&self, f: &FnInfo) -> Vec<(TypeId, bool)> {
+        let mut uses = Vec::new();
+        if !f.has_params() {
+            return uses;
+        }
+        for p in f.iter_param() {
+            let mut id = p.tid;
+            let mut in_ = true;
+            if let TypeInfo::Ptr { tid, dir, depth } = self.type_of(id) {
+                assert!(*depth == 1, "Multi-level pointer not supported");
+                id = *tid;
+                in_ = *dir == PtrDir::In;
+            }
+            if self.is_res(id) {
+                uses.push((id, in_));
+            }
+        }
+        if let Some(tid) = f.r_tid {
+            if self.is_res(tid) {
+                uses.push((tid, false));
+            }
+        }
+        uses
+    }
+
+    /// Find call by its declared name, e.g. `open@special_file`.
+    pub fn fn_by_name(&self, name: &str) -> Option<&FnInfo> {
+        self.fn_names.get(name).map(|fid| self.fn_of(*fid))
+    }
+
+    /// Find resource type by its name, e.g. `fd_t`.
+    pub fn res_by_name(&self, name: &str) -> Option<TypeId> {
+        self.res_names.get(name).copied()
+    }
+
+    /// Calls that produce resource `res`, either by return value or by out pointer.
+    pub fn producers_of(&self, res: TypeId) -> &[FnId] {
+        self.producers.get(&res).map(|ids| &ids[..]).unwrap_or(&[])
+    }
+
+    /// Calls that take resource `res` as input.
+    pub fn consumers_of(&self, res: TypeId) -> &[FnId] {
+        self.consumers.get(&res).map(|ids| &ids[..]).unwrap_or(&[])
+    }
+
+    /// Resource dependency graph: every resource type with its producers and consumers,
+    /// sorted by resource name. Built once when target is loaded.
+    pub fn resource_graph(&self) -> Vec<ResNode<'_>> {
+        let mut graph = self
+            .res_names
+            .iter()
+            .map(|(name, res)| ResNode {
+                res: *res,
+                name,
+                producers: self.producers_of(*res),
+                consumers: self.consumers_of(*res),
+            })
+            .collect::<Vec<_>>();
+        graph.sort_unstable_by_key(|n| n.name);
+        graph
+    }
+
+    /// Subsystem call `fid` belongs to, see `subsystem::classify`.
+    pub fn subsystem_of(&self, fid: FnId) -> &'static str {
+        self.subsystems[&fid]
+    }
+
+    /// All subsystems with their calls, sorted by subsystem name.
+    pub fn subsystems(&self) -> Vec<(&'static str, Vec<FnId>)> {
+        let mut subsystems: HashMap<&'static str, Vec<FnId>> = HashMap::new();
+        for (fid, subsystem) in self.subsystems.iter() {
+            subsystems.entry(subsystem).or_default().push(*fid);
+        }
+        let mut subsystems = subsystems.into_iter().collect::<Vec<_>>();
+        for (_, fids) in subsystems.iter_mut() {
+            fids.sort_unstable();
+        }
+        subsystems.sort_unstable_by_key(|(subsystem, _)| *subsystem);
+        subsystems
+    }
+
+    /// Name and type of each param of call `fid`, nested types can be resolved with `type_of`.
+    pub fn params_of(&self, fid: FnId) -> Vec<(&str, &TypeInfo)> {
+        let f = self.fn_of(fid);
+        if !f.has_params() {
+            return Vec::new();
+        }
+        f.iter_param()
+            .map(|p| (&p.ident[..], self.type_of(p.tid)))
+            .collect()
     }
 
     pub fn type_of(&self, tid: TypeId) -> &TypeInfo {
@@ -112,3 +269,70 @@ impl Target {
 // TODO
 unsafe impl Send for Target {}
 unsafe impl Sync for Target {}
+
+#[cfg(test)]
+mod tests {
+    use crate::bench::target;
+    use crate::target::Target;
+
+    #[test]
+    fn lookup() {
+        let (t, _) = target();
+
+        let f = t.fn_by_name("open@special_file").unwrap();
+        assert_eq!(f.dec_name, "open@special_file");
+        assert_eq!(t.params_of(f.id).len(), f.iter_param().count());
+        assert!(t.fn_by_name("no_such_call").is_none());
+
+        let fd = t.res_by_name("fd_t").unwrap();
+        assert!(t.producers_of(fd).contains(&f.id));
+        let close = t.fn_by_name("close").unwrap();
+        assert!(t.consumers_of(fd).contains(&close.id));
+
+        assert_eq!(t.subsystem_of(f.id), "fs");
+        let n = t
+            .subsystems()
+            .iter()
+            .map(|(_, fids)| fids.len())
+            .sum::<usize>();
+        assert_eq!(n, t.fns.len());
+        let counts = t
+            .subsystems()
+            .iter()
+            .map(|(s, fids)| (*s, fids.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            [
+                ("dma", 3),
+                ("drm", 2),
+                ("fs", 154),
+                ("fuse", 3),
+                ("ipc", 10),
+                ("kernel", 115),
+                ("kvm", 8),
+                ("mm", 22),
+                ("net", 10),
+                ("other", 7),
+                ("security", 2),
+                ("sound", 45),
+                ("tty", 2),
+                ("usb", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn resource_graph() {
+        let t =
+            Target::from(fots::parse_items(include_str!("../fots/examples/syscall.fots")).unwrap());
+        let graph = t.resource_graph();
+        let epoll = graph.iter().find(|n| n.name == "fd_epoll").unwrap();
+        let create = t.fn_by_name("epoll_create").unwrap();
+        let ctl = t.fn_by_name("epoll_ctl@EPOLL_CTL_ADD").unwrap();
+        assert!(epoll.producers.contains(&create.id));
+        assert!(epoll.consumers.contains(&ctl.id));
+        assert!(!epoll.producers.contains(&ctl.id));
+        assert!(graph.windows(2).all(|w| w[0].name < w[1].name));
+    }
+}
@@ -0,0 +1,571 @@
+//! Structural check of progs against target.
+//!
+//! Every call must be known and belong to group of prog, its args must match params of
+//! the call in number and type, values must match their types, e.g. a struct value has a
+//! value per field and a union value chooses an existing field, and slice lengths must be
+//! in range, `h` of range `(l, h)` excluded as in generation. Resource refs must point to an earlier call's arg or return value producing
+//! that resource, and len paths must name a param of the call or a field of the enclosing
+//! struct. Values of len types are filled when a prog is translated, so they are not
+//! checked against lengths of what they measure.
+use crate::prog::{ArgIndex, ArgPos, CId, Prog};
+use crate::target::Target;
+use crate::value::Value;
+use fots::types::{Field, FnId, GroupId, PtrDir, TypeId, TypeInfo};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    UnknownGroup(GroupId),
+    UnknownCall {
+        call: CId,
+        fid: FnId,
+    },
+    /// Call is not in group of prog
+    ForeignCall {
+        call: CId,
+        fid: FnId,
+    },
+    ArgCount {
+        call: CId,
+        expected: usize,
+        found: usize,
+    },
+    /// Arg or return value has type other than the param's
+    ArgType {
+        call: CId,
+        pos: ArgPos,
+    },
+    /// Value doesn't match its type, `expected` describes the type
+    ValueType {
+        call: CId,
+        pos: ArgPos,
+        expected: String,
+    },
+    SliceLen {
+        call: CId,
+        pos: ArgPos,
+        len: usize,
+        range: (isize, isize),
+    },
+    UnionChoice {
+        call: CId,
+        pos: ArgPos,
+        choice: usize,
+        fields: usize,
+    },
+    /// Ref to an arg that doesn't exist or is not of an earlier call
+    DanglingRef {
+        call: CId,
+        pos: ArgPos,
+        target: ArgIndex,
+    },
+    /// Ref to an arg that doesn't produce the resource
+    RefResource {
+        call: CId,
+        pos: ArgPos,
+        target: ArgIndex,
+    },
+    LenPath {
+        call: CId,
+        pos: ArgPos,
+        path: String,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ValidationError::*;
+        match self {
+            UnknownGroup(gid) => write!(f, "unknown group {}", gid),
+            UnknownCall { call, fid } => write!(f, "call {}: unknown fn {}", call, fid),
+            ForeignCall { call, fid } => {
+                write!(f, "call {}: fn {} is not in group of prog", call, fid)
+            }
+            ArgCount {
+                call,
+                expected,
+                found,
+            } => write!(
+                f,
+                "call {}: expect {} arg(s), found {}",
+                call, expected, found
+            ),
+            ArgType { call, pos } => write!(f, "call {} {:?}: type differs from param", call, pos),
+            ValueType {
+                call,
+                pos,
+                expected,
+            } => write!(f, "call {} {:?}: value is not of {}", call, pos, expected),
+            SliceLen {
+                call,
+                pos,
+                len,
+                range,
+            } => write!(
+                f,
+                "call {} {:?}: slice len {} out of range {:?}",
+                call, pos, len, range
+            ),
+            UnionChoice {
+                call,
+                pos,
+                choice,
+                fields,
+            } => write!(
+                f,
+                "call {} {:?}: union choice {} of {} field(s)",
+                call, pos, choice, fields
+            ),
+            DanglingRef { call, pos, target } => {
+                write!(f, "call {} {:?}: dangling ref to {:?}", call, pos, target)
+            }
+            RefResource { call, pos, target } => write!(
+                f,
+                "call {} {:?}: {:?} doesn't produce the resource",
+                call, pos, target
+            ),
+            LenPath { call, pos, path } => {
+                write!(
+                    f,
+                    "call {} {:?}: len path `{}` resolves to nothing",
+                    call, pos, path
+                )
+            }
+        }
+    }
+}
+
+/// Where a value being checked is.
+struct Ctx<'a> {
+    call: CId,
+    pos: ArgPos,
+    params: &'a [&'a str],
+    errs: Vec<ValidationError>,
+}
+
+impl Target {
+    /// Check `p` is structurally valid for this target, all errors found are returned.
+    pub fn validate(&self, p: &Prog) -> Result<(), Vec<ValidationError>> {
+        if !self.groups.contains_key(&p.gid) {
+            return Err(vec![ValidationError::UnknownGroup(p.gid)]);
+        }
+        let mut errs = Vec::new();
+        for (i, c) in p.calls.iter().enumerate() {
+            if !self.fns.contains_key(&c.fid) {
+                errs.push(ValidationError::UnknownCall {
+                    call: i,
+                    fid: c.fid,
+                });
+                continue;
+            }
+            let f = self.fn_of(c.fid);
+            if f.gid != p.gid {
+                errs.push(ValidationError::ForeignCall {
+                    call: i,
+                    fid: c.fid,
+                });
+            }
+            let params = if f.has_params() {
+                f.iter_param().collect::<Vec<_>>()
+            } else {
+                Vec::new()
+            };
+            if params.len() != c.args.len() {
+                errs.push(ValidationError::ArgCount {
+                    call: i,
+                    expected: params.len(),
+                    found: c.args.len(),
+                });
+                continue;
+            }
+            let names = params.iter().map(|p| &p.ident[..]).collect::<Vec<_>>();
+            let mut ctx = Ctx {
+                call: i,
+                pos: ArgPos::Ret,
+                params: &names,
+                errs: Vec::new(),
+            };
+            for (j, (arg, param)) in c.args.iter().zip(params.iter()).enumerate() {
+                ctx.pos = ArgPos::Arg(j);
+                if arg.tid != param.tid {
+                    ctx.errs.push(ValidationError::ArgType {
+                        call: i,
+                        pos: ctx.pos.clone(),
+                    });
+                    continue;
+                }
+                self.validate_val(p, arg.tid, &arg.val, None, &mut ctx);
+            }
+            if let Some(ret) = c.ret.as_ref() {
+                if Some(ret.tid) != f.r_tid {
+                    ctx.errs.push(ValidationError::ArgType {
+                        call: i,
+                        pos: ArgPos::Ret,
+                    });
+                }
+            }
+            errs.extend(ctx.errs);
+        }
+        if errs.is_empty() {
+            Ok(())
+        } else {
+            Err(errs)
+        }
+    }
+
+    fn validate_val(
+        &self,
+        p: &Prog,
+        tid: TypeId,
+        val: &Value,
+        fields: Option<&[Field]>,
+        ctx: &mut Ctx,
+    ) {
+        let mismatch = |ctx: &mut Ctx, expected: &str| {
+            ctx.errs.push(ValidationError::ValueType {
+                call: ctx.call,
+                pos: ctx.pos.clone(),
+                expected: expected.to_string(),
+            })
+        };
+        match (self.type_of(tid), val) {
+            (TypeInfo::Alias { .. }, Value::Ref(target))
+            | (TypeInfo::Res { .. }, Value::Ref(target)) => self.validate_ref(p, tid, target, ctx),
+            (TypeInfo::Alias { tid, .. }, _) | (TypeInfo::Res { tid }, _) => {
+                self.validate_val(p, *tid, val, fields, ctx)
+            }
+            (TypeInfo::Num(_), Value::Num(_)) | (TypeInfo::Flag { .. }, Value::Num(_)) => (),
+            (TypeInfo::Len { path, .. }, Value::Num(_)) => {
+                let head = path.split("->").next().unwrap_or_default();
+                let resolved = ctx.params.contains(&head)
+                    || fields.is_some_and(|fs| fs.iter().any(|f| f.ident == head));
+                if !resolved {
+                    ctx.errs.push(ValidationError::LenPath {
+                        call: ctx.call,
+                        pos: ctx.pos.clone(),
+                        path: path.clone(),
+                    });
+                }
+            }
+            (TypeInfo::Ptr { .. }, Value::None) => (),
+            (TypeInfo::Ptr { tid, .. }, _) => self.validate_val(p, *tid, val, None, ctx),
+            (TypeInfo::Str { .. }, Value::Str(_)) => (),
+            (TypeInfo::Slice { tid, l, h }, Value::Group(vals)) => {
+                let len = vals.len() as isize;
+                let in_range = match (*l, *h) {
+                    (-1, -1) => true,
+                    (l, -1) => len == l,
+                    (l, h) => l <= len && len < h,
+                };
+                if !in_range {
+                    ctx.errs.push(ValidationError::SliceLen {
+                        call: ctx.call,
+                        pos: ctx.pos.clone(),
+                        len: vals.len(),
+                        range: (*l, *h),
+                    });
+                }
+                for v in vals.iter() {
+                    self.validate_val(p, *tid, v, None, ctx);
+                }
+            }
+            (TypeInfo::Struct { fields, .. }, Value::Group(vals)) => {
+                if fields.len() != vals.len() {
+                    mismatch(ctx, &format!("struct of {} field(s)", fields.len()));
+                    return;
+                }
+                for (f, v) in fields.iter().zip(vals.iter()) {
+                    self.validate_val(p, f.tid, v, Some(fields), ctx);
+                }
+            }
+            (TypeInfo::Union { fields, .. }, Value::Opt { choice, val }) => {
+                match fields.get(*choice) {
+                    Some(f) => self.validate_val(p, f.tid, val, None, ctx),
+                    None => ctx.errs.push(ValidationError::UnionChoice {
+                        call: ctx.call,
+                        pos: ctx.pos.clone(),
+                        choice: *choice,
+                        fields: fields.len(),
+                    }),
+                }
+            }
+            (TypeInfo::Num(_), _) | (TypeInfo::Flag { .. }, _) | (TypeInfo::Len { .. }, _) => {
+                mismatch(ctx, "num")
+            }
+            (TypeInfo::Str { .. }, _) => mismatch(ctx, "str"),
+            (TypeInfo::Slice { .. }, _) => mismatch(ctx, "slice"),
+            (TypeInfo::Struct { .. }, _) => mismatch(ctx, "struct"),
+            (TypeInfo::Union { .. }, _) => mismatch(ctx, "union"),
+        }
+    }
+
+    /// Check ref to `target` for resource `res`.
+    fn validate_ref(&self, p: &Prog, res: TypeId, target: &ArgIndex, ctx: &mut Ctx) {
+        let dangling = ValidationError::DanglingRef {
+            call: ctx.call,
+            pos: ctx.pos.clone(),
+            target: target.clone(),
+        };
+        if !self.is_res(res) || target.0 >= ctx.call {
+            ctx.errs.push(dangling);
+            return;
+        }
+        let c = &p.calls[target.0];
+        let produced = match &target.1 {
+            ArgPos::Ret => match c.ret.as_ref() {
+                Some(ret) => ret.tid,
+                None => return ctx.errs.push(dangling),
+            },
+            ArgPos::Arg(i) => match c.args.get(*i) {
+                Some(arg) => match self.type_of(arg.tid) {
+                    TypeInfo::Ptr { tid, dir, .. } if *dir != PtrDir::In => *tid,
+                    _ => arg.tid,
+                },
+                None => return ctx.errs.push(dangling),
+            },
+        };
+        if produced != res {
+            ctx.errs.push(ValidationError::RefResource {
+                call: ctx.call,
+                pos: ctx.pos.clone(),
+                target: target.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ValidationError::*;
+    use super::*;
+    use crate::prog::{Arg, Call};
+    use crate::value::NumValue;
+
+    const DESCS: &str = "
+type fd_t = res<i32>
+union val_u { n i32, s str }
+struct msg { size len<u32, data>, data *[i8; (1, 4)] }
+group T {
+    fn make(size i32) fd_t
+    fn send(f fd_t, m *msg, n len<usize, m>, v val_u)
+    fn peek(f fd_t, n len<usize, nothing>)
+}
+group O {
+    fn other(f i32)
+}
+";
+
+    fn num() -> Value {
+        Value::Num(NumValue::Unsigned(0))
+    }
+
+    fn call(t: &Target, name: &str, vals: Vec<Value>) -> Call {
+        let f = t.fn_by_name(name).unwrap();
+        let mut c = Call::new(f.id);
+        for (p, val) in f.iter_param().zip(vals) {
+            c.add_arg(Arg { tid: p.tid, val });
+        }
+        c.ret = f.r_tid.map(Arg::new);
+        c
+    }
+
+    /// `make` producing a fd and `send` consuming it.
+    fn valid(t: &Target) -> Prog {
+        let mut p = Prog::new(t.fn_by_name("make").unwrap().gid);
+        p.add_call(call(t, "make", vec![num()]));
+        let msg = Value::Group(vec![num(), Value::Group(vec![num(), num()])]);
+        let v = Value::Opt {
+            choice: 0,
+            val: Box::new(num()),
+        };
+        p.add_call(call(
+            t,
+            "send",
+            vec![Value::Ref((0, ArgPos::Ret)), msg, num(), v],
+        ));
+        p
+    }
+
+    #[test]
+    fn validate_malformed_progs() {
+        let t = Target::from(fots::parse_items(DESCS).unwrap());
+        let p = valid(&t);
+        assert_eq!(t.validate(&p), Ok(()));
+
+        type Malform = fn(&Target, &mut Prog);
+        type Expect = fn(&ValidationError) -> bool;
+        let cases: Vec<(Malform, Expect)> = vec![
+            (|_, p| p.gid = 9999, |e| matches!(e, UnknownGroup(9999))),
+            (
+                |_, p| p.calls[1].fid = 9999,
+                |e| matches!(e, UnknownCall { call: 1, .. }),
+            ),
+            (
+                |t, p| {
+                    p.add_call(call(t, "other", vec![num()]));
+                },
+                |e| matches!(e, ForeignCall { call: 2, .. }),
+            ),
+            (
+                |_, p| {
+                    p.calls[1].args.pop();
+                },
+                |e| {
+                    matches!(
+                        e,
+                        ArgCount {
+                            call: 1,
+                            expected: 4,
+                            found: 3
+                        }
+                    )
+                },
+            ),
+            (
+                |_, p| p.calls[1].args[2].tid = p.calls[1].args[3].tid,
+                |e| {
+                    matches!(
+                        e,
+                        ArgType {
+                            call: 1,
+                            pos: ArgPos::Arg(2)
+                        }
+                    )
+                },
+            ),
+            (
+                |_, p| p.calls[0].args[0].val = Value::Str(String::from("1")),
+                |e| {
+                    matches!(
+                        e,
+                        ValueType {
+                            call: 0,
+                            pos: ArgPos::Arg(0),
+                            ..
+                        }
+                    )
+                },
+            ),
+            (
+                |_, p| p.calls[1].args[1].val = Value::Group(vec![num()]),
+                |e| {
+                    matches!(
+                        e,
+                        ValueType {
+                            call: 1,
+                            pos: ArgPos::Arg(1),
+                            ..
+                        }
+                    )
+                },
+            ),
+            (
+                |_, p| {
+                    p.calls[1].args[1].val = Value::Group(vec![num(), Value::Group(vec![num(); 5])])
+                },
+                |e| {
+                    matches!(
+                        e,
+                        SliceLen {
+                            call: 1,
+                            len: 5,
+                            range: (1, 4),
+                            ..
+                        }
+                    )
+                },
+            ),
+            (
+                |_, p| {
+                    p.calls[1].args[1].val = Value::Group(vec![num(), Value::Group(vec![num(); 4])])
+                },
+                |e| {
+                    matches!(
+                        e,
+                        SliceLen {
+                            call: 1,
+                            len: 4,
+                            range: (1, 4),
+                            ..
+                        }
+                    )
+                },
+            ),
+            (
+                |_, p| {
+                    p.calls[1].args[3].val = Value::Opt {
+                        choice: 2,
+                        val: Box::new(num()),
+                    }
+                },
+                |e| {
+                    matches!(
+                        e,
+                        UnionChoice {
+                            call: 1,
+                            choice: 2,
+                            fields: 2,
+                            ..
+                        }
+                    )
+                },
+            ),
+            (
+                |_, p| p.calls[1].args[0].val = Value::Ref((1, ArgPos::Ret)),
+                |e| {
+                    matches!(
+                        e,
+                        DanglingRef {
+                            call: 1,
+                            pos: ArgPos::Arg(0),
+                            ..
+                        }
+                    )
+                },
+            ),
+            (
+                |_, p| p.calls[1].args[0].val = Value::Ref((0, ArgPos::Arg(3))),
+                |e| {
+                    matches!(
+                        e,
+                        DanglingRef {
+                            call: 1,
+                            pos: ArgPos::Arg(0),
+                            ..
+                        }
+                    )
+                },
+            ),
+            (
+                |_, p| p.calls[1].args[0].val = Value::Ref((0, ArgPos::Arg(0))),
+                |e| {
+                    matches!(
+                        e,
+                        RefResource {
+                            call: 1,
+                            pos: ArgPos::Arg(0),
+                            ..
+                        }
+                    )
+                },
+            ),
+            (
+                |t, p| {
+                    p.add_call(call(t, "peek", vec![Value::Ref((0, ArgPos::Ret)), num()]));
+                },
+                |e| matches!(e, LenPath { call: 2, pos: ArgPos::Arg(1), path } if path == "nothing"),
+            ),
+        ];
+        for (i, (malform, expected)) in cases.into_iter().enumerate() {
+            let mut p = valid(&t);
+            malform(&t, &mut p);
+            let errs = t.validate(&p).unwrap_err();
+            assert!(
+                errs.len() == 1 && expected(&errs[0]),
+                "case {}: {:?}",
+                i,
+                errs
+            );
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use executor::transfer::{async_recv_report, async_send};
+use executor::{ExecReport, ExecResult};
+use std::io::Cursor;
+use tokio::runtime::Runtime;
+
+/// Calls of the reported prog and blocks each covered.
+const CALLS: usize = 16;
+const BLOCKS: usize = 4096;
+
+fn report() -> ExecReport {
+    ExecReport {
+        result: ExecResult::Ok((0..CALLS).map(|c| (c..c + BLOCKS).collect()).collect()),
+        call_times: vec![1; CALLS],
+        fault_injected: false,
+        timed_out: false,
+    }
+}
+
+/// Encoding a report and decoding it back, with fresh buffers for each round trip
+/// against buffers kept across them as fuzzer and executor do.
+fn round_trip(c: &mut Criterion) {
+    let r = report();
+    let mut rt = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("transfer");
+    group.throughput(Throughput::Bytes(bincode::serialized_size(&r).unwrap()));
+    group.bench_function("fresh", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let (mut send_buf, mut recv_buf, mut wire) = (Vec::new(), Vec::new(), Vec::new());
+                async_send(&r, &mut wire, &mut send_buf).await.unwrap();
+                async_recv_report(&mut Cursor::new(&wire[..]), &mut recv_buf)
+                    .await
+                    .unwrap()
+            })
+        })
+    });
+    let (mut send_buf, mut recv_buf, mut wire) = (Vec::new(), Vec::new(), Vec::new());
+    group.bench_function("reused", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                wire.clear();
+                async_send(&r, &mut wire, &mut send_buf).await.unwrap();
+                async_recv_report(&mut Cursor::new(&wire[..]), &mut recv_buf)
+                    .await
+                    .unwrap()
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, round_trip);
+criterion_main!(benches);
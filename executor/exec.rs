@@ -5,6 +5,7 @@ use core::prog::Prog;
 use core::target::Target;
 use nix::fcntl::{fcntl, FcntlArg};
 use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
 use nix::sys::signal::{kill, Signal};
 use nix::sys::wait::{wait, waitpid, WaitPidFlag};
 use nix::unistd::{dup2, fork, ForkResult, Pid};
@@ -17,16 +18,35 @@ use std::fs::{read_to_string, write};
 use std::io::Read;
 use std::mem;
 use std::ops::Index;
+use std::os::raw::c_void;
 use std::os::unix::io::AsRawFd;
 use std::path::PathBuf;
 use std::process::exit;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub fn fork_exec(p: Prog, t: &Target, conf: &Config) -> ExecResult {
+    let req = ExecReq {
+        p,
+        call_timeouts: Vec::new(),
+        fault: None,
+    };
+    exec_req(req, t, conf).result
+}
+
+/// Execute prog of `req` in subprocess, with time cost of each call recorded.
+pub fn exec_req(req: ExecReq, t: &Target, conf: &Config) -> ExecReport {
+    let ExecReq {
+        p,
+        call_timeouts,
+        fault,
+    } = req;
+    let fault = fault.filter(|_| conf.fault_injection);
     if conf.concurrency || random::<f64>() < 0.0025 {
         bg_run(&p, t);
     }
+    // set by subprocess if fault of `fault` was injected
+    let injected = fault.as_ref().map(|_| InjectedFlag::new());
     // transfer usefull data
     let (mut rp, mut wp) = os_pipe::pipe()
         .unwrap_or_else(|e| exits!(exitcode::OSERR, "Fail to create date pipe : {}", e));
@@ -71,7 +91,16 @@ pub fn fork_exec(p: Prog, t: &Target, conf: &Config) -> ExecResult {
             });
             drop(err_wp);
             #[cfg(feature = "kcov")]
-            sync_exec(&p, t, &mut wp, waiter, conf);
+            sync_exec(
+                &p,
+                t,
+                &mut wp,
+                waiter,
+                conf,
+                fault
+                    .as_ref()
+                    .zip(injected.as_ref().map(InjectedFlag::addr)),
+            );
             #[cfg(not(feature = "kcov"))]
             sync_exec(&p, t);
             // subprocess exits here
@@ -83,13 +112,29 @@ pub fn fork_exec(p: Prog, t: &Target, conf: &Config) -> ExecResult {
             #[cfg(feature = "kcov")]
             drop(waiter);
 
+            let mut call_times = Vec::new();
+            let mut timed_out = false;
             #[cfg(feature = "kcov")]
-            let ret = watch(child, &mut rp, &mut err_rp, notifer, conf);
+            let result = watch(
+                child,
+                &mut rp,
+                &mut err_rp,
+                notifer,
+                conf,
+                &call_timeouts,
+                &mut call_times,
+                &mut timed_out,
+            );
 
             #[cfg(not(feature = "kcov"))]
-            let ret = watch(child, &mut err_rp);
+            let result = watch(child, &mut err_rp, &mut timed_out);
 
-            ret
+            ExecReport {
+                result,
+                call_times,
+                fault_injected: injected.map(|f| f.get()).unwrap_or(false),
+                timed_out,
+            }
         }
         Err(e) => exits!(exitcode::OSERR, "Fail to fork: {}", e),
     }
@@ -183,12 +228,13 @@ fn bg_fork_run(p: &Prog, t: &Target) {
 }
 
 #[cfg(not(feature = "kcov"))]
-fn watch<T: Read + AsRawFd>(child: Pid, err: &mut T) -> ExecResult {
+fn watch<T: Read + AsRawFd>(child: Pid, err: &mut T, timed_out: &mut bool) -> ExecResult {
     let mut fds = vec![PollFd::new(err.as_raw_fd(), PollFlags::POLLIN)];
 
     match poll(&mut fds, 5_000) {
         Ok(0) => {
             kill_and_wait(child);
+            *timed_out = true;
             ExecResult::Failed(Reason(String::from("Time out")))
         }
         Ok(_) => {
@@ -213,20 +259,30 @@ fn watch<T: Read + AsRawFd>(
     err: &mut T,
     notifer: crate::utils::Notifier,
     conf: &Config,
+    call_timeouts: &[u32],
+    call_times: &mut Vec<u32>,
+    timed_out: &mut bool,
 ) -> ExecResult {
     let mut fds = vec![
         PollFd::new(data.as_raw_fd(), PollFlags::POLLIN),
         PollFd::new(err.as_raw_fd(), PollFlags::POLLIN),
     ];
     let mut covs = Vec::new();
-    let wait_timeout = if conf.memleak_check { 3000 } else { 1000 };
+    let default_timeout = if conf.memleak_check { 3000 } else { 1000 };
     let mut wait_time = Duration::from_secs(0);
+    let mut call_start = Instant::now();
 
     loop {
+        // timeout of the call being executed
+        let wait_timeout = call_timeouts
+            .get(covs.len())
+            .map(|t| *t as i32)
+            .unwrap_or(default_timeout);
         match poll(&mut fds, wait_timeout) {
             Ok(0) => {
                 // timeout
                 kill_and_wait(child);
+                *timed_out = true;
                 return if covs.is_empty() {
                     ExecResult::Failed(Reason(String::from("Time out")))
                 } else {
@@ -266,19 +322,16 @@ fn watch<T: Read + AsRawFd>(
                         let len = data.read_u32::<NativeEndian>().unwrap_or_else(|e| {
                             exits!(exitcode::OSERR, "Fail to read length of covs: {}", e)
                         });
-                        let len = len as usize * mem::size_of::<usize>();
-                        let mut buf = bytes::BytesMut::with_capacity(len);
-                        unsafe {
-                            buf.set_len(len);
-                        }
-                        data.read_exact(&mut buf).unwrap_or_else(|e| {
-                            exits!(exitcode::IOERR, "Fail to read covs(len {}): {}", len, e)
-                        });
+                        // read straight into covers of call, without copying.
+                        let mut new_cov = vec![0usize; len as usize];
+                        data.read_exact(new_cov.as_mut_byte_slice())
+                            .unwrap_or_else(|e| {
+                                exits!(exitcode::IOERR, "Fail to read covs(len {}): {}", len, e)
+                            });
                         notifer.notify();
-
-                        let mut new_cov = Vec::from(buf.as_ref().as_slice_of::<usize>().unwrap());
-                        new_cov.shrink_to_fit();
                         covs.push(new_cov);
+                        call_times.push(call_start.elapsed().as_millis() as u32);
+                        call_start = Instant::now();
                     }
                 }
             }
@@ -346,6 +399,118 @@ fn kill_and_wait(child: Pid) {
     waitpid(child, None);
 }
 
+/// Prog to execute, sent by fuzzer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecReq {
+    pub p: Prog,
+    /// Timeout of each call in ms, calls without one use the default timeout.
+    pub call_timeouts: Vec<u32>,
+    /// Fault to inject, only with kcov and executor started with fault injection.
+    pub fault: Option<Fault>,
+}
+
+/// `ExecReq` borrowing its prog, encoded the same, so fuzzer sends a prog without
+/// cloning it.
+#[derive(Debug, Serialize)]
+pub struct ExecReqRef<'a> {
+    pub p: &'a Prog,
+    pub call_timeouts: &'a [u32],
+    pub fault: Option<Fault>,
+}
+
+/// Fail the `nth` fault point, e.g. allocation, hit by call `call` of prog, through
+/// `/proc/thread-self/fail-nth` of kernel fault injection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fault {
+    pub call: usize,
+    pub nth: u32,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "fail fault point {} of call {}", self.nth, self.call)
+    }
+}
+
+/// Result of executing a prog, sent back to fuzzer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecReport {
+    pub result: ExecResult,
+    /// Time cost of each finished call in ms, only recorded with kcov.
+    pub call_times: Vec<u32>,
+    /// Fault of request was injected, false if its call hit fewer fault points.
+    pub fault_injected: bool,
+    /// Execution was killed for timeout, the call timed out is the one following those in
+    /// `call_times`. Result may still be `Ok` with covers of finished calls.
+    pub timed_out: bool,
+}
+
+/// Flag shared with subprocess executing prog, set by instrumented prog once fault is
+/// injected.
+struct InjectedFlag {
+    mem: *mut c_void,
+}
+
+impl InjectedFlag {
+    fn new() -> Self {
+        let mem = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                mem::size_of::<u32>(),
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_SHARED | MapFlags::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        }
+        .unwrap_or_else(|e| exits!(exitcode::OSERR, "Fail to map fault flag: {}", e));
+        Self { mem }
+    }
+
+    fn addr(&self) -> usize {
+        self.mem as usize
+    }
+
+    fn get(&self) -> bool {
+        unsafe { std::ptr::read_volatile(self.mem as *const u32) != 0 }
+    }
+}
+
+impl Drop for InjectedFlag {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.mem, mem::size_of::<u32>())
+                .unwrap_or_else(|e| exits!(exitcode::OSERR, "Fail to unmap fault flag: {}", e));
+        }
+    }
+}
+
+/// Knobs of kernel fault injection, so that fail-nth fails any allocation.
+const FAULT_KNOBS: [(&str, &str); 5] = [
+    ("/sys/kernel/debug/failslab/ignore-gfp-wait", "N"),
+    ("/sys/kernel/debug/fail_page_alloc/ignore-gfp-wait", "N"),
+    ("/sys/kernel/debug/fail_page_alloc/ignore-gfp-highmem", "N"),
+    ("/sys/kernel/debug/fail_page_alloc/min-order", "0"),
+    ("/sys/kernel/debug/fail_futex/ignore-private", "N"),
+];
+
+/// Prepare kernel fault injection, kernel must be built with CONFIG_FAULT_INJECTION and
+/// debugfs mounted.
+pub fn setup_fault_injection() {
+    if !PathBuf::from("/proc/self/fail-nth").exists() {
+        exits!(
+            exitcode::UNAVAILABLE,
+            "Fault injection is not supported by kernel: no /proc/self/fail-nth"
+        );
+    }
+    for (knob, val) in FAULT_KNOBS.iter() {
+        // failslab or fail_futex may be left out of kernel, fail-nth still works for others.
+        if let Err(e) = write(knob, val) {
+            eprintln!("Fail to write {} to {}: {}", val, knob, e);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExecResult {
     Ok(Vec<Vec<usize>>),
@@ -374,6 +539,7 @@ pub fn sync_exec(
     out: &mut PipeWriter,
     waiter: crate::utils::Waiter,
     conf: &Config,
+    fault: Option<(&Fault, usize)>,
 ) {
     if conf.memleak_check {
         mem_leak_clear();
@@ -383,7 +549,7 @@ pub fn sync_exec(
     use jit::exec;
     #[cfg(feature = "syscall")]
     use syscall::exec;
-    exec(p, t, out, waiter);
+    exec(p, t, out, waiter, fault);
 }
 
 #[cfg(not(feature = "kcov"))]
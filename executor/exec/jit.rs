@@ -1,3 +1,4 @@
+use crate::exec::Fault;
 use crate::utils::Waiter;
 use core::c;
 use core::c::cths::CTHS;
@@ -16,10 +17,16 @@ use std::process::exit;
 use tcc::{Context, Guard};
 
 #[cfg(feature = "kcov")]
-pub fn exec(p: &Prog, t: &Target, out: &mut PipeWriter, waiter: Waiter) {
+pub fn exec(
+    p: &Prog,
+    t: &Target,
+    out: &mut PipeWriter,
+    waiter: Waiter,
+    fault: Option<(&Fault, usize)>,
+) {
     prepare_env();
     let p = {
-        instrument_prog(p, t, out.as_raw_fd(), waiter.as_raw_fd()).unwrap_or_else(|e| {
+        instrument_prog(p, t, out.as_raw_fd(), waiter.as_raw_fd(), fault).unwrap_or_else(|e| {
             eprintln!("{}", e);
             exit(exitcode::SOFTWARE);
         })
@@ -100,11 +107,15 @@ pub fn bg_exec(p: &Prog, t: &Target) {
     }
 }
 
+/// Instrument `p` to send coverage of each call through `data_fd`. With `fault`, its
+/// call is executed with fail-nth set and flag at given address is set if the fault
+/// is injected.
 pub fn instrument_prog(
     p: &Prog,
     t: &Target,
     data_fd: RawFd,
     sync_fd: RawFd,
+    fault: Option<(&Fault, usize)>,
 ) -> Result<String, String> {
     let mut includes = hashset! {
         "stdio.h".to_string(),
@@ -169,6 +180,8 @@ int sync_send(unsigned long *cover, uint32_t len){{
     let kcov_open = format!(
         r#"
     int fd;
+    int fail_fd = -1;
+    char fail_buf[16] = {{0}};
     unsigned long *cover;
     uint32_t len = 0;
 
@@ -206,7 +219,30 @@ int sync_send(unsigned long *cover, uint32_t len){{
 
         includes.extend(header);
 
-        let generated_call = s.to_string();
+        let mut generated_call = s.to_string();
+        let mut fault_check = String::new();
+        if let Some((fault, flag)) = fault.filter(|(f, _)| f.call == i) {
+            // fail-nth counts fault points of following syscalls, so it's set right before
+            // the call and read right after it, "0" means fault was injected.
+            generated_call = format!(
+                r#"fail_fd = open("/proc/thread-self/fail-nth", O_RDWR);
+    if (fail_fd == -1 || write(fail_fd, "{}", {}) == -1)
+            return {};
+    {}
+    if (pread(fail_fd, fail_buf, sizeof(fail_buf) - 1, 0) > 0 && fail_buf[0] == '0')
+            *(volatile uint32_t *){:#x} = 1;"#,
+                fault.nth,
+                fault.nth.to_string().len(),
+                StatusCode::FaultErr as i32,
+                generated_call,
+                flag
+            );
+            fault_check = String::from(
+                r#"
+    write(fail_fd, "0", 1);
+    close(fail_fd);"#,
+            );
+        }
         let s = format!(
             r#"
     if (ioctl(fd, KCOV_ENABLE, KCOV_TRACE_PC))
@@ -215,12 +251,13 @@ int sync_send(unsigned long *cover, uint32_t len){{
     {}
     len = cover[0];
     if (ioctl(fd, KCOV_DISABLE, 0))
-            return {};
+            return {};{}
     if (sync_send(cover, len) == -1)
         return {};"#,
             StatusCode::KcovEnableErr as i32,
             generated_call,
             StatusCode::KcovDisableErr as i32,
+            fault_check,
             StatusCode::CovSendErr as i32
         );
         stmts.push(s);
@@ -271,6 +308,7 @@ enum StatusCode {
     KcovDisableErr,
     CovSendErr,
     MmapErr,
+    FaultErr,
 }
 
 impl From<i32> for StatusCode {
@@ -286,6 +324,7 @@ impl From<i32> for StatusCode {
             5 => KcovDisableErr,
             6 => CovSendErr,
             7 => MmapErr,
+            8 => FaultErr,
             _ => unreachable!(),
         }
     }
@@ -23,6 +23,10 @@ pub struct Settings {
 
     #[structopt(short = "m", long = "memleak-check")]
     memleak_check: bool,
+
+    /// Prepare kernel fault injection, so that faults requested by fuzzer can be injected
+    #[structopt(short = "f", long = "fault-injection")]
+    fault_injection: bool,
 }
 
 fn main() {
@@ -41,6 +45,9 @@ fn main() {
     if settings.memleak_check {
         write("/sys/kernel/debug/kmemleak", "clear").unwrap();
     }
+    if settings.fault_injection {
+        executor::exec::setup_fault_injection();
+    }
 
     let mut retry = 1;
     let conn = loop {
@@ -61,6 +68,7 @@ fn main() {
     let conf = Config {
         memleak_check: settings.memleak_check,
         concurrency: settings.concurrency,
+        fault_injection: settings.fault_injection,
     };
 
     exec_loop(target, conn, conf)
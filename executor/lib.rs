@@ -16,22 +16,27 @@ pub mod cover;
 pub mod exec;
 pub mod transfer;
 
-pub use exec::{ExecResult, Reason};
+pub use exec::{ExecReport, ExecReq, ExecReqRef, ExecResult, Fault, Reason};
 
 pub struct Config {
     pub memleak_check: bool,
     pub concurrency: bool,
+    pub fault_injection: bool,
 }
 
 /// Read prog from conn, translate by target, run the translated test program.
+/// Result is sent back with time cost of each call.
 pub fn exec_loop<T: Read + Write>(t: Target, mut conn: T, conf: Config) {
+    let (mut recv_buf, mut send_buf) = (Vec::new(), Vec::new());
+    transfer::send(&transfer::Hello::default(), &mut conn, &mut send_buf)
+        .unwrap_or_else(|e| exits!(exitcode::SOFTWARE, "Fail to send handshake:{}", e));
     loop {
-        let p = transfer::recv_prog(&mut conn)
+        let req = transfer::recv_req(&mut conn, &mut recv_buf)
             .unwrap_or_else(|e| exits!(exitcode::SOFTWARE, "Fail to recv:{}", e));
 
-        let result = exec::fork_exec(p, &t, &conf);
+        let report = exec::exec_req(req, &t, &conf);
 
-        transfer::send(&result, &mut conn)
-            .unwrap_or_else(|e| exits!(exitcode::SOFTWARE, "Fail to Send {:?}:{}", result, e));
+        transfer::send(&report, &mut conn, &mut send_buf)
+            .unwrap_or_else(|e| exits!(exitcode::SOFTWARE, "Fail to Send {:?}:{}", report, e));
     }
 }
@@ -1,8 +1,17 @@
 //! A implementation of very sample object transfer protocal.
-
-use crate::ExecResult;
-use bytes::BytesMut;
-use core::prog::Prog;
+//!
+//! Every message is a bincode encoded `Header` holding length of body, followed by
+//! bincode encoded body. Progs are sent as `ExecReq`, which carries the prog model
+//! itself, so executor translates it by target directly without any text parsing.
+//! Both ends pass buffers kept across messages, so encoding and reading a message
+//! allocates nothing once buffers have grown to fit.
+//!
+//! Right after connecting, executor sends a `Hello` with the protocol version it speaks.
+//! Fuzzer checks it before sending any prog, an executor built from another version of
+//! healer fails to start with a clear error instead of garbling reports.
+
+use crate::{ExecReport, ExecReq};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::io;
 use std::io::{Read, Write};
@@ -21,72 +30,207 @@ pub enum Error {
     Serialize(#[from] bincode::Error),
 }
 
-pub fn recv_prog<S: Read>(src: &mut S) -> Result<Prog, Error> {
-    let header = Header::default();
-    let headler_len = bincode::serialized_size(&header)? as usize;
+/// Size of encoded `Header`.
+const HEADER_LEN: usize = 4;
+
+/// Version of messages, bumped on any change of `ExecReq`, `ExecReport` or framing.
+pub const PROTOCOL_VERSION: u32 = 2;
+/// Oldest version fuzzer still talks to.
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+/// Tells a healer executor from anything else writing to the port.
+const HELLO_MAGIC: u32 = 0x4845_4c4f;
+
+/// First message of executor.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Hello {
+    pub magic: u32,
+    pub protocol: u32,
+}
 
-    let mut header_buf = BytesMut::with_capacity(headler_len);
-    assert!(header_buf.capacity() >= headler_len);
-    unsafe {
-        header_buf.set_len(headler_len);
+impl Default for Hello {
+    fn default() -> Self {
+        Self {
+            magic: HELLO_MAGIC,
+            protocol: PROTOCOL_VERSION,
+        }
     }
-    src.read_exact(&mut header_buf)?;
-    let header: Header = bincode::deserialize(&header_buf)?;
+}
 
-    let body_len = header.len as usize;
-    let mut body_buf = BytesMut::with_capacity(body_len);
-    unsafe {
-        body_buf.set_len(body_len);
+impl Hello {
+    /// Negotiated protocol version, or why executor can't be talked to.
+    pub fn check(&self) -> Result<u32, String> {
+        if self.magic != HELLO_MAGIC {
+            return Err(format!("bad handshake magic {:#x}", self.magic));
+        }
+        if self.protocol < MIN_PROTOCOL_VERSION || self.protocol > PROTOCOL_VERSION {
+            return Err(format!(
+                "executor speaks protocol {}, fuzzer supports {}..={}, rebuild both from the same healer",
+                self.protocol, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ));
+        }
+        Ok(self.protocol)
     }
-    src.read_exact(&mut body_buf)?;
-
-    bincode::deserialize(&body_buf).map_err(|e| e.into())
 }
 
-pub fn send<T: Serialize, S: Write>(v: &T, out: &mut S) -> Result<(), Error> {
+/// Encode `v` with its header into `buf`, which is cleared first and keeps its capacity.
+fn encode<T: Serialize>(v: &T, buf: &mut Vec<u8>) -> Result<(), Error> {
     let len = bincode::serialized_size(v)? as u32;
-    let header = Header { len };
+    buf.clear();
+    bincode::serialize_into(&mut *buf, &Header { len })?;
+    debug_assert_eq!(buf.len(), HEADER_LEN);
+    bincode::serialize_into(&mut *buf, v)?;
+    Ok(())
+}
 
-    let header = bincode::serialize(&header)?;
-    let body = bincode::serialize(v)?;
+fn body_len(header: &[u8; HEADER_LEN]) -> Result<usize, Error> {
+    let header: Header = bincode::deserialize(header)?;
+    Ok(header.len as usize)
+}
 
-    out.write_all(&header)?;
-    out.write_all(&body)?;
+/// Receive request from `src`, reading its body into `buf`.
+pub fn recv_req<S: Read>(src: &mut S, buf: &mut Vec<u8>) -> Result<ExecReq, Error> {
+    let mut header = [0; HEADER_LEN];
+    src.read_exact(&mut header)?;
+    buf.clear();
+    buf.resize(body_len(&header)?, 0);
+    src.read_exact(buf)?;
+    bincode::deserialize(buf).map_err(|e| e.into())
+}
 
+/// Send `v` to `out`, encoding it in `buf`.
+pub fn send<T: Serialize, S: Write>(v: &T, out: &mut S, buf: &mut Vec<u8>) -> Result<(), Error> {
+    encode(v, buf)?;
+    out.write_all(buf)?;
     Ok(())
 }
 
+/// Send `p` to `out`, encoding it in `buf`.
 pub async fn async_send<T: Serialize, S: AsyncWrite + Unpin>(
     p: &T,
     out: &mut S,
+    buf: &mut Vec<u8>,
 ) -> Result<(), Error> {
-    let len = bincode::serialized_size(p)? as u32;
-    let header = Header { len };
-    let header = bincode::serialize(&header)?;
-    let body = bincode::serialize(p)?;
-
-    out.write_all(&header).await?;
-    out.write_all(&body).await?;
+    encode(p, buf)?;
+    out.write_all(buf).await?;
     Ok(())
 }
 
-pub async fn async_recv_result<T: AsyncRead + Unpin>(src: &mut T) -> Result<ExecResult, Error> {
-    let header = Header::default();
-    let headler_len = bincode::serialized_size(&header)? as usize;
-    let mut header_buf = BytesMut::with_capacity(headler_len);
-    assert!(header_buf.capacity() >= headler_len);
-    unsafe {
-        header_buf.set_len(headler_len);
+/// Receive report from `src`, reading its body into `buf`.
+pub async fn async_recv_report<T: AsyncRead + Unpin>(
+    src: &mut T,
+    buf: &mut Vec<u8>,
+) -> Result<ExecReport, Error> {
+    async_recv(src, buf).await
+}
+
+/// Receive handshake of executor from `src`, reading its body into `buf`.
+pub async fn async_recv_hello<T: AsyncRead + Unpin>(
+    src: &mut T,
+    buf: &mut Vec<u8>,
+) -> Result<Hello, Error> {
+    async_recv(src, buf).await
+}
+
+async fn async_recv<M: DeserializeOwned, T: AsyncRead + Unpin>(
+    src: &mut T,
+    buf: &mut Vec<u8>,
+) -> Result<M, Error> {
+    let mut header = [0; HEADER_LEN];
+    src.read_exact(&mut header).await?;
+    buf.clear();
+    buf.resize(body_len(&header)?, 0);
+    src.read_exact(buf).await?;
+    bincode::deserialize(buf).map_err(|e| e.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExecReqRef, ExecResult, Reason};
+    use core::prog::Prog;
+    use std::io::Cursor;
+
+    fn report(calls: usize) -> ExecReport {
+        ExecReport {
+            result: ExecResult::Ok((0..calls).map(|c| (c..c + 4096).collect()).collect()),
+            call_times: vec![1; calls],
+            fault_injected: false,
+            timed_out: false,
+        }
+    }
+
+    #[test]
+    fn round_trip_with_reused_bufs() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (mut send_buf, mut recv_buf) = (Vec::new(), Vec::new());
+            for r in [
+                report(8),
+                report(1),
+                ExecReport {
+                    result: ExecResult::Failed(Reason(String::from("EINVAL"))),
+                    call_times: Vec::new(),
+                    fault_injected: true,
+                    timed_out: true,
+                },
+            ] {
+                let mut wire = Vec::new();
+                async_send(&r, &mut wire, &mut send_buf).await.unwrap();
+                assert_eq!(
+                    wire.len(),
+                    HEADER_LEN + bincode::serialized_size(&r).unwrap() as usize
+                );
+                let got = async_recv_report(&mut Cursor::new(wire), &mut recv_buf)
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    bincode::serialize(&got).unwrap(),
+                    bincode::serialize(&r).unwrap()
+                );
+            }
+        });
     }
-    src.read_exact(&mut header_buf).await?;
-    let header: Header = bincode::deserialize(&header_buf)?;
 
-    let body_len = header.len as usize;
-    let mut body_buf = BytesMut::with_capacity(body_len);
-    unsafe {
-        body_buf.set_len(body_len);
+    #[test]
+    fn borrowed_req_encoding() {
+        let req = ExecReq {
+            p: Prog::new(3),
+            call_timeouts: vec![1000, 2000],
+            fault: Some(crate::Fault { call: 1, nth: 2 }),
+        };
+        let req_ref = ExecReqRef {
+            p: &req.p,
+            call_timeouts: &req.call_timeouts,
+            fault: req.fault,
+        };
+        let (mut buf, mut buf_ref) = (Vec::new(), Vec::new());
+        send(&req, &mut Vec::new(), &mut buf).unwrap();
+        send(&req_ref, &mut Vec::new(), &mut buf_ref).unwrap();
+        assert_eq!(buf, buf_ref);
+        let got = recv_req(&mut Cursor::new(buf), &mut Vec::new()).unwrap();
+        assert_eq!(got.call_timeouts, req.call_timeouts);
+        assert_eq!(got.fault, req.fault);
     }
-    src.read_exact(&mut body_buf).await?;
 
-    bincode::deserialize(&body_buf).map_err(|e| e.into())
+    #[test]
+    fn hello_handshake() {
+        let mut wire = Vec::new();
+        send(&Hello::default(), &mut wire, &mut Vec::new()).unwrap();
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let hello = rt
+            .block_on(async_recv_hello(&mut Cursor::new(wire), &mut Vec::new()))
+            .unwrap();
+        assert_eq!(hello.check(), Ok(PROTOCOL_VERSION));
+
+        let newer = Hello {
+            protocol: PROTOCOL_VERSION + 1,
+            ..Hello::default()
+        };
+        assert!(newer.check().unwrap_err().contains("protocol"));
+        let garbage = Hello {
+            magic: 0,
+            ..Hello::default()
+        };
+        assert!(garbage.check().unwrap_err().contains("magic"));
+    }
 }
@@ -0,0 +1,269 @@
+//! Backlog of crashes waiting to be reproduced.
+//!
+//! A crash is spilled to `raw_crashes/` of run dir as soon as it's detected, with its report,
+//! the prog that triggered it and when and where it was found. Jobs drain the backlog before
+//! executing next prog, and a crash's file is removed only after it's reproduced and saved to
+//! `crashes/`, so crashes pending when healer exits or dies are left on disk and reproduced
+//! first by a run resuming this one.
+//!
+//! At most `CAPACITY` crashes are pending, which are a weighted reservoir sample of all
+//! crashes pushed: each crash gets key `u^(1/w)` with `u` uniform in (0, 1), and on overflow
+//! the one with smallest key is dropped from repro, saved without repro attempts and counted.
+//! A crash of a signature not seen before has weight 1, the n-th repeat of one has weight
+//! `1 / (REPEAT_PENALTY * n)`, so a crash storm of one bug hardly evicts other bugs.
+//!
+//! A crash whose repro runs out of budget is deferred: its progress is written back to its
+//! file and it's revisited, with a fresh budget, once no crash is pending and at least
+//! `REVISIT_AFTER` has passed, so a stubborn crash never holds up the others.
+use crate::fault::Fault;
+use crate::guest::Crash;
+use crate::report::signature_of;
+use chrono::prelude::*;
+use chrono::DateTime;
+use core::prog::Prog;
+use rand::random;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::fs::{remove_file, write};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+/// Max number of pending crashes.
+pub const CAPACITY: usize = 1024;
+
+/// How much less a repeated signature weighs than a new one.
+const REPEAT_PENALTY: f64 = 64.0;
+
+/// Time a deferred crash waits before it's revisited.
+pub const REVISIT_AFTER: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RawCrash {
+    pub p: Prog,
+    /// `p` as script, for reading only
+    pub prog: String,
+    pub crash: Crash,
+    /// Job that found the crash
+    pub job: usize,
+    pub found_time: DateTime<Local>,
+    /// Fault injected when crash was found, injected again in repro
+    #[serde(default)]
+    pub fault: Option<Fault>,
+    /// Repro done so far, kept across visits of a deferred crash
+    #[serde(default)]
+    pub progress: ReproProgress,
+}
+
+/// Partial repro of a crash.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ReproProgress {
+    /// Repro attempts done
+    pub attempts: usize,
+    /// Attempts that crashed again
+    pub reproduced: usize,
+    /// Crash of first reproduced attempt
+    pub crash: Option<Crash>,
+    /// Executions done to measure repro rate
+    pub rate_attempts: usize,
+    /// Rate executions that crashed
+    pub rate_hits: usize,
+    /// Times repro budget ran out
+    pub deferred: usize,
+}
+
+pub struct CrashBacklog {
+    dir: PathBuf,
+    capacity: usize,
+    /// Pending crashes in order of arrival, with their sampling keys
+    pending: Mutex<VecDeque<(f64, PathBuf, RawCrash)>>,
+    /// Times each signature is pushed
+    signatures: Mutex<HashMap<String, usize>>,
+    /// Crashes out of repro budget and when they can be revisited
+    deferred: Mutex<VecDeque<(Instant, PathBuf, RawCrash)>>,
+    /// Number of crashes ever pushed
+    id_n: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+impl CrashBacklog {
+    pub fn new(run_dir: &Path) -> Self {
+        Self::with_capacity(run_dir, CAPACITY)
+    }
+
+    fn with_capacity(run_dir: &Path, capacity: usize) -> Self {
+        Self {
+            dir: run_dir.join("raw_crashes"),
+            capacity,
+            pending: Mutex::new(VecDeque::new()),
+            signatures: Mutex::new(HashMap::new()),
+            deferred: Mutex::new(VecDeque::new()),
+            id_n: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Spill `crash` to disk and queue it. If backlog overflows, return the crash dropped
+    /// from sample, which can be `crash` itself.
+    pub async fn push(&self, crash: RawCrash) -> Option<RawCrash> {
+        let weight = {
+            let mut signatures = self.signatures.lock().await;
+            let n = signatures
+                .entry(signature_of(&crash.crash.inner))
+                .or_insert(0);
+            *n += 1;
+            if *n == 1 {
+                1.0
+            } else {
+                1.0 / (REPEAT_PENALTY * (*n - 1) as f64)
+            }
+        };
+        let key = random::<f64>().powf(1.0 / weight);
+
+        let id = self.id_n.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{}.json", id));
+        let data = serde_json::to_string_pretty(&crash).unwrap();
+        if let Err(e) = write(&path, data).await {
+            warn!("Backlog: fail to spill crash to {}: {}", path.display(), e);
+        }
+
+        let dropped = {
+            let mut pending = self.pending.lock().await;
+            pending.push_back((key, path, crash));
+            if pending.len() > self.capacity {
+                let (i, _) = pending
+                    .iter()
+                    .enumerate()
+                    .min_by(|(_, a), (_, b)| a.0.partial_cmp(&b.0).unwrap())
+                    .unwrap();
+                pending.remove(i)
+            } else {
+                None
+            }
+        };
+        let (_, path, crash) = dropped?;
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        self.done(&path).await;
+        Some(crash)
+    }
+
+    /// Oldest pending crash and path of its file, which is kept until `done` is called.
+    /// Without pending crash, a deferred one due to be revisited.
+    pub async fn pop(&self) -> Option<(PathBuf, RawCrash)> {
+        {
+            let mut pending = self.pending.lock().await;
+            if let Some((_, path, crash)) = pending.pop_front() {
+                return Some((path, crash));
+            }
+        }
+        let mut deferred = self.deferred.lock().await;
+        match deferred.front() {
+            Some((due, _, _)) if *due <= Instant::now() => {
+                deferred.pop_front().map(|(_, path, crash)| (path, crash))
+            }
+            _ => None,
+        }
+    }
+
+    /// Save progress of `crash` out of repro budget to its file at `path` and revisit it later.
+    pub async fn defer(&self, path: PathBuf, crash: RawCrash) {
+        let data = serde_json::to_string_pretty(&crash).unwrap();
+        if let Err(e) = write(&path, data).await {
+            warn!(
+                "Backlog: fail to save progress to {}: {}",
+                path.display(),
+                e
+            );
+        }
+        let mut deferred = self.deferred.lock().await;
+        deferred.push_back((Instant::now() + REVISIT_AFTER, path, crash));
+    }
+
+    /// Remove file of crash at `path`, it's reproduced and saved.
+    pub async fn done(&self, path: &Path) {
+        if let Err(e) = remove_file(path).await {
+            warn!("Backlog: fail to remove {}: {}", path.display(), e);
+        }
+    }
+
+    /// Number of crashes waiting to be reproduced.
+    pub async fn len(&self) -> usize {
+        let pending = self.pending.lock().await;
+        pending.len()
+    }
+
+    /// Number of crashes out of repro budget, waiting to be revisited.
+    pub async fn deferred(&self) -> usize {
+        let deferred = self.deferred.lock().await;
+        deferred.len()
+    }
+
+    /// Number of crashes dropped from repro because of overflow.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of crashes ever pushed, retained ones are those not dropped.
+    pub fn seen(&self) -> usize {
+        self.id_n.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::process::id;
+
+    fn raw_crash(report: &str) -> RawCrash {
+        RawCrash {
+            p: Prog::new(0),
+            prog: String::new(),
+            crash: Crash {
+                inner: report.to_string(),
+            },
+            job: 0,
+            found_time: Local::now(),
+            fault: None,
+            progress: ReproProgress::default(),
+        }
+    }
+
+    #[test]
+    fn sample_crash_storm() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let run_dir = temp_dir().join(format!("healer-backlog-{}", id()));
+            tokio::fs::create_dir_all(run_dir.join("raw_crashes"))
+                .await
+                .unwrap();
+            let backlog = CrashBacklog::with_capacity(&run_dir, 16);
+            let bugs = ["tcp_close", "ext4_writepages", "nla_put", "skb_put"];
+            for f in bugs.iter() {
+                assert!(backlog
+                    .push(raw_crash(&format!("BUG: KASAN: use-after-free in {}", f)))
+                    .await
+                    .is_none());
+            }
+            for _ in 0..1000 {
+                backlog
+                    .push(raw_crash(
+                        "WARNING: CPU: 0 PID: 1 at mm/page_alloc.c:1 __alloc_pages",
+                    ))
+                    .await;
+            }
+            assert_eq!(backlog.len().await, 16);
+            assert_eq!(backlog.seen(), 1004);
+            assert_eq!(backlog.dropped(), 988);
+
+            let mut retained = Vec::new();
+            while let Some((_, raw)) = backlog.pop().await {
+                retained.push(raw.crash.inner);
+            }
+            for f in bugs.iter() {
+                assert!(retained.iter().any(|r| r.ends_with(f)), "{} evicted", f);
+            }
+            tokio::fs::remove_dir_all(&run_dir).await.unwrap();
+        });
+    }
+}
@@ -0,0 +1,221 @@
+//! Bench mode: measure raw execution throughput of host and kernel, independent of
+//! fuzzing. Each vm executes the same progs in turn for a fixed time, nothing is triaged.
+//! Call sequences of progs come from a fixed seed, so runs with the same seed and target
+//! execute the same calls, values of calls and producers of their resources are generated
+//! once per run as usual.
+use crate::exec::{ExecError, Executor, QemuExecutor};
+use crate::work_dir;
+use crate::Config;
+use core::gen::{gen_seq, Config as GenConfig};
+use core::prog::Prog;
+use core::target::Target;
+use fots::types::GroupId;
+use rand::prelude::*;
+use std::path::PathBuf;
+use std::process::exit;
+use std::sync::Arc;
+use tokio::fs::write;
+use tokio::time::{Duration, Instant};
+
+/// Progs of each kind, trivial ones of one call and heavy ones of `HEAVY_LEN` calls.
+const PROGS: usize = 16;
+const HEAVY_LEN: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct BenchOpts {
+    /// Seconds each vm executes progs for
+    pub secs: u64,
+    /// Seed of call sequences of progs
+    pub seed: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct VmBench {
+    job: usize,
+    boot_ms: u64,
+    execs: usize,
+    execs_per_sec: f64,
+    /// Executions ended by a crash, hang or other failure
+    failed: usize,
+    /// Guest restarts after crashes
+    restarts: usize,
+    /// Executor reconnects after transport errors
+    reconnects: usize,
+    p50_ms: f64,
+    p95_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct BenchReport {
+    seed: u64,
+    secs: u64,
+    progs: usize,
+    execs_per_sec: f64,
+    vms: Vec<VmBench>,
+}
+
+/// Trivial and heavy progs of the largest group of `t`, calls picked by rng of `seed`.
+pub fn bench_progs(t: &Target, seed: u64) -> Vec<Prog> {
+    let (gid, seqs) = bench_seqs(t, seed);
+    let conf = GenConfig::default();
+    seqs.iter().map(|seq| gen_seq(seq, gid, t, &conf)).collect()
+}
+
+/// Group of bench progs and indexes of their calls in it, the same for the same `seed`.
+fn bench_seqs(t: &Target, seed: u64) -> (GroupId, Vec<Vec<usize>>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let g = t
+        .groups
+        .values()
+        .max_by_key(|g| (g.fns.len(), g.id))
+        .unwrap();
+    let seqs = [1, HEAVY_LEN]
+        .iter()
+        .flat_map(|len| std::iter::repeat_n(*len, PROGS))
+        .map(|len| {
+            (0..len)
+                .map(|_| rng.gen_range(0, g.fns.len()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    (g.id, seqs)
+}
+
+/// Boot `cfg.vm_num` vms, execute bench progs on each for `opts.secs`, print and save
+/// report to run dir, then exit.
+pub async fn bench_mode(cfg: Arc<Config>, t: Arc<Target>, run_dir: PathBuf, opts: BenchOpts) -> ! {
+    let progs = Arc::new(bench_progs(&t, opts.seed));
+    info!(
+        "bench: {} vm(s), {} prog(s) of seed {}, {}s each",
+        cfg.vm_num,
+        progs.len(),
+        opts.seed,
+        opts.secs
+    );
+    let secs = opts.secs;
+    let vms = (0..cfg.vm_num)
+        .map(|job| {
+            let (cfg, t, progs) = (cfg.clone(), t.clone(), progs.clone());
+            tokio::spawn(async move {
+                let now = Instant::now();
+                let mut executor = QemuExecutor::new(&cfg, job);
+                if let Err(e) = executor.start().await {
+                    exits!(exitcode::UNAVAILABLE, "job-{}: {}", job, e)
+                }
+                let boot = now.elapsed();
+                bench_vm(job, boot, &mut executor, &t, &progs, secs).await
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut report = BenchReport {
+        seed: opts.seed,
+        secs: opts.secs,
+        progs: progs.len(),
+        execs_per_sec: 0.0,
+        vms: Vec::new(),
+    };
+    for vm in vms {
+        report.vms.push(vm.await.unwrap());
+    }
+    report.execs_per_sec = report.vms.iter().map(|vm| vm.execs_per_sec).sum();
+
+    for vm in report.vms.iter() {
+        println!(
+            "job-{}: boot {}ms, {} exec(s), {:.1} exec/s, p50 {:.1}ms, p95 {:.1}ms, failed {}, restarts {}, reconnects {}",
+            vm.job,
+            vm.boot_ms,
+            vm.execs,
+            vm.execs_per_sec,
+            vm.p50_ms,
+            vm.p95_ms,
+            vm.failed,
+            vm.restarts,
+            vm.reconnects
+        );
+    }
+    println!("total: {:.1} exec/s", report.execs_per_sec);
+    let path = run_dir.join("bench.json");
+    let json = serde_json::to_string_pretty(&report).unwrap();
+    if let Err(e) = write(&path, json).await {
+        exits!(exitcode::IOERR, "Fail to write {}: {}", path.display(), e)
+    }
+    println!("report saved to {}", path.display());
+    work_dir::cleanup();
+    exit(exitcode::OK)
+}
+
+async fn bench_vm(
+    job: usize,
+    boot: Duration,
+    executor: &mut dyn Executor,
+    t: &Target,
+    progs: &[Prog],
+    secs: u64,
+) -> VmBench {
+    let boots = executor.boots();
+    let (mut failed, mut reconnects) = (0, 0);
+    let mut latencies = Vec::new();
+    let start = Instant::now();
+    for p in progs.iter().cycle() {
+        if start.elapsed().as_secs() >= secs {
+            break;
+        }
+        let now = Instant::now();
+        match executor.exec(p, t).await {
+            Ok(_) => latencies.push(now.elapsed()),
+            Err(e) => {
+                failed += 1;
+                if let ExecError::Transport(_) = e {
+                    reconnects += 1;
+                }
+                if e.is_crash() {
+                    executor.restart().await;
+                }
+            }
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    latencies.sort_unstable();
+    let execs = latencies.len() + failed;
+    VmBench {
+        job,
+        boot_ms: boot.as_millis() as u64,
+        execs,
+        execs_per_sec: execs as f64 / elapsed,
+        failed,
+        restarts: executor.boots() - boots,
+        reconnects,
+        p50_ms: percentile(&latencies, 50),
+        p95_ms: percentile(&latencies, 95),
+    }
+}
+
+/// `p`th percentile of sorted `latencies` in ms, 0 if empty.
+fn percentile(latencies: &[Duration], p: usize) -> f64 {
+    if latencies.is_empty() {
+        return 0.0;
+    }
+    let i = (latencies.len() - 1) * p / 100;
+    latencies[i].as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::bench::target;
+
+    #[test]
+    fn bench_progs_of_seed() {
+        let (t, _) = target();
+        let progs = bench_progs(&t, 1);
+        assert_eq!(progs.len(), 2 * PROGS);
+        assert!(progs.iter().all(|p| t.validate(p).is_ok()));
+        assert_eq!(bench_seqs(&t, 1), bench_seqs(&t, 1));
+        assert_ne!(bench_seqs(&t, 1), bench_seqs(&t, 2));
+
+        let latencies = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(percentile(&latencies, 50), 50.0);
+        assert_eq!(percentile(&latencies, 95), 95.0);
+        assert_eq!(percentile(&[], 95), 0.0);
+    }
+}
@@ -0,0 +1,109 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use fuzzer::corpus::Corpus;
+use fuzzer::feedback::{Block, Branch, FeedBack};
+use fuzzer::selection::{select, Recent, SelectionMode};
+use rand::prelude::*;
+use tokio::runtime::Runtime;
+
+/// Progs of corpus, about what a day of fuzzing collects.
+const CORPUS_LEN: usize = 10_000;
+/// Calls of each prog.
+const PROG_LEN: usize = 20;
+/// Branches covered by all of corpus and by a single prog.
+const GLOBAL_BRANCHES: usize = 300_000;
+const PROG_BRANCHES: usize = 3_000;
+
+fn corpus(c: &mut Criterion) {
+    let (t, _) = core::bench::target();
+    let mut rng = StdRng::seed_from_u64(650);
+    let corpus = Corpus::synthetic(&t, CORPUS_LEN, PROG_LEN, &mut rng);
+    let mut rt = Runtime::new().unwrap();
+    let progs = rt.block_on(async {
+        corpus
+            .inner
+            .lock()
+            .await
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+    });
+
+    let mut group = c.benchmark_group("corpus");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(CORPUS_LEN as u64));
+    group.bench_function("insert", |b| {
+        b.iter_batched(
+            || (Corpus::default(), progs.clone()),
+            |(corpus, progs)| {
+                rt.block_on(async {
+                    for p in progs {
+                        corpus.insert(p, Default::default(), 0).await;
+                    }
+                });
+                corpus
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+
+    let recent = Recent::default();
+    for p in progs.iter().take(32) {
+        recent.push(p);
+    }
+    let inner = rt.block_on(corpus.inner.lock());
+    let mut group = c.benchmark_group("select");
+    for (name, mode) in &[
+        ("random", SelectionMode::Random),
+        ("novelty", SelectionMode::Novelty),
+    ] {
+        group.bench_function(*name, |b| b.iter(|| select(&inner, *mode, &recent)));
+    }
+    group.finish();
+}
+
+/// Kernel text addresses of `n` blocks.
+fn blocks<R: Rng>(n: usize, rng: &mut R) -> Vec<Block> {
+    (0..n)
+        .map(|_| Block::from(0xffff_ffff_8100_0000 + rng.gen_range(0, 0x100_0000)))
+        .collect()
+}
+
+fn branches(blocks: &[Block]) -> Box<[Branch]> {
+    let mut branches = blocks
+        .windows(2)
+        .map(|w| Branch::from((w[0].clone(), w[1].clone())))
+        .collect::<Vec<_>>();
+    branches.sort_unstable();
+    branches.dedup();
+    branches.into_boxed_slice()
+}
+
+fn feedback(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(650);
+    let mut rt = Runtime::new().unwrap();
+    let feedback = FeedBack::default();
+    let global = blocks(GLOBAL_BRANCHES + 1, &mut rng);
+    rt.block_on(feedback.merge(Box::default(), branches(&global)));
+    // mostly known branches, like most execution of a long run
+    let mut covered = global[..PROG_BRANCHES].to_vec();
+    covered.extend(blocks(PROG_BRANCHES / 100, &mut rng));
+    let covered = branches(&covered);
+
+    let mut group = c.benchmark_group("feedback");
+    group.throughput(Throughput::Elements(covered.len() as u64));
+    group.bench_function("diff_branch", |b| {
+        b.iter(|| rt.block_on(feedback.diff_branch(&covered)))
+    });
+    group.bench_function("merge", |b| {
+        b.iter_batched(
+            || branches(&blocks(PROG_BRANCHES, &mut rng)),
+            |new| rt.block_on(feedback.merge(Box::default(), new)),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, corpus, feedback);
+criterion_main!(benches);
@@ -0,0 +1,212 @@
+//! Builder of `Config` for embedding healer as a library.
+//!
+//! Only paths fuzzing can't go without are required, everything else starts from the same
+//! defaults a minimal config file gets, and is changed by setters. `build` checks the
+//! config the same way as one loaded from file, so an invalid config never escapes.
+//! Command line flags of fuzzer are applied through this builder too.
+use crate::exec::ExecutorConf;
+use crate::fuzzer::FuzzerConf;
+use crate::guest::{GuestConf, QemuConf, SSHConf};
+use crate::stats::SamplerConf;
+use crate::Config;
+use std::path::PathBuf;
+
+pub struct ConfigBuilder {
+    pub(crate) conf: Config,
+}
+
+impl ConfigBuilder {
+    /// Builder of config fuzzing `fots_bin` with `executor`, in qemu guests of `image` and
+    /// `kernel` logged in with ssh key `ssh_key`.
+    pub fn new<P: Into<PathBuf>, S: Into<String>>(
+        fots_bin: P,
+        executor: P,
+        image: S,
+        kernel: S,
+        ssh_key: S,
+    ) -> Self {
+        let guest = GuestConf::default();
+        Self {
+            conf: Config {
+                fots_bin: fots_bin.into(),
+                curpus: None,
+                corpus_fots_bin: None,
+                syz_corpus: None,
+                export_corpus_db: None,
+                syz_crash_layout: None,
+                per_job_dirs: None,
+                rpc: None,
+                relations: None,
+                fuzzer_state: None,
+                resume: None,
+                vm_num: 0,
+                suppressions: None,
+                severity: None,
+                suppress_subsystems: None,
+                ignores: None,
+                disabled_calls: None,
+                required_calls: None,
+                dictionaries: None,
+                trim: None,
+                blackbox: None,
+                max_execs: None,
+                max_crashes: None,
+                out_dir: None,
+                tolerate_boot_failures: None,
+                runtime_threads: None,
+                guest_setup: None,
+                min_free_space: None,
+                work_dir: None,
+                qemu: QemuConf::new(&guest, image.into(), kernel.into()),
+                ssh: SSHConf::new(ssh_key.into()),
+                executor: ExecutorConf::new(&guest, executor.into()),
+                guest,
+                sampler: None,
+                fuzzer: None,
+                log: None,
+                symbolize: None,
+                plateau: None,
+                watchdog: None,
+                regression: None,
+                replay_log: None,
+                hub: None,
+                cover_dump: None,
+                #[cfg(feature = "mail")]
+                mail: None,
+                #[cfg(feature = "otlp")]
+                otlp: None,
+            },
+        }
+    }
+
+    pub fn fots_bin<P: Into<PathBuf>>(mut self, fots_bin: P) -> Self {
+        self.conf.fots_bin = fots_bin.into();
+        self
+    }
+
+    pub fn corpus<P: Into<PathBuf>>(mut self, corpus: P) -> Self {
+        self.conf.curpus = Some(corpus.into());
+        self
+    }
+
+    /// Number of vms, 0 means sized by host cpus and memory, which is the default.
+    pub fn vm_num(mut self, vm_num: usize) -> Self {
+        self.conf.vm_num = vm_num;
+        self
+    }
+
+    pub fn disabled_calls<P: Into<PathBuf>>(mut self, disabled_calls: P) -> Self {
+        self.conf.disabled_calls = Some(disabled_calls.into());
+        self
+    }
+
+    pub fn required_calls(mut self, required_calls: Vec<String>) -> Self {
+        self.conf.required_calls = Some(required_calls);
+        self
+    }
+
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.conf.trim = Some(trim);
+        self
+    }
+
+    pub fn blackbox(mut self, blackbox: bool) -> Self {
+        self.conf.blackbox = Some(blackbox);
+        self
+    }
+
+    pub fn max_execs(mut self, max_execs: u64) -> Self {
+        self.conf.max_execs = Some(max_execs);
+        self
+    }
+
+    pub fn out_dir<P: Into<PathBuf>>(mut self, out_dir: P) -> Self {
+        self.conf.out_dir = Some(out_dir.into());
+        self
+    }
+
+    pub fn guest_setup<P: Into<PathBuf>>(mut self, script: P) -> Self {
+        self.conf.guest_setup = Some(script.into());
+        self
+    }
+
+    /// Guest to fuzz, default linux/amd64 on qemu. Qemu and executor options are reset to
+    /// defaults of `guest`, so it's set before them.
+    pub fn guest(mut self, guest: GuestConf) -> Self {
+        self.conf.qemu = QemuConf::new(&guest, self.conf.qemu.image, self.conf.qemu.kernel);
+        self.conf.executor = ExecutorConf::new(&guest, self.conf.executor.path);
+        self.conf.guest = guest;
+        self
+    }
+
+    pub fn image<S: Into<String>>(mut self, image: S) -> Self {
+        self.conf.qemu.image = image.into();
+        self
+    }
+
+    pub fn kernel<S: Into<String>>(mut self, kernel: S) -> Self {
+        self.conf.qemu.kernel = kernel.into();
+        self
+    }
+
+    /// Cpus and memory in MB of each vm, default 1 and 2048 for linux/amd64.
+    pub fn vm_size(mut self, cpu_num: u32, mem_size: u32) -> Self {
+        self.conf.qemu.cpu_num = cpu_num;
+        self.conf.qemu.mem_size = mem_size;
+        self
+    }
+
+    pub fn executor<P: Into<PathBuf>>(mut self, executor: P) -> Self {
+        self.conf.executor.path = executor.into();
+        self
+    }
+
+    pub fn fuzzer(mut self, fuzzer: FuzzerConf) -> Self {
+        self.conf.fuzzer = Some(fuzzer);
+        self
+    }
+
+    pub fn sampler(mut self, sampler: SamplerConf) -> Self {
+        self.conf.sampler = Some(sampler);
+        self
+    }
+
+    /// Resolve vm num and check config, exit with config error if it's invalid.
+    pub fn build(mut self) -> Config {
+        self.conf.auto_size();
+        self.conf.check();
+        self.conf
+    }
+}
+
+/// Builder starting from `conf`, e.g. loaded from file.
+impl From<Config> for ConfigBuilder {
+    fn from(conf: Config) -> Self {
+        Self { conf }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_defaults() {
+        let builder = ConfigBuilder::new(
+            "./sys",
+            "./executor",
+            "./stretch.img",
+            "./bzImage",
+            "./stretch.id_rsa",
+        )
+        .vm_num(2)
+        .trim(false);
+        // same config as a minimal config file
+        let conf: Config = toml::from_str(&builder.conf.resolved()).unwrap();
+        assert_eq!(conf.vm_num, 2);
+        assert_eq!(conf.trim, Some(false));
+        assert_eq!(conf.guest.arch, "amd64");
+        assert_eq!(conf.qemu.mem_size, 2048);
+        assert!(conf.executor.concurrency);
+    }
+}
@@ -0,0 +1,188 @@
+//! Per-call timeouts learned from execution history.
+//!
+//! Executor reports time cost of each finished call. Once a call finished, its timeout
+//! is `SLACK` times of its slowest finished execution, but never less than the default
+//! one, timing out beyond that is a hang. A call that never finished is probed with
+//! doubled timeout a few times, so a slow-but-normal call like `sync` gets a chance to
+//! finish and be learned.
+use core::prog::Prog;
+use core::target::Target;
+use executor::ExecReport;
+use fots::types::FnId;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Upper bound of learned timeout in ms, a call still running after this is a hang.
+pub const MAX_CALL_TIMEOUT: u32 = 10_000;
+/// Learned timeout is at least this times of the slowest finished execution.
+const SLACK: u32 = 3;
+/// Times a call that never finished is retried with doubled timeout.
+const MAX_PROBES: usize = 2;
+
+/// Timeouts of calls in ms, shared by all executors.
+/// Lock is never held across await point, so std mutex is used.
+pub struct CallTimeouts {
+    default: u32,
+    calls: Mutex<HashMap<FnId, CallTime>>,
+}
+
+struct CallTime {
+    name: String,
+    samples: usize,
+    max: u32,
+    timed_out: usize,
+    timeout: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LearnedTimeout {
+    pub name: String,
+    pub timeout: u32,
+    /// Slowest finished execution in ms
+    pub max: u32,
+    pub samples: usize,
+    pub timed_out: usize,
+}
+
+impl CallTimeouts {
+    pub fn new(t: &Target, default: u32) -> Self {
+        let calls = t
+            .groups
+            .values()
+            .flat_map(|g| g.iter_fn())
+            .map(|f| {
+                let call = CallTime {
+                    name: f.dec_name.clone(),
+                    samples: 0,
+                    max: 0,
+                    timed_out: 0,
+                    timeout: default,
+                };
+                (f.id, call)
+            })
+            .collect();
+        Self {
+            default,
+            calls: Mutex::new(calls),
+        }
+    }
+
+    /// Timeout of each call of `p`.
+    pub fn timeouts_of(&self, p: &Prog) -> Vec<u32> {
+        let calls = self.calls.lock().unwrap();
+        p.calls.iter().map(|c| calls[&c.fid].timeout).collect()
+    }
+
+    /// Extra time in ms the whole prog may take because of learned timeouts.
+    pub fn extra_of(&self, timeouts: &[u32]) -> u64 {
+        timeouts
+            .iter()
+            .map(|t| t.saturating_sub(self.default) as u64)
+            .sum()
+    }
+
+    /// Learn from time cost of finished calls of `p` in `report`. If it timed out, the call
+    /// following the finished ones is the one that timed out.
+    pub fn learn(&self, p: &Prog, report: &ExecReport) {
+        let call_times = &report.call_times;
+        let mut calls = self.calls.lock().unwrap();
+        for (c, &tm) in p.calls.iter().zip(call_times) {
+            let call = calls.get_mut(&c.fid).unwrap();
+            call.samples += 1;
+            call.max = call.max.max(tm);
+            // timeout raised by probing is dropped here, only finished executions count.
+            call.timeout = call
+                .max
+                .saturating_mul(SLACK)
+                .clamp(self.default, MAX_CALL_TIMEOUT.max(self.default));
+        }
+
+        if !report.timed_out || call_times.len() >= p.calls.len() {
+            return;
+        }
+        let call = calls.get_mut(&p.calls[call_times.len()].fid).unwrap();
+        call.timed_out += 1;
+        if call.samples == 0 {
+            call.timeout = if call.timed_out <= MAX_PROBES {
+                (call.timeout * 2).min(MAX_CALL_TIMEOUT)
+            } else {
+                // never finished after probes, it's a hang.
+                self.default
+            };
+        }
+    }
+
+    /// Timeouts that differ from default one, sorted by timeout.
+    pub fn learned(&self) -> Vec<LearnedTimeout> {
+        let calls = self.calls.lock().unwrap();
+        let mut learned = calls
+            .values()
+            .filter(|c| c.timeout != self.default)
+            .map(|c| LearnedTimeout {
+                name: c.name.clone(),
+                timeout: c.timeout,
+                max: c.max,
+                samples: c.samples,
+                timed_out: c.timed_out,
+            })
+            .collect::<Vec<_>>();
+        learned.sort_unstable_by(|a, b| b.timeout.cmp(&a.timeout).then(a.name.cmp(&b.name)));
+        learned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::call_timeout::{CallTimeouts, MAX_CALL_TIMEOUT};
+    use core::bench::target;
+    use core::prog::{Call, Prog};
+    use executor::{ExecReport, ExecResult, Reason};
+
+    /// Report of executor killing `p` after calls of `call_times` finished, if `timed_out`.
+    fn report(call_times: &[u32], timed_out: bool) -> ExecReport {
+        let result = if timed_out && call_times.is_empty() {
+            ExecResult::Failed(Reason(String::from("Time out")))
+        } else {
+            ExecResult::Ok(vec![vec![0]; call_times.len()])
+        };
+        ExecReport {
+            result,
+            call_times: call_times.to_vec(),
+            fault_injected: false,
+            timed_out,
+        }
+    }
+
+    #[test]
+    fn learn_timeouts() {
+        let (t, _) = target();
+        let sync = t.fn_by_name("sync").unwrap();
+        let read = t.fn_by_name("read").unwrap();
+        let mut p = Prog::new(sync.gid);
+        p.add_call(Call::new(read.id));
+        p.add_call(Call::new(sync.id));
+
+        let timeouts = CallTimeouts::new(&t, 1000);
+        assert_eq!(timeouts.timeouts_of(&p), vec![1000, 1000]);
+
+        // sync never finished, probed with longer timeout. Covers of read are still reported.
+        timeouts.learn(&p, &report(&[5], true));
+        assert_eq!(timeouts.timeouts_of(&p), vec![1000, 2000]);
+        // finished in 1.5s, slow but normal.
+        timeouts.learn(&p, &report(&[5, 1500], false));
+        assert_eq!(timeouts.timeouts_of(&p), vec![1000, 4500]);
+        assert_eq!(timeouts.extra_of(&timeouts.timeouts_of(&p)), 3500);
+
+        // read is fast normally, timing out is a hang.
+        let mut p = Prog::new(read.gid);
+        p.add_call(Call::new(read.id));
+        timeouts.learn(&p, &report(&[], true));
+        assert_eq!(timeouts.timeouts_of(&p), vec![1000]);
+
+        timeouts.learn(&p, &report(&[60_000], false));
+        assert_eq!(timeouts.timeouts_of(&p), vec![MAX_CALL_TIMEOUT]);
+        let learned = timeouts.learned();
+        assert_eq!(learned[0].name, "read");
+        assert_eq!(learned[1].name, "sync");
+    }
+}
@@ -0,0 +1,259 @@
+//! Selecting calls of target by name.
+//!
+//! Call list files contain one name per line, `#` starts a comment. Each entry can
+//! be an exact declared name such as `open@special_file` or a glob pattern such as
+//! `ioctl@KVM_*`, patterns are expanded against all calls of the target at load time.
+//! syzkaller's `$` of variants is read as healer's `@`, so `ioctl$KVM_*` works too.
+//!
+//! A syzkaller manager config, or a fragment of it, can be used instead, see `SyzCalls`.
+use crate::utils::glob::glob_match;
+use fots::types::{FnId, Items};
+use std::collections::HashSet;
+use std::path::Path;
+use tokio::fs::read_to_string;
+use tokio::io::Result;
+
+pub async fn read_call_list<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+    let content = read_to_string(path).await?;
+    Ok(parse_call_list(&content))
+}
+
+pub fn parse_call_list(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|l| l.split('#').next().unwrap().trim())
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// `enable_syscalls` and `disable_syscalls` of a syzkaller manager config.
+#[derive(Debug, Default, Deserialize)]
+pub struct SyzCalls {
+    #[serde(default)]
+    pub enable_syscalls: Vec<String>,
+    #[serde(default)]
+    pub disable_syscalls: Vec<String>,
+}
+
+impl SyzCalls {
+    /// Parse a manager config, other fields are ignored, or a fragment of it, i.e. its
+    /// `"enable_syscalls": [...]` and `"disable_syscalls": [...]` without braces. None if
+    /// `content` isn't json, e.g. a call list.
+    pub fn parse(content: &str) -> Option<std::result::Result<Self, String>> {
+        let s = content.trim();
+        let json = if s.starts_with('{') {
+            s.to_string()
+        } else if s.starts_with('"') {
+            format!("{{{}}}", s.trim_end_matches(','))
+        } else {
+            return None;
+        };
+        Some(serde_json::from_str(&json).map_err(|e| e.to_string()))
+    }
+
+    /// Resolve lists against declared names `names` as syz-manager does: all calls are
+    /// enabled if `enable_syscalls` is empty, then calls of `disable_syscalls` are removed,
+    /// so a call in both is disabled. Return names of calls left disabled and patterns
+    /// matching nothing. syz-manager rejects the latter, here they're only returned, as
+    /// healer describes fewer calls than syzkaller.
+    pub fn disabled<'a, I>(&self, names: I) -> (HashSet<String>, Vec<String>)
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let names = names.into_iter().collect::<Vec<_>>();
+        let mut unmatched = Vec::new();
+        let mut matches = |pattern: &String| {
+            let matched = names
+                .iter()
+                .filter(|n| match_syscall(&n.replace('@', "$"), pattern))
+                .collect::<Vec<_>>();
+            if matched.is_empty() {
+                unmatched.push(pattern.clone());
+            }
+            matched
+        };
+        let mut enabled = if self.enable_syscalls.is_empty() {
+            names.iter().collect::<HashSet<_>>()
+        } else {
+            self.enable_syscalls.iter().flat_map(&mut matches).collect()
+        };
+        for n in self.disable_syscalls.iter().flat_map(&mut matches) {
+            enabled.remove(n);
+        }
+        let disabled = names
+            .iter()
+            .filter(|n| !enabled.contains(n))
+            .map(|n| n.to_string())
+            .collect();
+        (disabled, unmatched)
+    }
+}
+
+/// Whether syzkaller call `name`, e.g. `socket$inet`, matches `pattern` of a manager
+/// config: the same name, its base name, e.g. `socket`, or a prefix ending with `*`.
+pub fn match_syscall(name: &str, pattern: &str) -> bool {
+    if name == pattern
+        || name
+            .strip_prefix(pattern)
+            .is_some_and(|v| v.starts_with('$'))
+    {
+        return true;
+    }
+    pattern.len() > 1
+        && pattern
+            .strip_suffix('*')
+            .is_some_and(|prefix| name.starts_with(prefix))
+}
+
+#[derive(Debug, Default)]
+pub struct Expansion {
+    /// Declared names of every matched call.
+    pub matched: HashSet<String>,
+    /// Patterns that match nothing.
+    pub unmatched: Vec<String>,
+    /// Number of calls matched by each other pattern.
+    pub counts: Vec<(String, usize)>,
+}
+
+/// Whether declared name `name` matches glob `pattern`, `$` of `pattern` is read as `@`.
+pub fn match_call(pattern: &str, name: &str) -> bool {
+    glob_match(&pattern.replace('$', "@"), name)
+}
+
+/// Expand each pattern against `names`.
+pub fn expand<'a, I>(patterns: &[String], names: I) -> Expansion
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let names = names.into_iter().collect::<Vec<_>>();
+    let mut ret = Expansion::default();
+    for p in patterns {
+        let mut n = 0;
+        for name in names.iter().filter(|name| match_call(p, name)) {
+            ret.matched.insert(name.to_string());
+            n += 1;
+        }
+        if n == 0 {
+            ret.unmatched.push(p.clone());
+        } else {
+            ret.counts.push((p.clone(), n));
+        }
+    }
+    ret
+}
+
+/// Expand patterns against calls of `items`, logging the result under `kind`.
+pub fn expand_in(kind: &str, patterns: &[String], items: &Items) -> HashSet<String> {
+    let names = items
+        .groups
+        .iter()
+        .flat_map(|g| g.fns.iter().map(|f| &f.dec_name[..]));
+    let expansion = expand(patterns, names);
+    for p in expansion.unmatched.iter() {
+        warn!("{}: `{}` matches no call", kind, p);
+    }
+    for (p, n) in expansion.counts.iter() {
+        info!("{}: `{}` matches {} call(s)", kind, p, n);
+    }
+    expansion.matched
+}
+
+/// Remove calls in `names` from `items`, drop groups that become empty.
+/// Return ids of removed calls.
+pub fn remove_calls(items: &mut Items, names: &HashSet<String>) -> HashSet<FnId> {
+    let mut removed = HashSet::new();
+    for g in items.groups.iter_mut() {
+        g.fns.retain(|f| {
+            if names.contains(&f.dec_name) {
+                removed.insert(f.id);
+                false
+            } else {
+                true
+            }
+        });
+    }
+    items.groups.retain(|g| !g.fns.is_empty());
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calls::{expand, match_call, match_syscall, SyzCalls};
+    use core::bench::target;
+
+    #[test]
+    fn expand_patterns() {
+        let (t, _) = target();
+        let names = t
+            .groups
+            .values()
+            .flat_map(|g| g.fns.iter().map(|f| &f.dec_name[..]))
+            .collect::<Vec<_>>();
+        let patterns = vec![
+            "ioctl@KVM_*".to_string(),
+            "socket$kcm".to_string(),
+            "ioctl$DRM_*".to_string(),
+        ];
+        let e = expand(&patterns, names.iter().copied());
+        assert_eq!(e.matched.len(), 8);
+        assert!(e.matched.contains("ioctl@KVM_RUN"));
+        assert!(e.matched.contains("socket@kcm"));
+        assert!(!e.matched.contains("ioctl"));
+        assert!(!e.matched.contains("socket@ax25"));
+        assert_eq!(e.unmatched, vec!["ioctl$DRM_*".to_string()]);
+        assert_eq!(e.counts[0], ("ioctl@KVM_*".to_string(), 7));
+        assert_eq!(e.counts[1], ("socket$kcm".to_string(), 1));
+
+        // `$` is only read as `@`, other chars stay literal.
+        assert!(match_call("socket$inet*", "socket@inet_udplite"));
+        assert!(match_call("socket*", "socket@kcm"));
+        assert!(!match_call("socket$inet*", "socket$inet"));
+    }
+
+    #[test]
+    fn syz_manager_calls() {
+        assert!(match_syscall("socket$inet", "socket"));
+        assert!(match_syscall("socket$inet", "socket$inet"));
+        assert!(match_syscall("socket$inet6", "socket$inet*"));
+        assert!(!match_syscall("socket$inet6", "socket$inet"));
+        assert!(!match_syscall("socketpair", "socket"));
+        assert!(!match_syscall("socket", "*"));
+
+        let names = [
+            "socket@inet",
+            "socket@inet6",
+            "socketpair",
+            "open",
+            "openat",
+        ];
+        assert!(SyzCalls::parse("open\nsocket*\n").is_none());
+        let conf = r#"{
+            "target": "linux/amd64",
+            "enable_syscalls": ["socket", "openat", "mmap"],
+            "disable_syscalls": ["socket$inet6", "openat"]
+        }"#;
+        let conf = SyzCalls::parse(conf).unwrap().unwrap();
+        let (disabled, unmatched) = conf.disabled(names.iter().copied());
+        // disable_syscalls wins over enable_syscalls, as in syz-manager.
+        let mut disabled = disabled.into_iter().collect::<Vec<_>>();
+        disabled.sort();
+        assert_eq!(
+            disabled,
+            vec!["open", "openat", "socket@inet6", "socketpair"]
+        );
+        assert_eq!(unmatched, vec!["mmap".to_string()]);
+
+        // fragment with only disable_syscalls enables everything else.
+        let conf = SyzCalls::parse("\"disable_syscalls\": [\"open*\"],\n")
+            .unwrap()
+            .unwrap();
+        let (disabled, unmatched) = conf.disabled(names.iter().copied());
+        assert_eq!(disabled.len(), 2);
+        assert!(disabled.contains("open") && disabled.contains("openat"));
+        assert!(unmatched.is_empty());
+        assert!(SyzCalls::parse("{\"enable_syscalls\": \"open\"}")
+            .unwrap()
+            .is_err());
+    }
+}
@@ -0,0 +1,115 @@
+//! Self test before a long run: boot one vm, execute a few generated progs and check
+//! that executions succeed and collect coverage. Result of each stage is printed and
+//! exit code is non-zero if any stage failed, so CI can gate on it.
+use crate::exec::{QemuExecutor, RunError};
+use crate::work_dir;
+use crate::Config;
+use core::analyze::static_analyze;
+use core::gen::gen;
+use core::target::Target;
+use std::process::exit;
+use tokio::time::{Duration, Instant};
+
+/// Number of generated progs executed.
+const PROGS: usize = 16;
+/// Time limit of executing each prog.
+const PROG_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Report {
+    stages: Vec<(&'static str, Result<String, String>)>,
+}
+
+impl Report {
+    fn add(&mut self, stage: &'static str, result: Result<String, String>) {
+        match &result {
+            Ok(msg) => info!("check-run: {}: PASS, {}", stage, msg),
+            Err(msg) => error!("check-run: {}: FAIL, {}", stage, msg),
+        }
+        self.stages.push((stage, result));
+    }
+
+    fn passed(&self) -> bool {
+        self.stages.iter().all(|(_, r)| r.is_ok())
+    }
+
+    /// Print result of each stage and exit.
+    fn finish(&self) -> ! {
+        for (stage, result) in self.stages.iter() {
+            println!(
+                "{:<10} {}",
+                stage,
+                if result.is_ok() { "PASS" } else { "FAIL" }
+            );
+        }
+        work_dir::cleanup();
+        if self.passed() {
+            exit(exitcode::OK)
+        } else {
+            exit(exitcode::SOFTWARE)
+        }
+    }
+}
+
+pub async fn check_run(cfg: &Config, t: &Target) -> ! {
+    let mut report = Report { stages: Vec::new() };
+    report.add(
+        "target",
+        Ok(format!(
+            "{} call(s), {} group(s)",
+            t.fns.len(),
+            t.groups.len()
+        )),
+    );
+
+    let now = Instant::now();
+    let mut executor = QemuExecutor::new(cfg, 0);
+    let booted = executor.start().await;
+    report.add(
+        "boot",
+        booted
+            .map(|_| format!("cost {}s", now.elapsed().as_secs()))
+            .map_err(|e| e.to_string()),
+    );
+    if !report.passed() {
+        report.finish()
+    }
+
+    let rt = static_analyze(t);
+    let conf = cfg
+        .fuzzer
+        .as_ref()
+        .map(|f| f.gen_conf())
+        .unwrap_or_default();
+    let (mut ok, mut failed, mut crashed, mut blocks) = (0, 0, 0, 0);
+    for _ in 0..PROGS {
+        let p = gen(t, &rt, &conf);
+        match executor.run(&p, t, PROG_TIMEOUT).await {
+            Ok(out) => {
+                ok += 1;
+                blocks += out.calls.iter().map(|c| c.covers.len()).sum::<usize>();
+            }
+            Err(RunError::Failed(_)) | Err(RunError::Timeout(_)) => failed += 1,
+            Err(RunError::Crashed(_)) => crashed += 1,
+        }
+    }
+    let msg = format!("{} ok, {} failed, {} crashed", ok, failed, crashed);
+    report.add("exec", if ok != 0 { Ok(msg) } else { Err(msg) });
+    if cfg.blackbox == Some(true) {
+        info!(
+            "check-run: coverage: SKIP, blackbox mode, {} block(s) collected",
+            blocks
+        );
+    } else {
+        report.add(
+            "coverage",
+            if blocks != 0 {
+                Ok(format!("{} block(s) collected", blocks))
+            } else {
+                Err(String::from("no coverage collected, is kcov enabled?"))
+            },
+        );
+    }
+    info!("check-run: crash detection: SKIP, no way to trigger a benign crash");
+
+    report.finish()
+}
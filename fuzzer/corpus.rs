@@ -1,17 +1,245 @@
+use crate::energy::{energy, InputMeta, PowerSchedule};
+use crate::symbols::SymbolTable;
 use core::prog::Prog;
-use std::collections::HashSet;
+#[cfg(feature = "bench")]
+use core::target::Target;
+use rand::{thread_rng, Rng};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::iter::FromIterator;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
+/// Selection weight of a prog retired for long.
+const MIN_WEIGHT: f64 = 1.0 / 16.0;
+
 #[derive(Debug, Default)]
 pub struct Corpus {
     pub inner: Mutex<HashSet<Prog>>,
+    /// Energy inputs of progs, keyed by hash of prog. Lock is never held across await
+    /// point, so std mutex is used.
+    meta: std::sync::Mutex<Metas>,
+}
+
+#[derive(Debug, Default)]
+struct Metas {
+    inputs: HashMap<u64, InputMeta>,
+    /// Sum of measured execution times and scores, and number of measured progs
+    total_tm: Duration,
+    total_score: usize,
+    measured: usize,
+    /// Selections since last culling
+    selections: usize,
+    /// Reboots without kernel crash caused by mutations of each prog
+    reboots: HashMap<u64, usize>,
+    /// Progs taken out of corpus for causing too many of such reboots
+    quarantined: Vec<Prog>,
+    /// Whether blocks each prog first reached are retained, see `Corpus::explain`
+    explain: bool,
+    /// PCs of blocks each prog first reached when it was added
+    reasons: HashMap<u64, Box<[usize]>>,
 }
 
 impl Corpus {
-    pub async fn insert(&self, p: Prog) -> bool {
+    /// Insert `p`, which took `exec_tm` to execute and covered `score` branches.
+    pub async fn insert(&self, p: Prog, exec_tm: Duration, score: usize) -> bool {
+        let key = key_of(&p);
+        let mut inner = self.inner.lock().await;
+        if !inner.insert(p) {
+            return false;
+        }
+        let mut meta = self.meta.lock().unwrap();
+        meta.total_tm += exec_tm;
+        meta.total_score += score;
+        meta.measured += 1;
+        meta.inputs.insert(
+            key,
+            InputMeta {
+                exec_tm,
+                score,
+                ..Default::default()
+            },
+        );
+        true
+    }
+
+    /// Insert `p` executed without coverage, scored by its length, keeping at most `cap`
+    /// progs. Once full, `p` replaces a random prog, so a prog survives fewer insertions
+    /// the older it gets, like a reservoir. See `Fuzzer::blackbox`.
+    pub async fn insert_capped(&self, p: Prog, cap: usize) -> bool {
+        let score = p.len();
         let mut inner = self.inner.lock().await;
-        inner.insert(p)
+        if inner.contains(&p) {
+            return false;
+        }
+        if inner.len() >= cap {
+            let n = thread_rng().gen_range(0, inner.len());
+            let victim = inner.iter().nth(n).unwrap().clone();
+            inner.remove(&victim);
+            let mut meta = self.meta.lock().unwrap();
+            // entries made by selection alone were never measured.
+            let input = meta.inputs.remove(&key_of(&victim));
+            meta.reasons.remove(&key_of(&victim));
+            if let Some(input) = input.filter(|i| i.score != 0 || i.exec_tm != Duration::ZERO) {
+                meta.total_tm -= input.exec_tm;
+                meta.total_score -= input.score;
+                meta.measured -= 1;
+            }
+        }
+        drop(inner);
+        self.insert(p, Duration::ZERO, score).await
+    }
+
+    /// Energy of corpus prog `p` under `schedule`, counting this selection of it.
+    pub fn energy_of(&self, p: &Prog, schedule: PowerSchedule) -> usize {
+        let mut meta = self.meta.lock().unwrap();
+        let (avg_tm, avg_score) = match meta.measured {
+            0 => (Duration::default(), 0.0),
+            n => (meta.total_tm / n as u32, meta.total_score as f64 / n as f64),
+        };
+        let input = meta.inputs.entry(key_of(p)).or_default();
+        let e = energy(schedule, input, avg_tm, avg_score);
+        input.was_mutated += 1;
+        meta.selections += 1;
+        e
+    }
+
+    /// Credit corpus prog `key` with a prog its mutation added to corpus.
+    pub fn gain(&self, key: u64) {
+        let mut meta = self.meta.lock().unwrap();
+        if let Some(input) = meta.inputs.get_mut(&key) {
+            input.age = 0;
+            input.gained = true;
+        }
+    }
+
+    /// Cull corpus once as many selections as it has progs were made since last culling:
+    /// progs credited with a gain since then get age 0, others age by one. Return number
+    /// of progs at least `retire_age` old then, which are retired.
+    pub fn cull(&self, retire_age: usize) -> Option<usize> {
+        let mut meta = self.meta.lock().unwrap();
+        if meta.selections < meta.inputs.len() {
+            return None;
+        }
+        meta.selections = 0;
+        let mut retired = 0;
+        for input in meta.inputs.values_mut() {
+            if input.gained {
+                input.gained = false;
+                input.age = 0;
+            } else {
+                input.age += 1;
+            }
+            if input.age >= retire_age {
+                retired += 1;
+            }
+        }
+        Some(retired)
+    }
+
+    /// Selection weight of corpus prog `p`, 1.0 until it's `retire_age` old, then halved
+    /// with each culling down to `MIN_WEIGHT`. Retired progs stay in corpus, so their
+    /// coverage is still accounted and mutations of other progs may still splice them.
+    pub fn weight_of(&self, p: &Prog, retire_age: usize) -> f64 {
+        let meta = self.meta.lock().unwrap();
+        let age = meta.inputs.get(&key_of(p)).map_or(0, |i| i.age);
+        if age < retire_age {
+            1.0
+        } else {
+            0.5f64.powi((age + 1 - retire_age) as i32).max(MIN_WEIGHT)
+        }
+    }
+
+    /// Count a reboot without kernel crash caused by a mutation of corpus prog `key`, and
+    /// quarantine the prog once it caused `threshold` of them, so that it's never selected
+    /// again. Return the quarantined prog. Last prog of corpus is kept.
+    pub async fn false_reboot(&self, key: u64, threshold: usize) -> Option<Prog> {
+        {
+            let mut meta = self.meta.lock().unwrap();
+            let reboots = meta.reboots.entry(key).or_default();
+            *reboots += 1;
+            if *reboots < threshold {
+                return None;
+            }
+        }
+        let p = {
+            let mut inner = self.inner.lock().await;
+            if inner.len() == 1 {
+                return None;
+            }
+            let p = inner.iter().find(|p| key_of(p) == key)?.clone();
+            inner.remove(&p);
+            p
+        };
+        let mut meta = self.meta.lock().unwrap();
+        meta.reboots.remove(&key);
+        meta.reasons.remove(&key);
+        meta.quarantined.push(p.clone());
+        Some(p)
+    }
+
+    /// Retain blocks each prog added from now on first reached, so `explain` can tell why
+    /// it's kept. Costs memory of their PCs.
+    pub fn retain_reasons(&self) {
+        self.meta.lock().unwrap().explain = true;
+    }
+
+    pub fn retains_reasons(&self) -> bool {
+        self.meta.lock().unwrap().explain
+    }
+
+    /// Record PCs of blocks `pcs` corpus prog `key` first reached, if reasons are retained.
+    pub fn add_reason(&self, key: u64, pcs: Vec<usize>) {
+        let mut meta = self.meta.lock().unwrap();
+        if meta.explain {
+            meta.reasons.insert(key, pcs.into_boxed_slice());
+        }
+    }
+
+    /// Why corpus prog `p` is kept: blocks it first reached, as function and offset if
+    /// `symbols` is given, and whether culling with `retire_age` retired it since. None if
+    /// no reason of `p` was retained.
+    pub fn explain(
+        &self,
+        p: &Prog,
+        retire_age: usize,
+        symbols: Option<&SymbolTable>,
+    ) -> Option<String> {
+        const SHOWN: usize = 8;
+        let meta = self.meta.lock().unwrap();
+        let key = key_of(p);
+        let pcs = meta.reasons.get(&key)?;
+        let mut reached = pcs
+            .iter()
+            .take(SHOWN)
+            .map(|pc| match symbols.and_then(|s| s.resolve(*pc as u64)) {
+                Some((f, off)) => format!("{}+{:#x}", f, off),
+                None => format!("{:#x}", pc),
+            })
+            .collect::<Vec<_>>();
+        if pcs.len() > SHOWN {
+            reached.push(format!("{} more", pcs.len() - SHOWN));
+        }
+        let mut ret = format!("kept because it first reached {}", reached.join(", "));
+        let age = meta.inputs.get(&key).map_or(0, |i| i.age);
+        if retire_age != 0 {
+            if age >= retire_age {
+                ret += &format!("; retired, no gain for {} culling(s)", age);
+            } else {
+                ret += &format!("; active, no gain for {} of {} culling(s)", age, retire_age);
+            }
+        }
+        Some(ret)
+    }
+
+    /// Progs quarantined so far, oldest first.
+    pub fn quarantined(&self) -> Vec<Prog> {
+        self.meta.lock().unwrap().quarantined.clone()
+    }
+
+    pub fn quarantined_len(&self) -> usize {
+        self.meta.lock().unwrap().quarantined.len()
     }
 
     pub async fn len(&self) -> usize {
@@ -38,11 +266,164 @@ impl Corpus {
         bincode::serialize(&progs)
     }
 
+    /// Corpus of `n` progs of `len` calls, with execution time and score picked by `rng`
+    /// as if each was measured when added.
+    #[cfg(feature = "bench")]
+    pub fn synthetic<R: rand::Rng>(t: &Target, n: usize, len: usize, rng: &mut R) -> Self {
+        let mut inner = HashSet::with_capacity(n);
+        let mut meta = Metas::default();
+        while inner.len() < n {
+            let p = Prog::synthetic(t, len, rng);
+            let exec_tm = Duration::from_micros(rng.gen_range(500, 50_000));
+            let score = rng.gen_range(10, 3000);
+            if meta
+                .inputs
+                .insert(
+                    key_of(&p),
+                    InputMeta {
+                        exec_tm,
+                        score,
+                        ..Default::default()
+                    },
+                )
+                .is_none()
+            {
+                meta.total_tm += exec_tm;
+                meta.total_score += score;
+                meta.measured += 1;
+                inner.insert(p);
+            }
+        }
+        Self {
+            inner: Mutex::new(inner),
+            meta: std::sync::Mutex::new(meta),
+        }
+    }
+
     pub fn load(c: &[u8]) -> bincode::Result<Self> {
         let mut progs: Vec<Prog> = bincode::deserialize(c)?;
         progs.shrink_to_fit();
         Ok(Self {
             inner: Mutex::new(HashSet::from_iter(progs)),
+            meta: Default::default(),
         })
     }
 }
+
+/// Key of prog in energy inputs and reboot counts.
+pub fn key_of(p: &Prog) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    p.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::prog::Call;
+
+    #[test]
+    fn quarantine_after_false_reboots() {
+        let prog = |fid| {
+            let mut p = Prog::new(0);
+            p.add_call(Call::new(fid));
+            p
+        };
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let corpus = Corpus::default();
+            for fid in 0..2 {
+                corpus.insert(prog(fid), Duration::default(), 0).await;
+            }
+            let key = key_of(&prog(0));
+            assert_eq!(corpus.false_reboot(key, 2).await, None);
+            assert_eq!(corpus.false_reboot(key, 2).await, Some(prog(0)));
+            assert_eq!(corpus.len().await, 1);
+            assert_eq!(corpus.quarantined(), vec![prog(0)]);
+            // last prog is kept
+            let key = key_of(&prog(1));
+            assert_eq!(corpus.false_reboot(key, 1).await, None);
+            assert_eq!(corpus.len().await, 1);
+        });
+    }
+
+    #[test]
+    fn retire_stale_progs() {
+        let prog = |fid| {
+            let mut p = Prog::new(0);
+            p.add_call(Call::new(fid));
+            p
+        };
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let corpus = Corpus::default();
+            for fid in 0..2 {
+                corpus.insert(prog(fid), Duration::default(), 0).await;
+            }
+            for round in 1..=3 {
+                corpus.energy_of(&prog(0), PowerSchedule::Fixed);
+                assert_eq!(corpus.cull(2), None);
+                corpus.energy_of(&prog(1), PowerSchedule::Fixed);
+                corpus.gain(key_of(&prog(1)));
+                assert_eq!(corpus.cull(2), Some(if round < 2 { 0 } else { 1 }));
+            }
+            assert_eq!(corpus.weight_of(&prog(1), 2), 1.0);
+            assert_eq!(corpus.weight_of(&prog(0), 2), 0.25);
+        });
+    }
+
+    #[test]
+    fn explain_kept_progs() {
+        let prog = |fid| {
+            let mut p = Prog::new(0);
+            p.add_call(Call::new(fid));
+            p
+        };
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let corpus = Corpus::default();
+            corpus.insert(prog(0), Duration::default(), 0).await;
+            corpus.add_reason(key_of(&prog(0)), vec![0xffff_ffff_8100_0010]);
+            assert_eq!(corpus.explain(&prog(0), 0, None), None);
+
+            corpus.retain_reasons();
+            corpus.insert(prog(1), Duration::default(), 0).await;
+            let pcs = vec![0xffff_ffff_8100_0010, 0xffff_ffff_8100_0048];
+            corpus.add_reason(key_of(&prog(1)), pcs);
+            let map = "ffffffff81000000 T _stext\nffffffff81000040 T foo\nffffffff81000100 T _etext\n";
+            let symbols = SymbolTable::parse(map).unwrap();
+            assert_eq!(
+                corpus.explain(&prog(1), 2, Some(&symbols)).unwrap(),
+                "kept because it first reached _stext+0x10, foo+0x8; active, no gain for 0 of 2 culling(s)"
+            );
+            assert_eq!(
+                corpus.explain(&prog(1), 0, None).unwrap(),
+                "kept because it first reached 0xffffffff81000010, 0xffffffff81000048"
+            );
+        });
+    }
+
+    #[test]
+    fn capped_corpus_of_blackbox() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let corpus = Corpus::default();
+            for fid in 0..10 {
+                let mut p = Prog::new(0);
+                for _ in 0..=fid % 3 {
+                    p.add_call(Call::new(fid));
+                }
+                assert!(corpus.insert_capped(p.clone(), 4).await);
+                assert!(!corpus.insert_capped(p, 4).await);
+            }
+            assert_eq!(corpus.len().await, 4);
+            let meta = corpus.meta.lock().unwrap();
+            assert_eq!(meta.measured, 4);
+            let lens = corpus.inner.try_lock().unwrap();
+            assert_eq!(
+                meta.total_score,
+                lens.iter().map(|p| p.len()).sum::<usize>()
+            );
+        });
+    }
+}
@@ -0,0 +1,132 @@
+//! Raw PC dumps of coverage for syz-cover.
+//!
+//! After every `interval` coverage grew in and at exit, PCs of all blocks covered so far
+//! are written to `coverage/rawcover` of run dir, a hex PC per line, the format syz-cover
+//! takes to render line coverage of kernel source, e.g.
+//! `syz-cover -kernel_obj <dir> coverage/rawcover`.
+//! Blocks are PCs as kcov reports them, healer doesn't transform them, so no address
+//! needs restoring. `coverage/meta.json` notes kernel build dir they belong to, number of
+//! PCs and dump time. Dumps are written to a temp file and renamed, so readers, e.g.
+//! `covset`, never see a partial one.
+use crate::fuzzer::Fuzzer;
+use chrono::prelude::*;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use tokio::fs::{create_dir_all, rename, write};
+use tokio::sync::broadcast;
+use tokio::time::{delay_for, Duration};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CoverDumpConf {
+    /// Minutes between dumps, default 30
+    pub interval: Option<u64>,
+    /// Kernel build dir PCs belong to, noted in metadata, default kernel_obj of symbolize
+    pub kernel_obj: Option<PathBuf>,
+}
+
+impl CoverDumpConf {
+    pub fn check(&self) {
+        if self.interval == Some(0) {
+            eprintln!("Config Error: cover_dump: interval must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Meta<'a> {
+    kernel_obj: Option<&'a Path>,
+    pcs: usize,
+    time: DateTime<Local>,
+}
+
+/// Dump coverage of `fuzzer` every interval until shutdown.
+pub async fn watch(fuzzer: Fuzzer, conf: CoverDumpConf, mut shutdown: broadcast::Receiver<()>) {
+    let interval = Duration::from_secs(conf.interval.unwrap_or(30) * 60);
+    let dump = async {
+        let mut dumped = None;
+        loop {
+            delay_for(interval).await;
+            // epoch before collecting pcs, a merge in between is dumped next time.
+            let epoch = fuzzer.feedback.epoch();
+            if dumped != Some(epoch) {
+                dump(&fuzzer, &conf).await;
+                dumped = Some(epoch);
+            }
+        }
+    };
+    tokio::select! {
+        _ = shutdown.recv() => (),
+        _ = dump => (),
+    }
+}
+
+/// Dump coverage of `fuzzer` to its run dir, failures are only logged.
+pub async fn dump(fuzzer: &Fuzzer, conf: &CoverDumpConf) {
+    let pcs = fuzzer.feedback.pcs().await;
+    let dir = fuzzer.run_dir.join("coverage");
+    match write_dump(&dir, &pcs, conf.kernel_obj.as_deref()).await {
+        Ok(()) => info!("Coverage: dumped {} pc(s) to {}", pcs.len(), dir.display()),
+        Err(e) => warn!("Coverage: fail to dump to {}: {}", dir.display(), e),
+    }
+}
+
+async fn write_dump(dir: &Path, pcs: &[usize], kernel_obj: Option<&Path>) -> std::io::Result<()> {
+    create_dir_all(dir).await?;
+    let mut raw = String::with_capacity(pcs.len() * 19);
+    for pc in pcs {
+        writeln!(raw, "{:#x}", pc).unwrap();
+    }
+    let tmp = dir.join(".rawcover");
+    write(&tmp, raw).await?;
+    rename(&tmp, dir.join("rawcover")).await?;
+    let meta = Meta {
+        kernel_obj,
+        pcs: pcs.len(),
+        time: Local::now(),
+    };
+    write(
+        dir.join("meta.json"),
+        serde_json::to_string_pretty(&meta).unwrap(),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covset;
+    use crate::feedback::{Block, FeedBack};
+    use std::env::temp_dir;
+    use std::process::id;
+
+    #[test]
+    fn dump_raw_pcs() {
+        let dir = temp_dir().join(format!("healer-cover-dump-{}", id()));
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let pcs = rt.block_on(async {
+            let feedback = FeedBack::default();
+            let blocks = [0xffff_ffff_8100_0030, 0xffff_ffff_8100_0010]
+                .iter()
+                .map(|pc| Block::from(*pc))
+                .collect();
+            feedback.merge(blocks, Box::new([])).await;
+            let pcs = feedback.pcs().await;
+            write_dump(&dir, &pcs, Some(Path::new("/linux")))
+                .await
+                .unwrap();
+            pcs
+        });
+        let raw = std::fs::read_to_string(dir.join("rawcover")).unwrap();
+        let meta = std::fs::read_to_string(dir.join("meta.json")).unwrap();
+        let loaded = covset::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(pcs, vec![0xffff_ffff_8100_0010, 0xffff_ffff_8100_0030]);
+        assert_eq!(raw, "0xffffffff81000010\n0xffffffff81000030\n");
+        assert!(meta.contains("\"kernel_obj\": \"/linux\"") && meta.contains("\"pcs\": 2"));
+        assert_eq!(loaded.len(), 2);
+    }
+}
@@ -0,0 +1,174 @@
+//! Set operations on saved coverage, e.g. what campaign B covered that A didn't.
+//!
+//! Coverage is read from files of hex PCs, one per line, the format syz-cover accepts, or
+//! from dirs of such files, which are merged. Analysis is read-only; PCs of the result can
+//! be symbolized with `System.map` of kernel build dir, see `symbols`.
+use crate::symbols::SymbolTable;
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::fs::{read_dir, read_to_string};
+use std::path::Path;
+use std::str::FromStr;
+
+pub type PcSet = BTreeSet<u64>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+    Union,
+    Intersection,
+    /// PCs of first set missing in second
+    Difference,
+}
+
+impl FromStr for SetOp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "union" => Ok(Self::Union),
+            "intersection" => Ok(Self::Intersection),
+            "difference" => Ok(Self::Difference),
+            _ => Err(format!(
+                "unknown set op {}, expect union, intersection or difference",
+                s
+            )),
+        }
+    }
+}
+
+/// Sizes of two coverage sets and of sets derived from them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CovCounts {
+    pub a: usize,
+    pub b: usize,
+    pub union: usize,
+    pub intersection: usize,
+    /// PCs of `a` missing in `b`
+    pub only_a: usize,
+    /// PCs of `b` missing in `a`
+    pub only_b: usize,
+}
+
+pub fn counts(a: &PcSet, b: &PcSet) -> CovCounts {
+    let intersection = a.intersection(b).count();
+    CovCounts {
+        a: a.len(),
+        b: b.len(),
+        union: a.len() + b.len() - intersection,
+        intersection,
+        only_a: a.len() - intersection,
+        only_b: b.len() - intersection,
+    }
+}
+
+pub fn apply(op: SetOp, a: &PcSet, b: &PcSet) -> PcSet {
+    match op {
+        SetOp::Union => a.union(b).copied().collect(),
+        SetOp::Intersection => a.intersection(b).copied().collect(),
+        SetOp::Difference => a.difference(b).copied().collect(),
+    }
+}
+
+/// Parse hex PCs, with or without `0x`, one per line.
+pub fn parse(pcs: &str) -> Result<PcSet, String> {
+    pcs.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(|l| {
+            u64::from_str_radix(l.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("bad pc: {}", l))
+        })
+        .collect()
+}
+
+/// Load coverage of file `path`, or merged coverage of files in dir `path`. Json files of
+/// a dir, e.g. metadata, are skipped.
+pub fn load(path: &Path) -> Result<PcSet, String> {
+    let read = |p: &Path| {
+        read_to_string(p)
+            .map_err(|e| format!("fail to read {}: {}", p.display(), e))
+            .and_then(|s| parse(&s).map_err(|e| format!("{}: {}", p.display(), e)))
+    };
+    if !path.is_dir() {
+        return read(path);
+    }
+    let mut pcs = PcSet::new();
+    let entries = read_dir(path).map_err(|e| format!("fail to read {}: {}", path.display(), e))?;
+    for entry in entries {
+        let p = entry.map_err(|e| e.to_string())?.path();
+        if p.is_file() && p.extension() != Some(OsStr::new("json")) {
+            pcs.extend(read(&p)?);
+        }
+    }
+    Ok(pcs)
+}
+
+/// Compare coverage saved at `a` and `b`: counts, then PCs of `op` on them, annotated with
+/// function and offset if `kernel_obj` is given. Entry of `covset` tool.
+pub fn compare(a: &Path, b: &Path, op: SetOp, kernel_obj: Option<&Path>) -> Result<String, String> {
+    let symbols = kernel_obj.map(SymbolTable::load).transpose()?;
+    let (a, b) = (load(a)?, load(b)?);
+    Ok(render(&a, &b, op, symbols.as_ref()))
+}
+
+fn render(a: &PcSet, b: &PcSet, op: SetOp, symbols: Option<&SymbolTable>) -> String {
+    let c = counts(a, b);
+    let mut out = format!(
+        "a {}, b {}, union {}, intersection {}, only a {}, only b {}\n",
+        c.a, c.b, c.union, c.intersection, c.only_a, c.only_b
+    );
+    for pc in apply(op, a, b) {
+        match symbols.and_then(|s| s.resolve(pc)) {
+            Some((f, off)) => writeln!(out, "{:#x} {}+{:#x}", pc, f, off).unwrap(),
+            None => writeln!(out, "{:#x}", pc).unwrap(),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coverage_set_ops() {
+        let a = parse("0xffffffff81000010\n0xffffffff81000020\nffffffff81000030\n").unwrap();
+        let b = parse("0xffffffff81000030\n\n0xffffffff81000040\n").unwrap();
+        assert!(parse("0xzz\n").is_err());
+        assert_eq!(
+            counts(&a, &b),
+            CovCounts {
+                a: 3,
+                b: 2,
+                union: 4,
+                intersection: 1,
+                only_a: 2,
+                only_b: 1,
+            }
+        );
+        assert_eq!(apply(SetOp::Union, &a, &b).len(), 4);
+        assert_eq!(
+            apply(SetOp::Intersection, &a, &b)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![0xffff_ffff_8100_0030]
+        );
+        assert_eq!(
+            apply(SetOp::Difference, &b, &a)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![0xffff_ffff_8100_0040]
+        );
+        assert_eq!("difference".parse(), Ok(SetOp::Difference));
+        assert!("xor".parse::<SetOp>().is_err());
+
+        let map = "ffffffff81000000 T _stext\nffffffff81000038 T foo\nffffffff81000100 T _etext\n";
+        let symbols = SymbolTable::parse(map).unwrap();
+        let out = render(&b, &a, SetOp::Difference, Some(&symbols));
+        assert_eq!(
+            out,
+            "a 2, b 3, union 4, intersection 1, only a 1, only b 2\n0xffffffff81000040 foo+0x8\n"
+        );
+    }
+}
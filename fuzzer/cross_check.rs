@@ -0,0 +1,184 @@
+//! Cross-check coverage of new corpus progs with syzkaller's own tooling.
+//!
+//! A sampled fraction of progs added to corpus is encoded by `core::syz` and executed in
+//! the same guest by syz-execprog with coverage on. Coverage of each call is compared with
+//! what healer's executor reported: a call covering nothing under one of them, or sharing
+//! less than `MIN_OVERLAP` of healer's PCs, is a mismatch. Kernel coverage is noisy, so
+//! single mismatches are only counted, a call mismatching in `PERSISTENT` checks and in most
+//! of its checks is flagged once, as that usually means healer encodes its args wrongly.
+//! Errno isn't compared, healer's executor doesn't report it.
+use core::prog::Prog;
+use core::target::Target;
+use std::collections::{HashMap, HashSet};
+use std::process::exit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Share of healer's PCs of a call syz-execprog must cover too.
+const MIN_OVERLAP: f64 = 0.5;
+/// Mismatches of a call before it's flagged.
+const PERSISTENT: usize = 3;
+
+pub fn check_rate(rate: f64) {
+    if !(0.0..=1.0).contains(&rate) {
+        eprintln!("Config Error: executor: cross_check_rate must be within 0 to 1");
+        exit(exitcode::CONFIG)
+    }
+}
+
+/// Cross-checks of a run, shared by all jobs. Locks are never held across await point.
+#[derive(Debug, Default)]
+pub struct CrossChecks {
+    rate: f64,
+    checked: AtomicUsize,
+    mismatched: AtomicUsize,
+    /// Checks and mismatches of each call, by name
+    calls: Mutex<HashMap<String, (usize, usize)>>,
+    flagged: Mutex<HashSet<String>>,
+}
+
+impl CrossChecks {
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            ..Default::default()
+        }
+    }
+
+    /// Whether next new corpus prog is cross-checked.
+    pub fn sampled(&self) -> bool {
+        self.rate > 0.0 && rand::random::<f64>() < self.rate
+    }
+
+    /// Record check of `p` whose calls covered `healer` under healer and `syz` under
+    /// syz-execprog, return names of calls flagged by it.
+    pub fn record(
+        &self,
+        p: &Prog,
+        t: &Target,
+        healer: &[Vec<usize>],
+        syz: &[Vec<usize>],
+    ) -> Vec<String> {
+        let mismatches = mismatches(healer, syz);
+        self.checked.fetch_add(1, Ordering::Relaxed);
+        if !mismatches.is_empty() {
+            self.mismatched.fetch_add(1, Ordering::Relaxed);
+        }
+        let mut calls = self.calls.lock().unwrap();
+        let mut flagged = self.flagged.lock().unwrap();
+        let mut newly = Vec::new();
+        for (i, c) in p.calls.iter().enumerate().take(healer.len()) {
+            let name = &t.fn_of(c.fid).dec_name;
+            let (checks, bad) = calls.entry(name.clone()).or_default();
+            *checks += 1;
+            if mismatches.contains(&i) {
+                *bad += 1;
+                if *bad >= PERSISTENT && *bad * 2 > *checks && flagged.insert(name.clone()) {
+                    newly.push(name.clone());
+                }
+            }
+        }
+        newly
+    }
+
+    /// Progs checked and those with any mismatch.
+    pub fn counts(&self) -> (usize, usize) {
+        (
+            self.checked.load(Ordering::Relaxed),
+            self.mismatched.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Indices of calls whose coverage under healer, `healer`, and under syz-execprog, `syz`,
+/// disagree. Calls syz-execprog didn't report cover nothing. PCs are compared by their low
+/// 32 bits, as executors may truncate them.
+pub fn mismatches(healer: &[Vec<usize>], syz: &[Vec<usize>]) -> Vec<usize> {
+    healer
+        .iter()
+        .enumerate()
+        .filter(|(i, pcs)| {
+            let syz = syz
+                .get(*i)
+                .map(|s| s.iter().map(|pc| *pc as u32).collect::<HashSet<_>>())
+                .unwrap_or_default();
+            if pcs.is_empty() || syz.is_empty() {
+                return pcs.is_empty() != syz.is_empty();
+            }
+            let pcs = pcs.iter().map(|pc| *pc as u32).collect::<HashSet<_>>();
+            let shared = pcs.iter().filter(|pc| syz.contains(*pc)).count();
+            (shared as f64) < pcs.len() as f64 * MIN_OVERLAP
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Coverage of each call out of output of `execprog_cmd`, `== <call>` then a hex PC per
+/// line.
+pub fn parse_cover(out: &str) -> Result<Vec<Vec<usize>>, String> {
+    let mut cover: Vec<Vec<usize>> = Vec::new();
+    let mut call = None;
+    for line in out.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(i) = line.strip_prefix("== ") {
+            let i = i
+                .parse::<usize>()
+                .map_err(|_| format!("bad cover file of call {}", i))?;
+            if cover.len() <= i {
+                cover.resize(i + 1, Vec::new());
+            }
+            call = Some(i);
+            continue;
+        }
+        let i = call.ok_or_else(|| format!("unexpected output: {}", line))?;
+        let pc = usize::from_str_radix(line.trim_start_matches("0x"), 16)
+            .map_err(|_| format!("bad pc: {}", line))?;
+        cover[i].push(pc);
+    }
+    Ok(cover)
+}
+
+/// Shell command writing `text` to guest and executing it with syz-execprog at `execprog`
+/// and syz-executor at `executor`, printing coverage for `parse_cover`.
+pub fn execprog_cmd(text: &str, execprog: &str, executor: &str) -> String {
+    const DIR: &str = "/tmp/healer-cross-check";
+    format!(
+        "mkdir -p {dir} && rm -f {dir}/cover.* && cat > {dir}/prog <<'HEALER_PROG_EOF'\n\
+         {text}HEALER_PROG_EOF\n\
+         {execprog} -executor={executor} -cover -coverfile={dir}/cover -repeat=1 -procs=1 {dir}/prog >/dev/null 2>&1\n\
+         for f in {dir}/cover.*; do [ -e \"$f\" ] && echo \"== ${{f##*.}}\" && cat \"$f\"; done; true",
+        dir = DIR,
+        text = text,
+        execprog = execprog,
+        executor = executor
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cross_check_coverage() {
+        let out = "== 0\n0xffffffff81000010\n0xffffffff81000020\n== 2\n0xffffffff81000030\n";
+        let syz = parse_cover(out).unwrap();
+        assert_eq!(
+            syz,
+            vec![
+                vec![0xffff_ffff_8100_0010, 0xffff_ffff_8100_0020],
+                vec![],
+                vec![0xffff_ffff_8100_0030]
+            ]
+        );
+        assert!(parse_cover("0x10\n").is_err());
+
+        let healer = vec![
+            vec![0xffff_ffff_8100_0010, 0xffff_ffff_8100_0040],
+            vec![],
+            vec![0xffff_ffff_8100_0050, 0xffff_ffff_8100_0060],
+            vec![0xffff_ffff_8100_0070],
+        ];
+        // half of call 0 is shared, call 1 covers nothing under both, call 2 shares
+        // nothing and call 3 wasn't reported by syz-execprog.
+        assert_eq!(mismatches(&healer, &syz), vec![2, 3]);
+    }
+}
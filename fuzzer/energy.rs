@@ -0,0 +1,111 @@
+//! Energy of corpus progs, mutations made of a selected prog before selecting another.
+//!
+//! Like power schedules of AFLFast, energy grows with how fast a prog executes and how
+//! much it covers, both relative to corpus average, and with `fast`, shrinks with how
+//! often it has been selected already. `fixed` gives every prog one mutation, which is
+//! how progs were always mutated. Progs whose execution wasn't measured, e.g. of loaded
+//! corpus, are treated as average ones.
+use std::time::Duration;
+
+/// Mutations of a prog of average speed and coverage.
+const BASE_ENERGY: f64 = 8.0;
+/// Mutations of a prog at most.
+const MAX_ENERGY: f64 = 64.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerSchedule {
+    /// One mutation per selection
+    #[default]
+    Fixed,
+    /// Little energy for each prog, effort spread over more of corpus
+    Explore,
+    /// Much energy for fast, high-coverage progs
+    Exploit,
+    /// Like exploit, divided by times prog has been selected
+    Fast,
+}
+
+/// What energy of a corpus prog is computed from.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputMeta {
+    /// Execution time when prog was added, zero if not measured
+    pub exec_tm: Duration,
+    /// Branches covered by prog when it was added, zero if not measured
+    pub score: usize,
+    /// Times prog has been selected for mutation
+    pub was_mutated: usize,
+    /// Cullings since mutations of prog last added a prog to corpus, see `Corpus::cull`
+    pub age: usize,
+    /// Whether mutations of prog added a prog to corpus since last culling
+    pub gained: bool,
+}
+
+/// Energy of `input` under `schedule`, given average execution time and score of corpus.
+pub fn energy(
+    schedule: PowerSchedule,
+    input: &InputMeta,
+    avg_tm: Duration,
+    avg_score: f64,
+) -> usize {
+    if schedule == PowerSchedule::Fixed {
+        return 1;
+    }
+    let speed = if input.exec_tm.as_nanos() == 0 || avg_tm.as_nanos() == 0 {
+        1.0
+    } else {
+        avg_tm.as_secs_f64() / input.exec_tm.as_secs_f64()
+    };
+    let coverage = if input.score == 0 || avg_score == 0.0 {
+        1.0
+    } else {
+        input.score as f64 / avg_score
+    };
+    let perf = speed.clamp(0.25, 3.0) * coverage.clamp(0.25, 3.0);
+    let energy = match schedule {
+        PowerSchedule::Fixed => unreachable!(),
+        PowerSchedule::Explore => perf * BASE_ENERGY / 4.0,
+        PowerSchedule::Exploit => perf * BASE_ENERGY * 2.0,
+        PowerSchedule::Fast => perf * BASE_ENERGY * 2.0 / (1 + input.was_mutated) as f64,
+    };
+    energy.clamp(1.0, MAX_ENERGY).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn energy_of_inputs() {
+        let avg_tm = Duration::from_millis(20);
+        let input = |ms, score, was_mutated| InputMeta {
+            exec_tm: Duration::from_millis(ms),
+            score,
+            was_mutated,
+            ..Default::default()
+        };
+        let fast_rich = input(5, 300, 0);
+        let slow_poor = input(200, 10, 0);
+        for schedule in &[
+            PowerSchedule::Explore,
+            PowerSchedule::Exploit,
+            PowerSchedule::Fast,
+        ] {
+            let e = |i| energy(*schedule, &i, avg_tm, 100.0);
+            assert!(e(fast_rich) > e(slow_poor), "{:?}", schedule);
+            assert!(e(slow_poor) >= 1);
+            assert!(e(fast_rich) <= MAX_ENERGY as usize);
+        }
+        assert_eq!(energy(PowerSchedule::Fixed, &fast_rich, avg_tm, 100.0), 1);
+        assert_eq!(
+            energy(PowerSchedule::Exploit, &InputMeta::default(), avg_tm, 100.0),
+            16
+        );
+        let e = |n| energy(PowerSchedule::Fast, &input(20, 100, n), avg_tm, 100.0);
+        assert!(e(0) > e(3));
+        assert!(
+            energy(PowerSchedule::Explore, &fast_rich, avg_tm, 100.0)
+                < energy(PowerSchedule::Exploit, &fast_rich, avg_tm, 100.0)
+        );
+    }
+}
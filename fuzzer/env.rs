@@ -0,0 +1,160 @@
+//! Override config with environment variables.
+//!
+//! Variables are applied after config file is loaded and before command line flags,
+//! so value from environment overrides config file. Supported variables:
+//! - `HEALER_VM_NUM`: `vm_num`
+//! - `HEALER_TRIM`: `trim`
+//! - `HEALER_GEN_MUT_RATIO`: `fuzzer.gen_mut_ratio`
+//! - `HEALER_PROG_MAX_LEN`: `fuzzer.prog_max_len`
+//! - `HEALER_PROG_MIN_LEN`: `fuzzer.prog_min_len`
+//! - `HEALER_SAMPLE_INTERVAL`: `sampler.sample_interval`
+//! - `HEALER_REPORT_INTERVAL`: `sampler.report_interval`
+//!
+//! Unknown variables with `HEALER_` prefix are warned.
+use crate::fuzzer::FuzzerConf;
+use crate::stats::SamplerConf;
+use crate::Config;
+use std::env;
+use std::fmt::Display;
+use std::process::exit;
+use std::str::FromStr;
+
+const PREFIX: &str = "HEALER_";
+/// Variables with healer prefix that are not config overrides.
+const RESERVED: [&str; 2] = ["HEALER_FUZZER_PID", "HEALER_MAIL_PASSWD"];
+
+pub fn apply_env(cfg: &mut Config) {
+    if let Err(e) = apply_vars(cfg, env::vars()) {
+        eprintln!("Config Error: {}", e);
+        exit(exitcode::CONFIG)
+    }
+}
+
+/// Apply overrides among `vars` to `cfg`, in order of names.
+fn apply_vars<I: IntoIterator<Item = (String, String)>>(
+    cfg: &mut Config,
+    vars: I,
+) -> Result<(), String> {
+    let mut vars = vars
+        .into_iter()
+        .filter(|(k, _)| k.starts_with(PREFIX) && !RESERVED.contains(&k.as_str()))
+        .collect::<Vec<_>>();
+    vars.sort();
+
+    for (k, v) in vars {
+        match &k[PREFIX.len()..] {
+            "VM_NUM" => cfg.vm_num = parse(&k, &v)?,
+            "TRIM" => cfg.trim = Some(parse(&k, &v)?),
+            "GEN_MUT_RATIO" => fuzzer_conf(cfg).gen_mut_ratio = Some(parse(&k, &v)?),
+            "PROG_MAX_LEN" => fuzzer_conf(cfg).prog_max_len = Some(parse(&k, &v)?),
+            "PROG_MIN_LEN" => fuzzer_conf(cfg).prog_min_len = Some(parse(&k, &v)?),
+            "SAMPLE_INTERVAL" => sampler_conf(cfg).sample_interval = parse(&k, &v)?,
+            "REPORT_INTERVAL" => sampler_conf(cfg).report_interval = parse(&k, &v)?,
+            _ => {
+                eprintln!("Warning: unknown environment variable {}, ignored", k);
+                continue;
+            }
+        }
+        eprintln!("Config: {}={} applied", k, v);
+    }
+    Ok(())
+}
+
+fn parse<T>(k: &str, v: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    v.parse()
+        .map_err(|e| format!("invalid value of {}, `{}`: {}", k, v, e))
+}
+
+fn fuzzer_conf(cfg: &mut Config) -> &mut FuzzerConf {
+    cfg.fuzzer.get_or_insert_with(Default::default)
+}
+
+fn sampler_conf(cfg: &mut Config) -> &mut SamplerConf {
+    cfg.sampler.get_or_insert_with(Default::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConfigBuilder;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn builder() -> ConfigBuilder {
+        ConfigBuilder::new(
+            "./sys",
+            "./executor",
+            "./stretch.img",
+            "./bzImage",
+            "./stretch.id_rsa",
+        )
+        .vm_num(2)
+    }
+
+    #[test]
+    fn env_overrides() {
+        let mut cfg = builder().conf;
+        let env = vars(&[
+            ("HEALER_VM_NUM", "4"),
+            ("HEALER_TRIM", "false"),
+            ("HEALER_GEN_MUT_RATIO", "10"),
+            ("HEALER_PROG_MIN_LEN", "2"),
+            ("HEALER_PROG_MAX_LEN", "32"),
+            ("HEALER_SAMPLE_INTERVAL", "30"),
+            ("HEALER_REPORT_INTERVAL", "120"),
+            ("HEALER_FUZZER_PID", "1"),
+            ("HEALER_MAIL_PASSWD", "s3cret"),
+            ("HEALER_UNKNOWN", "1"),
+            ("PATH", "/bin"),
+        ]);
+        assert_eq!(apply_vars(&mut cfg, env), Ok(()));
+        assert_eq!(cfg.vm_num, 4);
+        assert_eq!(cfg.trim, Some(false));
+        let fuzzer = cfg.fuzzer.as_ref().unwrap();
+        assert_eq!(fuzzer.gen_mut_ratio, Some(10));
+        assert_eq!(fuzzer.prog_min_len, Some(2));
+        assert_eq!(fuzzer.prog_max_len, Some(32));
+        let sampler = cfg.sampler.as_ref().unwrap();
+        assert_eq!(sampler.sample_interval, 30);
+        assert_eq!(sampler.report_interval, 120);
+    }
+
+    #[test]
+    fn env_invalid_values() {
+        for (k, v) in &[
+            ("HEALER_VM_NUM", "two"),
+            ("HEALER_VM_NUM", "-1"),
+            ("HEALER_TRIM", "yes"),
+            ("HEALER_PROG_MAX_LEN", ""),
+        ] {
+            let mut cfg = builder().conf;
+            let e = apply_vars(&mut cfg, vars(&[(k, v)])).unwrap_err();
+            assert!(e.contains(k), "{}", e);
+            assert_eq!(cfg.vm_num, 2);
+        }
+    }
+
+    #[test]
+    fn env_precedence() {
+        // environment overrides config file.
+        let mut cfg = builder().trim(true).conf;
+        apply_vars(
+            &mut cfg,
+            vars(&[("HEALER_VM_NUM", "4"), ("HEALER_TRIM", "false")]),
+        )
+        .unwrap();
+        assert_eq!(cfg.vm_num, 4);
+        // flags are applied after, through builder, and override environment.
+        let cfg = ConfigBuilder::from(cfg).vm_num(8).conf;
+        assert_eq!(cfg.vm_num, 8);
+        assert_eq!(cfg.trim, Some(false));
+    }
+}
@@ -1,34 +1,72 @@
+use crate::call_timeout::CallTimeouts;
+use crate::cross_check;
+use crate::fault::{Fault, FaultConf};
 use crate::guest;
-use crate::guest::{Crash, Guest};
+pub use crate::guest::{BootError, BootStage, Crash};
+use crate::guest::{Guest, GuestConf};
+use crate::report::is_kernel_report;
 use crate::utils::cli::{App, Arg, OptVal};
 use crate::utils::free_ipv4_port;
+use crate::work_dir;
 use crate::Config;
 use core::c::to_prog;
 use core::prog::Prog;
 use core::target::Target;
-use executor::transfer::{async_recv_result, async_send};
-use executor::{ExecResult, Reason};
-use std::env::temp_dir;
+use executor::transfer::{self, async_recv_hello, async_recv_report, async_send};
+use executor::{ExecReport, ExecReqRef, ExecResult, Reason};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
 use std::path::PathBuf;
+use std::pin::Pin;
 use std::process::exit;
+use std::sync::Arc;
 use tokio::fs::write;
 use tokio::io::AsyncReadExt;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::process::Child;
 use tokio::sync::oneshot;
-use tokio::time::{delay_for, timeout, Duration};
+use tokio::time::{delay_for, timeout, Duration, Instant};
 
 // config for executor
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct ExecutorConf {
     pub path: PathBuf,
     pub host_ip: Option<String>,
     pub concurrency: bool,
     pub memleak_check: bool,
     pub script_mode: bool,
+    /// Calls of a prog at most, longer progs are truncated before execution, default of
+    /// target, unlimited if it has none
+    pub max_calls: Option<usize>,
+    /// Share of new corpus progs cross-checked with syz-execprog, see `cross_check`,
+    /// default 0, which disables it
+    pub cross_check_rate: Option<f64>,
+    /// Dir of syzkaller's guest binaries, syz-execprog and syz-executor, for cross-checks
+    pub syz_bin: Option<PathBuf>,
+    /// Deterministic fault injection, see `fault`. A table, so it's kept after every scalar
+    /// for toml serialization of `Config::resolved`.
+    pub fault_injection: Option<FaultConf>,
 }
 
 impl ExecutorConf {
+    /// Executor at `path` of `guest` with its defaults.
+    pub fn new(guest: &GuestConf, path: PathBuf) -> Self {
+        let mut executor = toml::value::Table::new();
+        executor.insert(
+            String::from("path"),
+            toml::Value::String(path.display().to_string()),
+        );
+        let mut executor = toml::Value::Table(executor);
+        if let Some(defaults) = guest.defaults().and_then(|d| d.get("executor").cloned()) {
+            executor = guest::merge(defaults, executor);
+        }
+        executor
+            .try_into()
+            .unwrap_or_else(|e| exits!(exitcode::CONFIG, "Config Error: executor: {}", e))
+    }
+
     pub fn check(&self) {
         if !self.path.is_file() {
             eprintln!(
@@ -50,10 +88,80 @@ impl ExecutorConf {
                 exit(exitcode::CONFIG)
             }
         }
+
+        if self.max_calls == Some(0) {
+            eprintln!("Config Error: executor: max_calls must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+
+        if let Some(rate) = self.cross_check_rate.filter(|r| *r != 0.0) {
+            cross_check::check_rate(rate);
+            if self.script_mode {
+                eprintln!("Config Error: cross_check_rate is not supported in script mode");
+                exit(exitcode::CONFIG)
+            }
+            let bins = ["syz-execprog", "syz-executor"];
+            if !self
+                .syz_bin
+                .as_ref()
+                .is_some_and(|dir| bins.iter().all(|b| dir.join(b).is_file()))
+            {
+                eprintln!(
+                    "Config Error: cross_check_rate needs syz_bin, a dir of {}",
+                    bins.join(" and ")
+                );
+                exit(exitcode::CONFIG)
+            }
+        }
+
+        if let Some(fault) = self.fault_injection.as_ref() {
+            if self.script_mode {
+                eprintln!("Config Error: fault_injection is not supported in script mode");
+                exit(exitcode::CONFIG)
+            }
+            fault.check();
+        }
     }
 }
 
-pub struct Executor {
+/// Future of an `Executor` method, boxed as traits can't have async fns.
+pub type ExecFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Execution backend of a fuzzing job. `QemuExecutor` runs progs in a guest, tests use
+/// `mock::MockExecutor` with scripted results instead.
+pub trait Executor: Send {
+    /// Execute `p`, returning coverage of each executed call.
+    fn exec<'a>(
+        &'a mut self,
+        p: &'a Prog,
+        t: &'a Target,
+    ) -> ExecFuture<'a, Result<ExecResult, ExecError>>;
+
+    /// Restart guest during fuzzing, e.g. after crash. Guest booted fine before, so
+    /// failing to boot it again is unrecoverable and fuzzer exits.
+    fn restart(&mut self) -> ExecFuture<'_, ()>;
+
+    /// Times guest has been booted, by `start`, `restart` or reconnecting after failures.
+    /// Fuzzer learns guest went down without a crash from this.
+    fn boots(&self) -> usize;
+
+    /// Inject `fault` in next execution, ignored by executors without fault injection.
+    fn set_fault(&mut self, fault: Option<Fault>);
+
+    /// Whether fault of last execution was injected.
+    fn fault_injected(&self) -> bool;
+
+    /// Execute syzkaller prog `text` with syz-execprog, returning coverage of each call,
+    /// see `cross_check`. Executors without syz-execprog fail.
+    fn execprog<'a>(
+        &'a mut self,
+        _text: &'a str,
+    ) -> ExecFuture<'a, Result<Vec<Vec<usize>>, String>> {
+        Box::pin(async { Err(String::from("syz-execprog is not supported")) })
+    }
+}
+
+pub struct QemuExecutor {
     inner: ExecutorImpl,
 }
 
@@ -62,60 +170,325 @@ enum ExecutorImpl {
     Scripy(ScriptExecutor),
 }
 
-impl Executor {
-    pub fn new(cfg: &Config) -> Self {
+impl QemuExecutor {
+    /// Create executor of fuzzing job `job`, job id is used to derive resources of its guest.
+    pub fn new(cfg: &Config, job: usize) -> Self {
         let inner = if cfg.executor.script_mode {
-            ExecutorImpl::Scripy(ScriptExecutor::new(cfg))
+            ExecutorImpl::Scripy(ScriptExecutor::new(cfg, job))
         } else {
-            ExecutorImpl::Linux(LinuxExecutor::new(cfg))
+            ExecutorImpl::Linux(LinuxExecutor::new(cfg, job))
         };
         Self { inner }
     }
 
-    pub async fn start(&mut self) {
+    /// Boot guest and start executor in it.
+    pub async fn start(&mut self) -> Result<(), BootError> {
         match self.inner {
             ExecutorImpl::Linux(ref mut e) => e.start().await,
             ExecutorImpl::Scripy(ref mut e) => e.start().await,
         }
     }
 
-    pub async fn exec(&mut self, p: &Prog, t: &Target) -> Result<ExecResult, Option<Crash>> {
+    /// Run shell command `cmd` on guest of started executor, return its stdout.
+    pub async fn query_guest(&self, cmd: &str) -> Result<String, String> {
         match self.inner {
-            ExecutorImpl::Linux(ref mut e) => e.exec(p).await,
-            ExecutorImpl::Scripy(ref mut e) => e.exec(p, t).await,
+            ExecutorImpl::Linux(ref e) => e.guest.query(cmd).await,
+            ExecutorImpl::Scripy(ref e) => e.guest.query(cmd).await,
+        }
+    }
+
+    /// Share learned per-call timeouts with executor, only used by linux executor.
+    pub fn set_call_timeouts(&mut self, call_timeouts: Arc<CallTimeouts>) {
+        if let ExecutorImpl::Linux(ref mut e) = self.inner {
+            e.call_timeouts = Some(call_timeouts);
+        }
+    }
+
+    /// Run single prog `p` outside of fuzzing loop, for custom harnesses and tests.
+    /// Executor must be started. Guest is restarted after a crash or if `timeout`
+    /// elapsed, so the executor is ready for next run either way.
+    pub async fn run(
+        &mut self,
+        p: &Prog,
+        t: &Target,
+        timeout: Duration,
+    ) -> Result<RunOutput, RunError> {
+        let now = Instant::now();
+        let ret = tokio::time::timeout(timeout, async {
+            match self.inner {
+                ExecutorImpl::Linux(ref mut e) => e.exec_report(p).await,
+                ExecutorImpl::Scripy(ref mut e) => e.exec(p, t).await.map(|result| ExecReport {
+                    result,
+                    call_times: Vec::new(),
+                    fault_injected: false,
+                    timed_out: false,
+                }),
+            }
+        })
+        .await;
+        let elapsed = now.elapsed();
+
+        match ret {
+            Ok(Ok(ExecReport {
+                result: ExecResult::Ok(covers),
+                call_times,
+                ..
+            })) => {
+                let calls = covers
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, covers)| CallOutput {
+                        covers,
+                        time: call_times
+                            .get(i)
+                            .map(|ms| Duration::from_millis(u64::from(*ms))),
+                    })
+                    .collect();
+                Ok(RunOutput { calls, elapsed })
+            }
+            Ok(Ok(ExecReport {
+                result: ExecResult::Failed(reason),
+                ..
+            })) => Err(RunError::Failed(reason)),
+            Ok(Err(ExecError::KernelCrash(crash))) => {
+                self.restart().await;
+                Err(RunError::Crashed(crash))
+            }
+            Ok(Err(ExecError::VmDied(_))) => {
+                self.restart().await;
+                Err(RunError::Crashed(Crash::default()))
+            }
+            Ok(Err(e)) => Err(RunError::Failed(Reason(e.to_string()))),
+            Err(_) => {
+                self.restart().await;
+                Err(RunError::Timeout(timeout))
+            }
         }
     }
 }
 
+impl Executor for QemuExecutor {
+    fn exec<'a>(
+        &'a mut self,
+        p: &'a Prog,
+        t: &'a Target,
+    ) -> ExecFuture<'a, Result<ExecResult, ExecError>> {
+        Box::pin(async move {
+            match self.inner {
+                ExecutorImpl::Linux(ref mut e) => e.exec(p).await,
+                ExecutorImpl::Scripy(ref mut e) => e.exec(p, t).await,
+            }
+        })
+    }
+
+    fn restart(&mut self) -> ExecFuture<'_, ()> {
+        Box::pin(async move {
+            if let Err(e) = self.start().await {
+                exits!(exitcode::UNAVAILABLE, "Fail to restart guest: {}", e)
+            }
+        })
+    }
+
+    fn boots(&self) -> usize {
+        match self.inner {
+            ExecutorImpl::Linux(ref e) => e.boots,
+            ExecutorImpl::Scripy(ref e) => e.boots,
+        }
+    }
+
+    /// Used by linux executor started with fault injection only, by `exec` or `run`.
+    fn set_fault(&mut self, fault: Option<Fault>) {
+        if let ExecutorImpl::Linux(ref mut e) = self.inner {
+            e.fault = fault;
+        }
+    }
+
+    fn fault_injected(&self) -> bool {
+        match self.inner {
+            ExecutorImpl::Linux(ref e) => e.fault_injected,
+            ExecutorImpl::Scripy(_) => false,
+        }
+    }
+
+    fn execprog<'a>(
+        &'a mut self,
+        text: &'a str,
+    ) -> ExecFuture<'a, Result<Vec<Vec<usize>>, String>> {
+        Box::pin(async move {
+            match self.inner {
+                ExecutorImpl::Linux(ref mut e) => e.execprog(text).await,
+                ExecutorImpl::Scripy(_) => Err(String::from("not supported in script mode")),
+            }
+        })
+    }
+}
+
+/// Output of [`QemuExecutor::run`].
+#[derive(Debug, Clone)]
+pub struct RunOutput {
+    /// One entry for each executed call, in order. Calls after a failed one are not executed.
+    pub calls: Vec<CallOutput>,
+    /// Time cost of whole run, including transfer.
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct CallOutput {
+    /// Branches covered by the call.
+    pub covers: Vec<usize>,
+    /// Time cost of the call, only recorded by linux executor with kcov.
+    pub time: Option<Duration>,
+}
+
+/// Lines of executor stderr or console log kept with a failure.
+const TAIL_LINES: usize = 20;
+
+/// Why an execution gave no result. Only `KernelCrash` and `VmDied` leave guest down,
+/// executor recovers from the others by itself.
+#[derive(Debug)]
+pub enum ExecError {
+    /// Guest kernel crashed, console shows a kernel report
+    KernelCrash(Crash),
+    /// No result in time while guest stayed alive, guest is restarted
+    Hang,
+    /// Guest went down without a kernel report, with tail of console log
+    VmDied(String),
+    /// Prog couldn't be sent or response was malformed, executor is reconnected
+    Transport(String),
+    /// Executor exited instead of executing prog, with tail of its stderr, it's restarted
+    ExecutorRejected(String),
+    /// Prog didn't finish in time in guest
+    Timeout,
+}
+
+/// Where an execution failure comes from. Only kernel failures are saved as crashes,
+/// the others are counted and logged on their own, see `failures`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// Kernel crashed, guest is rebooted
+    Kernel,
+    /// Executor in guest exited abnormally or stopped answering, executor is restarted,
+    /// or guest is rebooted if it hung
+    Guest,
+    /// Vm died without kernel report or connection to executor broke, executor is
+    /// reconnected, or guest is rebooted if that fails or it died
+    Host,
+}
+
+impl ExecError {
+    /// Guest went down, likely crashed, and must be restarted.
+    pub fn is_crash(&self) -> bool {
+        matches!(self, ExecError::KernelCrash(_) | ExecError::VmDied(_))
+    }
+
+    pub fn kind(&self) -> FailureKind {
+        match self {
+            ExecError::KernelCrash(_) => FailureKind::Kernel,
+            ExecError::Hang | ExecError::ExecutorRejected(_) | ExecError::Timeout => {
+                FailureKind::Guest
+            }
+            ExecError::VmDied(_) | ExecError::Transport(_) => FailureKind::Host,
+        }
+    }
+
+    /// Executor stderr or console log tail of failure, empty if there's none.
+    pub fn detail(&self) -> &str {
+        match self {
+            ExecError::VmDied(s) | ExecError::Transport(s) | ExecError::ExecutorRejected(s) => s,
+            _ => "",
+        }
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureKind::Kernel => write!(f, "kernel"),
+            FailureKind::Guest => write!(f, "guest"),
+            FailureKind::Host => write!(f, "host"),
+        }
+    }
+}
+
+/// Last `n` lines of `s`.
+fn tail(s: &str, n: usize) -> String {
+    let lines = s.lines().collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(n)..].join("\n")
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::KernelCrash(_) => write!(f, "kernel crashed"),
+            ExecError::Hang => write!(f, "execution hung"),
+            ExecError::VmDied(_) => write!(f, "guest died without kernel report"),
+            ExecError::Transport(e) => write!(f, "transport error: {}", e),
+            ExecError::ExecutorRejected(_) => write!(f, "executor exited abnormally"),
+            ExecError::Timeout => write!(f, "time out"),
+        }
+    }
+}
+
+impl Error for ExecError {}
+
+#[derive(Debug)]
+pub enum RunError {
+    /// Executor failed to execute the prog.
+    Failed(Reason),
+    /// Guest crashed, crash log is empty if it couldn't be collected.
+    Crashed(Crash),
+    /// Run didn't finish in time.
+    Timeout(Duration),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Failed(reason) => write!(f, "execution failed: {}", reason),
+            RunError::Crashed(crash) if crash.inner.is_empty() => write!(f, "guest crashed"),
+            RunError::Crashed(crash) => write!(f, "guest crashed:\n{}", crash),
+            RunError::Timeout(t) => write!(f, "not finished in {}ms", t.as_millis()),
+        }
+    }
+}
+
+impl Error for RunError {}
+
 struct ScriptExecutor {
     path_on_host: PathBuf,
+    /// Scratch dir of this vm, holding test case sent to guest
+    scratch: PathBuf,
     guest: Guest,
+    boots: usize,
 }
 
 impl ScriptExecutor {
-    pub fn new(cfg: &Config) -> Self {
-        let guest = Guest::new(cfg);
+    pub fn new(cfg: &Config, job: usize) -> Self {
+        let guest = Guest::new(cfg, job);
 
         Self {
             path_on_host: cfg.executor.path.clone(),
+            scratch: work_dir::vm_dir(job),
             guest,
+            boots: 0,
         }
     }
 
-    pub async fn start(&mut self) {
-        self.guest.boot().await;
+    pub async fn start(&mut self) -> Result<(), BootError> {
+        self.boots += 1;
+        self.guest.boot().await
     }
 
-    pub async fn exec(&mut self, p: &Prog, t: &Target) -> Result<ExecResult, Option<Crash>> {
+    pub async fn exec(&mut self, p: &Prog, t: &Target) -> Result<ExecResult, ExecError> {
         let p_text = to_prog(p, t);
-        let tmp = temp_dir().join("HEALER_test_case_v1-1-1.c");
+        let tmp = self.scratch.join("HEALER_test_case_v1-1-1.c");
         if let Err(e) = write(&tmp, &p_text).await {
-            eprintln!(
+            exits!(
+                1,
                 "Failed to write test case to tmp dir \"{}\": {}",
                 tmp.display(),
                 e
             );
-            exit(1);
         }
 
         let guest_case_file = self.guest.copy(&tmp).await;
@@ -125,7 +498,7 @@ impl ScriptExecutor {
         let mut exec_handle = self.guest.run_cmd(&executor).await;
 
         match timeout(Duration::new(15, 0), &mut exec_handle).await {
-            Err(_) => Ok(ExecResult::Failed(Reason("Time out".to_string()))),
+            Err(_) => Err(ExecError::Timeout),
             Ok(_) => {
                 let mut stdout = exec_handle.stdout.take().unwrap();
                 let mut output = String::new();
@@ -135,7 +508,7 @@ impl ScriptExecutor {
         }
     }
 
-    pub async fn parse_exec_result(&mut self, out: String) -> Result<ExecResult, Option<Crash>> {
+    pub async fn parse_exec_result(&mut self, out: String) -> Result<ExecResult, ExecError> {
         let mut result_line = String::new();
 
         for l in out.lines() {
@@ -150,19 +523,26 @@ impl ScriptExecutor {
                 return Ok(ExecResult::Ok(Default::default()));
             } else if result_line.contains("failed") {
                 return Ok(ExecResult::Failed(Reason(out)));
-            } else if result_line.contains("crashed") {
-                return Err(Some(Crash { inner: out }));
+            } else if result_line.contains("crashed") && is_kernel_report(&out) {
+                return Err(ExecError::KernelCrash(Crash { inner: out }));
+            } else if result_line.contains("crashed") && self.guest.is_alive().await {
+                return Err(ExecError::ExecutorRejected(tail(&out, TAIL_LINES)));
             }
         }
 
-        if !self.guest.is_alive().await {
-            Err(Some(Crash { inner: out }))
-        } else {
+        if self.guest.is_alive().await {
             Ok(ExecResult::Ok(Default::default()))
+        } else if is_kernel_report(&out) {
+            Err(ExecError::KernelCrash(Crash { inner: out }))
+        } else {
+            Err(ExecError::VmDied(tail(&out, TAIL_LINES)))
         }
     }
 }
 
+/// Seconds executor has to send its handshake once connected.
+const HANDSHAKE_TIMEOUT: u64 = 5;
+
 struct LinuxExecutor {
     guest: Guest,
     port: u16,
@@ -173,11 +553,28 @@ struct LinuxExecutor {
     executor_bin_path: PathBuf,
     target_path: PathBuf,
     host_ip: String,
+    wait_executor_time: u8,
+    call_timeouts: Option<Arc<CallTimeouts>>,
+    fault_injection: bool,
+    /// Fault to inject in next execution and whether fault of last one was injected
+    fault: Option<Fault>,
+    fault_injected: bool,
+    /// Buffers of encoded requests and reports, kept across executions
+    send_buf: Vec<u8>,
+    recv_buf: Vec<u8>,
+    /// Protocol version negotiated with executor, logged once it changes
+    protocol: Option<u32>,
+    /// Host dir of syz-execprog and syz-executor, and their guest paths with boot they
+    /// were copied in
+    syz_bin: Option<PathBuf>,
+    execprog: Option<(usize, PathBuf, PathBuf)>,
+    boots: usize,
+    job: usize,
 }
 
 impl LinuxExecutor {
-    pub fn new(cfg: &Config) -> Self {
-        let guest = Guest::new(cfg);
+    pub fn new(cfg: &Config, job: usize) -> Self {
+        let guest = Guest::new(cfg, job);
         let port = free_ipv4_port()
             .unwrap_or_else(|| exits!(exitcode::TEMPFAIL, "No Free port for executor driver"));
         let host_ip = cfg
@@ -198,18 +595,66 @@ impl LinuxExecutor {
             executor_bin_path: cfg.executor.path.clone(),
             target_path: PathBuf::from(&cfg.fots_bin),
             host_ip,
+            wait_executor_time: cfg.qemu.wait_executor_time.unwrap_or(32),
+            call_timeouts: None,
+            fault_injection: cfg.executor.fault_injection.is_some(),
+            fault: None,
+            fault_injected: false,
+            send_buf: Vec::new(),
+            recv_buf: Vec::new(),
+            protocol: None,
+            syz_bin: cfg.executor.syz_bin.clone(),
+            execprog: None,
+            boots: 0,
+            job,
+        }
+    }
+
+    /// Run syzkaller prog `text` with syz-execprog, copied to guest once per boot.
+    async fn execprog(&mut self, text: &str) -> Result<Vec<Vec<usize>>, String> {
+        if !matches!(self.execprog, Some((boots, _, _)) if boots == self.boots) {
+            let dir = self.syz_bin.as_ref().ok_or("no syz_bin")?;
+            let execprog = self.guest.copy(dir.join("syz-execprog")).await;
+            let executor = self.guest.copy(dir.join("syz-executor")).await;
+            self.execprog = Some((self.boots, execprog, executor));
         }
+        let (_, execprog, executor) = self.execprog.as_ref().unwrap();
+        let cmd = cross_check::execprog_cmd(
+            text,
+            &execprog.display().to_string(),
+            &executor.display().to_string(),
+        );
+        let out = self.guest.query(&cmd).await?;
+        cross_check::parse_cover(&out)
     }
 
-    pub async fn start(&mut self) {
+    pub async fn start(&mut self) -> Result<(), BootError> {
         // handle should be set to kill on drop
         self.exec_handle = None;
-        self.guest.boot().await;
+        self.boots += 1;
+        self.guest.boot().await?;
 
         self.start_executer().await
     }
 
-    pub async fn start_executer(&mut self) {
+    async fn restart(&mut self) {
+        if let Err(e) = self.start().await {
+            exits!(exitcode::UNAVAILABLE, "Fail to restart guest: {}", e)
+        }
+    }
+
+    /// Restart executor and connect to it again, reboot guest if that fails.
+    async fn reconnect(&mut self) {
+        if let Err(e) = self.start_executer().await {
+            warn!(
+                "job-{}: Fail to reconnect executor: {}, restarting guest...",
+                self.job, e
+            );
+            self.restart().await;
+        }
+    }
+
+    pub async fn start_executer(&mut self) -> Result<(), BootError> {
         use tokio::io::ErrorKind::*;
 
         self.exec_handle = None;
@@ -228,8 +673,8 @@ impl LinuxExecutor {
                         retry += 1;
                         continue;
                     } else {
-                        eprintln!("Fail to listen on {}: {}", host_addr, e);
-                        exit(1);
+                        let reason = format!("fail to listen on {}: {}", host_addr, e);
+                        return Err(self.guest.boot_error(BootStage::Executor, reason));
                     }
                 }
             };
@@ -243,8 +688,11 @@ impl LinuxExecutor {
                     tx.send(conn).unwrap();
                 }
                 Err(e) => {
-                    eprintln!("Executor driver: fail to get client: {}", e);
-                    exit(exitcode::OSERR);
+                    exits!(
+                        exitcode::OSERR,
+                        "Executor driver: fail to get client: {}",
+                        e
+                    )
                 }
             }
         });
@@ -266,59 +714,140 @@ impl LinuxExecutor {
         if self.concurrency {
             executor.arg(Arg::new_flag("-c"));
         }
+        if self.fault_injection {
+            executor.arg(Arg::new_flag("-f"));
+        }
 
         self.exec_handle = Some(self.guest.run_cmd(&executor).await);
-        self.conn = match timeout(Duration::new(32, 0), rx).await {
+        let wait_time = Duration::new(self.wait_executor_time as u64, 0);
+        self.conn = match timeout(wait_time, rx).await {
             Err(_) => {
-                self.exec_handle = None;
-                eprintln!("Time out: wait executor connection {}", host_addr);
-                exit(1)
+                let mut reason = format!(
+                    "executor did not connect to {} after {}s",
+                    host_addr,
+                    wait_time.as_secs()
+                );
+                let handle = self.exec_handle.take().unwrap();
+                if let Ok(Ok(output)) =
+                    timeout(Duration::new(1, 0), handle.wait_with_output()).await
+                {
+                    reason.push_str(&format!(
+                        ", executor exited with {}: {}",
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                return Err(self.guest.boot_error(BootStage::Executor, reason));
             }
             Ok(conn) => Some(conn.unwrap()),
         };
+        self.handshake().await
     }
 
-    pub async fn exec(&mut self, p: &Prog) -> Result<ExecResult, Option<Crash>> {
+    /// Check protocol version executor reports before sending any prog.
+    async fn handshake(&mut self) -> Result<(), BootError> {
+        let conn = self.conn.as_mut().unwrap();
+        let hello = match timeout(
+            Duration::new(HANDSHAKE_TIMEOUT, 0),
+            async_recv_hello(conn, &mut self.recv_buf),
+        )
+        .await
+        {
+            Ok(Ok(hello)) => hello.check(),
+            Ok(Err(e)) => Err(format!("malformed handshake: {}", e)),
+            Err(_) => Err(format!(
+                "no handshake in {}s, executor is likely older than protocol {}",
+                HANDSHAKE_TIMEOUT,
+                transfer::MIN_PROTOCOL_VERSION
+            )),
+        };
+        match hello {
+            Ok(protocol) => {
+                if self.protocol != Some(protocol) {
+                    info!("job-{}: executor protocol {}", self.job, protocol);
+                    self.protocol = Some(protocol);
+                }
+                Ok(())
+            }
+            Err(reason) => {
+                self.conn = None;
+                Err(self.guest.boot_error(BootStage::Executor, reason))
+            }
+        }
+    }
+
+    pub async fn exec(&mut self, p: &Prog) -> Result<ExecResult, ExecError> {
+        self.exec_report(p).await.and_then(|r| match r.result {
+            ExecResult::Failed(ref reason) if reason.0 == "Time out" => Err(ExecError::Timeout),
+            result => Ok(result),
+        })
+    }
+
+    async fn exec_report(&mut self, p: &Prog) -> Result<ExecReport, ExecError> {
         // send must be success
         assert!(self.conn.is_some());
+        let call_timeouts = self
+            .call_timeouts
+            .as_ref()
+            .map(|t| t.timeouts_of(p))
+            .unwrap_or_default();
+        // slow calls with learned timeouts make the whole prog slower too.
+        let extra = self
+            .call_timeouts
+            .as_ref()
+            .map(|t| t.extra_of(&call_timeouts))
+            .unwrap_or(0);
+        let req = ExecReqRef {
+            p,
+            call_timeouts: &call_timeouts,
+            fault: self.fault.take(),
+        };
+        self.fault_injected = false;
         if let Err(e) = timeout(
             Duration::new(15, 0),
-            async_send(p, self.conn.as_mut().unwrap()),
+            async_send(&req, self.conn.as_mut().unwrap(), &mut self.send_buf),
         )
         .await
         {
-            info!("Prog send blocked: {}, restarting...", e);
-            self.start().await;
-            return Ok(ExecResult::Failed(Reason("Prog send blocked".into())));
+            info!(
+                "job-{}: Prog send blocked: {}, reconnecting...",
+                self.job, e
+            );
+            self.reconnect().await;
+            return Err(ExecError::Transport(String::from("prog send blocked")));
         }
         // async_send(p, self.conn.as_mut().unwrap()).await.unwrap();
         let ret = {
             match timeout(
-                Duration::new(15, 0),
-                async_recv_result(self.conn.as_mut().unwrap()),
+                Duration::new(15, 0) + Duration::from_millis(extra),
+                async_recv_report(self.conn.as_mut().unwrap(), &mut self.recv_buf),
             )
             .await
             {
                 Err(e) => {
-                    info!("Prog recv blocked: {}, restarting...", e);
-                    self.start().await;
-                    return Ok(ExecResult::Failed(Reason("Prog send blocked".into())));
+                    info!("job-{}: Prog recv blocked: {}, restarting...", self.job, e);
+                    self.restart().await;
+                    return Err(ExecError::Hang);
                 }
                 Ok(ret) => ret,
             }
         };
         match ret {
-            Ok(result) => {
+            Ok(report) => {
+                self.fault_injected = report.fault_injected;
+                if let Some(call_timeouts) = self.call_timeouts.as_ref() {
+                    call_timeouts.learn(p, &report);
+                }
                 self.guest.clear().await;
-                if let ExecResult::Failed(ref reason) = result {
+                if let ExecResult::Failed(ref reason) = report.result {
                     let rea = reason.to_string();
                     if rea.contains("CRASH-MEMLEAK") {
-                        return Err(Some(Crash { inner: rea }));
+                        return Err(ExecError::KernelCrash(Crash { inner: rea }));
                     }
                 }
-                return Ok(result);
+                Ok(report)
             }
-            Err(_) => {
+            Err(e) => {
                 let mut crashed: bool;
                 let mut retry: u8 = 0;
                 loop {
@@ -332,30 +861,60 @@ impl LinuxExecutor {
                 }
 
                 if crashed {
-                    return Err(self.guest.try_collect_crash().await);
+                    Err(match self.guest.try_collect_crash().await {
+                        Some(crash) if is_kernel_report(&crash.inner) => {
+                            ExecError::KernelCrash(crash)
+                        }
+                        Some(crash) => ExecError::VmDied(tail(&crash.inner, TAIL_LINES)),
+                        None => ExecError::VmDied(String::new()),
+                    })
+                } else if let transfer::Error::Serialize(_) = e {
+                    warn!(
+                        "job-{}: Executor: malformed response: {}, reconnecting...",
+                        self.job, e
+                    );
+                    self.reconnect().await;
+                    Err(ExecError::Transport(format!("malformed response: {}", e)))
                 } else {
                     let mut handle = self.exec_handle.take().unwrap();
                     let mut stdout = handle.stdout.take().unwrap();
                     let mut stderr = handle.stderr.take().unwrap();
-                    handle.await.unwrap_or_else(|e| {
-                        exits!(exitcode::OSERR, "Fail to wait executor handle:{}", e)
-                    });
+                    match timeout(Duration::new(5, 0), &mut handle).await {
+                        Ok(status) => {
+                            status.unwrap_or_else(|e| {
+                                exits!(exitcode::OSERR, "Fail to wait executor handle:{}", e)
+                            });
+                        }
+                        // executor is still running, only connection to it broke.
+                        Err(_) => {
+                            warn!(
+                                "job-{}: Executor: connection lost: {}, reconnecting...",
+                                self.job, e
+                            );
+                            drop(handle);
+                            self.reconnect().await;
+                            return Err(ExecError::Transport(format!("connection lost: {}", e)));
+                        }
+                    }
 
                     let mut err = Vec::new();
                     stderr.read_to_end(&mut err).await.unwrap();
                     let mut out = Vec::new();
                     stdout.read_to_end(&mut out).await.unwrap();
 
+                    let err = String::from_utf8_lossy(&err).trim().to_string();
                     warn!(
-                        "Executor: Connection lost. STDOUT:{}. STDERR: {}",
-                        String::from_utf8(out).unwrap(),
-                        String::from_utf8(err).unwrap()
+                        "job-{}: Executor: Connection lost. STDOUT:{}. STDERR: {}",
+                        self.job,
+                        String::from_utf8_lossy(&out),
+                        err
                     );
-                    self.start_executer().await;
+                    if let Err(e) = self.start_executer().await {
+                        exits!(exitcode::UNAVAILABLE, "Fail to restart executor: {}", e)
+                    }
+                    Err(ExecError::ExecutorRejected(tail(&err, TAIL_LINES)))
                 }
             }
         }
-        // Caused by internal err
-        Ok(ExecResult::Ok(Vec::new()))
     }
 }
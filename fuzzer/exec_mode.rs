@@ -0,0 +1,163 @@
+//! Exec mode: execute progs of given files in one vm and print result of each call, to
+//! debug a single prog without fuzzing. A prog file is either a corpus or a crash case
+//! saved with its prog. Crashes are saved to run dir like ones found by fuzzing, and make
+//! exit code non-zero.
+use crate::exec::{QemuExecutor, RunError};
+use crate::report::{signature_of, CrashedCase, ReproScore, TestCaseRecord};
+use crate::work_dir;
+use crate::Config;
+use core::c::to_prog;
+use core::prog::Prog;
+use core::target::Target;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+/// Time limit of each execution.
+const PROG_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct ExecOpts {
+    /// Executions of each prog
+    pub repeat: usize,
+    /// Print blocks covered by each call
+    pub cover: bool,
+}
+
+/// Progs of file `path`, a crash case or a corpus, checked against `t`.
+pub fn load_progs(path: &Path, t: &Target) -> Result<Vec<Prog>, String> {
+    let data = std::fs::read(path).map_err(|e| format!("fail to read: {}", e))?;
+    let progs = if let Ok(case) = serde_json::from_slice::<CrashedCase>(&data) {
+        vec![case.prog.ok_or("crash case saved without prog")?]
+    } else {
+        bincode::deserialize::<Vec<Prog>>(&data)
+            .map_err(|_| String::from("neither a crash case nor a corpus"))?
+    };
+    for (i, p) in progs.iter().enumerate() {
+        if let Err(errs) = t.validate(p) {
+            return Err(format!("prog {} is invalid for target: {}", i, errs[0]));
+        }
+    }
+    Ok(progs)
+}
+
+/// Execute progs of `files` `opts.repeat` times each and print results, then exit.
+pub async fn exec_mode(
+    cfg: &Config,
+    t: Arc<Target>,
+    run_dir: PathBuf,
+    files: &[PathBuf],
+    opts: ExecOpts,
+) -> ! {
+    let mut progs = Vec::new();
+    for f in files {
+        let loaded = load_progs(f, &t)
+            .unwrap_or_else(|e| exits!(exitcode::DATAERR, "{}: {}", f.display(), e));
+        progs.extend(
+            loaded
+                .into_iter()
+                .enumerate()
+                .map(|(i, p)| (format!("{}#{}", f.display(), i), p)),
+        );
+    }
+
+    let mut executor = QemuExecutor::new(cfg, 0);
+    if let Err(e) = executor.start().await {
+        exits!(exitcode::UNAVAILABLE, "{}", e)
+    }
+    let record = TestCaseRecord::new(t.clone(), run_dir, None);
+    let mut crashed = 0;
+    for (name, p) in progs {
+        println!("{}:\n{}", name, to_prog(&p, &t));
+        let mut crash = None;
+        let mut hits = 0;
+        for i in 1..=opts.repeat {
+            let attempt = format!("attempt {}/{}", i, opts.repeat);
+            match executor.run(&p, &t, PROG_TIMEOUT).await {
+                Ok(out) => {
+                    println!("{}: ok, {}ms", attempt, out.elapsed.as_millis());
+                    for (j, c) in p.calls.iter().enumerate() {
+                        let name = &t.fn_of(c.fid).dec_name;
+                        let out = match out.calls.get(j) {
+                            Some(out) => out,
+                            None => {
+                                println!("  #{} {}: not executed", j, name);
+                                continue;
+                            }
+                        };
+                        let time = out
+                            .time
+                            .map(|tm| format!("{}ms", tm.as_millis()))
+                            .unwrap_or_else(|| String::from("-"));
+                        if opts.cover {
+                            println!("  #{} {}: {}, {} block(s)", j, name, time, out.covers.len());
+                        } else {
+                            println!("  #{} {}: {}", j, name, time);
+                        }
+                    }
+                }
+                Err(RunError::Failed(reason)) => println!("{}: failed: {}", attempt, reason),
+                Err(RunError::Timeout(tm)) => {
+                    println!("{}: timed out after {}s", attempt, tm.as_secs())
+                }
+                Err(RunError::Crashed(c)) => {
+                    hits += 1;
+                    if c.inner.is_empty() {
+                        println!("{}: crashed, lost connection to guest", attempt);
+                    } else {
+                        println!("{}: crashed: {}", attempt, signature_of(&c.inner));
+                        crash.get_or_insert(c);
+                    }
+                }
+            }
+        }
+        if hits != 0 {
+            crashed += 1;
+        }
+        // like fuzzing, crash without kernel report is not saved.
+        if let Some(c) = crash {
+            let score = ReproScore {
+                attempts: opts.repeat,
+                reproduced: hits,
+                rate: None,
+            };
+            let summary = record.insert_crash(p, c, score, None).await;
+            println!("crash saved to {}", summary.path.display());
+        }
+    }
+    work_dir::cleanup();
+    exit(if crashed == 0 {
+        exitcode::OK
+    } else {
+        exitcode::SOFTWARE
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::analyze::static_analyze;
+    use core::bench::target;
+    use core::gen::gen;
+    use std::env::temp_dir;
+    use std::process::id;
+
+    #[test]
+    fn load_prog_files() {
+        let (t, _) = target();
+        let p = gen(&t, &static_analyze(&t), &Default::default());
+        let path = temp_dir().join(format!("healer-exec-{}", id()));
+
+        std::fs::write(&path, bincode::serialize(&vec![p.clone()]).unwrap()).unwrap();
+        assert_eq!(load_progs(&path, &t).unwrap(), vec![p]);
+        std::fs::write(
+            &path,
+            b"r0 = open(&(0x7f0000000000)='./file0\\x00', 0x0, 0x0)",
+        )
+        .unwrap();
+        assert!(load_progs(&path, &t).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_progs(&path, &t).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+//! Execution failures that are not kernel crashes.
+//!
+//! Executor exiting abnormally in guest and vm or connection to executor breaking are not
+//! bugs of the kernel, so they are never saved as crashes. Each kind is counted and
+//! appended with its executor stderr or console log tail to its own log in run dir,
+//! `guest_failures.log` or `host_failures.log`.
+use crate::exec::{ExecError, FailureKind};
+use chrono::prelude::*;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+pub struct Failures {
+    run_dir: PathBuf,
+    guest: AtomicUsize,
+    host: AtomicUsize,
+    /// Transport failures, each reconnects executor
+    reconnects: AtomicUsize,
+}
+
+impl Failures {
+    pub fn new(run_dir: PathBuf) -> Self {
+        Self {
+            run_dir,
+            guest: AtomicUsize::new(0),
+            host: AtomicUsize::new(0),
+            reconnects: AtomicUsize::new(0),
+        }
+    }
+
+    /// Count and log failure `e` of `job`. Kernel crashes are left to crash analysis.
+    pub async fn record(&self, job: usize, e: &ExecError) {
+        let (cnt, name) = match e.kind() {
+            FailureKind::Kernel => return,
+            FailureKind::Guest => (&self.guest, "guest_failures.log"),
+            FailureKind::Host => (&self.host, "host_failures.log"),
+        };
+        cnt.fetch_add(1, Ordering::Relaxed);
+        if let ExecError::Transport(_) = e {
+            self.reconnects.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut entry = format!("[{}] job-{}: {}\n", Local::now(), job, e);
+        if !e.detail().is_empty() {
+            entry += e.detail();
+            entry.push('\n');
+        }
+        let path = self.run_dir.join(name);
+        let ret = match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+        {
+            Ok(mut f) => f.write_all(entry.as_bytes()).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = ret {
+            warn!("Fail to append failure to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Guest and host failures so far.
+    pub fn counts(&self) -> (usize, usize) {
+        (
+            self.guest.load(Ordering::Relaxed),
+            self.host.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Executor reconnects so far, part of host failures.
+    pub fn reconnects(&self) -> usize {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+}
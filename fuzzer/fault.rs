@@ -0,0 +1,94 @@
+//! Deterministic fault injection schedules.
+//!
+//! Each prog added to corpus is executed again with faults of its schedule, one per
+//! execution: fault point 1, 2, ... of call 0, then of call 1 and so on, each failing
+//! through `/proc/thread-self/fail-nth` of kernel fault injection. A call is left once
+//! its fault isn't injected, i.e. it hit fewer fault points, or after `max_nth` faults.
+//! This walks error-handling paths of each call systematically instead of randomly, and
+//! a crash is saved with the fault that caused it, so it is reproduced with it as well.
+//! Guest kernel must be built with CONFIG_FAULT_INJECTION and friends.
+pub use executor::Fault;
+use std::process::exit;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FaultConf {
+    /// Fault points of each call failed at most, default 32
+    pub max_nth: Option<u32>,
+}
+
+impl FaultConf {
+    pub fn check(&self) {
+        if self.max_nth == Some(0) {
+            eprintln!("Config Error: fault_injection: max_nth must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+    }
+
+    pub fn max_nth(&self) -> u32 {
+        self.max_nth.unwrap_or(32)
+    }
+}
+
+/// Faults of prog of `calls` calls, in order.
+#[derive(Debug, Clone)]
+pub struct FaultSchedule {
+    calls: usize,
+    max_nth: u32,
+    current: Option<Fault>,
+}
+
+impl FaultSchedule {
+    pub fn new(calls: usize, max_nth: u32) -> Self {
+        Self {
+            calls,
+            max_nth,
+            current: if calls == 0 || max_nth == 0 {
+                None
+            } else {
+                Some(Fault { call: 0, nth: 1 })
+            },
+        }
+    }
+
+    /// Fault of next execution, None once schedule is done.
+    pub fn current(&self) -> Option<Fault> {
+        self.current
+    }
+
+    /// Move on after executing with current fault, `injected` tells whether it was.
+    pub fn advance(&mut self, injected: bool) {
+        self.current = match self.current {
+            Some(Fault { call, nth }) if injected && nth < self.max_nth => {
+                Some(Fault { call, nth: nth + 1 })
+            }
+            Some(Fault { call, .. }) if call + 1 < self.calls => Some(Fault {
+                call: call + 1,
+                nth: 1,
+            }),
+            _ => None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fault_schedule() {
+        // fault points hit by each call
+        let points = [2, 0, 5];
+        let mut s = FaultSchedule::new(points.len(), 3);
+        let mut faults = Vec::new();
+        while let Some(f) = s.current() {
+            faults.push((f.call, f.nth));
+            s.advance(f.nth <= points[f.call]);
+        }
+        assert_eq!(
+            faults,
+            vec![(0, 1), (0, 2), (0, 3), (1, 1), (2, 1), (2, 2), (2, 3)]
+        );
+        assert_eq!(FaultSchedule::new(0, 3).current(), None);
+    }
+}
@@ -1,6 +1,10 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering::{Equal, Greater, Less};
+use std::collections::hash_map::RandomState;
 use std::collections::HashSet;
-use std::iter::Extend;
-use tokio::sync::Mutex;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::RwLock;
 
 #[derive(Clone, Debug, Default, Hash, PartialOrd, PartialEq, Ord, Eq)]
 pub struct Block(usize);
@@ -11,91 +15,401 @@ impl From<usize> for Block {
     }
 }
 
+impl Block {
+    /// PC kcov reported for block.
+    pub fn pc(&self) -> usize {
+        self.0
+    }
+}
+
 #[derive(Clone, Debug, Default, Hash, PartialOrd, PartialEq, Ord, Eq)]
 pub struct Branch(usize);
 
 impl From<(Block, Block)> for Branch {
     fn from((b1, b2): (Block, Block)) -> Self {
         let mut a = b1.0 as u32;
-        // hash algorithm from syzkaller
+        // hash algorithm from syzkaller, wrapping like its uint32
         a = (a ^ 61) ^ (a >> 16);
-        a = a + (a << 3);
+        a = a.wrapping_add(a << 3);
         a = a ^ (a >> 4);
-        a *= 0x27d4_eb2d;
+        a = a.wrapping_mul(0x27d4_eb2d);
         a = a ^ (a >> 15);
 
         Self(a as usize ^ b2.0)
     }
 }
 
-#[derive(Default)]
-pub struct FeedBack {
-    branches: Mutex<HashSet<Branch>>,
-    blocks: Mutex<HashSet<Block>>,
+/// Number of shards of a coverage set.
+const SHARDS: usize = 64;
+
+/// Set of coverage shared by all jobs, split into shards selected by hash of value so jobs
+/// merging coverage at the same time rarely wait for each other. Length is kept in an
+/// atomic counter, reading it takes no lock.
+pub struct ShardedSet<T> {
+    shards: Vec<RwLock<HashSet<T>>>,
+    hasher: RandomState,
+    len: AtomicUsize,
 }
 
-impl FeedBack {
-    pub async fn diff_branch(&self, branches: &[Branch]) -> HashSet<Branch> {
-        let inner = self.branches.lock().await;
+impl<T: Hash + Ord + Clone> Default for ShardedSet<T> {
+    fn default() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| RwLock::default()).collect(),
+            hasher: RandomState::new(),
+            len: AtomicUsize::new(0),
+        }
+    }
+}
 
-        let mut result = HashSet::new();
-        for b in branches {
-            if !inner.contains(b) {
-                result.insert(b.clone());
-            }
+impl<T: Hash + Ord + Clone> ShardedSet<T> {
+    fn shard_of(&self, v: &T) -> usize {
+        self.hasher.hash_one(v) as usize % SHARDS
+    }
+
+    /// `vals` grouped by shard, so that each shard is locked once per batch.
+    fn group<V: Borrow<T>>(&self, vals: impl IntoIterator<Item = V>) -> Vec<Vec<V>> {
+        let mut groups = (0..SHARDS).map(|_| Vec::new()).collect::<Vec<_>>();
+        for v in vals {
+            groups[self.shard_of(v.borrow())].push(v);
         }
-        result.shrink_to_fit();
-        result
+        groups
     }
 
-    pub async fn diff_block(&self, blocks: &[Block]) -> HashSet<Block> {
-        let inner = self.blocks.lock().await;
+    pub fn contains(&self, v: &T) -> bool {
+        self.shards[self.shard_of(v)].read().unwrap().contains(v)
+    }
 
-        let mut result = HashSet::new();
-        for b in blocks {
-            if !inner.contains(b) {
-                result.insert(b.clone());
+    /// Values of `vals` not in set, sorted.
+    pub fn diff(&self, vals: &[T]) -> Box<[T]> {
+        let mut ret = Vec::new();
+        for (shard, group) in self.shards.iter().zip(self.group(vals)) {
+            if !group.is_empty() {
+                let shard = shard.read().unwrap();
+                ret.extend(group.into_iter().filter(|v| !shard.contains(v)).cloned());
             }
         }
-        result.shrink_to_fit();
-        result
+        ret.sort_unstable();
+        ret.dedup();
+        ret.into_boxed_slice()
     }
 
-    pub async fn merge(&self, blocks: HashSet<Block>, branches: HashSet<Branch>) {
-        {
-            let mut inner = self.branches.lock().await;
-            inner.extend(branches);
+    /// Insert `vals`, returning number of values that were not in set.
+    pub fn insert_many(&self, vals: impl IntoIterator<Item = T>) -> usize {
+        let mut added = 0;
+        for (shard, group) in self.shards.iter().zip(self.group(vals)) {
+            if !group.is_empty() {
+                let mut shard = shard.write().unwrap();
+                for v in group {
+                    if shard.insert(v) {
+                        added += 1;
+                    }
+                }
+            }
         }
-        {
-            let mut inner = self.blocks.lock().await;
-            inner.extend(blocks);
+        self.len.fetch_add(added, Ordering::Relaxed);
+        added
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// All values, sorted. Shards are read one by one, so values inserted meanwhile may
+    /// be missing.
+    pub fn values(&self) -> Vec<T> {
+        let mut vals = Vec::with_capacity(self.len());
+        for shard in self.shards.iter() {
+            vals.extend(shard.read().unwrap().iter().cloned());
         }
+        vals.sort_unstable();
+        vals
     }
 
-    pub async fn is_empty(&self) -> bool {
-        let (block_empty, branch_empty) = tokio::join!(
-            async {
-                let inner = self.blocks.lock().await;
-                inner.is_empty()
-            },
-            async {
-                let inner = self.branches.lock().await;
-                inner.is_empty()
-            }
-        );
-        block_empty || branch_empty
-    }
-
-    pub async fn len(&self) -> (usize, usize) {
-        tokio::join!(
-            async {
-                let inner = self.blocks.lock().await;
-                inner.len()
-            },
-            async {
-                let inner = self.branches.lock().await;
-                inner.len()
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Values in both of sorted, deduplicated `a` and `b`.
+pub fn intersect<T: Ord + Clone>(a: &[T], b: &[T]) -> Box<[T]> {
+    let mut ret = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Less => i += 1,
+            Greater => j += 1,
+            Equal => {
+                ret.push(a[i].clone());
+                i += 1;
+                j += 1;
             }
+        }
+    }
+    ret.into_boxed_slice()
+}
+
+/// Whether sorted `a` and `b` have any value in common.
+pub fn overlaps<T: Ord>(a: &[T], b: &[T]) -> bool {
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Less => i += 1,
+            Greater => j += 1,
+            Equal => return true,
+        }
+    }
+    false
+}
+
+/// Coverage of all jobs. Coverage of a single prog, e.g. what it newly covers, is kept as
+/// sorted, deduplicated boxed slices, which take a fraction of memory of hash sets and
+/// are intersected by merging.
+///
+/// Counts of blocks and branches are published after each merge, so monitoring reads
+/// them with a single atomic load, see `counts`. Published counts never go down, even
+/// when merges of different jobs publish out of order.
+#[derive(Default)]
+pub struct FeedBack {
+    branches: ShardedSet<Branch>,
+    blocks: ShardedSet<Block>,
+    /// Merges published so far
+    epoch: AtomicUsize,
+    /// Blocks in high half and branches in low half, each saturated at `u32::MAX`
+    published: AtomicU64,
+}
+
+impl FeedBack {
+    pub async fn diff_branch(&self, branches: &[Branch]) -> Box<[Branch]> {
+        self.branches.diff(branches)
+    }
+
+    pub async fn diff_block(&self, blocks: &[Block]) -> Box<[Block]> {
+        self.blocks.diff(blocks)
+    }
+
+    pub async fn merge(&self, blocks: Box<[Block]>, branches: Box<[Branch]>) {
+        self.branches.insert_many(branches.into_vec());
+        self.blocks.insert_many(blocks.into_vec());
+        self.publish();
+    }
+
+    /// Publish current counts, each only if it's bigger than the published one, then bump
+    /// epoch.
+    fn publish(&self) {
+        let blocks = self.blocks.len().min(u32::MAX as usize) as u64;
+        let branches = self.branches.len().min(u32::MAX as usize) as u64;
+        let _ = self
+            .published
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |old| {
+                let new = ((old >> 32).max(blocks) << 32) | (old & 0xffff_ffff).max(branches);
+                if new == old {
+                    None
+                } else {
+                    Some(new)
+                }
+            });
+        self.epoch.fetch_add(1, Ordering::Release);
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.blocks.is_empty() || self.branches.is_empty()
+    }
+
+    /// Published blocks and branches, as of the last merge.
+    pub fn counts(&self) -> (usize, usize) {
+        let published = self.published.load(Ordering::Acquire);
+        (
+            (published >> 32) as usize,
+            (published & 0xffff_ffff) as usize,
         )
     }
+
+    /// Merges published so far, readers may skip work when it hasn't changed, e.g. plateau
+    /// watch and cover dumps.
+    pub fn epoch(&self) -> usize {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// PCs of all blocks covered, sorted.
+    pub async fn pcs(&self) -> Vec<usize> {
+        self.blocks.values().iter().map(Block::pc).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const JOBS: usize = 16;
+    const ROUNDS: usize = 200;
+    const BATCH: usize = 2000;
+
+    /// Coverage of round `r` of `job`: half shared by all jobs, half its own.
+    fn cov(job: usize, r: usize) -> Vec<Branch> {
+        let branch = |i, tag| Branch::from((Block::from(r * BATCH + i), Block::from(tag)));
+        let shared = (0..BATCH / 2).map(|i| branch(i, 0));
+        let own = (0..BATCH / 2).map(|i| branch(i, (job + 1) << 32));
+        shared.chain(own).collect()
+    }
+
+    fn time_jobs(merge: impl Fn(usize, usize) + Send + Sync + 'static) -> Duration {
+        let merge = Arc::new(merge);
+        let begin = Instant::now();
+        let jobs = (0..JOBS)
+            .map(|job| {
+                let merge = Arc::clone(&merge);
+                thread::spawn(move || (0..ROUNDS).for_each(|r| merge(job, r)))
+            })
+            .collect::<Vec<_>>();
+        jobs.into_iter().for_each(|j| j.join().unwrap());
+        begin.elapsed()
+    }
+
+    #[test]
+    fn sharded_set() {
+        let s = ShardedSet::<Block>::default();
+        assert!(s.is_empty());
+        assert_eq!(s.insert_many((0..100).map(Block::from)), 100);
+        assert_eq!(s.insert_many((50..150).map(Block::from)), 50);
+        assert_eq!(s.len(), 150);
+        assert!(s.contains(&Block::from(149)) && !s.contains(&Block::from(150)));
+        let diff = s.diff(&[Block::from(1), Block::from(200)]);
+        assert_eq!(&diff[..], &[Block::from(200)]);
+    }
+
+    /// Diffing and intersecting as sorted slices gives what hash sets gave.
+    #[test]
+    fn sorted_cov_matches_sets() {
+        let mut rng = StdRng::seed_from_u64(647);
+        let mut cov = |n| {
+            let mut c = (0..n)
+                .map(|_| Block::from(rng.gen_range(0, 4096)))
+                .collect::<Vec<_>>();
+            c.sort();
+            c.dedup();
+            c
+        };
+        let known = ShardedSet::default();
+        let mut known_set = HashSet::new();
+        for _ in 0..100 {
+            let (c1, c2) = (cov(300), cov(300));
+            let new1 = known.diff(&c1);
+            let new2 = known.diff(&c2);
+            let set1 = c1
+                .iter()
+                .filter(|b| !known_set.contains(*b))
+                .cloned()
+                .collect::<HashSet<_>>();
+            let set2 = c2
+                .iter()
+                .filter(|b| !known_set.contains(*b))
+                .cloned()
+                .collect::<HashSet<_>>();
+            assert_eq!(new1.iter().cloned().collect::<HashSet<_>>(), set1);
+            assert!(new1.windows(2).all(|w| w[0] < w[1]));
+
+            let both = intersect(&new1, &new2);
+            let both_set = set1.intersection(&set2).cloned().collect::<HashSet<_>>();
+            assert_eq!(both.iter().cloned().collect::<HashSet<_>>(), both_set);
+            assert_eq!(overlaps(&new1, &new2), !both_set.is_empty());
+
+            known_set.extend(both_set);
+            known.insert_many(both.into_vec());
+            assert_eq!(known.len(), known_set.len());
+        }
+    }
+
+    /// Readers polling published counts while jobs merge never see them go down.
+    #[test]
+    fn published_counts_monotonic() {
+        const MERGES: usize = 20;
+        let feedback = Arc::new(FeedBack::default());
+        let done = Arc::new(AtomicBool::new(false));
+        let readers = (0..4)
+            .map(|_| {
+                let (feedback, done) = (Arc::clone(&feedback), Arc::clone(&done));
+                thread::spawn(move || {
+                    let (mut last_epoch, mut last) = (0, (0, 0));
+                    while !done.load(Ordering::Relaxed) {
+                        let epoch = feedback.epoch();
+                        let counts = feedback.counts();
+                        assert!(epoch >= last_epoch);
+                        assert!(
+                            counts.0 >= last.0 && counts.1 >= last.1,
+                            "{:?} after {:?}",
+                            counts,
+                            last
+                        );
+                        last_epoch = epoch;
+                        last = counts;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let writers = (0..JOBS)
+            .map(|job| {
+                let feedback = Arc::clone(&feedback);
+                thread::spawn(move || {
+                    let mut rt = tokio::runtime::Builder::new()
+                        .basic_scheduler()
+                        .build()
+                        .unwrap();
+                    for r in 0..MERGES {
+                        let blocks = (0..BATCH).map(|i| Block::from(r * BATCH + i + job));
+                        let branches = cov(job, r).into_boxed_slice();
+                        rt.block_on(feedback.merge(blocks.collect(), branches));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        writers.into_iter().for_each(|w| w.join().unwrap());
+        done.store(true, Ordering::Relaxed);
+        readers.into_iter().for_each(|r| r.join().unwrap());
+
+        let expected = (feedback.blocks.len(), feedback.branches.len());
+        assert_eq!(feedback.counts(), expected);
+        assert_eq!(feedback.epoch(), JOBS * MERGES);
+    }
+
+    /// Jobs diffing and merging overlapping coverage, sharded set against one locked set.
+    /// Run with `cargo test --release -p fuzzer -- --ignored --nocapture sharded_merge`.
+    #[test]
+    #[ignore]
+    fn sharded_merge_contention() {
+        let locked = Arc::new(Mutex::new(HashSet::<Branch>::new()));
+        let l = Arc::clone(&locked);
+        let locked_tm = time_jobs(move |job, r| {
+            let cov = cov(job, r);
+            let new = {
+                let set = l.lock().unwrap();
+                cov.iter()
+                    .filter(|b| !set.contains(b))
+                    .cloned()
+                    .collect::<HashSet<_>>()
+            };
+            l.lock().unwrap().extend(new);
+        });
+
+        let sharded = Arc::new(ShardedSet::<Branch>::default());
+        let s = Arc::clone(&sharded);
+        let sharded_tm = time_jobs(move |job, r| {
+            let new = s.diff(&cov(job, r));
+            s.insert_many(new);
+        });
+
+        assert_eq!(locked.lock().unwrap().len(), sharded.len());
+        println!(
+            "{} jobs merging {} branches: one lock {:?}, {} shards {:?}",
+            JOBS,
+            sharded.len(),
+            locked_tm,
+            SHARDS,
+            sharded_tm
+        );
+    }
 }
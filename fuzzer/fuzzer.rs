@@ -1,70 +1,566 @@
-use crate::corpus::Corpus;
-use crate::exec::Executor;
-use crate::feedback::{Block, Branch, FeedBack};
+use crate::backlog::{CrashBacklog, RawCrash, ReproProgress};
+use crate::call_timeout::CallTimeouts;
+use crate::corpus::{key_of, Corpus};
+use crate::cover_dump::{self, CoverDumpConf};
+use crate::cross_check::CrossChecks;
+use crate::energy::PowerSchedule;
+use crate::exec::{ExecError, Executor};
+use crate::failures::Failures;
+use crate::fault::{Fault, FaultConf, FaultSchedule};
+use crate::feedback::{intersect, overlaps, Block, Branch, FeedBack};
 use crate::guest::Crash;
-use crate::report::TestCaseRecord;
-use crate::stats::StatSource;
+use crate::hooks::{HookSender, InputSummary};
+use crate::job_dir::JobDirs;
+use crate::plateau::PlateauConf;
+use crate::relations;
+use crate::replay::{ReplayLog, ReplayOutcome};
+use crate::report::{digest_of, signature_of, ReproRate, ReproScore, TestCaseRecord};
+use crate::selection::{self, Recent, SelectionMode};
+use crate::state::FuzzerState;
+use crate::stats::{StatSource, SubsystemStats};
+use crate::symbolize::Symbolizer;
+use crate::symbols::SymbolTable;
+use crate::syzdb;
+use crate::toggle::ActiveCalls;
+use crate::trace;
 use crate::utils::queue::CQueue;
 use crate::Config;
-use core::analyze::prog_analyze;
+use chrono::Local;
 use core::analyze::static_analyze;
 use core::analyze::RTable;
+use core::analyze::{self, LearnerKind};
 use core::c::to_prog;
-use core::gen::gen;
+use core::gen::{gen, LenCurve, LenPreset};
 use core::minimize::remove;
-use core::mutate::mutate;
+use core::mutate::mutate_prog;
 use core::prog::Prog;
+use core::subsystem::classify_crash;
+use core::sweep::sweep_flags;
 use core::target::Target;
 use executor::{ExecResult, Reason};
-use fots::types::GroupId;
+use fots::types::{FnId, GroupId};
 use itertools::Itertools;
+use rand::seq::IteratorRandom;
+use rand::thread_rng;
 use regex::Regex;
 use std::collections::{HashMap, HashSet};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use tokio::fs::write;
 use tokio::sync::broadcast;
 use tokio::sync::Mutex;
+use tokio::time::delay_for;
+
+/// Corpus progs kept at most in blackbox mode.
+const BLACKBOX_CORPUS: usize = 4096;
+
+/// Knobs of fuzzing loop
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FuzzerConf {
+    /// Number of generated progs per mutated prog, default 100
+    pub gen_mut_ratio: Option<usize>,
+    /// Max number of calls of generated prog, default 16
+    pub prog_max_len: Option<usize>,
+    /// Min number of calls of generated prog, default 1
+    pub prog_min_len: Option<usize>,
+    /// Times a crash prog is re-executed to verify it reproduces, default 1
+    pub repro_attempts: Option<usize>,
+    /// How corpus prog to mutate is selected, default random
+    pub selection_mode: Option<SelectionMode>,
+    /// How many mutations a selected prog gets, default fixed, see `energy`
+    pub power_schedule: Option<PowerSchedule>,
+    /// Extra executions of a reproduced crash prog to measure its repro rate, default 10
+    pub rate_attempts: Option<usize>,
+    /// Reproduced crashes with repro rate below this percent are unreliable, default 20
+    pub min_repro_rate: Option<u8>,
+    /// Seconds spent reproducing a crash before it's deferred, unlimited by default
+    pub repro_budget_secs: Option<u64>,
+    /// Executions spent reproducing a crash before it's deferred, unlimited by default
+    pub repro_budget_execs: Option<usize>,
+    /// Only generate until this many progs are generated, default 0
+    pub warmup_gens: Option<usize>,
+    /// Only generate until corpus has this many progs, default 0
+    pub warmup_corpus: Option<usize>,
+    /// Prepare next prog while guest executes current one, default false
+    pub pipeline: Option<bool>,
+    /// Corpus progs executed after each reboot to recalibrate coverage baseline, default 4
+    pub recalibrate_execs: Option<usize>,
+    /// Reboots without crash caused by mutations of a corpus prog before it's quarantined,
+    /// default 3, 0 disables it
+    pub quarantine_reboots: Option<usize>,
+    /// Variants a call's flag args are swept with once it first ends a corpus prog,
+    /// default 0, which disables sweep
+    pub flag_sweep: Option<usize>,
+    /// Cullings without gain before a corpus prog is retired, i.e. selected less and less
+    /// often, default 0, which disables retirement
+    pub retire_age: Option<usize>,
+    /// Retain blocks each corpus prog first reached, to explain why it's kept, see
+    /// `Corpus::explain`, default false as it costs memory
+    pub explain_corpus: Option<bool>,
+    /// Strategies relations between calls are learned with, default coverage_delta, see
+    /// `core::analyze::RelationLearner`
+    pub relation_learners: Option<Vec<LearnerKind>>,
+    /// Chance a str or byte buffer value is drawn from `dictionaries`, default 0.25
+    pub dict_prob: Option<f64>,
+    /// Distribution of generated prog length, a preset or a curve, default progressive.
+    /// Kept last, a curve is a table for toml serialization of `Config::resolved`.
+    pub len_curve: Option<LenCurveConf>,
+}
+
+impl FuzzerConf {
+    pub fn check(&self) {
+        if self.gen_mut_ratio == Some(0) {
+            eprintln!("Config Error: gen_mut_ratio must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+        if self.repro_attempts == Some(0) {
+            eprintln!("Config Error: repro_attempts must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+        if self.min_repro_rate.is_some_and(|r| r > 100) {
+            eprintln!("Config Error: min_repro_rate must be a percent, 0 to 100");
+            exit(exitcode::CONFIG)
+        }
+        if self.repro_budget_secs == Some(0) || self.repro_budget_execs == Some(0) {
+            eprintln!(
+                "Config Error: repro_budget_secs and repro_budget_execs must be bigger than 0"
+            );
+            exit(exitcode::CONFIG)
+        }
+        let gen_conf = self.gen_conf();
+        if gen_conf.prog_min_len == 0 || gen_conf.prog_min_len > gen_conf.prog_max_len {
+            eprintln!(
+                "Config Error: invalid prog len range [{}, {}]",
+                gen_conf.prog_min_len, gen_conf.prog_max_len
+            );
+            exit(exitcode::CONFIG)
+        }
+        if let Err(e) = gen_conf.len_curve.check() {
+            eprintln!("Config Error: len_curve: {}", e);
+            exit(exitcode::CONFIG)
+        }
+        if self
+            .relation_learners
+            .as_ref()
+            .is_some_and(|l| l.is_empty())
+        {
+            eprintln!("Config Error: relation_learners must not be empty");
+            exit(exitcode::CONFIG)
+        }
+        if self.dict_prob.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+            eprintln!("Config Error: dict_prob must be between 0 and 1");
+            exit(exitcode::CONFIG)
+        }
+    }
+
+    pub fn gen_conf(&self) -> core::gen::Config {
+        let mut conf = core::gen::Config::default();
+        if let Some(max) = self.prog_max_len {
+            conf.prog_max_len = max;
+        }
+        if let Some(min) = self.prog_min_len {
+            conf.prog_min_len = min;
+        }
+        if let Some(prob) = self.dict_prob {
+            conf.dict_prob = prob;
+        }
+        if let Some(curve) = self.len_curve {
+            conf.len_curve = match curve {
+                LenCurveConf::Preset(preset) => LenCurve::from(preset),
+                LenCurveConf::Curve(curve) => curve
+                    .curve()
+                    .unwrap_or_else(|e| exits!(exitcode::CONFIG, "Config Error: len_curve: {}", e)),
+            };
+            // don't cut a triangular curve short unless asked to
+            if let (None, LenCurve::Triangular { max, .. }) = (self.prog_max_len, conf.len_curve) {
+                conf.prog_max_len = conf.prog_max_len.max(max);
+            }
+        }
+        conf
+    }
+}
+
+/// Length curve of generated progs, either named, e.g. `len_curve = "short"`, or explicit,
+/// e.g. `len_curve = { kind = "triangular", min = 2, mode = 8, max = 24 }`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum LenCurveConf {
+    Preset(LenPreset),
+    Curve(CurveConf),
+}
+
+/// Explicit length curve, fields of its `kind` are required and others rejected. Flat
+/// rather than an enum of struct variants, which toml can't serialize.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CurveConf {
+    pub kind: CurveKind,
+    /// Mean of geometric curve
+    pub mean: Option<f64>,
+    /// Min, mode and max of triangular curve
+    pub min: Option<usize>,
+    pub mode: Option<usize>,
+    pub max: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CurveKind {
+    Progressive,
+    Geometric,
+    Triangular,
+}
+
+impl CurveConf {
+    pub fn curve(&self) -> Result<LenCurve, String> {
+        let (mean, tri) = (
+            self.mean.is_some(),
+            self.min.is_some() || self.mode.is_some() || self.max.is_some(),
+        );
+        match (self.kind, self.mean, self.min, self.mode, self.max) {
+            (CurveKind::Progressive, None, None, None, None) => Ok(LenCurve::Progressive),
+            (CurveKind::Geometric, Some(mean), None, None, None) => {
+                Ok(LenCurve::Geometric { mean })
+            }
+            (CurveKind::Triangular, None, Some(min), Some(mode), Some(max)) => {
+                Ok(LenCurve::Triangular { min, mode, max })
+            }
+            (CurveKind::Progressive, ..) => Err(String::from("progressive curve takes no fields")),
+            (CurveKind::Geometric, ..) if tri || !mean => {
+                Err(String::from("geometric curve takes only mean"))
+            }
+            _ => Err(String::from("triangular curve takes min, mode and max")),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Fuzzer {
     pub target: Arc<Target>,
     pub rt: Arc<Mutex<HashMap<GroupId, RTable>>>,
     pub conf: core::gen::Config,
+    pub gen_mut_ratio: usize,
+    pub repro_attempts: usize,
+    pub rate_attempts: usize,
+    pub min_repro_rate: u8,
+    /// Time and executions a crash is reproduced for at a time, see `backlog`
+    pub repro_budget: (Option<Duration>, Option<usize>),
+    pub selection_mode: SelectionMode,
+    pub power_schedule: PowerSchedule,
+    /// Next prog is generated or mutated while current one executes
+    pub pipeline: bool,
+    pub recent: Arc<Recent>,
+    pub warmup_gens: usize,
+    pub warmup_corpus: usize,
+    /// Warm-up is over, mutation is enabled
+    pub warmed_up: Arc<AtomicBool>,
+    /// Multiplier of `gen_mut_ratio` when coverage plateaued, None if reseed is disabled
+    pub reseed_intensity: Option<usize>,
+    /// Coverage plateaued, generation and novelty selection are favored, see `plateau`
+    pub reseeding: Arc<AtomicBool>,
     pub corpus: Arc<Corpus>,
     pub feedback: Arc<FeedBack>,
     pub candidates: Arc<CQueue<Prog>>,
     pub record: Arc<TestCaseRecord>,
     pub exec_cnt: Arc<AtomicUsize>,
+    pub trimmed_cnt: Arc<AtomicUsize>,
+    /// Calls of a prog executed at most, see `Fuzzer::truncate`
+    pub max_calls: Option<usize>,
+    /// Progs truncated to `max_calls` before execution
+    pub truncated_cnt: Arc<AtomicUsize>,
+    /// Generated progs of each job
+    pub gen_cnts: Arc<Vec<AtomicUsize>>,
+    pub subsystems: Arc<SubsystemStats>,
+    pub call_timeouts: Arc<CallTimeouts>,
     pub crash_digests: Arc<Mutex<HashSet<md5::Digest>>>,
+    /// Crashes waiting to be reproduced
+    pub backlog: Arc<CrashBacklog>,
+    /// Calls disabled during run, see `toggle`
+    pub active_calls: Arc<ActiveCalls>,
+    /// Guest and host failures, see `failures`
+    pub failures: Arc<Failures>,
+    /// Faults of each call injected at most for new corpus progs, None if disabled
+    pub fault_max_nth: Option<u32>,
+    /// Corpus progs executed after each reboot before coverage counts as new
+    pub recalibrate_execs: usize,
+    /// Post-reboot executions whose new coverage was taken as baseline
+    pub spurious_cnt: Arc<AtomicUsize>,
+    /// Reboots without crash a corpus prog may cause before it's quarantined, 0 if never
+    pub quarantine_reboots: usize,
+    /// Cullings without gain before a corpus prog is retired, 0 if never
+    pub retire_age: usize,
+    /// No coverage is collected, progs executed fine fill a capped corpus instead of
+    /// covering something new, see `Corpus::insert_capped`
+    pub blackbox: bool,
+    /// Callbacks of embedding program, see `hooks`
+    pub hooks: HookSender,
+    /// Log of every executed prog, None if disabled, see `replay`
+    pub replay: Option<Arc<ReplayLog>>,
+    /// Write corpus as syzkaller corpus.db at exit, see `syzdb::pack`
+    pub export_corpus_db: bool,
+    /// Output dir of each job, None if disabled, see `job_dir`
+    pub job_dirs: Option<JobDirs>,
+    /// Max variants of flag args a call is swept with, 0 if sweep is disabled
+    pub flag_sweep: usize,
+    /// Calls already swept, each is swept once per run
+    pub swept: Arc<Mutex<HashSet<FnId>>>,
+    /// Sweep executions and new blocks they found, counted apart from random ones
+    pub sweep_execs: Arc<AtomicUsize>,
+    pub sweep_blocks: Arc<AtomicUsize>,
+    /// Progs sent to and fetched from syz-hub, see `hub`
+    pub hub_sent: Arc<AtomicUsize>,
+    pub hub_recv: Arc<AtomicUsize>,
+    /// Strategies relations are learned with, and relations each of them learned
+    pub relation_learners: Vec<LearnerKind>,
+    pub relations_learned: Arc<Vec<AtomicUsize>>,
+    /// Corpus progs with a str or byte buffer value of `conf.dict`
+    pub dict_corpus: Arc<AtomicUsize>,
+    /// New corpus progs cross-checked with syz-execprog, None if disabled, see `cross_check`
+    pub cross_checks: Option<Arc<CrossChecks>>,
+    /// Raw PC dumps of coverage, kernel_obj resolved, None if disabled, see `cover_dump`
+    pub cover_dump: Option<CoverDumpConf>,
+    /// Kernel build dir of `symbolize`, to symbolize explanations of corpus progs
+    pub kernel_obj: Option<PathBuf>,
+    /// Jobs wait instead of starting next cycle, see `rpc`
+    pub paused: Arc<AtomicBool>,
+    /// Stop was requested through `rpc`
+    pub stop_requested: Arc<AtomicBool>,
 
-    pub suppressions: Vec<Regex>,
+    /// Grows during run, see `rpc`
+    pub suppressions: Arc<RwLock<Vec<Regex>>>,
+    pub suppress_subsystems: Vec<String>,
+    /// Signatures of crashes suppressed, e.g. fixed ones of `regression`
+    pub suppress_titles: HashSet<String>,
     pub ignores: Vec<Regex>,
+    pub trim: bool,
+    /// Total executions of all jobs to stop at
+    pub max_execs: Option<usize>,
+    /// Saved crashes to stop at
+    pub max_crashes: Option<usize>,
+    /// Guest reboots of all jobs, crashes and failures alike
+    pub reboots: Arc<AtomicUsize>,
+    /// Output dir of this run
+    pub run_dir: PathBuf,
+    /// Id of fuzzing job, prefix of its logs
+    pub job: usize,
+}
+
+/// What a `Fuzzer` is built from. State shared with the embedding program, e.g. corpus
+/// and coverage, is taken from here instead of created, so it can be passed in.
+pub struct FuzzerDeps<'a> {
+    pub target: Arc<Target>,
+    pub cfg: &'a Config,
+    /// Output dir of this run
+    pub run_dir: PathBuf,
+    /// Progs executed before any is generated or mutated
+    pub candidates: Vec<Prog>,
+    pub corpus: Arc<Corpus>,
+    pub feedback: Arc<FeedBack>,
+    pub record: Arc<TestCaseRecord>,
+    pub hooks: HookSender,
+}
+
+impl<'a> FuzzerDeps<'a> {
+    /// Deps of a fresh run, with empty corpus and coverage and no hooks.
+    pub fn new(
+        target: Arc<Target>,
+        candidates: Vec<Prog>,
+        cfg: &'a Config,
+        run_dir: PathBuf,
+    ) -> Self {
+        let symbolizer = cfg
+            .symbolize
+            .clone()
+            .map(|conf| Symbolizer::spawn(conf, &cfg.guest));
+        let mut record = TestCaseRecord::new(target.clone(), run_dir.clone(), symbolizer);
+        if cfg.syz_crash_layout.unwrap_or(false) {
+            record.enable_syz_crashes();
+        }
+        if let Some(rules) = cfg.severity.clone() {
+            record.set_severity_rules(rules);
+        }
+        let record = Arc::new(record);
+        Self {
+            target,
+            cfg,
+            run_dir,
+            candidates,
+            corpus: Arc::new(Corpus::default()),
+            feedback: Arc::new(FeedBack::default()),
+            record,
+            hooks: HookSender::default(),
+        }
+    }
 }
 
 impl Fuzzer {
-    pub fn new(target: Target, candidates: Vec<Prog>, cfg: &Config) -> Self {
-        let target = Arc::new(target);
-        let record = Arc::new(TestCaseRecord::new(target.clone()));
+    pub fn new(deps: FuzzerDeps) -> Self {
+        let FuzzerDeps {
+            target,
+            cfg,
+            run_dir,
+            candidates,
+            corpus,
+            feedback,
+            record,
+            hooks,
+        } = deps;
         let rt = static_analyze(&target);
+        let subsystems = Arc::new(SubsystemStats::new(&target));
+        let learners = cfg
+            .fuzzer
+            .as_ref()
+            .and_then(|f| f.relation_learners.clone())
+            .unwrap_or_else(|| vec![LearnerKind::CoverageDelta]);
+        if cfg.fuzzer.as_ref().and_then(|f| f.explain_corpus) == Some(true) {
+            corpus.retain_reasons();
+        }
+        let default_timeout = if cfg.executor.memleak_check {
+            3000
+        } else {
+            1000
+        };
+        let call_timeouts = Arc::new(CallTimeouts::new(&target, default_timeout));
         Self {
+            active_calls: Arc::new(ActiveCalls::new(&target)),
             target,
             record,
             crash_digests: Arc::new(Mutex::new(HashSet::new())),
+            backlog: Arc::new(CrashBacklog::new(&run_dir)),
+            failures: Arc::new(Failures::new(run_dir.clone())),
             exec_cnt: Arc::new(AtomicUsize::new(0)),
+            trimmed_cnt: Arc::new(AtomicUsize::new(0)),
+            max_calls: cfg.executor.max_calls,
+            truncated_cnt: Arc::new(AtomicUsize::new(0)),
+            gen_cnts: Arc::new((0..cfg.vm_num).map(|_| AtomicUsize::new(0)).collect()),
+            subsystems,
+            call_timeouts,
             rt: Arc::new(Mutex::new(rt)),
-            conf: Default::default(),
+            conf: cfg
+                .fuzzer
+                .as_ref()
+                .map(FuzzerConf::gen_conf)
+                .unwrap_or_default(),
+            gen_mut_ratio: cfg
+                .fuzzer
+                .as_ref()
+                .and_then(|f| f.gen_mut_ratio)
+                .unwrap_or(100),
+            repro_attempts: cfg
+                .fuzzer
+                .as_ref()
+                .and_then(|f| f.repro_attempts)
+                .unwrap_or(1),
+            rate_attempts: cfg
+                .fuzzer
+                .as_ref()
+                .and_then(|f| f.rate_attempts)
+                .unwrap_or(10),
+            min_repro_rate: cfg
+                .fuzzer
+                .as_ref()
+                .and_then(|f| f.min_repro_rate)
+                .unwrap_or(20),
+            repro_budget: cfg
+                .fuzzer
+                .as_ref()
+                .map(|f| {
+                    (
+                        f.repro_budget_secs.map(Duration::from_secs),
+                        f.repro_budget_execs,
+                    )
+                })
+                .unwrap_or_default(),
+            selection_mode: cfg
+                .fuzzer
+                .as_ref()
+                .and_then(|f| f.selection_mode)
+                .unwrap_or_default(),
+            power_schedule: cfg
+                .fuzzer
+                .as_ref()
+                .and_then(|f| f.power_schedule)
+                .unwrap_or_default(),
+            pipeline: cfg
+                .fuzzer
+                .as_ref()
+                .and_then(|f| f.pipeline)
+                .unwrap_or(false),
+            recalibrate_execs: cfg
+                .fuzzer
+                .as_ref()
+                .and_then(|f| f.recalibrate_execs)
+                .unwrap_or(4),
+            spurious_cnt: Arc::new(AtomicUsize::new(0)),
+            hooks,
+            replay: cfg
+                .replay_log
+                .as_ref()
+                .map(|conf| Arc::new(ReplayLog::new(conf, &run_dir))),
+            export_corpus_db: cfg.export_corpus_db.unwrap_or(false),
+            job_dirs: if cfg.per_job_dirs.unwrap_or(false) {
+                Some(JobDirs::new(run_dir.clone()))
+            } else {
+                None
+            },
+            flag_sweep: cfg.fuzzer.as_ref().and_then(|f| f.flag_sweep).unwrap_or(0),
+            swept: Arc::new(Mutex::new(HashSet::new())),
+            sweep_execs: Arc::new(AtomicUsize::new(0)),
+            sweep_blocks: Arc::new(AtomicUsize::new(0)),
+            hub_sent: Arc::new(AtomicUsize::new(0)),
+            hub_recv: Arc::new(AtomicUsize::new(0)),
+            relation_learners: learners.clone(),
+            relations_learned: Arc::new(learners.iter().map(|_| AtomicUsize::new(0)).collect()),
+            dict_corpus: Arc::new(AtomicUsize::new(0)),
+            cross_checks: cfg
+                .executor
+                .cross_check_rate
+                .filter(|r| *r > 0.0)
+                .map(|r| Arc::new(CrossChecks::new(r))),
+            cover_dump: cfg.cover_dump.clone().map(|mut conf| {
+                conf.kernel_obj = conf
+                    .kernel_obj
+                    .or_else(|| cfg.symbolize.as_ref().map(|s| s.kernel_obj.clone()));
+                conf
+            }),
+            kernel_obj: cfg.symbolize.as_ref().map(|s| s.kernel_obj.clone()),
+            paused: Arc::new(AtomicBool::new(false)),
+            stop_requested: Arc::new(AtomicBool::new(false)),
+            quarantine_reboots: cfg
+                .fuzzer
+                .as_ref()
+                .and_then(|f| f.quarantine_reboots)
+                .unwrap_or(3),
+            retire_age: cfg.fuzzer.as_ref().and_then(|f| f.retire_age).unwrap_or(0),
+            blackbox: cfg.blackbox.unwrap_or(false),
+            recent: Arc::new(Recent::default()),
+            warmup_gens: cfg.fuzzer.as_ref().and_then(|f| f.warmup_gens).unwrap_or(0),
+            warmup_corpus: cfg
+                .fuzzer
+                .as_ref()
+                .and_then(|f| f.warmup_corpus)
+                .unwrap_or(0),
+            warmed_up: Arc::new(AtomicBool::new(false)),
+            reseed_intensity: cfg.plateau.as_ref().map(PlateauConf::intensity),
+            reseeding: Arc::new(AtomicBool::new(false)),
             candidates: Arc::new(CQueue::from(candidates)),
-            corpus: Arc::new(Corpus::default()),
-            feedback: Arc::new(FeedBack::default()),
+            corpus,
+            feedback,
 
-            suppressions: cfg
-                .suppressions
-                .clone()
-                .unwrap_or_default()
-                .iter()
-                .map(|s| Regex::new(s).unwrap())
-                .collect(),
+            suppress_subsystems: cfg.suppress_subsystems.clone().unwrap_or_default(),
+            suppress_titles: HashSet::new(),
+            suppressions: Arc::new(RwLock::new(
+                cfg.suppressions
+                    .clone()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|s| Regex::new(s).unwrap())
+                    .collect(),
+            )),
             ignores: cfg
                 .ignores
                 .clone()
@@ -72,47 +568,277 @@ impl Fuzzer {
                 .iter()
                 .map(|i| Regex::new(i).unwrap())
                 .collect(),
+            fault_max_nth: cfg
+                .executor
+                .fault_injection
+                .as_ref()
+                .map(FaultConf::max_nth),
+            trim: cfg.trim.unwrap_or(true),
+            max_execs: cfg.max_execs.map(|n| n as usize),
+            max_crashes: cfg.max_crashes,
+            reboots: Arc::new(AtomicUsize::new(0)),
+            run_dir,
+            job: 0,
         }
     }
 
     pub fn stats(&self) -> StatSource {
         StatSource {
             exec: self.exec_cnt.clone(),
+            max_execs: self.max_execs,
+            trimmed: self.trimmed_cnt.clone(),
+            truncated: self.truncated_cnt.clone(),
+            spurious: self.spurious_cnt.clone(),
+            reboots: self.reboots.clone(),
+            sweep_execs: self.sweep_execs.clone(),
+            sweep_blocks: self.sweep_blocks.clone(),
+            hub_sent: self.hub_sent.clone(),
+            hub_recv: self.hub_recv.clone(),
+            relation_learners: self.relation_learners.clone(),
+            relations_learned: self.relations_learned.clone(),
+            dict_corpus: self.dict_corpus.clone(),
+            cross_checks: self.cross_checks.clone(),
+            hooks: self.hooks.clone(),
+            subsystems: self.subsystems.clone(),
+            call_timeouts: self.call_timeouts.clone(),
             corpus: self.corpus.clone(),
             feedback: self.feedback.clone(),
             candidates: self.candidates.clone(),
             record: self.record.clone(),
+            backlog: self.backlog.clone(),
+            active_calls: self.active_calls.clone(),
+            failures: self.failures.clone(),
+            run_dir: self.run_dir.clone(),
         }
     }
-    pub async fn fuzz(self, executor: Executor, mut shutdown: broadcast::Receiver<()>) {
+    pub async fn fuzz(
+        mut self,
+        job: usize,
+        executor: Box<dyn Executor>,
+        mut shutdown: broadcast::Receiver<()>,
+    ) {
+        self.job = job;
+        let mut state = JobState::new(&self, executor);
+        // in-flight prog is finished, including its triage, before job stops.
+        let run = async { while self.run_once(&mut state).await != StepOutcome::Exhausted {} };
         tokio::select! {
             _ = shutdown.recv() => (),
-            _ = self.do_fuzz(executor) => ()
+            _ = run => ()
         }
     }
 
-    async fn do_fuzz(&self, mut executor: Executor) {
-        let mut gen_cnt = 0;
-        loop {
-            let p = self.get_prog(&mut gen_cnt).await;
-            match executor.exec(&p, &self.target).await {
-                Ok(exec_result) => match exec_result {
-                    ExecResult::Ok(raw_branches) => {
-                        self.feedback_analyze(p, raw_branches, &mut executor).await
-                    }
-                    ExecResult::Failed(reason) => self.failed_analyze(p, reason).await,
-                },
-                Err(crash) => {
-                    self.crash_analyze(p, crash.unwrap_or_default(), &mut executor)
-                        .await
+    /// One cycle of job `self.job`: recalibrate after a reboot, reproduce a backlogged
+    /// crash, or get a prog by generation or mutation, execute and triage it.
+    pub async fn run_once(&self, state: &mut JobState) -> StepOutcome {
+        if self.budget_exhausted() {
+            return StepOutcome::Exhausted;
+        }
+        if self.paused.load(Ordering::Relaxed) {
+            delay_for(Duration::from_millis(200)).await;
+            return StepOutcome::Paused;
+        }
+        let JobState {
+            executor,
+            conf,
+            version,
+            seed,
+            next,
+            boots,
+        } = state;
+        let executor = &mut **executor;
+        let job = self.job;
+        let gen_cnt = &self.gen_cnts[job];
+        if executor.boots() != *boots {
+            self.reboots
+                .fetch_add(executor.boots() - *boots, Ordering::Relaxed);
+            *boots = executor.boots();
+            self.recalibrate(executor).await;
+            return StepOutcome::Recalibrated;
+        }
+        if let Some((path, raw)) = self.backlog.pop().await {
+            match self.repro(raw, executor).await {
+                Repro::Saved => self.backlog.done(&path).await,
+                // raw crash of unreliable repro is kept for re-reproducing by a resumed run.
+                Repro::Unreliable => (),
+                Repro::Deferred(raw) => self.backlog.defer(path, raw).await,
+            }
+            return StepOutcome::Reproduced;
+        }
+        if self.active_calls.version() != *version {
+            *version = self.active_calls.version();
+            conf.disabled_calls = self.active_calls.disabled().await;
+        }
+        let (p, parent) = match next.take() {
+            Some(next) => next,
+            None => self.get_prog(conf, gen_cnt, seed).await,
+        };
+        let p = self.truncate(p);
+        // candidates, mutated and prepared progs may still contain disabled calls.
+        if p.calls.iter().any(|c| conf.disabled_calls.contains(&c.fid)) {
+            return StepOutcome::Skipped;
+        }
+        for c in p.calls.iter() {
+            self.subsystems.inc_exec(self.target.subsystem_of(c.fid));
+        }
+        if self.selection_mode != SelectionMode::Random || self.reseed_intensity.is_some() {
+            self.recent.push(&p);
+        }
+        let boots = executor.boots();
+        let exec = async {
+            let mut span = trace::span("execute", job);
+            span.set_prog_len(p.len());
+            executor.exec(&p, &self.target).await
+        };
+        // next prog is prepared while waiting for guest, so it doesn't see corpus
+        // and relations updated by triage of this one, which still happens in order.
+        let ret = if self.pipeline {
+            let prepare = self.get_prog(conf, gen_cnt, seed);
+            let (ret, prepared) = tokio::join!(exec, prepare);
+            *next = Some(prepared);
+            ret
+        } else {
+            exec.await
+        };
+        let outcome = outcome_of(ret);
+        // vm died or guest was restarted for hanging, executor failing and so on.
+        let false_reboot = match &outcome {
+            Outcome::Crashed(_) => false,
+            Outcome::Infra(e) if e.is_crash() => true,
+            _ => executor.boots() != boots,
+        };
+        if let Some(key) = parent.filter(|_| false_reboot && self.quarantine_reboots != 0) {
+            self.false_reboot(key, seed).await;
+        }
+        if let Some(replay) = self.replay.as_ref() {
+            replay.log(job, &p, replay_outcome(&outcome));
+        }
+        let step = match outcome {
+            Outcome::Covered(_) if self.blackbox => {
+                self.blackbox_analyze(p).await;
+                StepOutcome::Executed
+            }
+            Outcome::Covered(raw_branches) => {
+                let _span = trace::span("ingest-coverage", job);
+                let gained = self.feedback_analyze(p, raw_branches, executor).await;
+                if let Some(key) = parent.filter(|_| gained && self.retire_age != 0) {
+                    self.corpus.gain(key);
                 }
-            };
-            self.exec_cnt.fetch_add(1, Ordering::SeqCst);
+                StepOutcome::Executed
+            }
+            Outcome::Failed(reason) => {
+                self.failed_analyze(p, reason).await;
+                StepOutcome::Failed
+            }
+            Outcome::Crashed(crash) => {
+                self.crash_analyze(p, None, crash, executor).await;
+                StepOutcome::Crashed
+            }
+            Outcome::Infra(e) => {
+                self.infra_failed(e, executor).await;
+                StepOutcome::Infra
+            }
+        };
+        self.exec_cnt.fetch_add(1, Ordering::SeqCst);
+        step
+    }
+
+    /// Cut `p` down to `max_calls`, e.g. after a long chain of mutations inserting calls.
+    /// Calls only refer to resources of calls before them, so the kept prefix is a valid prog.
+    fn truncate(&self, p: Prog) -> Prog {
+        match self.max_calls {
+            Some(max) if p.len() > max => {
+                if self.truncated_cnt.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!(
+                        "job-{}: prog of {} calls truncated to executor max_calls {}, see stats for how often",
+                        self.job,
+                        p.len(),
+                        max
+                    );
+                }
+                p.sub_prog(max - 1)
+            }
+            _ => p,
+        }
+    }
+
+    /// Count reboot without crash caused by a mutation of corpus prog `key`, see
+    /// `Corpus::false_reboot`. Job stops mutating the prog once it's quarantined.
+    async fn false_reboot(&self, key: u64, seed: &mut Option<(Prog, usize)>) {
+        if let Some(p) = self.corpus.false_reboot(key, self.quarantine_reboots).await {
+            warn!(
+                "job-{}: Corpus prog quarantined, its mutations caused {} reboot(s) without crash:\n{}",
+                self.job,
+                self.quarantine_reboots,
+                to_prog(&p, &self.target)
+            );
+            if seed.as_ref().is_some_and(|(s, _)| s == &p) {
+                *seed = None;
+            }
+        }
+    }
+
+    /// Progs are only generated during warm-up, which ends once any of the configured
+    /// thresholds is reached. Generated progs of all jobs count.
+    pub async fn warming_up(&self) -> bool {
+        if self.warmed_up.load(Ordering::Relaxed)
+            || (self.warmup_gens == 0 && self.warmup_corpus == 0)
+        {
+            return false;
+        }
+        let gens = self
+            .gen_cnts
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .sum::<usize>();
+        let corpus = self.corpus.len().await;
+        let reached = |n: usize, threshold: usize| threshold != 0 && n >= threshold;
+        if !reached(gens, self.warmup_gens) && !reached(corpus, self.warmup_corpus) {
+            return true;
         }
+        if !self.warmed_up.swap(true, Ordering::Relaxed) {
+            info!(
+                "Warm-up done: {} prog(s) generated, corpus {}, mutation enabled",
+                gens, corpus
+            );
+        }
+        false
+    }
+
+    pub fn budget_exhausted(&self) -> bool {
+        self.max_execs
+            .is_some_and(|n| self.exec_cnt.load(Ordering::SeqCst) >= n)
+    }
+
+    /// Write why each corpus prog is kept, see `Corpus::explain`, followed by the prog, to
+    /// `corpus_explained` of run dir.
+    async fn explain_corpus(&self) {
+        let symbols = self.kernel_obj.as_deref().and_then(|dir| {
+            SymbolTable::load(dir)
+                .map_err(|e| warn!("Corpus: fail to load symbols: {}", e))
+                .ok()
+        });
+        let progs = self
+            .corpus
+            .inner
+            .lock()
+            .await
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut explained = String::new();
+        for p in progs.iter() {
+            if let Some(reason) = self.corpus.explain(p, self.retire_age, symbols.as_ref()) {
+                explained += &format!("# {}\n{}\n", reason, to_prog(p, &self.target));
+            }
+        }
+        let path = self.run_dir.join("corpus_explained");
+        write(&path, explained)
+            .await
+            .unwrap_or_else(|e| warn!("Fail to write {}: {}", path.display(), e));
     }
 
     pub async fn persist(self) {
-        let corpus_path = "./corpus";
+        let corpus_path = self.run_dir.join("corpus");
         let corpus = self
             .corpus
             .dump()
@@ -122,54 +848,311 @@ impl Fuzzer {
             exits!(
                 exitcode::IOERR,
                 "Fail to persist corpus to {} : {}",
-                corpus_path,
+                corpus_path.display(),
                 e
             )
         });
+        let quarantined = self.corpus.quarantined();
+        if !quarantined.is_empty() {
+            let path = self.run_dir.join("quarantined_corpus");
+            let progs = bincode::serialize(&quarantined).unwrap_or_else(|e| {
+                exits!(exitcode::DATAERR, "Fail to dump quarantined corpus: {}", e)
+            });
+            write(&path, progs).await.unwrap_or_else(|e| {
+                warn!(
+                    "Fail to persist quarantined corpus to {}: {}",
+                    path.display(),
+                    e
+                )
+            });
+        }
         self.record.psersist().await;
+        if let Some(replay) = self.replay.as_ref() {
+            replay.flush();
+        }
+        if let Some(dirs) = self.job_dirs.as_ref() {
+            dirs.aggregate(self.gen_cnts.len()).await;
+        }
+        if let Some(conf) = self.cover_dump.as_ref() {
+            cover_dump::dump(&self, conf).await;
+        }
+        if self.corpus.retains_reasons() {
+            self.explain_corpus().await;
+        }
+        if self.export_corpus_db {
+            let progs = self
+                .corpus
+                .inner
+                .lock()
+                .await
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            let path = self.run_dir.join("corpus.db");
+            let db = syzdb::pack(&progs, &self.target);
+            write(&path, db)
+                .await
+                .unwrap_or_else(|e| warn!("Fail to export corpus to {}: {}", path.display(), e));
+        }
+
+        let relations_path = self.run_dir.join("relations.json");
+        let relations = relations::export_json(&self.target, &*self.rt.lock().await);
+        write(&relations_path, relations).await.unwrap_or_else(|e| {
+            warn!(
+                "Fail to export relations to {}: {}",
+                relations_path.display(),
+                e
+            )
+        });
+
+        let state_path = self.run_dir.join("fuzzer_state.json");
+        let state = serde_json::to_string_pretty(&FuzzerState::save(&self).await).unwrap();
+        write(&state_path, state).await.unwrap_or_else(|e| {
+            exits!(
+                exitcode::IOERR,
+                "Fail to persist fuzzer state to {} : {}",
+                state_path.display(),
+                e
+            )
+        });
     }
 
     async fn failed_analyze(&self, p: Prog, reason: Reason) {
         self.record.insert_failed(p, reason).await
     }
 
-    async fn crash_analyze(&self, p: Prog, crash: Crash, executor: &mut Executor) {
+    /// Analyze `crash` of `p`, executed with `fault` injected if any.
+    async fn crash_analyze(
+        &self,
+        p: Prog,
+        fault: Option<Fault>,
+        crash: Crash,
+        executor: &mut dyn Executor,
+    ) {
         if self.should_ignore(&crash.inner) {
-            warn!("Crashed, match ignores, restarting ...");
-            executor.start().await;
+            warn!("job-{}: Crashed, match ignores, restarting ...", self.job);
+            executor.restart().await;
             return;
         }
 
         if self.should_suppress(&crash.inner).await {
-            self.record.insert_crash(p, crash, false).await;
-            warn!("Crashed, match suppressions, restarting ...");
-            executor.start().await;
+            self.save_crash(self.job, p, crash, ReproScore::default(), fault)
+                .await;
+            warn!(
+                "job-{}: Crashed, match suppressions, restarting ...",
+                self.job
+            );
+            executor.restart().await;
             return;
         }
 
-        warn!("========== Crashed ========= \n{}", crash);
+        warn!("job-{}: ========== Crashed ========= \n{}", self.job, crash);
         let p_str = to_prog(&p, &self.target);
-        warn!("Caused by:\n{}", p_str);
-        warn!("Restarting to repro ...");
-        executor.start().await;
+        warn!("job-{}: Caused by:\n{}", self.job, p_str);
+        if let Some(fault) = fault.as_ref() {
+            warn!("job-{}: With fault injection: {}", self.job, fault);
+        }
+        warn!("job-{}: Restarting to repro ...", self.job);
+        executor.restart().await;
 
-        self.exec_cnt.fetch_add(1, Ordering::SeqCst);
-        match executor.exec(&p, &self.target).await {
-            Ok(exec_result) => {
-                match exec_result {
-                    ExecResult::Ok(_) => warn!("Repo failed, executed successfully"),
-                    ExecResult::Failed(reason) => warn!("Repo failed, executed failed: {}", reason),
-                };
-                self.record.insert_crash(p, crash, false).await
+        let raw = RawCrash {
+            prog: p_str.to_string(),
+            p,
+            crash,
+            job: self.job,
+            found_time: Local::now(),
+            fault,
+            progress: ReproProgress::default(),
+        };
+        if let Some(dropped) = self.backlog.push(raw).await {
+            warn!(
+                "job-{}: Crash backlog is full, saving oldest crash of job-{} without repro",
+                self.job, dropped.job
+            );
+            self.save_crash(
+                dropped.job,
+                dropped.p,
+                dropped.crash,
+                ReproScore::default(),
+                dropped.fault,
+            )
+            .await;
+        }
+    }
+
+    /// Save crash of `p` found by `job`, in its job dir too if enabled.
+    async fn save_crash(
+        &self,
+        job: usize,
+        p: Prog,
+        crash: Crash,
+        score: ReproScore,
+        fault: Option<Fault>,
+    ) {
+        let log = self.job_dirs.as_ref().map(|_| crash.inner.clone());
+        let summary = self.record.insert_crash(p, crash, score, fault).await;
+        if let (Some(dirs), Some(log)) = (self.job_dirs.as_ref(), log) {
+            dirs.add_crash(job, &summary.path, &log).await;
+        }
+        self.hooks.crash(|| summary);
+    }
+
+    /// Re-execute prog of `raw` to verify the crash, measure repro rate of reproduced
+    /// one, then save it. Repro continues from progress of `raw` and stops once repro
+    /// budget runs out, the crash is deferred then.
+    /// Reproduce crash of `p` found outside of fuzzing jobs, e.g. by repro mode, the same
+    /// way as crashes they find, and save it. It's resumed right away whenever repro budget
+    /// defers it. Return false if its repro rate is below `min_repro_rate`.
+    pub(crate) async fn repro_found(
+        &self,
+        p: Prog,
+        crash: Crash,
+        executor: &mut dyn Executor,
+    ) -> bool {
+        let mut raw = RawCrash {
+            prog: to_prog(&p, &self.target).to_string(),
+            p,
+            crash,
+            job: self.job,
+            found_time: Local::now(),
+            fault: None,
+            progress: ReproProgress::default(),
+        };
+        loop {
+            match self.repro(raw, executor).await {
+                Repro::Saved => return true,
+                Repro::Unreliable => return false,
+                Repro::Deferred(deferred) => raw = deferred,
+            }
+        }
+    }
+
+    async fn repro(&self, mut raw: RawCrash, executor: &mut dyn Executor) -> Repro {
+        let mut budget = ReproBudget::new(self.repro_budget);
+        if !self
+            .resume_repro(&raw.p, raw.fault, &mut raw.progress, &mut budget, executor)
+            .await
+        {
+            raw.progress.deferred += 1;
+            warn!(
+                "job-{}: Repro budget exhausted after {} exec(s) in {}s, {}/{} attempt(s) and {}/{} rate exec(s) done, deferred",
+                self.job,
+                budget.execs,
+                budget.start.elapsed().as_secs(),
+                raw.progress.attempts,
+                self.repro_attempts,
+                raw.progress.rate_attempts,
+                self.rate_attempts
+            );
+            return Repro::Deferred(raw);
+        }
+
+        let RawCrash {
+            p,
+            crash,
+            progress,
+            fault,
+            job,
+            ..
+        } = raw;
+        let mut score = ReproScore {
+            attempts: self.repro_attempts,
+            reproduced: progress.reproduced,
+            rate: None,
+        };
+        warn!("job-{}: Repo score: {}", self.job, score);
+        if score.reproduced != 0 && self.rate_attempts != 0 {
+            let mut rate = ReproRate {
+                attempts: self.rate_attempts,
+                hits: progress.rate_hits,
+                ..ReproRate::default()
+            };
+            rate.unreliable = rate.percent() < f64::from(self.min_repro_rate);
+            warn!("job-{}: Repro rate: {}", self.job, rate);
+            score.rate = Some(rate);
+        }
+        self.save_crash(job, p, progress.crash.unwrap_or(crash), score, fault)
+            .await;
+        if score.rate.is_some_and(|r| r.unreliable) {
+            Repro::Unreliable
+        } else {
+            Repro::Saved
+        }
+    }
+
+    /// Run repro attempts of `p` left in `progress`, then rate executions if reproduced,
+    /// each with `fault` injected. Return false if `budget` runs out first.
+    async fn resume_repro(
+        &self,
+        p: &Prog,
+        fault: Option<Fault>,
+        progress: &mut ReproProgress,
+        budget: &mut ReproBudget,
+        executor: &mut dyn Executor,
+    ) -> bool {
+        while progress.attempts < self.repro_attempts {
+            if !budget.spend() {
+                return false;
             }
-            Err(repo_crash) => {
-                self.record
-                    .insert_crash(p, repo_crash.unwrap_or(crash), true)
-                    .await;
-                warn!("Repo successfully, restarting guest ...");
-                executor.start().await;
+            progress.attempts += 1;
+            let i = progress.attempts;
+            self.exec_cnt.fetch_add(1, Ordering::SeqCst);
+            executor.set_fault(fault);
+            match executor.exec(p, &self.target).await {
+                Ok(ExecResult::Ok(_)) => warn!(
+                    "job-{}: Repo attempt {}/{} failed, executed successfully",
+                    self.job, i, self.repro_attempts
+                ),
+                Ok(ExecResult::Failed(reason)) => warn!(
+                    "job-{}: Repo attempt {}/{} failed, executed failed: {}",
+                    self.job, i, self.repro_attempts, reason
+                ),
+                Err(ExecError::KernelCrash(c)) => {
+                    progress.reproduced += 1;
+                    progress.crash.get_or_insert(c);
+                    warn!(
+                        "job-{}: Repo attempt {}/{} successfully, restarting guest ...",
+                        self.job, i, self.repro_attempts
+                    );
+                    executor.restart().await;
+                }
+                Err(e) => {
+                    warn!(
+                        "job-{}: Repo attempt {}/{} failed: {}",
+                        self.job, i, self.repro_attempts, e
+                    );
+                    self.record_failure(&e).await;
+                    if e.is_crash() {
+                        executor.restart().await;
+                    }
+                }
             }
         }
+        if progress.reproduced == 0 {
+            return true;
+        }
+        while progress.rate_attempts < self.rate_attempts {
+            if !budget.spend() {
+                return false;
+            }
+            progress.rate_attempts += 1;
+            self.exec_cnt.fetch_add(1, Ordering::SeqCst);
+            executor.set_fault(fault);
+            match executor.exec(p, &self.target).await {
+                Err(ExecError::KernelCrash(_)) => {
+                    progress.rate_hits += 1;
+                    executor.restart().await;
+                }
+                Err(e) => {
+                    self.record_failure(&e).await;
+                    if e.is_crash() {
+                        executor.restart().await;
+                    }
+                }
+                Ok(_) => (),
+            }
+        }
+        true
     }
 
     fn should_ignore(&self, reason: &str) -> bool {
@@ -187,21 +1170,59 @@ impl Fuzzer {
             return true;
         }
 
-        if self.suppressions.iter().any(|s| s.is_match(reason)) {
+        if self
+            .suppressions
+            .read()
+            .unwrap()
+            .iter()
+            .any(|s| s.is_match(reason))
+        {
             return true;
         }
 
-        let digest = md5::compute(reason);
+        if !self.suppress_subsystems.is_empty() {
+            let subsystem = classify_crash(reason);
+            if self.suppress_subsystems.iter().any(|s| s == subsystem) {
+                return true;
+            }
+        }
+
+        if !self.suppress_titles.is_empty() && self.suppress_titles.contains(&signature_of(reason))
+        {
+            return true;
+        }
+
+        let digest = digest_of(reason);
         let mut g = self.crash_digests.lock().await;
         !g.insert(digest)
     }
 
+    /// Add sub progs of `p` covering something new to corpus, return whether any was added.
+    /// Learn relations of `p` executed without coverage and keep it for mutation.
+    async fn blackbox_analyze(&self, p: Prog) {
+        self.learn_relations(&p).await;
+        self.corpus.insert_capped(p, BLACKBOX_CORPUS).await;
+    }
+
+    /// Learn relations of `p` with each relation learner, counting what each learned.
+    async fn learn_relations(&self, p: &Prog) {
+        let g = &self.target.groups[&p.gid];
+        let learned = {
+            let mut r = self.rt.lock().await;
+            analyze::learn(&self.relation_learners, g, r.get_mut(&p.gid).unwrap(), p)
+        };
+        for (cnt, n) in self.relations_learned.iter().zip(learned) {
+            cnt.fetch_add(n, Ordering::Relaxed);
+        }
+    }
+
     async fn feedback_analyze(
         &self,
         p: Prog,
         raw_blocks: Vec<Vec<usize>>,
-        executor: &mut Executor,
-    ) {
+        executor: &mut dyn Executor,
+    ) -> bool {
+        let mut gained = false;
         for (call_index, raw_blocks) in raw_blocks.iter().enumerate() {
             let (new_blocks_1, new_branches_1) = self.check_new_feedback(raw_blocks).await;
 
@@ -214,21 +1235,23 @@ impl Fuzzer {
                         let (new_block_2, new_branches_2) =
                             self.check_new_feedback(&raw_blocks[call_index]).await;
 
-                        let new_block: HashSet<_> =
-                            new_blocks_1.intersection(&new_block_2).cloned().collect();
-                        let new_branches: HashSet<_> = new_branches_1
-                            .intersection(&new_branches_2)
-                            .cloned()
-                            .collect();
+                        let new_block = intersect(&new_blocks_1, &new_block_2);
+                        let new_branches = intersect(&new_branches_1, &new_branches_2);
 
                         if !new_block.is_empty() || !new_branches.is_empty() {
-                            let minimized_p = self.minimize(&p, &new_block, executor).await;
+                            let minimized_p = if self.trim {
+                                let _span = trace::span("cull", self.job);
+                                let minimized_p = self.minimize(&p, &new_block, executor).await;
+                                self.trimmed_cnt
+                                    .fetch_add(p.len() - minimized_p.len(), Ordering::SeqCst);
+                                minimized_p
+                            } else {
+                                p.clone()
+                            };
+                            let now = Instant::now();
                             let raw_branches = self.exec_no_fail(executor, &minimized_p).await;
-                            {
-                                let g = &self.target.groups[&p.gid];
-                                let mut r = self.rt.lock().await;
-                                prog_analyze(g, r.get_mut(&p.gid).unwrap(), &p);
-                            }
+                            let exec_tm = now.elapsed();
+                            self.learn_relations(&p).await;
 
                             let mut blocks = Vec::new();
                             let mut branches = Vec::new();
@@ -250,21 +1273,204 @@ impl Fuzzer {
                                     &new_branches,
                                 )
                                 .await;
-                            self.corpus.insert(minimized_p).await;
+                            let subsystem = self.target.subsystem_of(p.calls[call_index].fid);
+                            self.subsystems.add_blocks(subsystem, new_block.len());
+                            let score = branches.iter().map(|b| b.len()).sum();
+                            let new_cnts = (new_block.len(), new_branches.len());
+                            let reason = self
+                                .corpus
+                                .retains_reasons()
+                                .then(|| new_block.iter().map(Block::pc).collect::<Vec<_>>());
                             self.feedback.merge(new_block, new_branches).await;
+                            if let Some(max_nth) = self.fault_max_nth {
+                                let _span = trace::span("inject-faults", self.job);
+                                self.inject_faults(&minimized_p, max_nth, executor).await;
+                            }
+                            if self.flag_sweep != 0 {
+                                let _span = trace::span("sweep-flags", self.job);
+                                self.sweep(&minimized_p, executor).await;
+                            }
+                            if let Some(checks) = self.cross_checks.as_ref().filter(|c| c.sampled())
+                            {
+                                let _span = trace::span("cross-check", self.job);
+                                self.cross_check(checks, &minimized_p, &raw_branches, executor)
+                                    .await;
+                            }
+                            self.hooks.new_input(|| InputSummary {
+                                job: self.job,
+                                prog: to_prog(&minimized_p, &self.target).to_string(),
+                                calls: minimized_p.len(),
+                                new_blocks: new_cnts.0,
+                                new_branches: new_cnts.1,
+                                exec_ms: exec_tm.as_millis() as u64,
+                            });
+                            if let Some(dirs) = self.job_dirs.as_ref() {
+                                let name = format!(
+                                    "{:x}",
+                                    md5::compute(bincode::serialize(&minimized_p).unwrap())
+                                );
+                                let prog = to_prog(&minimized_p, &self.target).to_string();
+                                dirs.add_queue(self.job, &name, &prog).await;
+                            }
+                            let key = key_of(&minimized_p);
+                            let has_token = self.conf.dict.contains_token(&minimized_p);
+                            if self.corpus.insert(minimized_p, exec_tm, score).await {
+                                gained = true;
+                                if has_token {
+                                    self.dict_corpus.fetch_add(1, Ordering::Relaxed);
+                                }
+                                if let Some(pcs) = reason {
+                                    self.corpus.add_reason(key, pcs);
+                                }
+                            }
                         }
                     }
                 }
             }
         }
+        gained
     }
 
-    async fn minimize(
+    /// Execute `p`, which covered `raw_branches`, with syz-execprog too and warn about
+    /// calls whose coverage persistently differs.
+    async fn cross_check(
         &self,
+        checks: &CrossChecks,
         p: &Prog,
-        new_block: &HashSet<Block>,
-        executor: &mut Executor,
-    ) -> Prog {
+        raw_branches: &[Vec<usize>],
+        executor: &mut dyn Executor,
+    ) {
+        let text = core::syz::encode(p, &self.target);
+        match executor.execprog(&text).await {
+            Ok(syz) => {
+                for name in checks.record(p, &self.target, raw_branches, &syz) {
+                    warn!(
+                        "job-{}: {} persistently mismatches syz-execprog coverage, args may be encoded wrongly",
+                        self.job, name
+                    );
+                }
+            }
+            Err(e) => warn!("job-{}: failed to cross-check: {}", self.job, e),
+        }
+    }
+
+    /// Execute a few corpus progs after guest rebooted and take what they newly cover as
+    /// baseline. Their coverage was known before reboot, so anything new comes from boot,
+    /// e.g. shifted addresses or boot-time state, and would make progs executed right
+    /// after reboot look interesting spuriously.
+    async fn recalibrate(&self, executor: &mut dyn Executor) {
+        if self.recalibrate_execs == 0 {
+            return;
+        }
+        let progs = {
+            let corpus = self.corpus.inner.lock().await;
+            corpus
+                .iter()
+                .choose_multiple(&mut thread_rng(), self.recalibrate_execs)
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let mut shifted = false;
+        for p in progs {
+            if self.budget_exhausted() {
+                break;
+            }
+            self.exec_cnt.fetch_add(1, Ordering::SeqCst);
+            // guest rebooting again is left to caller, which recalibrates once more.
+            let raw_blocks = match outcome_of(executor.exec(&p, &self.target).await) {
+                Outcome::Covered(raw_blocks) => raw_blocks,
+                Outcome::Failed(_) => continue,
+                Outcome::Crashed(crash) => {
+                    return self.crash_analyze(p, None, crash, executor).await;
+                }
+                Outcome::Infra(e) => return self.infra_failed(e, executor).await,
+            };
+            let (mut covered, mut new) = (0, 0);
+            for raw_blocks in raw_blocks.iter() {
+                let (blocks, branches) = self.cook_raw_block(raw_blocks);
+                let new_blocks = self.feedback.diff_block(&blocks[..]).await;
+                let new_branches = self.feedback.diff_branch(&branches[..]).await;
+                covered += blocks.len();
+                new += new_blocks.len();
+                self.feedback.merge(new_blocks, new_branches).await;
+            }
+            if new != 0 {
+                self.spurious_cnt.fetch_add(1, Ordering::Relaxed);
+            }
+            if new * 2 > covered && !shifted {
+                shifted = true;
+                warn!(
+                    "job-{}: {} of {} blocks of corpus prog are new after reboot, coverage \
+                     baseline shifted, is KASLR disabled in guest?",
+                    self.job, new, covered
+                );
+            }
+        }
+    }
+
+    /// Execute `p` with each fault of its schedule, see `fault`.
+    async fn inject_faults(&self, p: &Prog, max_nth: u32, executor: &mut dyn Executor) {
+        let mut schedule = FaultSchedule::new(p.len(), max_nth);
+        while let Some(fault) = schedule.current() {
+            if self.budget_exhausted() {
+                break;
+            }
+            self.exec_cnt.fetch_add(1, Ordering::SeqCst);
+            executor.set_fault(Some(fault));
+            let injected = match outcome_of(executor.exec(p, &self.target).await) {
+                Outcome::Covered(_) | Outcome::Failed(_) => executor.fault_injected(),
+                Outcome::Crashed(crash) => {
+                    self.crash_analyze(p.clone(), Some(fault), crash, executor)
+                        .await;
+                    true
+                }
+                Outcome::Infra(e) => {
+                    self.infra_failed(e, executor).await;
+                    true
+                }
+            };
+            schedule.advance(injected);
+        }
+    }
+
+    /// Execute variants of `p` with flag args of its last call, the one that newly
+    /// covered, swept, see `core::sweep`. Variants covering anything new are queued as
+    /// candidates, so they're triaged and minimized like any other prog.
+    async fn sweep(&self, p: &Prog, executor: &mut dyn Executor) {
+        let call = p.len() - 1;
+        if !self.swept.lock().await.insert(p.calls[call].fid) {
+            return;
+        }
+        let mut found = HashSet::new();
+        for v in sweep_flags(p, call, &self.target, self.flag_sweep) {
+            if self.budget_exhausted() {
+                break;
+            }
+            self.exec_cnt.fetch_add(1, Ordering::SeqCst);
+            self.sweep_execs.fetch_add(1, Ordering::Relaxed);
+            let raw_blocks = match outcome_of(executor.exec(&v, &self.target).await) {
+                Outcome::Covered(raw_blocks) => raw_blocks,
+                Outcome::Failed(_) => continue,
+                Outcome::Crashed(crash) => {
+                    return self.crash_analyze(v, None, crash, executor).await
+                }
+                Outcome::Infra(e) => return self.infra_failed(e, executor).await,
+            };
+            let mut new = false;
+            for raw_blocks in raw_blocks.iter() {
+                let (new_blocks, new_branches) = self.check_new_feedback(raw_blocks).await;
+                new |= !new_blocks.is_empty() || !new_branches.is_empty();
+                found.extend(new_blocks.into_vec());
+            }
+            if new {
+                self.candidates.push(v).await;
+            }
+        }
+        self.sweep_blocks.fetch_add(found.len(), Ordering::Relaxed);
+    }
+
+    async fn minimize(&self, p: &Prog, new_block: &[Block], executor: &mut dyn Executor) -> Prog {
         assert!(!p.calls.is_empty());
 
         let mut p = p.clone();
@@ -280,7 +1486,7 @@ impl Fuzzer {
                 i += 1;
             } else if let ExecResult::Ok(cover) = self.exec_no_crash(executor, &p).await {
                 let (new_blocks_1, _) = self.check_new_feedback(cover.last().unwrap()).await;
-                if new_blocks_1.is_empty() || new_blocks_1.intersection(new_block).count() == 0 {
+                if !overlaps(&new_blocks_1, new_block) {
                     i += 1;
                     p = p_orig;
                 }
@@ -292,7 +1498,7 @@ impl Fuzzer {
         p
     }
 
-    async fn check_new_feedback(&self, raw_blocks: &[usize]) -> (HashSet<Block>, HashSet<Branch>) {
+    async fn check_new_feedback(&self, raw_blocks: &[usize]) -> (Box<[Block]>, Box<[Branch]>) {
         let (blocks, branches) = self.cook_raw_block(raw_blocks);
         let new_blocks = self.feedback.diff_block(&blocks[..]).await;
         let new_branches = self.feedback.diff_branch(&branches[..]).await;
@@ -318,47 +1524,489 @@ impl Fuzzer {
         (blocks, branches)
     }
 
-    async fn exec_no_crash(&self, executor: &mut Executor, p: &Prog) -> ExecResult {
+    async fn exec_no_crash(&self, executor: &mut dyn Executor, p: &Prog) -> ExecResult {
         self.exec_cnt.fetch_add(1, Ordering::SeqCst);
-        match executor.exec(p, &self.target).await {
-            Ok(exec_result) => exec_result,
-            Err(crash) => {
-                self.crash_analyze(p.clone(), crash.unwrap_or_default(), executor)
-                    .await;
+        match outcome_of(executor.exec(p, &self.target).await) {
+            Outcome::Covered(raw_branches) => ExecResult::Ok(raw_branches),
+            Outcome::Failed(reason) => ExecResult::Failed(reason),
+            Outcome::Crashed(crash) => {
+                self.crash_analyze(p.clone(), None, crash, executor).await;
                 ExecResult::Failed(Reason(String::from("Crashed")))
             }
+            Outcome::Infra(e) => {
+                let reason = Reason(e.to_string());
+                self.infra_failed(e, executor).await;
+                ExecResult::Failed(reason)
+            }
         }
     }
 
-    async fn exec_no_fail(&self, executor: &mut Executor, p: &Prog) -> Vec<Vec<usize>> {
-        self.exec_cnt.fetch_add(1, Ordering::SeqCst);
-        match executor.exec(p, &self.target).await {
-            Ok(exec_result) => match exec_result {
-                ExecResult::Ok(raw_branches) => raw_branches,
-                ExecResult::Failed(_) => Default::default(),
-            },
-            Err(crash) => {
-                self.crash_analyze(p.clone(), crash.unwrap_or_default(), executor)
-                    .await;
-                Default::default()
-            }
+    async fn exec_no_fail(&self, executor: &mut dyn Executor, p: &Prog) -> Vec<Vec<usize>> {
+        match self.exec_no_crash(executor, p).await {
+            ExecResult::Ok(raw_branches) => raw_branches,
+            ExecResult::Failed(_) => Default::default(),
         }
     }
 
-    async fn get_prog(&self, gen_cnt: &mut usize) -> Prog {
+    /// Count and log failure `e`, in job dir too if enabled.
+    async fn record_failure(&self, e: &ExecError) {
+        self.failures.record(self.job, e).await;
+        if let Some(dirs) = self.job_dirs.as_ref() {
+            let entry = format!("{} failure: {}\n{}", e.kind(), e, e.detail());
+            dirs.append(self.job, "failures.log", &entry).await;
+        }
+    }
+
+    /// Guest or executor broke rather than the prog, nothing is recorded.
+    async fn infra_failed(&self, e: ExecError, executor: &mut dyn Executor) {
+        warn!(
+            "job-{}: {} failure: {}, prog skipped",
+            self.job,
+            e.kind(),
+            e
+        );
+        self.record_failure(&e).await;
+        if e.is_crash() {
+            executor.restart().await;
+        }
+    }
+
+    async fn get_prog(
+        &self,
+        conf: &core::gen::Config,
+        gen_cnt: &AtomicUsize,
+        seed: &mut Option<(Prog, usize)>,
+    ) -> (Prog, Option<u64>) {
+        let (gen_mut_ratio, selection_mode) = match self.reseed_intensity {
+            Some(intensity) if self.reseeding.load(Ordering::Relaxed) => {
+                (self.gen_mut_ratio * intensity, SelectionMode::Novelty)
+            }
+            _ => (self.gen_mut_ratio, self.selection_mode),
+        };
         if let Some(p) = self.candidates.pop().await {
-            p
-        } else if self.corpus.is_empty().await || *gen_cnt % 100 != 0 {
-            *gen_cnt += 1;
+            (p, None)
+        } else if self.corpus.is_empty().await
+            || self.warming_up().await
+            || !gen_cnt.load(Ordering::SeqCst).is_multiple_of(gen_mut_ratio)
+        {
+            gen_cnt.fetch_add(1, Ordering::SeqCst);
+            let _span = trace::span("generate", self.job);
             let rt = self.rt.lock().await;
-            gen(&self.target, &rt, &self.conf)
+            (gen(&self.target, &rt, conf), None)
         } else {
+            let _span = trace::span("mutate", self.job);
             let rt = {
                 let rt = self.rt.lock().await;
                 rt.clone()
             };
             let corpus = self.corpus.inner.lock().await;
-            mutate(&corpus, &self.target, &rt, &self.conf)
+            if !matches!(seed, Some((_, left)) if *left != 0) {
+                let retire_age = self.retire_age;
+                let weight = |p: &Prog| self.corpus.weight_of(p, retire_age);
+                let weight = Some(&weight as &dyn Fn(&Prog) -> f64).filter(|_| retire_age != 0);
+                let p = selection::select(&corpus, selection_mode, &self.recent, weight).clone();
+                let energy = self.corpus.energy_of(&p, self.power_schedule);
+                let culled = Some(retire_age).filter(|a| *a != 0);
+                if let Some(retired) = culled.and_then(|a| self.corpus.cull(a)) {
+                    info!(
+                        "job-{}: Corpus culled, {} of {} prog(s) retired",
+                        self.job,
+                        retired,
+                        corpus.len()
+                    );
+                }
+                *seed = Some((p, energy));
+            }
+            let (p, left) = seed.as_mut().unwrap();
+            *left -= 1;
+            (
+                mutate_prog(p, &corpus, &self.target, &rt, conf),
+                Some(key_of(p)),
+            )
+        }
+    }
+}
+
+/// State of a fuzzing job kept between its cycles, see `Fuzzer::run_once`.
+pub struct JobState {
+    executor: Box<dyn Executor>,
+    /// Job's copy of gen conf, refreshed once calls are toggled
+    conf: core::gen::Config,
+    /// Version of active calls `conf` was refreshed at
+    version: usize,
+    /// Corpus prog being mutated and mutations left of its energy
+    seed: Option<(Prog, usize)>,
+    /// Prog prepared while previous one executed, with pipeline
+    next: Option<(Prog, Option<u64>)>,
+    /// Boots of executor coverage baseline was calibrated at
+    boots: usize,
+}
+
+impl JobState {
+    pub fn new(fuzzer: &Fuzzer, executor: Box<dyn Executor>) -> Self {
+        Self {
+            boots: executor.boots(),
+            executor,
+            conf: fuzzer.conf.clone(),
+            version: 0,
+            seed: None,
+            next: None,
+        }
+    }
+
+    pub fn executor(&mut self) -> &mut dyn Executor {
+        &mut *self.executor
+    }
+
+    pub fn into_executor(self) -> Box<dyn Executor> {
+        self.executor
+    }
+}
+
+/// What a cycle of `Fuzzer::run_once` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// Execution budget is used up, nothing was done
+    Exhausted,
+    /// Guest rebooted, coverage baseline was recalibrated
+    Recalibrated,
+    /// A backlogged crash was reproduced
+    Reproduced,
+    /// Prog contains disabled calls, it was dropped without executing
+    Skipped,
+    /// Prog executed, its coverage was analyzed
+    Executed,
+    /// Prog failed
+    Failed,
+    /// Kernel crashed, crash was analyzed and reproduced
+    Crashed,
+    /// Guest or executor broke, prog was skipped
+    Infra,
+    /// Fuzzer is paused, nothing was done, see `rpc`
+    Paused,
+}
+
+/// How repro of a raw crash ended.
+enum Repro {
+    /// Crash is saved
+    Saved,
+    /// Crash is saved, its repro rate is below `min_repro_rate`
+    Unreliable,
+    /// Repro budget ran out, crash with its progress is to be revisited
+    Deferred(RawCrash),
+}
+
+/// Time and executions left of one visit of a crash in repro.
+struct ReproBudget {
+    start: Instant,
+    execs: usize,
+    limit: (Option<Duration>, Option<usize>),
+}
+
+impl ReproBudget {
+    fn new(limit: (Option<Duration>, Option<usize>)) -> Self {
+        Self {
+            start: Instant::now(),
+            execs: 0,
+            limit,
+        }
+    }
+
+    /// Take one execution from budget, false if it's run out.
+    fn spend(&mut self) -> bool {
+        let (time, execs) = self.limit;
+        if time.is_some_and(|t| self.start.elapsed() >= t) || execs.is_some_and(|n| self.execs >= n)
+        {
+            return false;
+        }
+        self.execs += 1;
+        true
+    }
+}
+
+/// What fuzzing loop does with result of an execution.
+#[derive(Debug)]
+enum Outcome {
+    /// Prog executed, its coverage is analyzed
+    Covered(Vec<Vec<usize>>),
+    /// Prog failed, it's recorded as failed case
+    Failed(Reason),
+    /// Kernel crashed, crash is analyzed and reproduced
+    Crashed(Crash),
+    /// Guest or executor broke, not the prog
+    Infra(ExecError),
+}
+
+fn replay_outcome(outcome: &Outcome) -> ReplayOutcome {
+    match outcome {
+        Outcome::Covered(raw_blocks) => {
+            ReplayOutcome::Covered(raw_blocks.iter().map(Vec::len).sum())
+        }
+        Outcome::Failed(reason) => ReplayOutcome::Failed(reason.0.clone()),
+        Outcome::Crashed(crash) => {
+            ReplayOutcome::Crashed(crash.inner.lines().next().unwrap_or_default().to_string())
+        }
+        Outcome::Infra(e) => ReplayOutcome::Infra(e.to_string()),
+    }
+}
+
+fn outcome_of(ret: Result<ExecResult, ExecError>) -> Outcome {
+    match ret {
+        Ok(ExecResult::Ok(raw_branches)) => Outcome::Covered(raw_branches),
+        Ok(ExecResult::Failed(reason)) => Outcome::Failed(reason),
+        Err(ExecError::KernelCrash(crash)) => Outcome::Crashed(crash),
+        Err(ExecError::Timeout) => Outcome::Failed(Reason(String::from("Time out"))),
+        // guest is alive but unresponsive, the prog may well hang kernel.
+        Err(ExecError::Hang) => Outcome::Failed(Reason(String::from("Hang"))),
+        Err(e @ ExecError::VmDied(_))
+        | Err(e @ ExecError::Transport(_))
+        | Err(e @ ExecError::ExecutorRejected(_)) => Outcome::Infra(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exec::FailureKind;
+    use crate::mock::{self, MockExecutor};
+    use core::gen::gen;
+    use std::env::temp_dir;
+    use std::process::id;
+
+    fn target() -> Arc<Target> {
+        Arc::new(core::bench::target().0)
+    }
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+fots_bin = "./sys"
+vm_num = 2
+[qemu]
+cpu_num = 1
+mem_size = 2048
+image = "./stretch.img"
+kernel = "./bzImage"
+[ssh]
+key_path = "./stretch.id_rsa"
+[executor]
+path = "./executor"
+concurrency = true
+memleak_check = false
+script_mode = false
+[fuzzer]
+gen_mut_ratio = 50
+"#,
+        )
+        .unwrap()
+    }
+
+    /// Run dir `name` of a test, with dirs crashes are saved to.
+    fn run_dir(name: &str) -> PathBuf {
+        let run_dir = temp_dir().join(format!("healer-fuzzer-{}-{}", name, id()));
+        for dir in &["crashes", "raw_crashes"] {
+            std::fs::create_dir_all(run_dir.join(dir)).unwrap();
+        }
+        run_dir
+    }
+
+    #[test]
+    fn fuzzer_from_deps() {
+        let cfg = config();
+        let corpus = Arc::new(Corpus::default());
+        let fuzzer = Fuzzer::new(FuzzerDeps {
+            corpus: corpus.clone(),
+            ..FuzzerDeps::new(target(), Vec::new(), &cfg, temp_dir())
+        });
+        assert!(Arc::ptr_eq(&fuzzer.corpus, &corpus));
+        assert_eq!(fuzzer.gen_mut_ratio, 50);
+        assert_eq!(fuzzer.gen_cnts.len(), 2);
+        assert!(!fuzzer.budget_exhausted());
+    }
+
+    #[test]
+    fn long_progs_are_truncated() {
+        let t = target();
+        let rt = static_analyze(&t);
+        let p = std::iter::repeat_with(|| gen(&t, &rt, &core::gen::Config::default()))
+            .find(|p| p.len() > 2)
+            .unwrap();
+        let cfg = config();
+        let mut fuzzer = Fuzzer::new(FuzzerDeps::new(t, Vec::new(), &cfg, temp_dir()));
+        fuzzer.max_calls = Some(2);
+        assert_eq!(fuzzer.truncate(p.clone()), p.sub_prog(1));
+        assert_eq!(fuzzer.truncate(p.sub_prog(0)), p.sub_prog(0));
+        assert_eq!(fuzzer.truncated_cnt.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn new_coverage_appends_corpus() {
+        let t = target();
+        let p = gen(&t, &static_analyze(&t), &core::gen::Config::default());
+        let cfg = config();
+        let run_dir = run_dir("coverage");
+        let fuzzer = Fuzzer::new(FuzzerDeps::new(
+            t,
+            vec![p.clone(), p.clone()],
+            &cfg,
+            run_dir.clone(),
+        ));
+        let mut state = JobState::new(&fuzzer, Box::new(MockExecutor::covering()));
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            assert_eq!(fuzzer.run_once(&mut state).await, StepOutcome::Executed);
+            // each distinct call covers blocks of its own, and is kept by one corpus prog
+            // ending with it.
+            let fids = p.calls.iter().map(|c| c.fid).collect::<HashSet<_>>();
+            assert_eq!(fuzzer.corpus.len().await, fids.len());
+            for fid in fids.iter() {
+                let blocks = mock::blocks_of(*fid)
+                    .into_iter()
+                    .map(Block::from)
+                    .collect::<Vec<_>>();
+                assert!(fuzzer.feedback.diff_block(&blocks).await.is_empty());
+            }
+            let (executed, _, _) = fuzzer.record.len().await;
+            assert_eq!(executed, fids.len());
+
+            // same prog again covers nothing new.
+            assert_eq!(fuzzer.run_once(&mut state).await, StepOutcome::Executed);
+            assert_eq!(fuzzer.corpus.len().await, fids.len());
+        });
+        std::fs::remove_dir_all(&run_dir).unwrap();
+    }
+
+    #[test]
+    fn crash_is_reproduced_and_saved() {
+        const KASAN: &str = "BUG: KASAN: use-after-free in tcp_v4_rcv+0x2f1/0x3a0
+Read of size 8 at addr ffff888012345678 by task executor/1234
+";
+        let t = target();
+        let p = gen(&t, &static_analyze(&t), &core::gen::Config::default());
+        let cfg = config();
+        let run_dir = run_dir("crash");
+        let fuzzer = Fuzzer::new(FuzzerDeps::new(t, vec![p], &cfg, run_dir.clone()));
+        let executor = MockExecutor::new(|_| {
+            Err(ExecError::KernelCrash(Crash {
+                inner: String::from(KASAN),
+            }))
+        });
+        let mut state = JobState::new(&fuzzer, Box::new(executor));
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            assert_eq!(fuzzer.run_once(&mut state).await, StepOutcome::Crashed);
+            assert_eq!(fuzzer.backlog.len().await, 1);
+            // guest was restarted after crash, empty corpus leaves nothing to recalibrate.
+            assert_eq!(fuzzer.run_once(&mut state).await, StepOutcome::Recalibrated);
+            assert_eq!(fuzzer.run_once(&mut state).await, StepOutcome::Reproduced);
+            let (_, _, crashed) = fuzzer.record.len().await;
+            assert_eq!(crashed, 1);
+            assert_eq!(fuzzer.record.repro_summary().await, (1, 0));
+            // crashing execution, repro attempt and rate executions.
+            assert_eq!(fuzzer.exec_cnt.load(Ordering::SeqCst), 1 + 1 + 10);
+        });
+        assert_eq!(
+            std::fs::read_dir(run_dir.join("crashes")).unwrap().count(),
+            1
+        );
+        std::fs::remove_dir_all(&run_dir).unwrap();
+    }
+
+    #[test]
+    fn outcome_of_exec_errors() {
+        assert!(matches!(
+            outcome_of(Ok(ExecResult::Ok(vec![vec![1, 2]]))),
+            Outcome::Covered(b) if b == vec![vec![1, 2]]
+        ));
+        assert!(matches!(
+            outcome_of(Ok(ExecResult::Failed(Reason(String::from("EINVAL"))))),
+            Outcome::Failed(r) if r.0 == "EINVAL"
+        ));
+        let crash = Crash {
+            inner: String::from("BUG: KASAN: use-after-free in tcp_close"),
+        };
+        assert!(matches!(
+            outcome_of(Err(ExecError::KernelCrash(crash))),
+            Outcome::Crashed(c) if c.inner.starts_with("BUG: KASAN")
+        ));
+        assert!(matches!(
+            outcome_of(Err(ExecError::Timeout)),
+            Outcome::Failed(r) if r.0 == "Time out"
+        ));
+        assert!(matches!(
+            outcome_of(Err(ExecError::Hang)),
+            Outcome::Failed(r) if r.0 == "Hang"
+        ));
+        assert!(matches!(
+            outcome_of(Err(ExecError::VmDied(String::from("qemu: terminating on signal 15")))),
+            Outcome::Infra(e) if e.is_crash() && e.kind() == FailureKind::Host
+        ));
+        for e in [
+            ExecError::Transport(String::from("prog send blocked")),
+            ExecError::ExecutorRejected(String::from("Segmentation fault")),
+        ] {
+            assert!(matches!(outcome_of(Err(e)), Outcome::Infra(e) if !e.is_crash()));
+        }
+        assert_eq!(
+            ExecError::Transport(String::from("malformed response")).kind(),
+            FailureKind::Host
+        );
+        assert_eq!(
+            ExecError::ExecutorRejected(String::from("Segmentation fault")).kind(),
+            FailureKind::Guest
+        );
+    }
+
+    /// Generating long progs and executing them, execution simulated by waiting `EXEC_TM` as
+    /// guest would take, without and with pipeline.
+    /// Run with `cargo test --release -p fuzzer -- --ignored --nocapture pipeline`.
+    #[test]
+    #[ignore]
+    fn pipeline_throughput() {
+        const PROGS: u32 = 1000;
+        const EXEC_TM: Duration = Duration::from_millis(2);
+        let (t, rt) = core::bench::target();
+        // long progs, so that generation takes about as long as execution.
+        let conf = core::gen::Config {
+            prog_min_len: 128,
+            prog_max_len: 256,
+            str_max_len: 4096,
+            ..Default::default()
+        };
+
+        let begin = Instant::now();
+        for _ in 0..PROGS {
+            gen(&t, &rt, &conf);
         }
+        let gen_tm = begin.elapsed() / PROGS;
+
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        let (sequential, pipelined) = runtime.block_on(async {
+            let begin = Instant::now();
+            for _ in 0..PROGS {
+                let _p = gen(&t, &rt, &conf);
+                tokio::time::delay_for(EXEC_TM).await;
+            }
+            let sequential = begin.elapsed();
+
+            let begin = Instant::now();
+            let mut next = gen(&t, &rt, &conf);
+            for _ in 0..PROGS {
+                let _p = next;
+                let prepare = async { gen(&t, &rt, &conf) };
+                let (_, prepared) = tokio::join!(tokio::time::delay_for(EXEC_TM), prepare);
+                next = prepared;
+            }
+            (sequential, begin.elapsed())
+        });
+        let rate = |tm: Duration| f64::from(PROGS) / tm.as_secs_f64();
+        println!(
+            "gen {:?}/prog, exec {:?}/prog: {:.0} exec/s without pipeline, {:.0} exec/s with",
+            gen_tm,
+            EXEC_TM,
+            rate(sequential),
+            rate(pipelined)
+        );
+        assert!(pipelined < sequential);
     }
 }
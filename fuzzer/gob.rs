@@ -0,0 +1,582 @@
+//! Just enough of Go's gob encoding to make net/rpc calls, as syz-hub speaks nothing else.
+//!
+//! A gob stream is a sequence of messages, each a length followed by a type id and either
+//! a type definition, if the id is negative, or a value of that type. Each side defines
+//! its types once per stream before sending values of them. Outgoing values are described
+//! by `Type`, incoming ones are decoded along whatever types the peer defined into generic
+//! `Value`s, so fields the hub adds or drops in other versions don't matter, as with Go's
+//! own decoder.
+use std::collections::HashMap;
+
+/// Ids of types every gob stream has.
+const BOOL: i64 = 1;
+const INT: i64 = 2;
+const UINT: i64 = 3;
+const FLOAT: i64 = 4;
+const BYTES: i64 = 5;
+const STRING: i64 = 6;
+/// First id of types a stream defines.
+const FIRST_ID: i64 = 65;
+
+/// Type of outgoing value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Bool,
+    Uint,
+    Bytes,
+    Str,
+    Slice(Box<Type>),
+    /// Name and fields
+    Struct(&'static str, Vec<(&'static str, Type)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Bytes(Vec<u8>),
+    Str(String),
+    /// Slice or array
+    List(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    /// Fields sent, zero ones are left out by peer
+    Struct(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Field `name` of struct value.
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Struct(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Uint(n) => Some(*n as i64),
+            _ => None,
+        }
+    }
+
+    /// Byte slices of a `[][]byte` value.
+    pub fn as_bytes_list(&self) -> Vec<&[u8]> {
+        match self {
+            Value::List(vals) => vals
+                .iter()
+                .filter_map(|v| match v {
+                    Value::Bytes(b) => Some(&b[..]),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Value::Bool(b) => !b,
+            Value::Int(n) => *n == 0,
+            Value::Uint(n) => *n == 0,
+            Value::Float(f) => *f == 0.0,
+            Value::Bytes(b) => b.is_empty(),
+            Value::Str(s) => s.is_empty(),
+            Value::List(vals) => vals.is_empty(),
+            Value::Map(vals) => vals.is_empty(),
+            Value::Struct(_) => false,
+        }
+    }
+}
+
+/// Outgoing half of a stream.
+#[derive(Default)]
+pub struct Encoder {
+    /// Ids of types defined so far, by name
+    ids: HashMap<String, i64>,
+}
+
+impl Encoder {
+    /// Messages of `val` of type `ty`, preceded by definitions of types not sent yet.
+    pub fn encode(&mut self, ty: &Type, val: &Value) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        let id = self.define(ty, &mut out);
+        let mut msg = Vec::new();
+        put_int(&mut msg, id);
+        if !matches!(ty, Type::Struct(..)) {
+            // a non-struct value is sent as a singleton field.
+            put_uint(&mut msg, 0);
+        }
+        put_val(&mut msg, ty, val)?;
+        put_msg(&mut out, &msg);
+        Ok(out)
+    }
+
+    /// Id of `ty`, its definition and those of its parts are appended to `out` if new.
+    fn define(&mut self, ty: &Type, out: &mut Vec<u8>) -> i64 {
+        let (name, elems) = match ty {
+            Type::Bool => return BOOL,
+            Type::Uint => return UINT,
+            Type::Bytes => return BYTES,
+            Type::Str => return STRING,
+            Type::Slice(elem) => (type_name(ty), vec![&**elem]),
+            Type::Struct(name, fields) => (
+                String::from(*name),
+                fields.iter().map(|(_, ty)| ty).collect(),
+            ),
+        };
+        if let Some(id) = self.ids.get(&name) {
+            return *id;
+        }
+        let id = FIRST_ID + self.ids.len() as i64;
+        self.ids.insert(name.clone(), id);
+        let elem_ids = elems
+            .into_iter()
+            .map(|ty| self.define(ty, out))
+            .collect::<Vec<_>>();
+
+        // wireType {ArrayT, SliceT, StructT, ..} with CommonType {Name, Id} first in each.
+        let mut msg = Vec::new();
+        put_int(&mut msg, -id);
+        let common = |msg: &mut Vec<u8>| {
+            put_uint(msg, 1);
+            put_uint(msg, 1);
+            put_str(msg, &name);
+            put_uint(msg, 1);
+            put_int(msg, id);
+            put_uint(msg, 0);
+        };
+        match ty {
+            Type::Slice(_) => {
+                put_uint(&mut msg, 2);
+                common(&mut msg);
+                put_uint(&mut msg, 1);
+                put_int(&mut msg, elem_ids[0]);
+                put_uint(&mut msg, 0);
+            }
+            Type::Struct(_, fields) => {
+                put_uint(&mut msg, 3);
+                common(&mut msg);
+                if !fields.is_empty() {
+                    put_uint(&mut msg, 1);
+                    put_uint(&mut msg, fields.len() as u64);
+                    for ((name, _), id) in fields.iter().zip(elem_ids) {
+                        put_uint(&mut msg, 1);
+                        put_str(&mut msg, name);
+                        put_uint(&mut msg, 1);
+                        put_int(&mut msg, id);
+                        put_uint(&mut msg, 0);
+                    }
+                }
+                put_uint(&mut msg, 0);
+            }
+            _ => unreachable!(),
+        }
+        put_uint(&mut msg, 0);
+        put_msg(out, &msg);
+        id
+    }
+}
+
+/// Name Go gives `ty`, e.g. `[][]uint8`.
+fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Bool => String::from("bool"),
+        Type::Uint => String::from("uint"),
+        Type::Bytes => String::from("[]uint8"),
+        Type::Str => String::from("string"),
+        Type::Slice(elem) => format!("[]{}", type_name(elem)),
+        Type::Struct(name, _) => String::from(*name),
+    }
+}
+
+fn put_val(out: &mut Vec<u8>, ty: &Type, val: &Value) -> Result<(), String> {
+    match (ty, val) {
+        (Type::Bool, Value::Bool(b)) => put_uint(out, *b as u64),
+        (Type::Uint, Value::Uint(n)) => put_uint(out, *n),
+        (Type::Bytes, Value::Bytes(b)) => {
+            put_uint(out, b.len() as u64);
+            out.extend(b);
+        }
+        (Type::Str, Value::Str(s)) => put_str(out, s),
+        (Type::Slice(elem), Value::List(vals)) => {
+            put_uint(out, vals.len() as u64);
+            for v in vals {
+                put_val(out, elem, v)?;
+            }
+        }
+        (Type::Struct(name, fields), Value::Struct(vals)) => {
+            let mut last = -1;
+            for (i, (field, ty)) in fields.iter().enumerate() {
+                let v = match vals.iter().find(|(n, _)| n == field) {
+                    Some((_, v)) if !v.is_zero() => v,
+                    _ => continue,
+                };
+                put_uint(out, (i as i64 - last) as u64);
+                put_val(out, ty, v).map_err(|e| format!("{}.{}: {}", name, field, e))?;
+                last = i as i64;
+            }
+            put_uint(out, 0);
+        }
+        (ty, val) => return Err(format!("{:?} isn't a {}", val, type_name(ty))),
+    }
+    Ok(())
+}
+
+fn put_msg(out: &mut Vec<u8>, msg: &[u8]) {
+    put_uint(out, msg.len() as u64);
+    out.extend(msg);
+}
+
+/// Small uints are a byte, others are the negated count of big-endian bytes that follow.
+fn put_uint(out: &mut Vec<u8>, n: u64) {
+    if n < 0x80 {
+        out.push(n as u8);
+        return;
+    }
+    let bytes = n.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    out.push((skip as u8).wrapping_sub(8));
+    out.extend(&bytes[skip..]);
+}
+
+/// Sign goes in the lowest bit, complemented if negative.
+fn put_int(out: &mut Vec<u8>, n: i64) {
+    let u = if n < 0 {
+        (!(n as u64) << 1) | 1
+    } else {
+        (n as u64) << 1
+    };
+    put_uint(out, u);
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_uint(out, s.len() as u64);
+    out.extend(s.as_bytes());
+}
+
+/// Type defined by peer.
+#[derive(Debug, Clone)]
+enum WireType {
+    List(i64),
+    Map(i64, i64),
+    Struct(Vec<(String, i64)>),
+    /// Values of types with their own encoding, e.g. of GobEncoder, are opaque bytes
+    Opaque,
+}
+
+/// Incoming half of a stream.
+#[derive(Default)]
+pub struct Decoder {
+    types: HashMap<i64, WireType>,
+}
+
+impl Decoder {
+    /// Decode message `msg`, without its length. None if it's a type definition.
+    pub fn decode(&mut self, mut msg: &[u8]) -> Result<Option<Value>, String> {
+        let r = &mut msg;
+        let id = get_int(r)?;
+        if id < 0 {
+            let ty = get_wire_type(r)?;
+            self.types.insert(-id, ty);
+            return Ok(None);
+        }
+        if !matches!(self.types.get(&id), Some(WireType::Struct(_))) && get_uint(r)? != 0 {
+            return Err(String::from("bad singleton value"));
+        }
+        self.get_val(r, id).map(Some)
+    }
+
+    fn get_val(&self, r: &mut &[u8], id: i64) -> Result<Value, String> {
+        let val = match id {
+            BOOL => Value::Bool(get_uint(r)? != 0),
+            INT => Value::Int(get_int(r)?),
+            UINT => Value::Uint(get_uint(r)?),
+            FLOAT => Value::Float(f64::from_bits(get_uint(r)?.swap_bytes())),
+            BYTES => Value::Bytes(get_bytes(r)?.to_vec()),
+            STRING => Value::Str(String::from_utf8_lossy(get_bytes(r)?).into_owned()),
+            id => match self.types.get(&id) {
+                Some(WireType::List(elem)) => {
+                    let n = get_uint(r)?;
+                    let mut vals = Vec::new();
+                    for _ in 0..n {
+                        vals.push(self.get_val(r, *elem)?);
+                    }
+                    Value::List(vals)
+                }
+                Some(WireType::Map(key, elem)) => {
+                    let n = get_uint(r)?;
+                    let mut vals = Vec::new();
+                    for _ in 0..n {
+                        vals.push((self.get_val(r, *key)?, self.get_val(r, *elem)?));
+                    }
+                    Value::Map(vals)
+                }
+                Some(WireType::Struct(fields)) => {
+                    let mut vals = Vec::new();
+                    let mut field = -1;
+                    loop {
+                        let delta = get_uint(r)?;
+                        if delta == 0 {
+                            break;
+                        }
+                        field += delta as i64;
+                        let (name, id) = fields
+                            .get(field as usize)
+                            .ok_or_else(|| format!("no field {} in type {}", field, id))?;
+                        vals.push((name.clone(), self.get_val(r, *id)?));
+                    }
+                    Value::Struct(vals)
+                }
+                Some(WireType::Opaque) => Value::Bytes(get_bytes(r)?.to_vec()),
+                None => return Err(format!("undefined type {}", id)),
+            },
+        };
+        Ok(val)
+    }
+}
+
+fn get_wire_type(r: &mut &[u8]) -> Result<WireType, String> {
+    let mut ty = WireType::Opaque;
+    get_struct(r, |r, field| {
+        ty = match field {
+            // ArrayT {CommonType, Elem, Len}, SliceT {CommonType, Elem}
+            0 | 1 => {
+                let mut elem = 0;
+                get_struct(r, |r, f| {
+                    match f {
+                        0 => skip_common(r)?,
+                        1 => elem = get_int(r)?,
+                        _ => {
+                            get_uint(r)?;
+                        }
+                    }
+                    Ok(())
+                })?;
+                WireType::List(elem)
+            }
+            // StructT {CommonType, Field []fieldType {Name, Id}}
+            2 => {
+                let mut fields = Vec::new();
+                get_struct(r, |r, f| {
+                    match f {
+                        0 => skip_common(r)?,
+                        _ => {
+                            for _ in 0..get_uint(r)? {
+                                let (mut name, mut id) = (String::new(), 0);
+                                get_struct(r, |r, f| {
+                                    match f {
+                                        0 => name = String::from_utf8_lossy(get_bytes(r)?).into(),
+                                        _ => id = get_int(r)?,
+                                    }
+                                    Ok(())
+                                })?;
+                                fields.push((name, id));
+                            }
+                        }
+                    }
+                    Ok(())
+                })?;
+                WireType::Struct(fields)
+            }
+            // MapT {CommonType, Key, Elem}
+            3 => {
+                let (mut key, mut elem) = (0, 0);
+                get_struct(r, |r, f| {
+                    match f {
+                        0 => skip_common(r)?,
+                        1 => key = get_int(r)?,
+                        _ => elem = get_int(r)?,
+                    }
+                    Ok(())
+                })?;
+                WireType::Map(key, elem)
+            }
+            // GobEncoderT, BinaryMarshalerT, TextMarshalerT {CommonType}
+            _ => {
+                get_struct(r, |r, _| skip_common(r))?;
+                WireType::Opaque
+            }
+        };
+        Ok(())
+    })?;
+    Ok(ty)
+}
+
+fn skip_common(r: &mut &[u8]) -> Result<(), String> {
+    get_struct(r, |r, f| {
+        match f {
+            0 => {
+                get_bytes(r)?;
+            }
+            _ => {
+                get_int(r)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Read fields of a struct, calling `f` with index of each.
+fn get_struct<F>(r: &mut &[u8], mut f: F) -> Result<(), String>
+where
+    F: FnMut(&mut &[u8], i64) -> Result<(), String>,
+{
+    let mut field = -1;
+    loop {
+        let delta = get_uint(r)?;
+        if delta == 0 {
+            return Ok(());
+        }
+        field += delta as i64;
+        f(r, field)?;
+    }
+}
+
+pub fn get_uint(r: &mut &[u8]) -> Result<u64, String> {
+    let b = *r.first().ok_or("unexpected end of message")?;
+    *r = &r[1..];
+    if b < 0x80 {
+        return Ok(b as u64);
+    }
+    let n = b.wrapping_neg() as usize;
+    if n > 8 || r.len() < n {
+        return Err(String::from("bad uint"));
+    }
+    let v = r[..n].iter().fold(0u64, |v, b| v << 8 | *b as u64);
+    *r = &r[n..];
+    Ok(v)
+}
+
+fn get_int(r: &mut &[u8]) -> Result<i64, String> {
+    let u = get_uint(r)?;
+    Ok(if u & 1 == 1 {
+        !(u >> 1) as i64
+    } else {
+        (u >> 1) as i64
+    })
+}
+
+fn get_bytes<'a>(r: &mut &'a [u8]) -> Result<&'a [u8], String> {
+    let n = get_uint(r)? as usize;
+    if r.len() < n {
+        return Err(String::from("unexpected end of message"));
+    }
+    let (bytes, rest) = r.split_at(n);
+    *r = rest;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Strip length of each message in `data`.
+    fn messages(mut data: &[u8]) -> Vec<&[u8]> {
+        let mut msgs = Vec::new();
+        while !data.is_empty() {
+            let n = get_uint(&mut data).unwrap() as usize;
+            msgs.push(&data[..n]);
+            data = &data[n..];
+        }
+        msgs
+    }
+
+    #[test]
+    fn gob_round_trip() {
+        let mut out = Vec::new();
+        for n in &[0u64, 1, 0x7f, 0x80, 0x1234, u64::MAX] {
+            put_uint(&mut out, *n);
+        }
+        for n in &[0i64, -1, 1, -129, i64::MIN] {
+            put_int(&mut out, *n);
+        }
+        // Go encodes 256 as fe 01 00 and -1 as 01.
+        assert_eq!(&out[..5], &[0x00, 0x01, 0x7f, 0xff, 0x80]);
+        let mut r = &out[..];
+        for n in &[0u64, 1, 0x7f, 0x80, 0x1234, u64::MAX] {
+            assert_eq!(get_uint(&mut r).unwrap(), *n);
+        }
+        for n in &[0i64, -1, 1, -129, i64::MIN] {
+            assert_eq!(get_int(&mut r).unwrap(), *n);
+        }
+        let mut out = Vec::new();
+        put_uint(&mut out, 256);
+        put_int(&mut out, -1);
+        assert_eq!(out, vec![0xfe, 0x01, 0x00, 0x01]);
+
+        let args = Type::Struct(
+            "Args",
+            vec![
+                ("Name", Type::Str),
+                ("Fresh", Type::Bool),
+                ("Progs", Type::Slice(Box::new(Type::Bytes))),
+                ("Calls", Type::Slice(Box::new(Type::Str))),
+            ],
+        );
+        let val = Value::Struct(vec![
+            (String::from("Name"), Value::Str(String::from("healer"))),
+            (String::from("Fresh"), Value::Bool(false)),
+            (
+                String::from("Progs"),
+                Value::List(vec![Value::Bytes(b"close(0x1)".to_vec())]),
+            ),
+            (
+                String::from("Calls"),
+                Value::List(vec![Value::Str(String::from("close"))]),
+            ),
+        ]);
+        let mut enc = Encoder::default();
+        let data = enc.encode(&args, &val).unwrap();
+        let mut dec = Decoder::default();
+        let mut vals = messages(&data)
+            .into_iter()
+            .filter_map(|m| dec.decode(m).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vals.len(), 1);
+        let decoded = vals.pop().unwrap();
+        // zero fields are left out.
+        assert_eq!(decoded.field("Fresh"), None);
+        assert_eq!(decoded.field("Name").unwrap().as_str(), Some("healer"));
+        assert_eq!(
+            decoded.field("Progs").unwrap().as_bytes_list(),
+            vec![&b"close(0x1)"[..]]
+        );
+        // types are defined once per stream.
+        let data = enc.encode(&args, &val).unwrap();
+        assert_eq!(messages(&data).len(), 1);
+        let data = enc.encode(&Type::Uint, &Value::Uint(3)).unwrap();
+        assert_eq!(
+            dec.decode(messages(&data)[0]).unwrap(),
+            Some(Value::Uint(3))
+        );
+
+        // what Go sends for `struct{ A int; B string }{7, "x"}` in a fresh stream.
+        let go = [
+            0x1b, 0xff, 0x81, 0x03, 0x01, 0x01, 0x01, 0x54, 0x01, 0xff, 0x82, 0x00, 0x01, 0x02,
+            0x01, 0x01, 0x41, 0x01, 0x04, 0x00, 0x01, 0x01, 0x42, 0x01, 0x0c, 0x00, 0x00, 0x00,
+            0x08, 0xff, 0x82, 0x01, 0x0e, 0x01, 0x01, 0x78, 0x00,
+        ];
+        let mut dec = Decoder::default();
+        let vals = messages(&go)
+            .into_iter()
+            .filter_map(|m| dec.decode(m).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vals,
+            vec![Value::Struct(vec![
+                (String::from("A"), Value::Int(7)),
+                (String::from("B"), Value::Str(String::from("x"))),
+            ])]
+        );
+    }
+}
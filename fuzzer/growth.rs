@@ -0,0 +1,132 @@
+//! Coverage growth of a run, for deciding when to stop it.
+//!
+//! Sampler feeds branches and crash titles of every sample, so time since the last new
+//! branch or crash title is as precise as `sample_interval`. Recent samples are kept in a
+//! ring buffer and growth is projected assuming the rate of new branches decays
+//! exponentially as coverage saturates: rates of the older and the newer half of the
+//! buffer give the decay, the decaying rate integrated over `HORIZON_SECS` gives expected
+//! new branches. A rate that doesn't decay is projected linearly, so the projection is
+//! rough either way.
+use crate::stats::Stats;
+use circular_queue::CircularQueue;
+use std::time::{Duration, Instant};
+
+/// Samples kept, an hour at default sample interval.
+const WINDOW: usize = 240;
+/// Span growth is projected over.
+pub const HORIZON_SECS: u64 = 6 * 3600;
+
+pub struct Growth {
+    /// Time and branches of recent samples
+    samples: CircularQueue<(Instant, usize)>,
+    branches: usize,
+    crash_titles: usize,
+    last_cov_find: Instant,
+    last_new_crash: Instant,
+}
+
+impl Growth {
+    /// Growth of a run started at `start`, nothing found counts from it.
+    pub fn new(start: Instant) -> Self {
+        Self {
+            samples: CircularQueue::with_capacity(WINDOW),
+            branches: 0,
+            crash_titles: 0,
+            last_cov_find: start,
+            last_new_crash: start,
+        }
+    }
+
+    /// Record sample `stat` taken at `now` and fill its growth fields.
+    pub fn observe(&mut self, stat: &mut Stats, now: Instant) {
+        if stat.branches > self.branches {
+            self.branches = stat.branches;
+            self.last_cov_find = now;
+        }
+        if stat.crash_titles > self.crash_titles {
+            self.crash_titles = stat.crash_titles;
+            self.last_new_crash = now;
+        }
+        self.samples.push((now, stat.branches));
+        stat.secs_since_cov_find = Some((now - self.last_cov_find).as_secs());
+        stat.secs_since_new_crash = Some((now - self.last_new_crash).as_secs());
+        let samples = self.samples.asc_iter().copied().collect::<Vec<_>>();
+        stat.projected_growth = project(&samples, Duration::from_secs(HORIZON_SECS));
+    }
+}
+
+/// Expected growth of branches over `horizon` in percent of current ones, none until there
+/// are enough samples to tell a rate.
+fn project(samples: &[(Instant, usize)], horizon: Duration) -> Option<f64> {
+    if samples.len() < 4 {
+        return None;
+    }
+    let (first, mid, last) = (
+        samples[0],
+        samples[samples.len() / 2],
+        samples[samples.len() - 1],
+    );
+    let rate = |a: (Instant, usize), b: (Instant, usize)| {
+        b.1.saturating_sub(a.1) as f64 / (b.0 - a.0).as_secs_f64().max(1.0)
+    };
+    let (older, newer) = (rate(first, mid), rate(mid, last));
+    let current = last.1;
+    if current == 0 {
+        return None;
+    }
+    let horizon = horizon.as_secs_f64();
+    let expected = if newer == 0.0 {
+        0.0
+    } else if newer < older {
+        // rate decays by newer / older between centers of both halves.
+        let gap = (last.0 - first.0).as_secs_f64() / 2.0;
+        let tau = gap / (older / newer).ln();
+        newer * tau * (1.0 - (-horizon / tau).exp())
+    } else {
+        newer * horizon
+    };
+    Some(expected * 100.0 / current as f64)
+}
+
+/// `secs` as e.g. `45s`, `12m` or `3h12m`.
+pub fn fmt_secs(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s => format!("{}h{}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_growth() {
+        let start = Instant::now();
+        let at = |secs: u64, branches: usize| (start + Duration::from_secs(secs), branches);
+        let horizon = Duration::from_secs(HORIZON_SECS);
+        assert_eq!(project(&[at(0, 100), at(60, 200)], horizon), None);
+
+        let flat = (0..8).map(|i| at(i * 60, 1000)).collect::<Vec<_>>();
+        assert_eq!(project(&flat, horizon), Some(0.0));
+        // a branch a minute for 6h on about 1000 branches.
+        let linear = (0..8)
+            .map(|i| at(i * 60, 1000 + i as usize))
+            .collect::<Vec<_>>();
+        let p = project(&linear, horizon).unwrap();
+        assert!((p - 36.0).abs() < 0.5, "{}", p);
+        // rate halving every 4 minutes saturates soon after.
+        let saturating = (0..=8)
+            .map(|i| {
+                at(
+                    i * 60,
+                    10000 - (1000.0 * 0.5f64.powf(i as f64 / 4.0)) as usize,
+                )
+            })
+            .collect::<Vec<_>>();
+        let p = project(&saturating, horizon).unwrap();
+        assert!(p > 0.0 && p < 5.0, "{}", p);
+        assert_eq!(fmt_secs(3 * 3600 + 12 * 60 + 5), "3h12m");
+    }
+}
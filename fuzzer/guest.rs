@@ -1,16 +1,24 @@
 /// Driver for kernel to be tested
 use crate::utils::cli::{App, Arg, OptVal};
 use crate::utils::free_ipv4_port;
+use crate::work_dir;
 use crate::Config;
 use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::sched::{sched_getaffinity, sched_setaffinity, CpuSet};
+use nix::unistd::Pid;
 use os_pipe::{pipe, PipeReader, PipeWriter};
 use std::collections::HashMap;
 use std::fmt;
+use std::fs::File;
 use std::io::{ErrorKind, Read};
+use std::os::unix::fs::PermissionsExt;
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::process::exit;
-use tokio::process::Child;
+use std::str::FromStr;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
 use tokio::time::{delay_for, timeout, Duration};
 
 lazy_static! {
@@ -26,12 +34,7 @@ lazy_static! {
 
         let mut linux_amd64 = App::new("qemu-system-x86_64");
         linux_amd64
-            .arg(Arg::new_flag("-enable-kvm"))
             .args(arg_common.iter())
-            .arg(Arg::new_opt(
-                "-cpu",
-                OptVal::multiple(vec!["host", "migratable=off"], Some(',')),
-            ))
             .arg(Arg::new_opt(
                 "-net",
                 OptVal::multiple(vec!["nic", "model=e1000"], Some(',')),
@@ -81,15 +84,23 @@ lazy_static! {
         let mut linux_arm64 = App::new("qemu-system-aarch64");
         linux_arm64
             .args(arg_common.iter())
-            .arg(Arg::new_opt(
-                "-machine",
-                OptVal::multiple(vec!["virt", "virtualization=on"], Some(',')),
-            ))
-            .arg(Arg::new_opt("-cpu", OptVal::normal("cortex-a57")))
             .arg(Arg::new_opt("-net", OptVal::normal("nic")))
             .arg(Arg::new_opt(
                 "-append",
-                OptVal::multiple(vec!["root=/dev/vda", "console=ttyAMA0"], Some(' ')),
+                OptVal::multiple(
+                    vec![
+                        "earlycon",
+                        "oops=panic",
+                        "panic_on_warn=1",
+                        "panic=1",
+                        "ftrace_dump_on_oops=orig_cpu",
+                        "net.ifnames=0",
+                        "biosdevname=0",
+                        "root=/dev/vda",
+                        "console=ttyAMA0",
+                    ],
+                    Some(' '),
+                ),
             ));
 
         qemus.insert("linux/amd64".to_string(), linux_amd64);
@@ -130,7 +141,8 @@ lazy_static! {
     };
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct GuestConf {
     /// Kernel to be tested
     pub os: String,
@@ -141,11 +153,114 @@ pub struct GuestConf {
 }
 
 pub const PLATFORM: [&str; 1] = ["qemu"];
-pub const ARCH: [&str; 1] = ["amd64"];
+pub const ARCH: [&str; 2] = ["amd64", "arm64"];
 pub const OS: [&str; 1] = ["linux"];
 
+/// Default qemu and executor options of each target, merged under config file.
+const TARGET_DEFAULTS: [(&str, &str); 2] = [
+    ("linux/amd64", include_str!("targets/linux-amd64.toml")),
+    ("linux/arm64", include_str!("targets/linux-arm64.toml")),
+];
+
+/// Target of guest, `os/arch` as syzkaller names it, e.g. `linux/amd64`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TargetName {
+    os: String,
+    arch: String,
+}
+
+impl TargetName {
+    /// Target of `os` and `arch`, each a non-empty name of lowercase letters, digits and `_`.
+    pub fn new(os: &str, arch: &str) -> Result<Self, String> {
+        for (part, name) in [("os", os), ("arch", arch)] {
+            if name.is_empty() {
+                return Err(format!("{} is empty", part));
+            }
+            if let Some(c) = name
+                .chars()
+                .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '_'))
+            {
+                return Err(format!("{} {:?} contains {:?}", part, name, c));
+            }
+        }
+        Ok(Self {
+            os: os.to_string(),
+            arch: arch.to_string(),
+        })
+    }
+
+    pub fn os(&self) -> &str {
+        &self.os
+    }
+
+    pub fn arch(&self) -> &str {
+        &self.arch
+    }
+}
+
+impl FromStr for TargetName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(os), Some(arch), None) => {
+                Self::new(os, arch).map_err(|e| format!("invalid target {:?}: {}", s, e))
+            }
+            _ => Err(format!(
+                "invalid target {:?}: expect os/arch, e.g. linux/amd64",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TargetName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.os, self.arch)
+    }
+}
+
+/// linux/amd64 on qemu.
+impl Default for GuestConf {
+    fn default() -> Self {
+        Self {
+            os: String::from("linux"),
+            arch: String::from("amd64"),
+            platform: String::from("qemu"),
+        }
+    }
+}
+
 impl GuestConf {
+    /// Default options of this target, a table of `qemu` and `executor` tables, if any.
+    pub fn defaults(&self) -> Option<toml::Value> {
+        let target = self.target();
+        let (_, defaults) = TARGET_DEFAULTS
+            .iter()
+            .find(|(t, _)| t.parse().as_ref() == Ok(&target))?;
+        let defaults = toml::from_str(defaults).unwrap_or_else(|e| {
+            exits!(
+                exitcode::SOFTWARE,
+                "Defaults of {} are invalid: {}",
+                target,
+                e
+            )
+        });
+        Some(defaults)
+    }
+
+    /// `os/arch` of guest, exit if either is malformed.
+    pub fn target(&self) -> TargetName {
+        TargetName::new(&self.os, &self.arch)
+            .unwrap_or_else(|e| exits!(exitcode::CONFIG, "Config Error: guest: {}", e))
+    }
+
     pub fn check(&self) {
+        if let Err(e) = TargetName::new(&self.os, &self.arch) {
+            eprintln!("Config Error: guest: {}", e);
+            exit(exitcode::CONFIG)
+        }
         if !PLATFORM.contains(&self.platform.as_str())
             || !ARCH.contains(&self.arch.as_str())
             || !OS.contains(&self.os.as_str())
@@ -159,17 +274,40 @@ impl GuestConf {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct QemuConf {
     pub cpu_num: u32,
     pub mem_size: u32,
     pub image: String,
     pub kernel: String,
+    /// Qemu -machine, e.g. `virt`, none by default
+    pub machine: Option<String>,
+    /// Qemu -cpu, e.g. `host`, none by default
+    pub cpu: Option<String>,
+    /// Qemu -accel, e.g. `kvm`, none by default, which is tcg
+    pub accel: Option<String>,
     pub wait_boot_time: Option<u8>,
+    pub wait_executor_time: Option<u8>,
+    /// Pin vm of each job to its own cpus, default false.
+    pub pin_cpus: Option<bool>,
 }
 
 impl QemuConf {
-    pub fn check(&self) {
+    /// Vm of `guest` with its defaults, booting `kernel` with `image`.
+    pub fn new(guest: &GuestConf, image: String, kernel: String) -> Self {
+        let mut qemu = toml::value::Table::new();
+        qemu.insert(String::from("image"), toml::Value::String(image));
+        qemu.insert(String::from("kernel"), toml::Value::String(kernel));
+        let mut qemu = toml::Value::Table(qemu);
+        if let Some(defaults) = guest.defaults().and_then(|d| d.get("qemu").cloned()) {
+            qemu = merge(defaults, qemu);
+        }
+        qemu.try_into()
+            .unwrap_or_else(|e| exits!(exitcode::CONFIG, "Config Error: qemu: {}", e))
+    }
+
+    pub fn check(&self, arch: &str) {
         let cpu_num = num_cpus::get() as u32;
         if self.cpu_num > cpu_num * 8 || self.cpu_num == 0 {
             eprintln!(
@@ -188,31 +326,121 @@ impl QemuConf {
             exit(exitcode::CONFIG)
         }
 
+        if let Err(e) = self.check_files(arch) {
+            eprintln!("Config Error: {}", e);
+            exit(exitcode::CONFIG)
+        }
+    }
+
+    /// Image and kernel should exist and look bootable by guest of `arch`.
+    fn check_files(&self, arch: &str) -> Result<(), String> {
         let image = Path::new(&self.image);
         let kernel = Path::new(&self.kernel);
         if !image.is_file() {
-            eprintln!("Config Error: image {} is invalid", self.image);
-            exit(exitcode::CONFIG)
+            return Err(format!("image {} is invalid", self.image));
         }
         if !kernel.is_file() {
-            eprintln!("Config Error: kernel {} is invalid", self.kernel);
-            exit(exitcode::CONFIG)
+            return Err(format!("kernel {} is invalid", self.kernel));
         }
+        check_image(image).map_err(|e| format!("qemu.image {}: {}", self.image, e))?;
+        check_kernel(kernel, arch).map_err(|e| format!("qemu.kernel {}: {}", self.kernel, e))
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+const QCOW2_MAGIC: &[u8] = b"QFI\xfb";
+
+/// Image should be readable, qcow2 image should have valid header.
+fn check_image(image: &Path) -> Result<(), String> {
+    let header = read_header(image, 8)?;
+    if header.starts_with(QCOW2_MAGIC) {
+        let version = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        if version != 2 && version != 3 {
+            return Err(format!("unsupported qcow2 version {}", version));
+        }
+    } else if image.extension().map(|e| e == "qcow2").unwrap_or(false) {
+        return Err("has .qcow2 extension but no qcow2 header, image may be corrupted".into());
+    }
+    Ok(())
+}
+
+/// Kernel should be bootable image of `arch`, not the elf vmlinux.
+fn check_kernel(kernel: &Path, arch: &str) -> Result<(), String> {
+    let header = read_header(kernel, 64)?;
+    if header.starts_with(b"\x7fELF") {
+        return Err(
+            "is an elf file, use bzImage (amd64) or Image (arm64) instead of vmlinux".into(),
+        );
+    }
+    let (offset, magic, name) = match arch {
+        "amd64" => (0x202, &b"HdrS"[..], "bzImage"),
+        "arm64" => (56, &b"ARM\x64"[..], "arm64 Image"),
+        _ => return Ok(()),
+    };
+    if header.get(offset..offset + magic.len()) != Some(magic) {
+        return Err(format!("is not a {} of guest arch {}", name, arch));
+    }
+    Ok(())
+}
+
+/// Enough for all headers checked.
+const HEADER_LEN: u64 = 0x206;
+
+fn read_header(path: &Path, min_len: usize) -> Result<Vec<u8>, String> {
+    let f = File::open(path).map_err(|e| format!("fail to open: {}", e))?;
+    let mut header = Vec::new();
+    f.take(HEADER_LEN)
+        .read_to_end(&mut header)
+        .map_err(|e| format!("fail to read: {}", e))?;
+    if header.len() < min_len {
+        return Err(format!("too small, only {} bytes", header.len()));
+    }
+    Ok(header)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct SSHConf {
     pub key_path: String,
+    pub auth_timeout: Option<u8>,
 }
 
 impl SSHConf {
+    pub fn new(key_path: String) -> Self {
+        Self {
+            key_path,
+            auth_timeout: None,
+        }
+    }
+
     pub fn check(&self) {
+        if let Err(e) = self.check_key() {
+            eprintln!("Config Error: {}", e);
+            exit(exitcode::CONFIG)
+        }
+    }
+
+    /// Key should be readable and private to its owner, as ssh requires.
+    fn check_key(&self) -> Result<(), String> {
         let key = Path::new(&self.key_path);
         if !key.is_file() {
-            eprintln!("Config Error: ssh key file {} is invalid", self.key_path);
-            exit(exitcode::CONFIG)
+            return Err(format!("ssh key file {} is invalid", self.key_path));
         }
+        if let Err(e) = File::open(key) {
+            return Err(format!(
+                "ssh.key_path {}: fail to open: {}",
+                self.key_path, e
+            ));
+        }
+        let mode = key.metadata().map(|m| m.permissions().mode()).unwrap_or(0);
+        if mode & 0o077 != 0 {
+            return Err(format!(
+                "ssh.key_path {}: permissions {:o} are too open, ssh will refuse the key, run `chmod 600 {}`",
+                self.key_path,
+                mode & 0o777,
+                self.key_path
+            ));
+        }
+        Ok(())
     }
 }
 
@@ -221,15 +449,15 @@ pub enum Guest {
 }
 
 impl Guest {
-    pub fn new(cfg: &Config) -> Self {
-        // only support linux/amd64 on qemu now.
-        Guest::LinuxQemu(LinuxQemu::new(cfg))
+    pub fn new(cfg: &Config, job: usize) -> Self {
+        // only support linux/amd64 and linux/arm64 on qemu now.
+        Guest::LinuxQemu(LinuxQemu::new(cfg, job))
     }
 }
 
 impl Guest {
-    /// Boot guest or panic
-    pub async fn boot(&mut self) {
+    /// Boot guest, return the failed boot stage and reason if failed.
+    pub async fn boot(&mut self) -> Result<(), BootError> {
         match self {
             Guest::LinuxQemu(ref mut guest) => guest.boot().await,
         }
@@ -249,6 +477,13 @@ impl Guest {
         }
     }
 
+    /// Run shell command `cmd` on guest, return its stdout.
+    pub async fn query(&self, cmd: &str) -> Result<String, String> {
+        match self {
+            Guest::LinuxQemu(ref guest) => guest.query(cmd).await,
+        }
+    }
+
     /// Try collect crash info guest, this could be none sometimes
     pub async fn try_collect_crash(&mut self) -> Option<Crash> {
         match self {
@@ -268,6 +503,86 @@ impl Guest {
             Guest::LinuxQemu(ref guest) => guest.copy(path).await,
         }
     }
+
+    /// Build boot error of `stage` with tail of serial log.
+    pub fn boot_error(&mut self, stage: BootStage, reason: String) -> BootError {
+        match self {
+            Guest::LinuxQemu(ref mut guest) => guest.boot_error(stage, reason),
+        }
+    }
+}
+
+/// Stages of bringing up a guest, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootStage {
+    /// Qemu process started and kept running.
+    Qemu,
+    /// Guest network is up, sshd answers on forwarded port.
+    Network,
+    /// Ssh login with configured key succeeded.
+    Ssh,
+    /// Guest setup script exited successfully.
+    Setup,
+    /// Executor in guest connected back to fuzzer.
+    Executor,
+}
+
+impl fmt::Display for BootStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (stage, hint) = match self {
+            BootStage::Qemu => ("qemu start", "check qemu binary, kernel and image path"),
+            BootStage::Network => (
+                "guest network",
+                "kernel may hang or panic during boot, or sshd is not enabled in image",
+            ),
+            BootStage::Ssh => (
+                "ssh authentication",
+                "check ssh.key_path matches the image and root login is allowed",
+            ),
+            BootStage::Setup => (
+                "guest setup",
+                "check guest_setup script runs in guest, its output is in reason",
+            ),
+            BootStage::Executor => (
+                "executor handshake",
+                "check executor.path is built for guest arch and guest can reach executor.host_ip",
+            ),
+        };
+        write!(f, "{} ({})", stage, hint)
+    }
+}
+
+/// Lines of serial log kept in boot error.
+const SERIAL_TAIL_LINES: usize = 32;
+/// Lines of guest setup output kept in boot error.
+const SETUP_TAIL_LINES: usize = 16;
+/// Time limit of guest setup script.
+const SETUP_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+pub struct BootError {
+    pub stage: BootStage,
+    pub reason: String,
+    pub serial_tail: String,
+}
+
+impl fmt::Display for BootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Fail to boot guest, stage failed: {}", self.stage)?;
+        writeln!(f, "Reason: {}", self.reason)?;
+        writeln!(
+            f,
+            "==================== Serial (last {} lines) ====================",
+            SERIAL_TAIL_LINES
+        )?;
+        write!(f, "{}", self.serial_tail)
+    }
+}
+
+fn tail(log: &str, n: usize) -> String {
+    let lines = log.lines().collect::<Vec<_>>();
+    let start = lines.len().saturating_sub(n);
+    lines[start..].join("\n")
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -298,34 +613,52 @@ pub struct LinuxQemu {
     rp: Option<PipeReader>,
 
     wait_boot_time: u8,
+    auth_timeout: u8,
+    serial: String,
     addr: String,
     port: u16,
     key: String,
     user: String,
     guest: GuestConf,
     qemu: QemuConf,
+    cpus: Option<Vec<usize>>,
+    setup: Option<PathBuf>,
+    /// Scratch dir of this vm, holding snapshot overlay of image
+    scratch: PathBuf,
+    job: usize,
 }
 
 impl LinuxQemu {
-    pub fn new(cfg: &Config) -> Self {
+    pub fn new(cfg: &Config, job: usize) -> Self {
         assert_eq!(cfg.guest.os, "linux");
+        let cpus = if cfg.qemu.pin_cpus.unwrap_or(false) {
+            Some(job_cpus(job, cfg.qemu.cpu_num as usize))
+        } else {
+            None
+        };
 
         Self {
             handle: Option::None,
             rp: Option::None,
             wait_boot_time: cfg.qemu.wait_boot_time.unwrap_or(15),
+            auth_timeout: cfg.ssh.auth_timeout.unwrap_or(30),
+            serial: String::new(),
             addr: LINUX_QEMU_HOST_IP_ADDR.to_string(),
             port: 0,
             key: cfg.ssh.key_path.clone(),
             user: LINUX_QEMU_HOST_USER.to_string(),
             guest: cfg.guest.clone(),
             qemu: cfg.qemu.clone(),
+            cpus,
+            setup: cfg.guest_setup.clone(),
+            scratch: work_dir::vm_dir(job),
+            job,
         }
     }
 }
 
 impl LinuxQemu {
-    async fn boot(&mut self) {
+    async fn boot(&mut self) -> Result<(), BootError> {
         if let Some(ref mut h) = self.handle {
             h.kill()
                 .unwrap_or_else(|e| exits!(exitcode::OSERR, "Fail to kill running guest:{}", e));
@@ -340,6 +673,10 @@ impl LinuxQemu {
 
             let (mut handle, mut rp) = {
                 let mut cmd = qemu.clone().into_cmd();
+                cmd.env("TMPDIR", &self.scratch);
+                if let Some(cpus) = self.cpus.as_ref() {
+                    pin_cpus(&mut cmd, cpus);
+                }
                 let (rp, wp) = long_pipe();
                 fcntl(rp.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))
                     .unwrap_or_else(|e| exits!(exitcode::OSERR, "Fail to set flag on pipe:{}", e));
@@ -358,55 +695,150 @@ impl LinuxQemu {
                 (handle, rp)
             };
 
-            let mut waited = Duration::new(0, 0);
-            let wait_duration = Duration::from_millis(500);
-            let max_wait_time = Duration::new(self.wait_boot_time as u64 * 3, 0);
-            let mut started = false;
-            let mut failed_reason = String::new();
-            loop {
-                delay_for(wait_duration).await;
-                waited += wait_duration;
-                failed_reason
-                    .push_str(String::from_utf8_lossy(&read_all_nonblock(&mut rp)).as_ref());
-
-                if self.is_alive().await {
-                    started = true;
-                    break;
+            self.serial.clear();
+            match self.wait_stages(&mut handle, &mut rp).await {
+                Ok(()) => {
+                    // clear useless data in pipe
+                    read_all_nonblock(&mut rp);
+                    self.handle = Some(handle);
+                    self.rp = Some(rp);
+                    if let Some(setup) = self.setup.clone() {
+                        if let Err(reason) = self.run_setup(&setup).await {
+                            return Err(self.boot_error(BootStage::Setup, reason));
+                        }
+                    }
+                    return Ok(());
                 }
-
-                if waited >= max_wait_time {
-                    handle.kill().unwrap_or_else(|e| {
-                        exits!(exitcode::OSERR, "Fail to kill failed guest:{}", e)
-                    });
-                    failed_reason
-                        .push_str(String::from_utf8_lossy(&read_all_nonblock(&mut rp)).as_ref());
-                    break;
+                Err((stage, reason)) => {
+                    handle.kill().ok();
+                    self.read_serial(&mut rp);
+                    if stage == BootStage::Qemu
+                        && self.serial.contains("ould not set up host forwarding rule")
+                        && retry != MAX_RETRY
+                    {
+                        retry += 1;
+                        continue;
+                    }
+                    let reason = format!("{}\nCommand: {:?}", reason, qemu);
+                    return Err(self.boot_error(stage, reason));
                 }
             }
+        }
+    }
 
-            if !started {
-                if !failed_reason.contains("ould not set up host forwarding rule")
-                    || retry == MAX_RETRY
-                {
-                    eprintln!("Fail to boot kernel:");
-                    eprintln!("{}", failed_reason);
-                    eprintln!("======================= Command ===========================");
-                    eprintln!("{:?}", qemu);
-                    exit(1)
-                } else {
-                    retry += 1
-                }
-            } else {
-                // clear useless data in pipe
-                read_all_nonblock(&mut rp);
-                self.handle = Some(handle);
-                self.rp = Some(rp);
+    /// Wait guest passing each boot stage, return the failed stage and reason.
+    async fn wait_stages(
+        &mut self,
+        handle: &mut Child,
+        rp: &mut PipeReader,
+    ) -> Result<(), (BootStage, String)> {
+        let wait_duration = Duration::from_millis(500);
+
+        let mut waited = Duration::new(0, 0);
+        let max_wait_time = Duration::new(self.wait_boot_time as u64 * 3, 0);
+        loop {
+            delay_for(wait_duration).await;
+            waited += wait_duration;
+            self.read_serial(rp);
+            check_qemu_running(handle).await?;
+
+            if sshd_ready(&self.addr, self.port).await {
                 break;
             }
+            if waited >= max_wait_time {
+                return Err((
+                    BootStage::Network,
+                    format!(
+                        "no ssh banner from {}:{} after {}s",
+                        self.addr,
+                        self.port,
+                        max_wait_time.as_secs()
+                    ),
+                ));
+            }
+        }
+
+        let mut waited = Duration::new(0, 0);
+        let max_wait_time = Duration::new(self.auth_timeout as u64, 0);
+        loop {
+            let ssh_err = match self.ssh_check().await {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            self.read_serial(rp);
+            check_qemu_running(handle).await?;
+            if waited >= max_wait_time {
+                return Err((
+                    BootStage::Ssh,
+                    format!("ssh still failing after {}s: {}", waited.as_secs(), ssh_err),
+                ));
+            }
+            delay_for(wait_duration).await;
+            waited += wait_duration;
+        }
+    }
+
+    /// Upload guest setup `script` and run it, its output is logged.
+    async fn run_setup(&self, script: &Path) -> Result<(), String> {
+        let path = self.copy(script).await;
+        let cmd = format!("sh {} 2>&1", path.display());
+        let app = ssh_app(&self.key, &self.user, &self.addr, self.port, App::new(&cmd));
+        let output = app.into_cmd().kill_on_drop(true).output();
+        let output = match timeout(SETUP_TIMEOUT, output).await {
+            Err(_) => {
+                return Err(format!(
+                    "{} timed out after {}s",
+                    script.display(),
+                    SETUP_TIMEOUT.as_secs()
+                ))
+            }
+            Ok(Err(e)) => return Err(format!("fail to spawn ssh: {}", e)),
+            Ok(Ok(output)) => output,
+        };
+        let out = String::from_utf8_lossy(&output.stdout);
+        if !out.trim().is_empty() {
+            info!(
+                "job-{}: guest setup {}:\n{}",
+                self.job,
+                script.display(),
+                out.trim_end()
+            );
+        }
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} {}:\n{}",
+                script.display(),
+                output.status,
+                tail(&out, SETUP_TAIL_LINES)
+            ))
+        }
+    }
+
+    fn read_serial(&mut self, rp: &mut PipeReader) {
+        self.serial
+            .push_str(String::from_utf8_lossy(&read_all_nonblock(rp)).as_ref());
+    }
+
+    fn boot_error(&mut self, stage: BootStage, reason: String) -> BootError {
+        if let Some(mut rp) = self.rp.take() {
+            self.read_serial(&mut rp);
+            self.rp = Some(rp);
+        }
+        BootError {
+            stage,
+            reason,
+            serial_tail: tail(&self.serial, SERIAL_TAIL_LINES),
         }
     }
 
     async fn is_alive(&self) -> bool {
+        self.ssh_check().await.is_ok()
+    }
+
+    /// Run `pwd` in guest via ssh, return stderr of ssh on failure.
+    async fn ssh_check(&self) -> Result<(), String> {
         let mut pwd = ssh_app(
             &self.key,
             &self.user,
@@ -417,11 +849,13 @@ impl LinuxQemu {
         .into_cmd();
         pwd.stdin(std::process::Stdio::piped())
             .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped());
-        match timeout(Duration::new(10, 0), pwd.status()).await {
-            Err(_) => false,
-            Ok(status) => match status {
-                Ok(status) => status.success(),
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        match timeout(Duration::new(10, 0), pwd.output()).await {
+            Err(_) => Err("ssh timeout".into()),
+            Ok(output) => match output {
+                Ok(output) if output.status.success() => Ok(()),
+                Ok(output) => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
                 Err(e) => exits!(exitcode::OSERR, "Fail to spawn detector(ssh:pwd):{}", e),
             },
         }
@@ -442,6 +876,22 @@ impl LinuxQemu {
             .unwrap_or_else(|e| exits!(exitcode::OSERR, "Fail to spawn:{}", e))
     }
 
+    async fn query(&self, cmd: &str) -> Result<String, String> {
+        let app = ssh_app(&self.key, &self.user, &self.addr, self.port, App::new(cmd));
+        let output = app.into_cmd().kill_on_drop(true).output();
+        match timeout(Duration::from_secs(10), output).await {
+            Err(_) => Err(format!("`{}` timed out", cmd)),
+            Ok(Err(e)) => Err(format!("fail to spawn ssh: {}", e)),
+            Ok(Ok(output)) if !output.status.success() => Err(format!(
+                "`{}` {}: {}",
+                cmd,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Ok(Ok(output)) => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        }
+    }
+
     async fn clear(&mut self) {
         if let Some(r) = self.rp.as_mut() {
             read_all_nonblock(r);
@@ -501,8 +951,131 @@ impl LinuxQemu {
     }
 }
 
+async fn check_qemu_running(handle: &mut Child) -> Result<(), (BootStage, String)> {
+    match timeout(Duration::from_millis(0), handle).await {
+        Err(_) => Ok(()),
+        Ok(status) => Err((BootStage::Qemu, format!("qemu exited early: {:?}", status))),
+    }
+}
+
+/// Guest network is considered up when sshd sends its banner through forwarded port.
+/// Connecting alone is not enough, since qemu accepts on forwarded port before guest is up.
+async fn sshd_ready(addr: &str, port: u16) -> bool {
+    let conn = timeout(Duration::new(1, 0), TcpStream::connect((addr, port))).await;
+    let mut conn = match conn {
+        Ok(Ok(conn)) => conn,
+        _ => return false,
+    };
+    let mut banner = [0; 4];
+    match timeout(Duration::new(2, 0), conn.read_exact(&mut banner)).await {
+        Ok(Ok(_)) => &banner == b"SSH-",
+        _ => false,
+    }
+}
+
+/// Cpus this process is allowed to run on.
+fn allowed_cpus() -> Vec<usize> {
+    let set = sched_getaffinity(Pid::from_raw(0))
+        .unwrap_or_else(|e| exits!(exitcode::OSERR, "Fail to get cpu affinity: {}", e));
+    (0..CpuSet::count())
+        .filter(|&cpu| set.is_set(cpu).unwrap_or(false))
+        .collect()
+}
+
+/// Cpus of job `job`, each job owns `cpu_num` cpus of allowed ones.
+fn job_cpus(job: usize, cpu_num: usize) -> Vec<usize> {
+    allowed_cpus()
+        .into_iter()
+        .skip(job * cpu_num)
+        .take(cpu_num)
+        .collect()
+}
+
+pub fn check_pin_cpus(vm_num: usize, cpu_num: usize) {
+    let allowed = allowed_cpus();
+    if vm_num * cpu_num > allowed.len() {
+        eprintln!(
+            "Config Error: can't pin {} vm(s) with {} cpu(s) each, only {} cpu(s) available: {:?}",
+            vm_num,
+            cpu_num,
+            allowed.len(),
+            allowed
+        );
+        exit(exitcode::CONFIG)
+    }
+}
+
+/// Host threads a vm needs besides its vcpus, e.g. qemu io thread.
+const VM_EXTRA_THREADS: usize = 1;
+/// Host memory in MB a vm needs besides guest memory.
+const VM_EXTRA_MEM: u64 = 256;
+
+/// Largest number of vms that fits host cpus and available memory, each vm takes
+/// `cpu_num` + 1 host threads and `mem_size` + 256 MB memory. 10% of memory is left for host.
+pub fn auto_vm_num(conf: &QemuConf) -> usize {
+    let cpus = allowed_cpus().len();
+    let mem = available_mem().unwrap_or_else(|e| {
+        exits!(
+            exitcode::OSERR,
+            "Fail to read available memory from /proc/meminfo: {}",
+            e
+        )
+    });
+    let by_cpu = cpus / (conf.cpu_num as usize + VM_EXTRA_THREADS);
+    let by_mem = (mem * 9 / 10 / (conf.mem_size as u64 + VM_EXTRA_MEM)) as usize;
+    let vm_num = by_cpu.min(by_mem).max(1);
+    eprintln!(
+        "Config: vm_num auto-sized: {} cpu(s), {} MB available, {} cpu(s) and {} MB per vm -> {} vm(s)",
+        cpus, mem, conf.cpu_num, conf.mem_size, vm_num
+    );
+    vm_num
+}
+
+/// Available memory of host in MB.
+fn available_mem() -> Result<u64, String> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").map_err(|e| e.to_string())?;
+    meminfo
+        .lines()
+        .find(|l| l.starts_with("MemAvailable:"))
+        .and_then(|l| l.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb >> 10)
+        .ok_or_else(|| String::from("no MemAvailable"))
+}
+
+/// Merge options of `over` into `base` recursively, options of `over` take precedence.
+pub fn merge(base: toml::Value, over: toml::Value) -> toml::Value {
+    match (base, over) {
+        (toml::Value::Table(mut base), toml::Value::Table(over)) => {
+            for (k, v) in over {
+                let v = match base.remove(&k) {
+                    Some(b) => merge(b, v),
+                    None => v,
+                };
+                base.insert(k, v);
+            }
+            toml::Value::Table(base)
+        }
+        (_, over) => over,
+    }
+}
+
+/// Set affinity in child before exec, so every thread of qemu inherits it.
+fn pin_cpus(cmd: &mut Command, cpus: &[usize]) {
+    let mut set = CpuSet::new();
+    for &cpu in cpus {
+        set.set(cpu)
+            .unwrap_or_else(|e| exits!(exitcode::OSERR, "Fail to set cpu {}: {}", cpu, e));
+    }
+    unsafe {
+        cmd.pre_exec(move || {
+            sched_setaffinity(Pid::from_raw(0), &set).map_err(std::io::Error::other)
+        });
+    }
+}
+
 fn build_qemu_cli(g: &GuestConf, q: &QemuConf) -> (App, u16) {
-    let target = format!("{}/{}", g.os, g.arch);
+    let target = g.target().to_string();
 
     let mut qemu = QEMUS
         .get(&target)
@@ -530,8 +1103,28 @@ fn build_qemu_cli(g: &GuestConf, q: &QemuConf) -> (App, u16) {
                 sp: Some(','),
             },
         ))
-        .arg(Arg::new_opt("-hda", OptVal::Normal(cfg.image.clone())))
         .arg(Arg::new_opt("-kernel", OptVal::Normal(cfg.kernel.clone())));
+    for (opt, val) in &[
+        ("-machine", &cfg.machine),
+        ("-cpu", &cfg.cpu),
+        ("-accel", &cfg.accel),
+    ] {
+        if let Some(val) = val {
+            qemu.arg(Arg::new_opt(opt, OptVal::normal(val)));
+        }
+    }
+    if g.arch == "arm64" {
+        // virt machine has no ide controller, attach image as virtio disk (/dev/vda).
+        qemu.arg(Arg::new_opt(
+            "-drive",
+            OptVal::multiple(
+                vec![format!("file={}", cfg.image), String::from("if=virtio")],
+                Some(','),
+            ),
+        ));
+    } else {
+        qemu.arg(Arg::new_opt("-hda", OptVal::Normal(cfg.image.clone())));
+    }
     (qemu, port)
 }
 
@@ -578,3 +1171,113 @@ fn read_all_nonblock(rp: &mut PipeReader) -> Vec<u8> {
     result.shrink_to_fit();
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_target_name() {
+        let target: TargetName = "linux/amd64".parse().unwrap();
+        assert_eq!((target.os(), target.arch()), ("linux", "amd64"));
+        assert_eq!(target.to_string(), "linux/amd64");
+        assert_eq!(GuestConf::default().target(), target);
+
+        for bad in &[
+            "",
+            "linux",
+            "linux/",
+            "/amd64",
+            "linux/amd64/x",
+            "Linux/amd64",
+            "linux/amd 64",
+        ] {
+            assert!(bad.parse::<TargetName>().is_err(), "{:?}", bad);
+        }
+        assert_eq!(
+            "linux".parse::<TargetName>(),
+            Err(String::from(
+                "invalid target \"linux\": expect os/arch, e.g. linux/amd64"
+            ))
+        );
+        assert_eq!(
+            TargetName::new("linux", "amd64/x"),
+            Err(String::from("arch \"amd64/x\" contains '/'"))
+        );
+    }
+
+    #[test]
+    fn check_guest_files() {
+        let dir = std::env::temp_dir().join(format!("healer-guest-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, data: &[u8]| {
+            let path = dir.join(name);
+            std::fs::write(&path, data).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let mut bz_image = vec![0; 0x210];
+        bz_image[0x202..0x206].copy_from_slice(b"HdrS");
+        let mut arm64_image = vec![0; 64];
+        arm64_image[56..60].copy_from_slice(b"ARM\x64");
+        let mut vmlinux = vec![0; 64];
+        vmlinux[..4].copy_from_slice(b"\x7fELF");
+        let raw = file("stretch.img", &[0; 512]);
+        let qcow2 = file("stretch.qcow2", b"QFI\xfb\0\0\0\x03");
+        let old_qcow2 = file("old.qcow2", b"QFI\xfb\0\0\0\x01");
+        let bad_qcow2 = file("bad.qcow2", &[0; 512]);
+        let bz_image = file("bzImage", &bz_image);
+        let arm64_image = file("Image", &arm64_image);
+        let vmlinux = file("vmlinux", &vmlinux);
+        let missing = dir.join("missing").to_str().unwrap().to_string();
+        let check = |image: &str, kernel: &str, arch: &str| {
+            QemuConf::new(&GuestConf::default(), image.to_string(), kernel.to_string())
+                .check_files(arch)
+        };
+
+        let rets = vec![
+            check(&raw, &bz_image, "amd64"),
+            check(&qcow2, &bz_image, "amd64"),
+            check(&raw, &arm64_image, "arm64"),
+            check(&missing, &bz_image, "amd64"),
+            check(&raw, &missing, "amd64"),
+            check(&old_qcow2, &bz_image, "amd64"),
+            check(&bad_qcow2, &bz_image, "amd64"),
+            check(&raw, &vmlinux, "amd64"),
+            check(&raw, &arm64_image, "amd64"),
+        ];
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(rets[..3].iter().all(Result::is_ok), "{:?}", rets);
+        let errs = rets[3..]
+            .iter()
+            .map(|r| r.clone().unwrap_err())
+            .collect::<Vec<_>>();
+        assert!(errs[0].starts_with("image") && errs[0].ends_with("is invalid"));
+        assert!(errs[1].starts_with("kernel") && errs[1].ends_with("is invalid"));
+        assert!(errs[2].contains("unsupported qcow2 version 1"));
+        assert!(errs[3].contains("no qcow2 header"));
+        assert!(errs[4].contains("use bzImage"));
+        assert!(errs[5].contains("is not a bzImage"));
+    }
+
+    #[test]
+    fn check_ssh_key() {
+        let dir = std::env::temp_dir().join(format!("healer-ssh-key-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = dir.join("stretch.id_rsa");
+        std::fs::write(&key, "key").unwrap();
+        let conf = SSHConf::new(key.to_str().unwrap().to_string());
+
+        let set_mode = |mode| std::fs::set_permissions(&key, PermissionsExt::from_mode(mode));
+        set_mode(0o600).unwrap();
+        let private = conf.check_key();
+        set_mode(0o644).unwrap();
+        let open = conf.check_key();
+        let missing = SSHConf::new(dir.join("missing").to_str().unwrap().to_string()).check_key();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(private, Ok(()));
+        assert!(open.unwrap_err().contains("permissions 644 are too open"));
+        assert!(missing.unwrap_err().ends_with("is invalid"));
+    }
+}
@@ -0,0 +1,164 @@
+//! Callbacks of embedding programs, see `fuzz_with_hooks`.
+//!
+//! Events are sent over a channel to a dedicated thread that calls the hooks, so a slow
+//! hook delays only later hooks, never fuzzing. An event is only built when its hook is
+//! set, so unset hooks cost a branch. Events still queued when fuzzer exits are dropped.
+use crate::report::{CrashClass, ReproScore};
+use crate::stats::Stats;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+
+/// Prog added to corpus.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputSummary {
+    pub job: usize,
+    /// Prog in C
+    pub prog: String,
+    pub calls: usize,
+    pub new_blocks: usize,
+    pub new_branches: usize,
+    pub exec_ms: u64,
+}
+
+/// Crash saved to crashes dir.
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashSummary {
+    pub title: String,
+    pub signature: String,
+    pub class: CrashClass,
+    pub subsystem: String,
+    pub repro: ReproScore,
+    /// Path of crash case
+    pub path: PathBuf,
+}
+
+/// Stats sampled at each sample interval.
+pub type StatsSnapshot = Stats;
+
+type Hook<T> = Option<Box<dyn Fn(&T) + Send>>;
+
+#[derive(Default)]
+pub struct Hooks {
+    new_input: Hook<InputSummary>,
+    crash: Hook<CrashSummary>,
+    stats: Hook<StatsSnapshot>,
+}
+
+impl Hooks {
+    pub fn on_new_input<F: Fn(&InputSummary) + Send + 'static>(mut self, f: F) -> Self {
+        self.new_input = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_crash<F: Fn(&CrashSummary) + Send + 'static>(mut self, f: F) -> Self {
+        self.crash = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_stats<F: Fn(&StatsSnapshot) + Send + 'static>(mut self, f: F) -> Self {
+        self.stats = Some(Box::new(f));
+        self
+    }
+
+    /// Start dispatcher thread of hooks if any is set, return sender of their events.
+    pub fn spawn(self) -> HookSender {
+        if self.new_input.is_none() && self.crash.is_none() && self.stats.is_none() {
+            return HookSender::default();
+        }
+        let enabled = [
+            self.new_input.is_some(),
+            self.crash.is_some(),
+            self.stats.is_some(),
+        ];
+        let (tx, rx) = channel();
+        thread::Builder::new()
+            .name(String::from("healer-hooks"))
+            .spawn(move || {
+                for event in rx {
+                    match (&event, &self.new_input, &self.crash, &self.stats) {
+                        (Event::NewInput(i), Some(f), _, _) => f(i),
+                        (Event::Crash(c), _, Some(f), _) => f(c),
+                        (Event::Stats(s), _, _, Some(f)) => f(s),
+                        _ => (),
+                    }
+                }
+            })
+            .unwrap_or_else(|e| exits!(exitcode::OSERR, "Fail to spawn hooks thread: {}", e));
+        HookSender {
+            tx: Some(tx),
+            enabled,
+        }
+    }
+}
+
+enum Event {
+    NewInput(InputSummary),
+    Crash(CrashSummary),
+    Stats(Box<StatsSnapshot>),
+}
+
+/// Sender of hook events, shared by all jobs. Events of unset hooks are never built.
+#[derive(Clone, Default)]
+pub struct HookSender {
+    tx: Option<Sender<Event>>,
+    /// Whether new input, crash and stats hooks are set
+    enabled: [bool; 3],
+}
+
+impl HookSender {
+    pub fn new_input<F: FnOnce() -> InputSummary>(&self, f: F) {
+        if self.enabled[0] {
+            self.send(Event::NewInput(f()));
+        }
+    }
+
+    pub fn crash<F: FnOnce() -> CrashSummary>(&self, f: F) {
+        if self.enabled[1] {
+            self.send(Event::Crash(f()));
+        }
+    }
+
+    pub fn stats<F: FnOnce() -> StatsSnapshot>(&self, f: F) {
+        if self.enabled[2] {
+            self.send(Event::Stats(Box::new(f())));
+        }
+    }
+
+    fn send(&self, event: Event) {
+        if let Some(tx) = self.tx.as_ref() {
+            // dispatcher only stops if a hook panicked
+            tx.send(event).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::sync_channel;
+    use std::time::Duration;
+
+    #[test]
+    fn hooks_dispatch() {
+        let (tx, rx) = sync_channel(1);
+        let hooks = Hooks::default()
+            .on_crash(move |c: &CrashSummary| tx.send(c.title.clone()).unwrap())
+            .spawn();
+        // event of unset hook is never built
+        hooks.new_input(|| unreachable!());
+        hooks.crash(|| CrashSummary {
+            title: String::from("WARNING in __alloc_pages_0_1a2b3c4d"),
+            signature: String::from("WARNING in __alloc_pages"),
+            class: CrashClass::default(),
+            subsystem: String::from("mm"),
+            repro: ReproScore::default(),
+            path: PathBuf::from("crashes/WARNING in __alloc_pages_0_1a2b3c4d"),
+        });
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            "WARNING in __alloc_pages_0_1a2b3c4d"
+        );
+        assert!(HookSender::default().tx.is_none());
+    }
+}
@@ -0,0 +1,422 @@
+//! Exchange corpus and repros with other fuzzers through a syz-hub.
+//!
+//! Every `interval` the hub is synced over net/rpc, see `gob`: the first sync connects with
+//! the whole corpus, later ones upload progs added since and repros of new crashes, and
+//! fetch what other managers added, until the hub has no more. Progs travel in syzkaller's
+//! text format, see `core::syz`, fetched ones are decoded and queued as candidates, so they
+//! are triaged like any other. Healer keeps no favored subset of corpus, so all of it is
+//! shared, and progs leaving it aren't deleted from hub. Failures are logged and retried
+//! with backoff on a new connection, fuzzing never waits on the hub.
+use crate::corpus::key_of;
+use crate::fuzzer::Fuzzer;
+use crate::gob::{self, Type, Value};
+use core::prog::Prog;
+use core::syz::{decode, encode};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::collections::HashSet;
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::exit;
+use std::sync::atomic::Ordering;
+use tokio::sync::broadcast;
+use tokio::time::{delay_for, Duration};
+
+/// Time a call may take before the connection counts as broken.
+const CALL_TIMEOUT: Duration = Duration::from_secs(180);
+/// Longest wait before retrying a failed sync.
+const MAX_BACKOFF: Duration = Duration::from_secs(1800);
+/// Syncs of a round at most, in case hub keeps reporting more.
+const MAX_FETCHES: usize = 100;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HubConf {
+    /// Address of hub, e.g. `host:port`
+    pub addr: String,
+    /// Client name and key hub knows, the key is redacted when config is serialized
+    pub client: String,
+    #[serde(serialize_with = "redact")]
+    pub key: String,
+    /// Manager name reported to hub, must start with client name, default client name
+    pub manager: Option<String>,
+    /// Seconds between syncs, default 60
+    pub interval: Option<u64>,
+    /// Deflate the connection as syzkaller does, default true
+    pub compression: Option<bool>,
+}
+
+fn redact<S: serde::Serializer>(_: &str, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str("<redacted>")
+}
+
+impl HubConf {
+    pub fn check(&self) {
+        if self.addr.is_empty() || self.client.is_empty() {
+            eprintln!("Config Error: hub: addr and client must be given");
+            exit(exitcode::CONFIG)
+        }
+        if self
+            .manager
+            .as_ref()
+            .is_some_and(|m| !m.starts_with(&self.client))
+        {
+            eprintln!("Config Error: hub: manager must start with client name");
+            exit(exitcode::CONFIG)
+        }
+        if self.interval == Some(0) {
+            eprintln!("Config Error: hub: interval must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+    }
+
+    fn manager(&self) -> &str {
+        self.manager.as_deref().unwrap_or(&self.client)
+    }
+}
+
+/// Sync `fuzzer` with hub until shutdown. `fresh` tells hub this run starts without corpus.
+pub async fn sync(
+    fuzzer: Fuzzer,
+    conf: HubConf,
+    fresh: bool,
+    mut shutdown: broadcast::Receiver<()>,
+) {
+    tokio::select! {
+        _ = shutdown.recv() => (),
+        _ = do_sync(&fuzzer, &conf, fresh) => (),
+    }
+}
+
+async fn do_sync(fuzzer: &Fuzzer, conf: &HubConf, mut fresh: bool) {
+    let interval = Duration::from_secs(conf.interval.unwrap_or(60));
+    let calls = fuzzer
+        .target
+        .groups
+        .values()
+        .flat_map(|g| g.fns.iter().map(|f| f.dec_name.replace('@', "$")))
+        .collect::<Vec<_>>();
+    let mut conn: Option<Conn> = None;
+    // progs and repros hub has got from this run, by key.
+    let mut sent = HashSet::new();
+    let mut repros_sent = HashSet::new();
+    let mut wait = interval;
+
+    loop {
+        delay_for(wait).await;
+        if conn.is_none() {
+            // a new connection starts with the whole corpus.
+            sent.clear();
+        }
+        let add = unsent(fuzzer.corpus.inner.lock().await.iter(), &mut sent);
+        let repros = unsent(fuzzer.record.repro_progs().await.iter(), &mut repros_sent);
+        let round = Round {
+            connect: conn.is_none(),
+            fresh,
+            calls: calls.clone(),
+            add: add.iter().map(|p| encode(p, &fuzzer.target)).collect(),
+            repros: repros.iter().map(|p| encode(p, &fuzzer.target)).collect(),
+        };
+        let c = conn.take();
+        let conf_ = conf.clone();
+        let (c, fetched) = tokio::task::spawn_blocking(move || round.run(c, &conf_))
+            .await
+            .unwrap_or_else(|e| (None, Err(e.to_string())));
+        conn = c;
+
+        match fetched {
+            Ok(progs) => {
+                fresh = false;
+                wait = interval;
+                fuzzer
+                    .hub_sent
+                    .fetch_add(add.len() + repros.len(), Ordering::Relaxed);
+                inject(fuzzer, &progs).await;
+            }
+            Err(e) => {
+                // unsent progs go again next time.
+                for p in add.iter() {
+                    sent.remove(&key_of(p));
+                }
+                for p in repros.iter() {
+                    repros_sent.remove(&key_of(p));
+                }
+                wait = std::cmp::min(wait * 2, MAX_BACKOFF);
+                warn!("Hub: sync failed, retry in {}s: {}", wait.as_secs(), e);
+            }
+        }
+    }
+}
+
+/// Progs of `progs` not in `sent` yet, marked as sent.
+fn unsent<'a, I: IntoIterator<Item = &'a Prog>>(progs: I, sent: &mut HashSet<u64>) -> Vec<Prog> {
+    progs
+        .into_iter()
+        .filter(|p| sent.insert(key_of(p)))
+        .cloned()
+        .collect()
+}
+
+/// Queue fetched progs decodable for target as candidates.
+async fn inject(fuzzer: &Fuzzer, progs: &[Vec<u8>]) {
+    let mut first_err = None;
+    let mut decoded = 0;
+    for text in progs {
+        let p = std::str::from_utf8(text)
+            .map_err(|_| String::from("non utf-8 prog"))
+            .and_then(|text| decode(text, &fuzzer.target));
+        match p {
+            Ok(p) => {
+                fuzzer.candidates.push(p).await;
+                decoded += 1;
+            }
+            Err(e) => {
+                first_err.get_or_insert(e);
+            }
+        }
+    }
+    fuzzer.hub_recv.fetch_add(decoded, Ordering::Relaxed);
+    if decoded != 0 {
+        info!("Hub: {} prog(s) fetched", decoded);
+    }
+    if let Some(e) = first_err {
+        warn!(
+            "Hub: {} undecodable prog(s) skipped, e.g. {}",
+            progs.len() - decoded,
+            e
+        );
+    }
+}
+
+/// One sync, run on a blocking thread.
+struct Round {
+    connect: bool,
+    fresh: bool,
+    calls: Vec<String>,
+    add: Vec<String>,
+    repros: Vec<String>,
+}
+
+impl Round {
+    /// Progs and repros fetched, with connection to keep if it's still fine.
+    fn run(
+        self,
+        conn: Option<Conn>,
+        conf: &HubConf,
+    ) -> (Option<Conn>, Result<Vec<Vec<u8>>, String>) {
+        let mut conn = match conn {
+            Some(conn) => conn,
+            None => match Conn::dial(conf) {
+                Ok(conn) => conn,
+                Err(e) => return (None, Err(e)),
+            },
+        };
+        match self.exchange(&mut conn, conf) {
+            Ok(progs) => (Some(conn), Ok(progs)),
+            Err(e) => (None, Err(e)),
+        }
+    }
+
+    fn exchange(self, conn: &mut Conn, conf: &HubConf) -> Result<Vec<Vec<u8>>, String> {
+        let bytes_list = |texts: Vec<String>| {
+            Value::List(
+                texts
+                    .into_iter()
+                    .map(|t| Value::Bytes(t.into_bytes()))
+                    .collect(),
+            )
+        };
+        let auth = || {
+            vec![
+                (String::from("Client"), Value::Str(conf.client.clone())),
+                (String::from("Key"), Value::Str(conf.key.clone())),
+                (String::from("Manager"), Value::Str(conf.manager().into())),
+            ]
+        };
+        let mut add = bytes_list(self.add);
+        if self.connect {
+            let mut args = auth();
+            args.push((String::from("Fresh"), Value::Bool(self.fresh)));
+            args.push((
+                String::from("Calls"),
+                Value::List(self.calls.into_iter().map(Value::Str).collect()),
+            ));
+            args.push((String::from("Corpus"), add));
+            conn.call("Hub.Connect", &connect_args(), Value::Struct(args))?;
+            add = Value::List(Vec::new());
+        }
+
+        let mut fetched = Vec::new();
+        let mut repros = bytes_list(self.repros);
+        for _ in 0..MAX_FETCHES {
+            let mut args = auth();
+            args.push((String::from("NeedRepros"), Value::Bool(true)));
+            args.push((
+                String::from("Add"),
+                std::mem::replace(&mut add, Value::List(Vec::new())),
+            ));
+            args.push((
+                String::from("Repros"),
+                std::mem::replace(&mut repros, Value::List(Vec::new())),
+            ));
+            let res = conn.call("Hub.Sync", &sync_args(), Value::Struct(args))?;
+            if let Some(Value::List(inputs)) = res.field("Inputs") {
+                for input in inputs {
+                    if let Some(Value::Bytes(p)) = input.field("Prog") {
+                        fetched.push(p.clone());
+                    }
+                }
+            }
+            for field in &["Progs", "Repros"] {
+                if let Some(progs) = res.field(field) {
+                    fetched.extend(progs.as_bytes_list().into_iter().map(Vec::from));
+                }
+            }
+            if res.field("More").and_then(Value::as_int).unwrap_or(0) <= 0 {
+                break;
+            }
+        }
+        Ok(fetched)
+    }
+}
+
+fn connect_args() -> Type {
+    Type::Struct(
+        "HubConnectArgs",
+        vec![
+            ("Client", Type::Str),
+            ("Key", Type::Str),
+            ("Manager", Type::Str),
+            ("Fresh", Type::Bool),
+            ("Calls", Type::Slice(Box::new(Type::Str))),
+            ("Corpus", Type::Slice(Box::new(Type::Bytes))),
+        ],
+    )
+}
+
+fn sync_args() -> Type {
+    Type::Struct(
+        "HubSyncArgs",
+        vec![
+            ("Client", Type::Str),
+            ("Key", Type::Str),
+            ("Manager", Type::Str),
+            ("NeedRepros", Type::Bool),
+            ("Add", Type::Slice(Box::new(Type::Bytes))),
+            ("Del", Type::Slice(Box::new(Type::Str))),
+            ("Repros", Type::Slice(Box::new(Type::Bytes))),
+        ],
+    )
+}
+
+/// net/rpc client connection with its gob streams.
+struct Conn {
+    reader: Box<dyn Read + Send>,
+    writer: Box<dyn Write + Send>,
+    enc: gob::Encoder,
+    dec: gob::Decoder,
+    seq: u64,
+}
+
+impl Conn {
+    fn dial(conf: &HubConf) -> Result<Self, String> {
+        let addr = conf
+            .addr
+            .to_socket_addrs()
+            .map_err(|e| format!("{}: {}", conf.addr, e))?
+            .next()
+            .ok_or_else(|| format!("{}: no address", conf.addr))?;
+        let stream = TcpStream::connect_timeout(&addr, CALL_TIMEOUT)
+            .map_err(|e| format!("connect {}: {}", conf.addr, e))?;
+        let setup = |stream: &TcpStream| {
+            stream.set_read_timeout(Some(CALL_TIMEOUT))?;
+            stream.set_write_timeout(Some(CALL_TIMEOUT))?;
+            stream.set_nodelay(true)?;
+            stream.try_clone()
+        };
+        let read_half = setup(&stream).map_err(|e| format!("connect {}: {}", conf.addr, e))?;
+        let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write + Send>) =
+            if conf.compression.unwrap_or(true) {
+                (
+                    Box::new(DeflateDecoder::new(read_half)),
+                    Box::new(DeflateEncoder::new(stream, Compression::best())),
+                )
+            } else {
+                (Box::new(BufReader::new(read_half)), Box::new(stream))
+            };
+        Ok(Self {
+            reader,
+            writer,
+            enc: gob::Encoder::default(),
+            dec: gob::Decoder::default(),
+            seq: 0,
+        })
+    }
+
+    /// Call `method` with `args` of type `ty`, returning reply.
+    fn call(&mut self, method: &str, ty: &Type, args: Value) -> Result<Value, String> {
+        let header = Type::Struct(
+            "Request",
+            vec![("ServiceMethod", Type::Str), ("Seq", Type::Uint)],
+        );
+        let seq = self.seq;
+        self.seq += 1;
+        let mut data = self.enc.encode(
+            &header,
+            &Value::Struct(vec![
+                (String::from("ServiceMethod"), Value::Str(method.into())),
+                (String::from("Seq"), Value::Uint(seq)),
+            ]),
+        )?;
+        data.extend(self.enc.encode(ty, &args)?);
+        self.writer
+            .write_all(&data)
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| format!("{}: {}", method, e))?;
+
+        let res = self
+            .next_value()
+            .map_err(|e| format!("{}: {}", method, e))?;
+        let reply = self
+            .next_value()
+            .map_err(|e| format!("{}: {}", method, e))?;
+        if res.field("Seq").and_then(Value::as_int).unwrap_or(0) != seq as i64 {
+            return Err(format!("{}: reply out of sequence", method));
+        }
+        match res.field("Error").and_then(Value::as_str) {
+            Some(e) if !e.is_empty() => Err(format!("{}: {}", method, e)),
+            _ => Ok(reply),
+        }
+    }
+
+    /// Next value of incoming stream, reading past type definitions.
+    fn next_value(&mut self) -> Result<Value, String> {
+        loop {
+            let len = read_uint(&mut self.reader)? as usize;
+            let mut msg = vec![0; len];
+            self.reader
+                .read_exact(&mut msg)
+                .map_err(|e| e.to_string())?;
+            if let Some(val) = self.dec.decode(&msg)? {
+                return Ok(val);
+            }
+        }
+    }
+}
+
+/// Length of next message, as encoded by `gob`.
+fn read_uint(r: &mut dyn Read) -> Result<u64, String> {
+    let mut buf = [0; 9];
+    r.read_exact(&mut buf[..1]).map_err(|e| e.to_string())?;
+    let n = if buf[0] < 0x80 {
+        0
+    } else {
+        buf[0].wrapping_neg() as usize
+    };
+    if n > 8 {
+        return Err(String::from("bad message length"));
+    }
+    r.read_exact(&mut buf[1..=n]).map_err(|e| e.to_string())?;
+    gob::get_uint(&mut &buf[..=n])
+}
@@ -0,0 +1,177 @@
+//! Output dir of each fuzzing job, enabled by `per_job_dirs`.
+//!
+//! Crashes, corpus and failure logs of run dir are shared by all jobs. To debug one
+//! misbehaving vm, everything about job N is also written to `job-N/` of run dir:
+//! `crashes/`, links to crash cases of crashes it found, `serial.log`, console output of
+//! its guest at each of them, `failures.log`, its guest and host failures, and `queue/`,
+//! progs it added to corpus. Crashes found by a job keep being attributed to it when
+//! another job reproduces them. At exit `jobs.json` of run dir sums up all job dirs.
+use chrono::prelude::*;
+use std::path::{Path, PathBuf};
+use tokio::fs::{create_dir_all, read_dir, write, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone)]
+pub struct JobDirs {
+    run_dir: PathBuf,
+}
+
+/// Artifacts of a job, entry of `jobs.json`.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct JobSummary {
+    pub job: usize,
+    pub dir: String,
+    pub crashes: usize,
+    pub queue: usize,
+}
+
+impl JobDirs {
+    pub fn new(run_dir: PathBuf) -> Self {
+        Self { run_dir }
+    }
+
+    pub fn dir_of(&self, job: usize) -> PathBuf {
+        self.run_dir.join(format!("job-{}", job))
+    }
+
+    /// Link crash case `case`, saved for crash of `job` with console `log`.
+    pub async fn add_crash(&self, job: usize, case: &Path, log: &str) {
+        let dir = self.dir_of(job).join("crashes");
+        let ret = match (create_dir_all(&dir).await, case.file_name()) {
+            (Err(e), _) => Err(e),
+            // relative, so run dir can be moved.
+            (Ok(()), Some(name)) => {
+                let target = Path::new("../../crashes").join(name);
+                std::os::unix::fs::symlink(target, dir.join(name))
+            }
+            (Ok(()), None) => Ok(()),
+        };
+        if let Err(e) = ret {
+            warn!(
+                "job-{}: fail to link crash into {}: {}",
+                job,
+                dir.display(),
+                e
+            );
+        }
+        self.append(job, "serial.log", log).await;
+    }
+
+    /// Save prog `p`, added to corpus by `job`, as file `name` of its queue.
+    pub async fn add_queue(&self, job: usize, name: &str, p: &str) {
+        let dir = self.dir_of(job).join("queue");
+        let ret = match create_dir_all(&dir).await {
+            Ok(()) => write(dir.join(name), p).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = ret {
+            warn!(
+                "job-{}: fail to save queue prog to {}: {}",
+                job,
+                dir.display(),
+                e
+            );
+        }
+    }
+
+    /// Append `text` to log `name` of `job`, headed by current time.
+    pub async fn append(&self, job: usize, name: &str, text: &str) {
+        let dir = self.dir_of(job);
+        let entry = format!("==== [{}]\n{}\n", Local::now(), text.trim_end());
+        let ret = match create_dir_all(&dir).await {
+            Ok(()) => match OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(dir.join(name))
+                .await
+            {
+                Ok(mut f) => f.write_all(entry.as_bytes()).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = ret {
+            warn!("job-{}: fail to append to {}: {}", job, name, e);
+        }
+    }
+
+    /// Sum up dirs of `jobs` jobs into `jobs.json` of run dir.
+    pub async fn aggregate(&self, jobs: usize) -> Vec<JobSummary> {
+        let mut summaries = Vec::new();
+        for job in 0..jobs {
+            let dir = self.dir_of(job);
+            summaries.push(JobSummary {
+                job,
+                dir: format!("job-{}", job),
+                crashes: count(&dir.join("crashes")).await,
+                queue: count(&dir.join("queue")).await,
+            });
+        }
+        let path = self.run_dir.join("jobs.json");
+        let json = serde_json::to_string_pretty(&summaries).unwrap();
+        write(&path, json)
+            .await
+            .unwrap_or_else(|e| warn!("Fail to write {}: {}", path.display(), e));
+        summaries
+    }
+}
+
+/// Entries of `dir`, 0 if it doesn't exist.
+async fn count(dir: &Path) -> usize {
+    let mut n = 0;
+    if let Ok(mut entries) = read_dir(dir).await {
+        while let Ok(Some(_)) = entries.next_entry().await {
+            n += 1;
+        }
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::process::id;
+
+    #[test]
+    fn job_dirs_of_artifacts() {
+        let run_dir = temp_dir().join(format!("healer-job-dirs-{}", id()));
+        std::fs::create_dir_all(run_dir.join("crashes")).unwrap();
+        let case = run_dir.join("crashes").join("case_0");
+        std::fs::write(&case, "case").unwrap();
+        let dirs = JobDirs::new(run_dir.clone());
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let summaries = rt.block_on(async {
+            dirs.add_crash(1, &case, "BUG: oops\n").await;
+            dirs.add_queue(1, "p0", "sync()").await;
+            dirs.add_queue(0, "p1", "sync()").await;
+            dirs.aggregate(2).await
+        });
+        let linked = std::fs::read_to_string(run_dir.join("job-1/crashes/case_0")).unwrap();
+        let serial = std::fs::read_to_string(run_dir.join("job-1/serial.log")).unwrap();
+        let json = run_dir.join("jobs.json").is_file();
+        std::fs::remove_dir_all(&run_dir).unwrap();
+
+        assert_eq!(linked, "case");
+        assert!(serial.starts_with("==== [") && serial.ends_with("BUG: oops\n"));
+        assert!(json);
+        assert_eq!(
+            summaries,
+            vec![
+                JobSummary {
+                    job: 0,
+                    dir: "job-0".to_string(),
+                    crashes: 0,
+                    queue: 1
+                },
+                JobSummary {
+                    job: 1,
+                    dir: "job-1".to_string(),
+                    crashes: 1,
+                    queue: 1
+                },
+            ]
+        );
+    }
+}
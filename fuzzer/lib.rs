@@ -1,5 +1,9 @@
-use std::path::PathBuf;
+use chrono::Local;
+use std::collections::HashSet;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
 use std::process::{exit, id};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 #[macro_use]
@@ -9,54 +13,253 @@ extern crate serde;
 #[macro_use]
 extern crate log;
 
+use nix::sys::statvfs::statvfs;
 use regex::Regex;
-use tokio::fs::{create_dir_all, read};
+use tokio::fs::{create_dir_all, read, read_to_string, remove_file, write};
 use tokio::signal::ctrl_c;
-use tokio::sync::{broadcast, Barrier};
+use tokio::sync::{broadcast, mpsc};
 use tokio::time::{delay_for, Duration, Instant};
 
+use core::dict::Dict;
+use core::diff::diff;
 use core::prog::Prog;
 use core::target::Target;
-use fots::types::Items;
+use core::validate::ValidationError;
+use fots::types::{FnId, Items};
 
-use crate::exec::{Executor, ExecutorConf};
-use crate::fuzzer::Fuzzer;
+pub use crate::bench_mode::BenchOpts;
+pub use crate::builder::ConfigBuilder;
+use crate::cover_dump::CoverDumpConf;
+use crate::exec::{ExecutorConf, QemuExecutor};
+pub use crate::exec_mode::ExecOpts;
+use crate::fuzzer::FuzzerConf;
+pub use crate::fuzzer::{Fuzzer, FuzzerDeps, JobState, StepOutcome};
 use crate::guest::{GuestConf, QemuConf, SSHConf};
+use crate::hooks::Hooks;
+use crate::hub::HubConf;
+use crate::logger::LogConf;
 #[cfg(feature = "mail")]
 use crate::mail::MailConf;
+use crate::plateau::PlateauConf;
+use crate::provenance::RunMeta;
+use crate::regression::RegressionConf;
+use crate::replay::ReplayConf;
+use crate::severity::SeverityRule;
+use crate::state::FuzzerState;
 use crate::stats::SamplerConf;
+use crate::summary::{CrashStatus, EndReason};
+use crate::symbolize::SymbolizeConf;
+#[cfg(feature = "otlp")]
+use crate::trace::OtlpConf;
+use crate::watchdog::WatchdogConf;
 
 #[macro_use]
 #[allow(dead_code)]
 mod utils;
+mod backlog;
+mod bench_mode;
+pub mod builder;
+pub mod call_timeout;
+pub mod calls;
+mod check_run;
 pub mod corpus;
-mod exec;
+mod cover_dump;
+pub mod covset;
+mod cross_check;
+mod energy;
+pub mod env;
+pub mod exec;
+mod exec_mode;
+mod failures;
+pub mod fault;
 pub mod feedback;
-mod fuzzer;
+pub mod fuzzer;
+mod gob;
+mod growth;
 mod guest;
+pub mod hooks;
+pub mod hub;
+mod job_dir;
+mod logger;
 #[cfg(feature = "mail")]
 mod mail;
+mod maintainers;
+#[cfg(test)]
+mod mock;
+mod plateau;
+mod provenance;
+mod regression;
+pub mod relations;
+pub mod replay;
 pub mod report;
+mod repro_mode;
+pub mod resume;
+pub mod rpc;
+pub mod selection;
+pub mod severity;
+pub mod state;
 mod stats;
+mod summary;
+mod symbolize;
+mod symbols;
+mod syz_crash;
+pub mod syzdb;
+mod title;
+mod toggle;
+mod trace;
+mod watchdog;
+mod work_dir;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub fots_bin: PathBuf,
     pub curpus: Option<PathBuf>,
+    /// Fots target the corpus was generated with, if it's older than `fots_bin`.
+    pub corpus_fots_bin: Option<PathBuf>,
+    /// syzkaller corpus.db to seed corpus with, see `syzdb`.
+    #[serde(alias = "corpus_db")]
+    pub syz_corpus: Option<PathBuf>,
+    /// Also write corpus as syzkaller corpus.db to run dir at exit, see `syzdb`, default false.
+    pub export_corpus_db: Option<bool>,
+    /// Also write crashes in syzkaller's layout to run dir, see `syz_crash`, default false.
+    pub syz_crash_layout: Option<bool>,
+    /// Also write artifacts of each job to its own dir of run dir, see `job_dir`, default
+    /// false.
+    pub per_job_dirs: Option<bool>,
+    /// Serve control requests on a unix socket of run dir, see `rpc`, default false.
+    pub rpc: Option<bool>,
+    /// Fuzzer state saved by previous run, see `state`.
+    pub fuzzer_state: Option<PathBuf>,
+    /// Relations between calls to start with, `relations.json` of a previous run, see
+    /// `relations`.
+    pub relations: Option<PathBuf>,
+    /// Run dir of previous run to continue, see `resume`.
+    pub resume: Option<PathBuf>,
+    /// Number of vms, 0 means sized by host cpus and memory.
     pub vm_num: usize,
     pub suppressions: Option<Vec<String>>,
+    /// Crashes classified into these subsystems are suppressed.
+    pub suppress_subsystems: Option<Vec<String>>,
     pub ignores: Option<Vec<String>>,
+    /// File of calls that should never be generated, glob patterns allowed.
+    pub disabled_calls: Option<PathBuf>,
+    /// Calls each generated prog must contain one of, glob patterns allowed.
+    pub required_calls: Option<Vec<String>>,
+    /// AFL dict files str and byte buffer values are drawn from, see `core::dict`.
+    pub dictionaries: Option<Vec<PathBuf>>,
+    /// Remove calls that don't contribute new coverage before inserting prog to corpus, default true.
+    pub trim: Option<bool>,
+    /// Fuzz without coverage, e.g. kernels without kcov, see `Fuzzer::blackbox`, default false.
+    pub blackbox: Option<bool>,
+    /// Stop after this many executions of all jobs, for comparing runs at equal executions.
+    pub max_execs: Option<u64>,
+    /// Stop after this many crashes are saved.
+    pub max_crashes: Option<usize>,
+    /// Dir holding output of every run, each run gets its own sub dir, default current dir.
+    pub out_dir: Option<PathBuf>,
+    /// Number of vms allowed to fail booting, fuzzing goes on with the rest, default 0.
+    pub tolerate_boot_failures: Option<usize>,
+    /// Worker threads of the runtime driving all vms and jobs, default number of cpus.
+    pub runtime_threads: Option<usize>,
+    /// Shell script run in guest after each boot, before executor starts.
+    pub guest_setup: Option<PathBuf>,
+    /// Warn if output filesystem has less free space in MB, default 1024.
+    pub min_free_space: Option<u64>,
+    /// Dir of host side scratch files, each run and vm gets its own sub dir, see `work_dir`,
+    /// default system temp dir.
+    pub work_dir: Option<PathBuf>,
+    /// Rules rating crash severity, tried before default ones, see `severity`. An array of
+    /// tables, so it's kept after every scalar for toml serialization of `resolved`.
+    pub severity: Option<Vec<SeverityRule>>,
+    /// linux/amd64 on qemu if omitted.
+    #[serde(default)]
     pub guest: GuestConf,
     pub qemu: QemuConf,
     pub ssh: SSHConf,
     pub executor: ExecutorConf,
     pub sampler: Option<SamplerConf>,
+    pub fuzzer: Option<FuzzerConf>,
+    pub log: Option<LogConf>,
+    pub symbolize: Option<SymbolizeConf>,
+    pub plateau: Option<PlateauConf>,
+    /// Stall detection of all jobs, on with defaults if omitted, see `watchdog`.
+    pub watchdog: Option<WatchdogConf>,
+    /// Replay crashes of a previous run before fuzzing, see `regression`.
+    pub regression: Option<RegressionConf>,
+    /// Log every executed prog with its outcome, disabled if omitted, see `replay`.
+    pub replay_log: Option<ReplayConf>,
+    /// syz-hub to exchange corpus and repros with, disabled if omitted, see `hub`.
+    pub hub: Option<HubConf>,
+    /// Dump coverage as raw PCs for syz-cover, disabled if omitted, see `cover_dump`.
+    pub cover_dump: Option<CoverDumpConf>,
 
     #[cfg(feature = "mail")]
     pub mail: Option<MailConf>,
+    #[cfg(feature = "otlp")]
+    pub otlp: Option<OtlpConf>,
 }
 
 impl Config {
+    /// Load config from toml file, unknown keys are rejected. Qemu and executor options
+    /// left out are taken from defaults of guest, see `targets`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Config file not found: {}: {}", path.display(), e);
+            exit(exitcode::IOERR)
+        });
+        Self::from_toml(&data).unwrap_or_else(|e| {
+            eprintln!("Config Error: {}: {}", path.display(), e);
+            exit(exitcode::CONFIG)
+        })
+    }
+
+    /// Parse config of toml `data`, merged over defaults of its guest.
+    pub fn from_toml(data: &str) -> Result<Self, toml::de::Error> {
+        let conf: toml::Value = toml::from_str(data)?;
+        let guest = match conf.get("guest") {
+            Some(guest) => guest.clone().try_into::<GuestConf>()?,
+            None => GuestConf::default(),
+        };
+        match guest.defaults() {
+            Some(defaults) => guest::merge(defaults, conf).try_into(),
+            None => conf.try_into(),
+        }
+    }
+
+    /// Config after applying environment and command line overrides, in toml.
+    /// Mail password is only read from environment and hub key is redacted, so nothing
+    /// secret is included.
+    pub fn resolved(&self) -> String {
+        toml::to_string_pretty(self)
+            .unwrap_or_else(|e| exits!(exitcode::SOFTWARE, "Fail to serialize config: {}", e))
+    }
+
+    pub fn out_dir(&self) -> PathBuf {
+        self.out_dir.clone().unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// Runtime that drives all vms and jobs. Executions are async, a job waiting for its
+    /// guest doesn't hold a thread, so one thread can keep many vms busy.
+    pub fn runtime(&self) -> tokio::runtime::Runtime {
+        let mut builder = tokio::runtime::Builder::new();
+        builder.threaded_scheduler().enable_all();
+        if let Some(n) = self.runtime_threads {
+            builder.core_threads(n);
+        }
+        builder
+            .build()
+            .unwrap_or_else(|e| exits!(exitcode::OSERR, "Fail to build runtime: {}", e))
+    }
+
+    /// Resolve `vm_num = 0` to number of vms that fits host cpus and memory.
+    pub fn auto_size(&mut self) {
+        if self.vm_num == 0 {
+            self.vm_num = guest::auto_vm_num(&self.qemu);
+        }
+    }
+
     pub fn check(&self) {
         if !self.fots_bin.is_file() {
             eprintln!(
@@ -65,6 +268,33 @@ impl Config {
             );
             exit(exitcode::CONFIG)
         }
+        let loaded = std::fs::read(&self.fots_bin)
+            .map_err(|e| format!("fail to read: {}", e))
+            .and_then(|data| {
+                Items::load(&data).map_err(|e| format!("not a compiled fots file: {}", e))
+            });
+        if let Err(e) = loaded {
+            eprintln!("Config Error: fots_bin {}: {}", self.fots_bin.display(), e);
+            exit(exitcode::CONFIG)
+        }
+
+        if let Some(subsystems) = &self.suppress_subsystems {
+            let names = core::subsystem::names();
+            for s in subsystems {
+                if !names.contains(&s.as_str()) {
+                    eprintln!(
+                        "Config Error: suppress_subsystems: unknown subsystem \"{}\", known ones: {}",
+                        s,
+                        names.join(", ")
+                    );
+                    exit(exitcode::CONFIG)
+                }
+            }
+        }
+
+        for rule in self.severity.iter().flatten() {
+            rule.check()
+        }
 
         if let Some(suppressions) = &self.suppressions {
             for s in suppressions {
@@ -90,6 +320,50 @@ impl Config {
             }
         }
 
+        if self.required_calls.as_ref().is_some_and(|c| c.is_empty()) {
+            eprintln!("Config Error: required_calls is empty");
+            exit(exitcode::CONFIG)
+        }
+
+        if self.max_crashes == Some(0) {
+            eprintln!("Config Error: max_crashes must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+        if self.max_execs == Some(0) {
+            eprintln!("Config Error: max_execs must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+
+        if let Some(setup) = &self.guest_setup {
+            if !setup.is_file() {
+                eprintln!(
+                    "Config Error: guest setup script {} is invalid",
+                    setup.display()
+                );
+                exit(exitcode::CONFIG)
+            }
+        }
+
+        if let Some(disabled) = &self.disabled_calls {
+            if !disabled.is_file() {
+                eprintln!(
+                    "Config Error: disabled calls file {} is invalid",
+                    disabled.display()
+                );
+                exit(exitcode::CONFIG)
+            }
+        }
+
+        for dict in self.dictionaries.iter().flatten() {
+            if !dict.is_file() {
+                eprintln!(
+                    "Config Error: dictionary file {} is invalid",
+                    dict.display()
+                );
+                exit(exitcode::CONFIG)
+            }
+        }
+
         if let Some(corpus) = &self.curpus {
             if !corpus.is_file() {
                 eprintln!("Config Error: corpus file {} is invalid", corpus.display());
@@ -97,6 +371,57 @@ impl Config {
             }
         }
 
+        if let Some(corpus_fots_bin) = &self.corpus_fots_bin {
+            if self.curpus.is_none() {
+                eprintln!("Config Error: corpus_fots_bin is set without corpus");
+                exit(exitcode::CONFIG)
+            }
+            if !corpus_fots_bin.is_file() {
+                eprintln!(
+                    "Config Error: corpus fots file {} is invalid",
+                    corpus_fots_bin.display()
+                );
+                exit(exitcode::CONFIG)
+            }
+        }
+
+        if let Some(syz_corpus) = &self.syz_corpus {
+            if !syz_corpus.is_file() {
+                eprintln!(
+                    "Config Error: syz corpus file {} is invalid",
+                    syz_corpus.display()
+                );
+                exit(exitcode::CONFIG)
+            }
+        }
+
+        if let Some(relations) = &self.relations {
+            if !relations.is_file() || relations.extension() != Some("json".as_ref()) {
+                eprintln!(
+                    "Config Error: relations file {} is invalid, it must be a .json file",
+                    relations.display()
+                );
+                exit(exitcode::CONFIG)
+            }
+        }
+
+        if let Some(resume) = &self.resume {
+            if !resume.is_dir() {
+                eprintln!("Config Error: resume dir {} is invalid", resume.display());
+                exit(exitcode::CONFIG)
+            }
+        }
+
+        if let Some(fuzzer_state) = &self.fuzzer_state {
+            if !fuzzer_state.is_file() {
+                eprintln!(
+                    "Config Error: fuzzer state file {} is invalid",
+                    fuzzer_state.display()
+                );
+                exit(exitcode::CONFIG)
+            }
+        }
+
         let cpu_num = num_cpus::get();
         if self.vm_num == 0 || self.vm_num > cpu_num * 8 {
             eprintln!(
@@ -107,26 +432,203 @@ impl Config {
             exit(exitcode::CONFIG)
         }
 
+        if self.tolerate_boot_failures.unwrap_or(0) >= self.vm_num {
+            eprintln!(
+                "Config Error: tolerate_boot_failures must be less than vm num {}",
+                self.vm_num
+            );
+            exit(exitcode::CONFIG)
+        }
+        if self.runtime_threads == Some(0) {
+            eprintln!("Config Error: runtime_threads must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+
         if let Some(sampler) = self.sampler.as_ref() {
             sampler.check()
         }
 
+        if let Some(symbolize) = self.symbolize.as_ref() {
+            symbolize.check()
+        }
+
+        if let Some(fuzzer) = self.fuzzer.as_ref() {
+            fuzzer.check()
+        }
+
+        if let Some(plateau) = self.plateau.as_ref() {
+            plateau.check()
+        }
+        if self.blackbox == Some(true) && self.plateau.is_some() {
+            eprintln!("Config Error: plateau needs coverage, it can't be used with blackbox");
+            exit(exitcode::CONFIG)
+        }
+        if let Some(replay) = self.replay_log.as_ref() {
+            replay.check()
+        }
+        if let Some(cover_dump) = self.cover_dump.as_ref() {
+            cover_dump.check()
+        }
+        if let Some(hub) = self.hub.as_ref() {
+            hub.check()
+        }
+
+        if let Some(watchdog) = self.watchdog.as_ref() {
+            watchdog.check()
+        }
+
+        if let Some(regression) = self.regression.as_ref() {
+            regression.check()
+        }
+
+        if let Some(log) = self.log.as_ref() {
+            log.check()
+        }
+
         #[cfg(feature = "mail")]
         if let Some(mail) = mail.as_ref() {
             mail.check()
         }
 
+        #[cfg(feature = "otlp")]
+        if let Some(otlp) = self.otlp.as_ref() {
+            otlp.check()
+        }
+
         self.guest.check();
         self.executor.check();
-        self.qemu.check();
+        if let Some(max_calls) = self.executor.max_calls {
+            let gen_conf = self
+                .fuzzer
+                .as_ref()
+                .map(FuzzerConf::gen_conf)
+                .unwrap_or_default();
+            if gen_conf.prog_max_len > max_calls {
+                eprintln!(
+                    "Config Error: prog_max_len {} exceeds executor max_calls {}",
+                    gen_conf.prog_max_len, max_calls
+                );
+                exit(exitcode::CONFIG)
+            }
+        }
+        self.qemu.check(&self.guest.arch);
+        check_free_space(
+            "output dir",
+            &self.out_dir(),
+            self.min_free_space.unwrap_or(1024),
+        );
+        if let Some(dir) = self.work_dir.as_ref() {
+            work_dir::check(dir);
+            check_free_space("work dir", dir, self.min_free_space.unwrap_or(1024));
+        }
+        if self.qemu.pin_cpus.unwrap_or(false) {
+            guest::check_pin_cpus(self.vm_num, self.qemu.cpu_num as usize);
+        }
         self.ssh.check();
     }
 }
 
-pub async fn fuzz(cfg: Config) {
+/// Warn if filesystem of `dir` has less than `min` MB free space, `what` names the dir.
+fn check_free_space(what: &str, dir: &Path, min: u64) {
+    let dir = if dir.exists() { dir } else { Path::new(".") };
+    match statvfs(dir) {
+        Ok(stat) => {
+            let free = (stat.blocks_available() * stat.fragment_size()) >> 20;
+            if free < min {
+                eprintln!(
+                    "Config Warning: min_free_space: only {} MB free in {} {}, less than {} MB",
+                    free,
+                    what,
+                    dir.display(),
+                    min
+                );
+            }
+        }
+        Err(e) => eprintln!(
+            "Config Warning: fail to get free space of {} {}: {}",
+            what,
+            dir.display(),
+            e
+        ),
+    }
+}
+
+/// Boot one vm and check whether fuzzing works, then exit, see `check_run`.
+pub async fn check_run(cfg: Config) -> ! {
+    let (target, _, _) = load_target(&cfg).await;
+    check_run::check_run(&cfg, &target).await
+}
+
+/// Boot one vm, execute progs of `files` and print results, then exit, see `exec_mode`.
+pub async fn exec_progs(cfg: Config, run_dir: PathBuf, files: Vec<PathBuf>, opts: ExecOpts) -> ! {
+    let (target, _, _) = load_target(&cfg).await;
+    exec_mode::exec_mode(&cfg, Arc::new(target), run_dir, &files, opts).await
+}
+
+/// Boot all vms, measure their execution throughput, then exit, see `bench_mode`.
+pub async fn bench(cfg: Config, run_dir: PathBuf, opts: BenchOpts) -> ! {
+    let (target, _, _) = load_target(&cfg).await;
+    bench_mode::bench_mode(Arc::new(cfg), Arc::new(target), run_dir, opts).await
+}
+
+/// Boot one vm, reproduce crash of `report` with progs of `files`, then exit, see
+/// `repro_mode`.
+pub async fn repro_crash(cfg: Config, run_dir: PathBuf, report: PathBuf, files: Vec<PathBuf>) -> ! {
+    let (target, _, _) = load_target(&cfg).await;
+    repro_mode::repro_mode(&cfg, Arc::new(target), run_dir, &report, &files).await
+}
+
+/// Fuzz with `cfg`, every output of this run goes to `run_dir`, see `prepare_env`.
+pub async fn fuzz(cfg: Config, run_dir: PathBuf) {
+    fuzz_with_hooks(cfg, run_dir, Hooks::default()).await
+}
+
+/// Same as `fuzz`, calling `hooks` on new corpus progs, saved crashes and stats samples.
+pub async fn fuzz_with_hooks(cfg: Config, run_dir: PathBuf, hooks: Hooks) {
+    summary::register(run_dir.clone());
+    let resolved_path = run_dir.join("config.resolved.toml");
+    write(&resolved_path, cfg.resolved())
+        .await
+        .unwrap_or_else(|e| {
+            exits!(
+                exitcode::IOERR,
+                "Fail to write {}: {}",
+                resolved_path.display(),
+                e
+            )
+        });
+    #[cfg(feature = "otlp")]
+    if let Some(otlp) = cfg.otlp.as_ref() {
+        trace::init(otlp);
+    }
     let cfg = Arc::new(cfg);
-    let (target, corpus) = tokio::join!(load_target(&cfg), load_corpus(&cfg.curpus));
-    check_corpus(&target, &corpus);
+    let now = Instant::now();
+    let ((target, disabled, required), mut corpus) =
+        tokio::join!(load_target(&cfg), load_corpus(&cfg.curpus));
+    info!("Target loaded, cost {}ms", now.elapsed().as_millis());
+    // Target is immutable after loading, all fuzzing jobs share this one.
+    let target = Arc::new(target);
+    if let Some(path) = cfg.corpus_fots_bin.as_ref() {
+        corpus = migrate_corpus(path, &target, corpus).await;
+    }
+    if let Some(dir) = cfg.resume.as_ref() {
+        info!("Resuming run {}", dir.display());
+        corpus.extend(resume::corpus_of(dir, &target).await);
+    }
+    if let Some(path) = cfg.syz_corpus.as_ref() {
+        corpus.extend(syzdb::seeds_of(path, &target).await);
+    }
+    if !disabled.is_empty() {
+        let n = corpus.len();
+        corpus.retain(|p| p.calls.iter().all(|c| !disabled.contains(&c.fid)));
+        if n != corpus.len() {
+            warn!(
+                "Corpus: {} prog(s) contain disabled calls, skipped",
+                n - corpus.len()
+            );
+        }
+    }
+    check_corpus(&target, &mut corpus);
     info!("Corpus: {}", corpus.len());
     info!(
         "Syscalls: {}  Groups: {}",
@@ -134,36 +636,131 @@ pub async fn fuzz(cfg: Config) {
         target.groups.len()
     );
 
-    let fuzzer = Fuzzer::new(target, corpus, &cfg);
+    let mut fuzzer = Fuzzer::new(FuzzerDeps {
+        hooks: hooks.spawn(),
+        ..FuzzerDeps::new(target, corpus, &cfg, run_dir)
+    });
+    fuzzer.conf.required_calls = required;
+    if let Some(paths) = cfg.dictionaries.as_ref() {
+        fuzzer.conf.dict = Arc::new(load_dicts(paths).await);
+    }
+    if let Some(path) = cfg.fuzzer_state.as_ref() {
+        restore_state(path, &fuzzer).await;
+    } else if let Some(dir) = cfg.resume.as_ref() {
+        resume::restore_state(dir, &fuzzer).await;
+    }
+    if let Some(path) = cfg.relations.as_ref() {
+        import_relations(path, &fuzzer).await;
+    }
+    if let Some(dir) = cfg.resume.as_ref() {
+        resume::import_crashes(dir, &fuzzer).await;
+        resume::import_raw_crashes(dir, &fuzzer).await;
+    }
+    if let Some(conf) = cfg.regression.as_ref() {
+        regression::check(conf, &cfg, &mut fuzzer).await;
+    }
     info!(
-        "Booting {} {}/{} on {} ...",
-        cfg.vm_num, cfg.guest.os, cfg.guest.arch, cfg.guest.platform
+        "Booting {} {} on {} ...",
+        cfg.vm_num,
+        cfg.guest.target(),
+        cfg.guest.platform
     );
     let now = std::time::Instant::now();
     let shutdown = start_fuzz(fuzzer.clone(), cfg.clone()).await;
     info!("Boot finished, cost {}s.", now.elapsed().as_secs());
 
-    wait_exit_signal(fuzzer, shutdown).await
+    let watchdog = cfg.watchdog.clone().unwrap_or_default();
+    wait_exit_signal(fuzzer, shutdown, watchdog).await
 }
 
+/// Boot all vms, then start fuzzing jobs on booted ones. If more than
+/// `tolerate_boot_failures` vms fail to boot, booted ones are torn down and fuzzer exits.
 async fn start_fuzz(fuzzer: Fuzzer, cfg: Arc<Config>) -> broadcast::Sender<()> {
     let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
-    let barrier = Arc::new(Barrier::new(cfg.vm_num + 1));
-    for _ in 0..cfg.vm_num {
+    let (boot_tx, mut boot_rx) = mpsc::unbounded_channel();
+    for job in 0..cfg.vm_num {
         let cfg = cfg.clone();
         let fuzzer = fuzzer.clone();
-        let barrier = barrier.clone();
-        let shutdown = shutdown_tx.subscribe();
+        let boot_tx = boot_tx.clone();
 
         tokio::spawn(async move {
-            let mut executor = Executor::new(&cfg);
-            executor.start().await;
-            barrier.wait().await;
-            fuzzer.fuzz(executor, shutdown).await;
+            let mut executor = QemuExecutor::new(&cfg, job);
+            executor.set_call_timeouts(fuzzer.call_timeouts.clone());
+            let booted = executor.start().await.map(|_| executor);
+            boot_tx.send((job, booted)).ok();
         });
     }
-    barrier.wait().await;
+    drop(boot_tx);
+
+    let mut executors = Vec::new();
+    let mut failed = Vec::new();
+    while let Some((job, booted)) = boot_rx.recv().await {
+        match booted {
+            Ok(executor) => executors.push((job, executor)),
+            Err(e) => {
+                error!("job-{}: {}", job, e);
+                failed.push(job);
+            }
+        }
+    }
+    if failed.len() > cfg.tolerate_boot_failures.unwrap_or(0) {
+        // guests and executors are killed on drop.
+        drop(executors);
+        failed.sort_unstable();
+        exits!(
+            exitcode::UNAVAILABLE,
+            "{} of {} vm(s) failed to boot, job(s): {:?}",
+            failed.len(),
+            cfg.vm_num,
+            failed
+        );
+    } else if !failed.is_empty() {
+        warn!(
+            "{} vm(s) failed to boot, fuzzing with {} job(s)",
+            failed.len(),
+            executors.len()
+        );
+    }
+
+    if let Some((_, executor)) = executors.first() {
+        let meta = RunMeta::capture(&cfg, executor).await;
+        meta.persist(&fuzzer.run_dir).await;
+        fuzzer.record.set_provenance(meta.provenance).await;
+    }
+
+    for (job, executor) in executors {
+        let fuzzer = fuzzer.clone();
+        let shutdown = shutdown_tx.subscribe();
+        tokio::spawn(fuzzer.fuzz(job, Box::new(executor), shutdown));
+    }
 
+    tokio::spawn(toggle::watch(fuzzer.clone(), shutdown_tx.subscribe()));
+    if let Some(conf) = cfg.plateau.clone() {
+        tokio::spawn(plateau::watch(
+            fuzzer.clone(),
+            conf,
+            shutdown_tx.subscribe(),
+        ));
+    }
+    if let Some(conf) = cfg.hub.clone() {
+        let fresh = cfg.curpus.is_none() && cfg.resume.is_none() && cfg.syz_corpus.is_none();
+        tokio::spawn(hub::sync(
+            fuzzer.clone(),
+            conf,
+            fresh,
+            shutdown_tx.subscribe(),
+        ));
+    }
+    if let Some(conf) = fuzzer.cover_dump.clone() {
+        tokio::spawn(cover_dump::watch(
+            fuzzer.clone(),
+            conf,
+            shutdown_tx.subscribe(),
+        ));
+    }
+    if cfg.rpc.unwrap_or(false) {
+        rpc::spawn(fuzzer.clone(), shutdown_tx.subscribe());
+    }
     let stats_source = fuzzer.stats();
     tokio::spawn(async move {
         let mut sampler = stats::Sampler::new(stats_source);
@@ -172,7 +769,8 @@ async fn start_fuzz(fuzzer: Fuzzer, cfg: Arc<Config>) -> broadcast::Sender<()> {
     shutdown_tx
 }
 
-async fn wait_exit_signal(fuzzer: Fuzzer, shutdown: broadcast::Sender<()>) {
+async fn wait_exit_signal(fuzzer: Fuzzer, shutdown: broadcast::Sender<()>, watchdog: WatchdogConf) {
+    let mut reason = EndReason::Interrupted;
     if cfg!(unix) {
         use tokio::signal::unix::{signal, SignalKind};
         let mut sig_ir =
@@ -187,46 +785,246 @@ async fn wait_exit_signal(fuzzer: Fuzzer, shutdown: broadcast::Sender<()>) {
             _= sig_term.recv() => {
                     warn!("TERM signal signal recved");
             }
+            _ = wait_budget(&fuzzer), if fuzzer.max_execs.is_some() => reason = EndReason::ExecBudget,
+            _ = wait_crash_budget(&fuzzer), if fuzzer.max_crashes.is_some() => reason = EndReason::CrashBudget,
+            _ = watchdog::watch(&fuzzer, &watchdog) => reason = EndReason::Stalled,
+            _ = rpc::wait_stop(&fuzzer) => reason = EndReason::Requested,
         }
     } else {
         info!("Send SIGINT to stop fuzzer");
-        ctrl_c()
-            .await
-            .expect("failed to set up ctrl-c signal handler");
-        warn!("INTERUPTE signal recved");
+        tokio::select! {
+            r = ctrl_c() => {
+                r.expect("failed to set up ctrl-c signal handler");
+                warn!("INTERUPTE signal recved");
+            }
+            _ = wait_budget(&fuzzer), if fuzzer.max_execs.is_some() => reason = EndReason::ExecBudget,
+            _ = wait_crash_budget(&fuzzer), if fuzzer.max_crashes.is_some() => reason = EndReason::CrashBudget,
+            _ = watchdog::watch(&fuzzer, &watchdog) => reason = EndReason::Stalled,
+            _ = rpc::wait_stop(&fuzzer) => reason = EndReason::Requested,
+        }
     }
 
     warn!("Stopping, persisting data...");
     shutdown.send(()).unwrap();
+    let (_, _, crashed) = fuzzer.record.len().await;
+    let (deterministic, flaky) = fuzzer.record.repro_summary().await;
+    info!(
+        "Crashes: {}, deterministic {}, flaky {}, not reproduced {} (attempts exhausted)",
+        crashed,
+        deterministic,
+        flaky,
+        crashed - deterministic - flaky
+    );
+    let deferred = fuzzer.backlog.deferred().await;
+    if deferred != 0 {
+        info!(
+            "Crashes not reproduced yet: {} (repro budget exhausted, resumable from raw_crashes)",
+            deferred
+        );
+    }
+    let rates = fuzzer.record.repro_rates().await;
+    if !rates.is_empty() {
+        info!("Repro rates of recent crashes:");
+        for (title, signature, rate) in rates {
+            info!("  {} {}: {}", rate, title, signature);
+        }
+    }
+    let stats = fuzzer.stats().snapshot().await;
+    let crashes = fuzzer
+        .record
+        .crash_statuses()
+        .await
+        .into_iter()
+        .map(|(title, hits, reproduced, severity)| CrashStatus {
+            title,
+            hits,
+            reproduced: Some(reproduced),
+            severity: Some(severity),
+        })
+        .collect();
     fuzzer.persist().await;
+    trace::shutdown();
+    let code = summary::write(reason, &stats, crashes);
 
     let now = Instant::now();
     let wait_time = Duration::new(5, 0);
     while shutdown.receiver_count() != 0 {
         delay_for(Duration::from_millis(200)).await;
+        // e.g. jobs of a stalled run are stuck, they never finish.
         if now.elapsed() >= wait_time {
             warn!("Wait time out, force to exit...");
-            exit(exitcode::SOFTWARE);
+            end_work_dir(reason);
+            exit(code);
         }
     }
     info!("All done");
     // TODO clear resources when exiting, e.g. qemu process.
-    exit(exitcode::OK);
+    end_work_dir(reason);
+    exit(code);
 }
 
-fn check_corpus(t: &Target, corpus: &[Prog]) {
-    for p in corpus.iter() {
-        if !t.groups.contains_key(&p.gid) {
-            eprintln!("Config Error: fots_bin/corpus not match: corpus contains unknown groups");
-            exit(1);
+/// Scratch files of a stalled run are kept for inspection, others are removed.
+fn end_work_dir(reason: EndReason) {
+    if reason == EndReason::Stalled {
+        work_dir::keep();
+    } else {
+        work_dir::cleanup();
+    }
+}
+
+/// Wait until execution budget is used up.
+async fn wait_budget(fuzzer: &Fuzzer) {
+    while !fuzzer.budget_exhausted() {
+        delay_for(Duration::from_millis(200)).await;
+    }
+    warn!(
+        "Execution budget used up: {} of {} executions",
+        fuzzer.exec_cnt.load(Ordering::SeqCst),
+        fuzzer.max_execs.unwrap()
+    );
+}
+
+/// Wait until crash budget is used up.
+async fn wait_crash_budget(fuzzer: &Fuzzer) {
+    let max_crashes = fuzzer.max_crashes.unwrap();
+    loop {
+        let (_, _, crashed) = fuzzer.record.len().await;
+        if crashed >= max_crashes {
+            warn!("Crash budget used up: {} crash(es) saved", crashed);
+            return;
         }
-        for c in p.calls.iter() {
-            if !t.fns.contains_key(&c.fid) {
-                eprintln!("Config Error: fots_bin/corpus not match: corpus contains unknown fn");
-                exit(1);
+        delay_for(Duration::from_millis(200)).await;
+    }
+}
+
+/// Exit if corpus contains groups or calls unknown to `t`, drop progs otherwise invalid.
+fn check_corpus(t: &Target, corpus: &mut Vec<Prog>) {
+    let n = corpus.len();
+    let mut first_err = None;
+    corpus.retain(|p| match t.validate(p) {
+        Ok(()) => true,
+        Err(errs) => {
+            for e in errs.iter() {
+                match e {
+                    ValidationError::UnknownGroup(_) => {
+                        eprintln!("Config Error: fots_bin/corpus not match: corpus contains unknown groups");
+                        exit(1);
+                    }
+                    ValidationError::UnknownCall { .. } => {
+                        eprintln!("Config Error: fots_bin/corpus not match: corpus contains unknown fn");
+                        exit(1);
+                    }
+                    _ => (),
+                }
             }
+            first_err.get_or_insert_with(|| errs[0].clone());
+            false
+        }
+    });
+    if let Some(e) = first_err {
+        warn!(
+            "Corpus: {} invalid prog(s) dropped, e.g. {}",
+            n - corpus.len(),
+            e
+        );
+    }
+}
+
+/// Rewrite corpus generated with older target for current one, progs containing
+/// removed or changed calls are dropped.
+async fn migrate_corpus(path: &PathBuf, t: &Target, corpus: Vec<Prog>) -> Vec<Prog> {
+    let items = Items::load(
+        &read(path)
+            .await
+            .unwrap_or_else(|e| exits!(exitcode::DATAERR, "Fail to load corpus fots file: {}", e)),
+    )
+    .unwrap_or_else(|e| exits!(exitcode::DATAERR, "Fail to load corpus fots file: {}", e));
+    let old = Target::from(items);
+    let d = diff(&old, t);
+    if d.is_empty() {
+        return corpus;
+    }
+    info!(
+        "Target changed since corpus generated: {} added, {} removed, {} changed call(s)",
+        d.added.len(),
+        d.removed.len(),
+        d.changed.len()
+    );
+
+    let n = corpus.len();
+    let corpus = corpus
+        .iter()
+        .filter_map(|p| d.migrate(p, t))
+        .collect::<Vec<_>>();
+    if n != corpus.len() {
+        warn!(
+            "Corpus: {} prog(s) contain removed or changed calls, dropped",
+            n - corpus.len()
+        );
+    }
+    corpus
+}
+
+async fn restore_state(path: &PathBuf, fuzzer: &Fuzzer) {
+    let data = read(path)
+        .await
+        .unwrap_or_else(|e| exits!(exitcode::IOERR, "Fail to load fuzzer state: {}", e));
+    let state: FuzzerState = serde_json::from_slice(&data)
+        .unwrap_or_else(|e| exits!(exitcode::DATAERR, "Fail to load fuzzer state: {}", e));
+    let relations = state.restore(fuzzer).await;
+    info!(
+        "Fuzzer state restored: exec {}, {} relation(s)",
+        state.exec, relations
+    );
+}
+
+async fn import_relations(path: &Path, fuzzer: &Fuzzer) {
+    let json = read_to_string(path).await.unwrap_or_else(|e| {
+        exits!(
+            exitcode::IOERR,
+            "Fail to read relations {}: {}",
+            path.display(),
+            e
+        )
+    });
+    let mut rt = fuzzer.rt.lock().await;
+    let imported = relations::import_json(&fuzzer.target, &mut rt, &json)
+        .unwrap_or_else(|e| exits!(exitcode::DATAERR, "Fail to load relations: {}", e));
+    info!(
+        "Relations: {} imported, {} already known, {} duplicate(s) merged",
+        imported.added, imported.known, imported.duplicates
+    );
+    if !imported.unknown.is_empty() {
+        warn!(
+            "Relations: {} unknown group(s) or call(s) skipped: {}",
+            imported.unknown.len(),
+            imported.unknown.join(", ")
+        );
+    }
+}
+
+/// Load tokens of AFL dict files `paths`, malformed lines are skipped with a warning.
+async fn load_dicts(paths: &[PathBuf]) -> Dict {
+    let mut dict = Dict::default();
+    for path in paths {
+        let text = read_to_string(path).await.unwrap_or_else(|e| {
+            exits!(
+                exitcode::IOERR,
+                "Fail to read dictionary {}: {}",
+                path.display(),
+                e
+            )
+        });
+        for e in dict.add(&path.display().to_string(), &text) {
+            warn!("Dictionary: {}, skipped", e);
         }
     }
+    info!(
+        "Dictionaries: {} token(s) from {} file(s)",
+        dict.len(),
+        paths.len()
+    );
+    dict
 }
 
 async fn load_corpus(path: &Option<PathBuf>) -> Vec<Prog> {
@@ -238,81 +1036,144 @@ async fn load_corpus(path: &Option<PathBuf>) -> Vec<Prog> {
     }
 }
 
-async fn load_target(cfg: &Config) -> Target {
-    let items = Items::load(&read(&cfg.fots_bin).await.unwrap_or_else(|e| {
+/// Load target with disabled calls removed, return it with ids of disabled and required calls.
+async fn load_target(cfg: &Config) -> (Target, HashSet<FnId>, HashSet<FnId>) {
+    let mut items = Items::load(&read(&cfg.fots_bin).await.unwrap_or_else(|e| {
         error!("Fail to load fots file: {}", e);
         exit(exitcode::DATAERR);
     }))
     .unwrap();
-    Target::from(items)
-}
 
-pub async fn prepare_env() {
-    init_logger();
-    let pid = id(); // pid
-    std::env::set_var("HEALER_FUZZER_PID", format!("{}", pid));
-    info!("Pid: {}", pid);
+    // required calls are expanded before disabling, to tell unknown ones from disabled ones.
+    let required = cfg.required_calls.as_ref().map(|patterns| {
+        let expansion = calls::expand(
+            patterns,
+            items
+                .groups
+                .iter()
+                .flat_map(|g| g.fns.iter().map(|f| &f.dec_name[..])),
+        );
+        if !expansion.unmatched.is_empty() {
+            exits!(
+                exitcode::CONFIG,
+                "Config Error: required calls match no call: {}",
+                expansion.unmatched.join(", ")
+            );
+        }
+        (patterns, expansion.matched)
+    });
+
+    let mut disabled = HashSet::new();
+    if let Some(path) = cfg.disabled_calls.as_ref() {
+        let content = read_to_string(path).await.unwrap_or_else(|e| {
+            exits!(
+                exitcode::IOERR,
+                "Fail to read disabled calls {}: {}",
+                path.display(),
+                e
+            )
+        });
+        let names = match calls::SyzCalls::parse(&content) {
+            Some(Ok(syz)) => {
+                let names = items
+                    .groups
+                    .iter()
+                    .flat_map(|g| g.fns.iter().map(|f| &f.dec_name[..]));
+                let (names, unmatched) = syz.disabled(names);
+                for p in unmatched {
+                    warn!("Disabled calls: syzkaller pattern `{}` matches no call", p);
+                }
+                names
+            }
+            Some(Err(e)) => exits!(
+                exitcode::CONFIG,
+                "Config Error: bad syzkaller config of disabled calls {}: {}",
+                path.display(),
+                e
+            ),
+            None => {
+                let patterns = calls::parse_call_list(&content);
+                calls::expand_in("Disabled calls", &patterns, &items)
+            }
+        };
+        disabled = calls::remove_calls(&mut items, &names);
+        info!("Disabled calls: {} call(s) disabled", disabled.len());
+        if items.groups.is_empty() {
+            exits!(exitcode::CONFIG, "Config Error: all calls are disabled");
+        }
+    }
+    let target = Target::from(items);
 
-    use tokio::io::ErrorKind::*;
-    if let Err(e) = create_dir_all("./crashes").await {
-        if e.kind() != AlreadyExists {
-            exits!(exitcode::IOERR, "Fail to create crash dir: {}", e);
+    let mut required_ids = HashSet::new();
+    if let Some((patterns, names)) = required {
+        for p in patterns {
+            let enabled = names
+                .iter()
+                .filter(|n| calls::match_call(p, n))
+                .filter_map(|n| target.fn_by_name(n))
+                .map(|f| f.id)
+                .collect::<Vec<_>>();
+            if enabled.is_empty() {
+                exits!(
+                    exitcode::CONFIG,
+                    "Config Error: required calls: every call `{}` matches is disabled",
+                    p
+                );
+            }
+            info!("Required calls: `{}` matches {} call(s)", p, enabled.len());
+            required_ids.extend(enabled);
         }
     }
+    (target, disabled, required_ids)
 }
 
-fn init_logger() {
-    use log::LevelFilter;
-    use log4rs::append::console::ConsoleAppender;
-    use log4rs::append::file::FileAppender;
-    use log4rs::append::rolling_file::policy::compound::{roll, trigger, CompoundPolicy};
-    use log4rs::append::rolling_file::RollingFileAppender;
-    use log4rs::config::{Appender, Config, Logger, Root};
-    use log4rs::encode::pattern::PatternEncoder;
-
-    let stdout = ConsoleAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(
-            "{d(%Y-%m-%d %H:%M:%S)} {h({l})} {t} - {m}{n}",
-        )))
-        .build();
-
-    let fuzzer_appender = FileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(
-            "{d(%Y-%m-%d %H:%M:%S)} {h({l})} - {m}{n}",
-        )))
-        .build("log/fuzzer.log")
-        .unwrap();
+/// Create `out_dir/run-<timestamp>-<os>-<arch>` for output of this run, e.g. corpus,
+/// stats, crashes and logs, and point `out_dir/latest` to it. Return the run dir.
+/// Log levels are reloaded from config file `cfg_path` on SIGHUP.
+pub async fn prepare_env(cfg: &Config, cfg_path: &Path) -> PathBuf {
+    let out_dir = cfg.out_dir();
+    let run_name = format!(
+        "run-{}-{}-{}",
+        Local::now().format("%Y%m%d-%H%M%S"),
+        cfg.guest.os,
+        cfg.guest.arch
+    );
+    let run_dir = out_dir.join(&run_name);
+    let create = tokio::try_join!(
+        create_dir_all(run_dir.join("crashes")),
+        create_dir_all(run_dir.join("raw_crashes"))
+    );
+    create.unwrap_or_else(|e| {
+        exits!(
+            exitcode::IOERR,
+            "Fail to create run dir {}: {}",
+            run_dir.display(),
+            e
+        )
+    });
 
-    let stats_trigger = trigger::size::SizeTrigger::new(1024 * 1024 * 100);
-    let stats_roll = roll::fixed_window::FixedWindowRoller::builder()
-        .build("stats.log.{}", 2)
-        .unwrap();
-    let stats_policy = CompoundPolicy::new(Box::new(stats_trigger), Box::new(stats_roll));
-    let stats_appender = RollingFileAppender::builder()
-        .append(false)
-        .encoder(Box::new(PatternEncoder::new(
-            "{d(%Y-%m-%d %H:%M:%S)} {h({l})} - {m}{n}",
-        )))
-        .build("log/stats.log", Box::new(stats_policy))
-        .unwrap();
+    let handle = logger::init(&run_dir, &cfg.log.clone().unwrap_or_default());
+    tokio::spawn(logger::reload_on_hup(
+        handle,
+        run_dir.clone(),
+        cfg_path.to_path_buf(),
+    ));
+    let pid = id(); // pid
+    std::env::set_var("HEALER_FUZZER_PID", format!("{}", pid));
+    info!("Pid: {}", pid);
+    info!("Run dir: {}", run_dir.display());
+    info!("Work dir: {}", work_dir::init(cfg, &run_name).display());
 
-    let config = Config::builder()
-        .appender(Appender::builder().build("stdout", Box::new(stdout)))
-        .appender(Appender::builder().build("fuzzer_appender", Box::new(fuzzer_appender)))
-        .appender(Appender::builder().build("stats_appender", Box::new(stats_appender)))
-        .logger(
-            Logger::builder()
-                .appender("stats_appender")
-                .build("fuzzer::stats", LevelFilter::Info),
-        )
-        .logger(
-            Logger::builder()
-                .appender("fuzzer_appender")
-                .build("fuzzer::fuzzer", LevelFilter::Info),
-        )
-        .build(Root::builder().appender("stdout").build(LevelFilter::Info))
-        .unwrap();
-    log4rs::init_config(config).unwrap();
+    let latest = out_dir.join("latest");
+    if latest.symlink_metadata().is_ok() {
+        if let Err(e) = remove_file(&latest).await {
+            warn!("Fail to remove {}: {}", latest.display(), e);
+        }
+    }
+    if let Err(e) = symlink(&run_name, &latest) {
+        warn!("Fail to link {}: {}", latest.display(), e);
+    }
+    run_dir
 }
 
 const HEALER: &str = r"
@@ -328,3 +1189,190 @@ const HEALER: &str = r"
 pub fn show_info() {
     println!("{}", HEALER);
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::fuzzer::{CurveConf, CurveKind};
+    use crate::Config;
+    use core::gen::LenCurve;
+
+    const CONFIG: &str = r#"
+fots_bin = "./sys"
+vm_num = 2
+ignores = ["KCSAN: data-race in ip6_tnl_xmit"]
+
+[guest]
+os = "linux"
+arch = "amd64"
+platform = "qemu"
+
+[qemu]
+cpu_num = 1
+mem_size = 2048
+image = "./stretch.img"
+kernel = "./bzImage"
+
+[ssh]
+key_path = "./stretch.id_rsa"
+
+[executor]
+path = "./executor"
+concurrency = true
+memleak_check = false
+script_mode = false
+
+[fuzzer]
+gen_mut_ratio = 50
+"#;
+
+    #[test]
+    fn config_resolved() {
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        let resolved: Config = toml::from_str(&cfg.resolved()).unwrap();
+        assert_eq!(resolved.vm_num, 2);
+        assert_eq!(resolved.fuzzer.unwrap().gen_mut_ratio, Some(50));
+
+        let typo = CONFIG.replace("vm_num", "vm_nums");
+        assert!(toml::from_str::<Config>(&typo).is_err());
+        let typo = CONFIG.replace("mem_size", "mem_sz");
+        assert!(toml::from_str::<Config>(&typo).is_err());
+
+        let db = CONFIG.replace("vm_num", "corpus_db = \"./corpus.db\"\nvm_num");
+        let cfg: Config = toml::from_str(&db).unwrap();
+        assert_eq!(cfg.syz_corpus.unwrap().to_str(), Some("./corpus.db"));
+
+        let hub = format!(
+            "{}\n[hub]\naddr = \"hub:7000\"\nclient = \"healer\"\nkey = \"s3cret\"\n",
+            CONFIG
+        );
+        let cfg: Config = toml::from_str(&hub).unwrap();
+        assert_eq!(cfg.hub.as_ref().unwrap().key, "s3cret");
+        let text = cfg.resolved();
+        assert!(!text.contains("s3cret"));
+        let resolved: Config = toml::from_str(&text).unwrap();
+        assert_eq!(resolved.hub.unwrap().client, "healer");
+
+        // arrays of tables are written after every scalar, e.g. ignores and trim.
+        let severity = CONFIG.replace(
+            "vm_num = 2",
+            "vm_num = 2\ntrim = false\nseverity = [{ pattern = \"WARNING in foo\", severity = \"critical\" }]",
+        );
+        let cfg: Config = toml::from_str(&severity).unwrap();
+        let resolved: Config = toml::from_str(&cfg.resolved()).unwrap();
+        assert_eq!(resolved.severity.unwrap()[0].pattern, "WARNING in foo");
+        assert_eq!(resolved.ignores.unwrap().len(), 1);
+        assert_eq!(resolved.trim, Some(false));
+
+        let log = format!(
+            "{}\n[log]\nlevel = \"warn\"\nmax_size = 10\n[log.modules]\n\"fuzzer::exec\" = \"debug\"\n",
+            CONFIG
+        );
+        let cfg: Config = toml::from_str(&log).unwrap();
+        let log = toml::from_str::<Config>(&cfg.resolved())
+            .unwrap()
+            .log
+            .unwrap();
+        assert_eq!(log.max_size, Some(10));
+        assert_eq!(log.modules.unwrap()["fuzzer::exec"], "debug");
+    }
+
+    #[test]
+    fn config_resolved_fault_injection() {
+        let fault = CONFIG.replace(
+            "script_mode = false",
+            "script_mode = false\nmax_calls = 64\ncross_check_rate = 0.1\n[executor.fault_injection]\nmax_nth = 8",
+        );
+        let cfg: Config = toml::from_str(&fault).unwrap();
+        let resolved: Config = toml::from_str(&cfg.resolved()).unwrap();
+        assert_eq!(resolved.executor.max_calls, Some(64));
+        assert_eq!(resolved.executor.cross_check_rate, Some(0.1));
+        assert!(resolved.executor.fault_injection.is_some());
+    }
+
+    #[test]
+    fn config_resolved_len_curve() {
+        let curves = [
+            (
+                "\"deep\"",
+                LenCurve::Triangular {
+                    min: 8,
+                    mode: 16,
+                    max: 32,
+                },
+            ),
+            ("{ kind = \"progressive\" }", LenCurve::Progressive),
+            (
+                "{ kind = \"geometric\", mean = 4.0 }",
+                LenCurve::Geometric { mean: 4.0 },
+            ),
+            (
+                "{ kind = \"triangular\", min = 2, mode = 8, max = 24 }",
+                LenCurve::Triangular {
+                    min: 2,
+                    mode: 8,
+                    max: 24,
+                },
+            ),
+        ];
+        for (curve, expected) in curves {
+            let text = CONFIG.replace(
+                "gen_mut_ratio = 50",
+                &format!("gen_mut_ratio = 50\nlen_curve = {}\ndict_prob = 0.5", curve),
+            );
+            let cfg: Config = toml::from_str(&text).unwrap();
+            let resolved: Config = toml::from_str(&cfg.resolved()).unwrap();
+            let fuzzer = resolved.fuzzer.unwrap();
+            assert_eq!(fuzzer.dict_prob, Some(0.5));
+            assert_eq!(fuzzer.gen_conf().len_curve, expected);
+        }
+
+        let missing = CurveConf {
+            kind: CurveKind::Triangular,
+            mean: None,
+            min: Some(2),
+            mode: None,
+            max: Some(24),
+        };
+        assert!(missing.curve().is_err());
+        let stray = CurveConf {
+            kind: CurveKind::Geometric,
+            mean: Some(4.0),
+            ..missing
+        };
+        assert!(stray.curve().is_err());
+    }
+
+    #[test]
+    fn config_target_defaults() {
+        let cfg = Config::from_toml(
+            r#"
+fots_bin = "./sys"
+vm_num = 0
+[guest]
+os = "linux"
+arch = "arm64"
+platform = "qemu"
+[qemu]
+image = "./buster.img"
+kernel = "./Image"
+mem_size = 4096
+[ssh]
+key_path = "./buster.id_rsa"
+[executor]
+path = "./executor"
+memleak_check = true
+"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.qemu.machine.as_deref(), Some("virt,virtualization=on"));
+        assert_eq!(cfg.qemu.cpu.as_deref(), Some("cortex-a57"));
+        assert_eq!(cfg.qemu.mem_size, 4096);
+        assert!(cfg.executor.concurrency && cfg.executor.memleak_check);
+        assert_eq!(cfg.executor.max_calls, Some(64));
+
+        let cfg = Config::from_toml(CONFIG).unwrap();
+        assert_eq!(cfg.qemu.accel.as_deref(), Some("kvm"));
+        let typo = CONFIG.replace("mem_size", "mem_sz");
+        assert!(Config::from_toml(&typo).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+//! Logging setup of fuzzer.
+//!
+//! Every record goes to stdout and `healer.log` of run dir, which is rotated by size.
+//! Records of fuzzing jobs are prefixed with `job-<id>:`. Crash analysis and stats are
+//! also written to `log/fuzzer.log` and `log/stats.log`. Level of root and of each
+//! module comes from `log` section of config, and is reloaded from config file on SIGHUP,
+//! so e.g. debug logging of `fuzzer::exec` can be turned on without restarting.
+use crate::Config as FuzzerConfig;
+use log::LevelFilter;
+use log4rs::append::console::ConsoleAppender;
+use log4rs::append::rolling_file::policy::compound::{roll, trigger, CompoundPolicy};
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::config::{Appender, Config, Logger, Root};
+use log4rs::encode::pattern::PatternEncoder;
+use log4rs::Handle;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::str::FromStr;
+use tokio::fs::read_to_string;
+use tokio::signal::unix::{signal, SignalKind};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConf {
+    /// Level of all modules, default info
+    pub level: Option<String>,
+    /// Size in MB healer.log is rotated at, default 100
+    pub max_size: Option<u64>,
+    /// Level of each module, e.g. `"fuzzer::exec" = "debug"`. A table, so it's kept after every
+    /// scalar for toml serialization of `Config::resolved`.
+    pub modules: Option<BTreeMap<String, String>>,
+}
+
+impl LogConf {
+    pub fn check(&self) {
+        if let Err(e) = self.levels() {
+            eprintln!("Config Error: log: {}", e);
+            exit(exitcode::CONFIG)
+        }
+        if self.max_size == Some(0) {
+            eprintln!("Config Error: log: max_size must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+    }
+
+    /// Root level and level of each module.
+    fn levels(&self) -> Result<(LevelFilter, BTreeMap<String, LevelFilter>), String> {
+        let parse =
+            |l: &str| LevelFilter::from_str(l).map_err(|_| format!("invalid log level \"{}\"", l));
+        let root = self.level.as_deref().map(parse).transpose()?;
+        let mut modules = BTreeMap::new();
+        for (module, level) in self.modules.iter().flatten() {
+            modules.insert(module.clone(), parse(level)?);
+        }
+        Ok((root.unwrap_or(LevelFilter::Info), modules))
+    }
+}
+
+/// Modules that also log to their own file.
+const MODULE_FILES: [(&str, &str); 2] = [("fuzzer::stats", "stats"), ("fuzzer::fuzzer", "fuzzer")];
+
+pub fn init(run_dir: &Path, conf: &LogConf) -> Handle {
+    log4rs::init_config(build(run_dir, conf))
+        .unwrap_or_else(|e| exits!(exitcode::SOFTWARE, "Fail to init logger: {}", e))
+}
+
+fn build(run_dir: &Path, conf: &LogConf) -> Config {
+    let (root_level, mut levels) = conf
+        .levels()
+        .unwrap_or((LevelFilter::Info, BTreeMap::new()));
+    let encoder = || {
+        Box::new(PatternEncoder::new(
+            "{d(%Y-%m-%d %H:%M:%S)} {h({l})} {t} - {m}{n}",
+        ))
+    };
+
+    let stdout = ConsoleAppender::builder().encoder(encoder()).build();
+    let max_size = conf.max_size.unwrap_or(100) * 1024 * 1024;
+    let healer_log = rolling(&run_dir.join("healer.log"), max_size, encoder());
+
+    let mut builder = Config::builder()
+        .appender(Appender::builder().build("stdout", Box::new(stdout)))
+        .appender(Appender::builder().build("healer_log", Box::new(healer_log)));
+    for (module, name) in MODULE_FILES.iter() {
+        let appender = rolling(
+            &run_dir.join(format!("log/{}.log", name)),
+            max_size,
+            Box::new(PatternEncoder::new(
+                "{d(%Y-%m-%d %H:%M:%S)} {h({l})} - {m}{n}",
+            )),
+        );
+        let appender_name = format!("{}_appender", name);
+        builder = builder
+            .appender(Appender::builder().build(&appender_name, Box::new(appender)))
+            .logger(
+                Logger::builder()
+                    .appender(appender_name)
+                    .build(*module, levels.remove(*module).unwrap_or(root_level)),
+            );
+    }
+    for (module, level) in levels {
+        builder = builder.logger(Logger::builder().build(module, level));
+    }
+    builder
+        .build(
+            Root::builder()
+                .appender("stdout")
+                .appender("healer_log")
+                .build(root_level),
+        )
+        .unwrap_or_else(|e| exits!(exitcode::SOFTWARE, "Fail to build logger config: {}", e))
+}
+
+/// Log file at `path` rotated when it's bigger than `max_size` bytes, 3 old files are kept.
+fn rolling(path: &Path, max_size: u64, encoder: Box<PatternEncoder>) -> RollingFileAppender {
+    let roller = roll::fixed_window::FixedWindowRoller::builder()
+        .build(&format!("{}.{{}}", path.display()), 3)
+        .unwrap();
+    let policy = CompoundPolicy::new(
+        Box::new(trigger::size::SizeTrigger::new(max_size)),
+        Box::new(roller),
+    );
+    RollingFileAppender::builder()
+        .encoder(encoder)
+        .build(path, Box::new(policy))
+        .unwrap_or_else(|e| exits!(exitcode::IOERR, "Fail to create {}: {}", path.display(), e))
+}
+
+/// Reload `log` section of config file `cfg_path` on SIGHUP.
+pub async fn reload_on_hup(handle: Handle, run_dir: PathBuf, cfg_path: PathBuf) {
+    let mut hup = match signal(SignalKind::hangup()) {
+        Ok(hup) => hup,
+        Err(e) => {
+            warn!(
+                "Fail to set up SIGHUP handler, log level can't be reloaded: {}",
+                e
+            );
+            return;
+        }
+    };
+    while hup.recv().await.is_some() {
+        let conf = read_to_string(&cfg_path)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|data| FuzzerConfig::from_toml(&data).map_err(|e| e.to_string()))
+            .and_then(|cfg| {
+                let conf = cfg.log.unwrap_or_default();
+                conf.levels().map(|_| conf)
+            });
+        match conf {
+            Ok(conf) => {
+                handle.set_config(build(&run_dir, &conf));
+                info!("Log levels reloaded from {}", cfg_path.display());
+            }
+            Err(e) => warn!(
+                "Fail to reload log levels from {}: {}, keep current ones",
+                cfg_path.display(),
+                e
+            ),
+        }
+    }
+}
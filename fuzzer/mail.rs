@@ -12,7 +12,8 @@ static mut MAILER: Option<Mutex<SmtpTransport>> = None;
 static mut ENVELOPE: Option<Envelope> = None;
 static ONCE: Once = Once::new();
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct MailConf {
     pub sender: String,
     pub receivers: Vec<String>,
@@ -1,35 +1,153 @@
-use fuzzer::{fuzz, prepare_env, show_info, Config};
+use fuzzer::env::apply_env;
+use fuzzer::{
+    bench, check_run, exec_progs, fuzz, prepare_env, repro_crash, show_info, BenchOpts, Config,
+    ConfigBuilder, ExecOpts,
+};
 use std::path::PathBuf;
-use std::process::exit;
 use structopt::StructOpt;
-use tokio::fs::read_to_string;
 
+/// Flags other than `config` override values of config file when given.
 #[derive(Debug, StructOpt)]
 #[structopt(name = "fuzzer", about = "Kernel fuzzer of healer.")]
 struct Settings {
     #[structopt(short = "c", long = "config", default_value = "healer-fuzzer.toml")]
     config: PathBuf,
+    /// Path to compiled fots file
+    #[structopt(long)]
+    fots_bin: Option<PathBuf>,
+    /// Path to corpus
+    #[structopt(long)]
+    corpus: Option<PathBuf>,
+    /// Number of virtual machines, 0 means sized by host cpus and memory
+    #[structopt(long)]
+    vm_num: Option<usize>,
+    /// File of calls that should never be fuzzed
+    #[structopt(long)]
+    disabled_calls: Option<PathBuf>,
+    /// Calls each generated prog must contain one of, glob patterns allowed
+    #[structopt(long)]
+    required_calls: Vec<String>,
+    /// Trim progs before inserting to corpus, true or false
+    #[structopt(long)]
+    trim: Option<bool>,
+    /// Image of qemu guest
+    #[structopt(long)]
+    image: Option<String>,
+    /// Kernel of qemu guest
+    #[structopt(long)]
+    kernel: Option<String>,
+    /// Path to executor
+    #[structopt(long)]
+    executor: Option<PathBuf>,
+    /// Boot one vm, execute a few progs and report whether fuzzing works, then exit
+    #[structopt(long)]
+    check_run: bool,
+    /// Boot one vm, execute progs of these corpus or crash case files and print results, then exit
+    #[structopt(long)]
+    exec: Vec<PathBuf>,
+    /// Executions of each prog with --exec
+    #[structopt(long, default_value = "1")]
+    repeat: usize,
+    /// Print blocks covered by each call with --exec
+    #[structopt(long)]
+    cover: bool,
+    /// Boot one vm, reproduce crash of this report with progs of --repro-progs, then exit
+    #[structopt(long)]
+    repro: Option<PathBuf>,
+    /// Corpus or crash case files, or dirs of them, executed in order before the crash
+    #[structopt(long)]
+    repro_progs: Vec<PathBuf>,
+    /// Boot all vms, execute fixed progs on each for this many seconds and report
+    /// throughput, then exit
+    #[structopt(long)]
+    bench: Option<u64>,
+    /// Seed of call sequences of progs executed with --bench
+    #[structopt(long, default_value = "1")]
+    bench_seed: u64,
 }
 
-#[tokio::main]
-async fn main() {
-    let settings = Settings::from_args();
-    let cfg_data = read_to_string(&settings.config).await.unwrap_or_else(|e| {
-        eprintln!(
-            "Config file not found: {}: {}",
-            settings.config.display(),
-            e
-        );
-        exit(exitcode::IOERR)
-    });
+impl Settings {
+    fn apply(self, mut builder: ConfigBuilder) -> ConfigBuilder {
+        if let Some(fots_bin) = self.fots_bin {
+            builder = builder.fots_bin(fots_bin);
+        }
+        if let Some(corpus) = self.corpus {
+            builder = builder.corpus(corpus);
+        }
+        if let Some(vm_num) = self.vm_num {
+            builder = builder.vm_num(vm_num);
+        }
+        if let Some(disabled_calls) = self.disabled_calls {
+            builder = builder.disabled_calls(disabled_calls);
+        }
+        if !self.required_calls.is_empty() {
+            builder = builder.required_calls(self.required_calls);
+        }
+        if let Some(trim) = self.trim {
+            builder = builder.trim(trim);
+        }
+        if let Some(image) = self.image {
+            builder = builder.image(image);
+        }
+        if let Some(kernel) = self.kernel {
+            builder = builder.kernel(kernel);
+        }
+        if let Some(executor) = self.executor {
+            builder = builder.executor(executor);
+        }
+        if self.check_run || !self.exec.is_empty() || self.repro.is_some() {
+            builder = builder.vm_num(1);
+        }
+        builder
+    }
+}
 
-    let conf: Config = toml::from_str(&cfg_data).unwrap_or_else(|e| {
-        eprintln!("Config Error:{}", e);
-        exit(exitcode::CONFIG);
+fn main() {
+    let settings = Settings::from_args();
+    let mut conf = Config::from_file(&settings.config);
+    let is_check_run = settings.check_run;
+    let exec = settings.exec.clone();
+    let opts = ExecOpts {
+        repeat: settings.repeat,
+        cover: settings.cover,
+    };
+    if opts.repeat == 0 {
+        eprintln!("Config Error: --repeat must be bigger than 0");
+        std::process::exit(exitcode::CONFIG)
+    }
+    let repro = settings.repro.clone();
+    let repro_progs = settings.repro_progs.clone();
+    if repro.is_some() && repro_progs.is_empty() {
+        eprintln!("Config Error: --repro needs progs from --repro-progs");
+        std::process::exit(exitcode::CONFIG)
+    }
+    let bench_opts = settings.bench.map(|secs| BenchOpts {
+        secs,
+        seed: settings.bench_seed,
     });
+    if bench_opts.as_ref().is_some_and(|o| o.secs == 0) {
+        eprintln!("Config Error: --bench must be bigger than 0");
+        std::process::exit(exitcode::CONFIG)
+    }
+    let cfg_path = settings.config.clone();
 
-    conf.check();
+    apply_env(&mut conf);
+    let conf = settings.apply(ConfigBuilder::from(conf)).build();
     show_info();
-    prepare_env().await;
-    fuzz(conf).await
+    conf.runtime().block_on(async move {
+        let run_dir = prepare_env(&conf, &cfg_path).await;
+        if is_check_run {
+            check_run(conf).await
+        }
+        if !exec.is_empty() {
+            exec_progs(conf, run_dir, exec, opts).await
+        }
+        if let Some(opts) = bench_opts {
+            bench(conf, run_dir, opts).await
+        }
+        if let Some(report) = repro {
+            repro_crash(conf, run_dir, report, repro_progs).await
+        }
+        fuzz(conf, run_dir).await
+    })
 }
@@ -0,0 +1,109 @@
+//! Maintainers and mailing lists to report a crash to.
+//!
+//! Source files of the top frames of symbolized report are looked up with
+//! `scripts/get_maintainer.pl` of kernel source tree. Results are cached per file, as a
+//! crash storm hits the same few files over and over. If the script is missing or can't
+//! be run, suggestion is given up for the rest of run; a file it fails on is not retried.
+use core::subsystem::crash_files;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::process::Command;
+use tokio::time::{timeout, Duration};
+
+/// Number of frames whose files are looked up.
+const TOP_FRAMES: usize = 3;
+
+pub struct Maintainers {
+    src: PathBuf,
+    limit: Duration,
+    /// Maintainers of each looked up file, None if script failed on it
+    cache: HashMap<String, Option<Vec<String>>>,
+    usable: bool,
+}
+
+impl Maintainers {
+    /// Suggest with script of kernel source tree `src`, each lookup takes at most `limit`.
+    pub fn new(src: PathBuf, limit: Duration) -> Self {
+        let usable = src.join("scripts/get_maintainer.pl").is_file();
+        if !usable {
+            warn!(
+                "Maintainers: no scripts/get_maintainer.pl in {}, maintainers won't be suggested",
+                src.display()
+            );
+        }
+        Self {
+            src,
+            limit,
+            cache: HashMap::new(),
+            usable,
+        }
+    }
+
+    /// Maintainers and lists of files of top frames of `report`, without duplicates.
+    pub async fn suggest(&mut self, report: &str) -> Vec<String> {
+        let mut ret = Vec::new();
+        for file in crash_files(report, TOP_FRAMES) {
+            if !self.usable {
+                break;
+            }
+            if !self.cache.contains_key(file) {
+                let found = self.lookup(file).await;
+                self.cache.insert(file.to_string(), found);
+            }
+            for m in self.cache[file].iter().flatten() {
+                if !ret.contains(m) {
+                    ret.push(m.clone());
+                }
+            }
+        }
+        ret
+    }
+
+    async fn lookup(&mut self, file: &str) -> Option<Vec<String>> {
+        let output = Command::new("perl")
+            .arg("scripts/get_maintainer.pl")
+            .args(["--no-git", "--no-rolestats", "-f", file])
+            .current_dir(&self.src)
+            .kill_on_drop(true)
+            .output();
+        let output = match timeout(self.limit, output).await {
+            Err(_) => {
+                warn!(
+                    "Maintainers: {}: timed out after {}s",
+                    file,
+                    self.limit.as_secs()
+                );
+                return None;
+            }
+            Ok(Err(e)) => {
+                warn!(
+                    "Maintainers: fail to run get_maintainer.pl: {}, maintainers won't be suggested",
+                    e
+                );
+                self.usable = false;
+                return None;
+            }
+            Ok(Ok(output)) => output,
+        };
+        if !output.status.success() {
+            warn!(
+                "Maintainers: {}: {}: {}",
+                file,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return None;
+        }
+        Some(parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+/// Parse output of get_maintainer.pl, one maintainer or list per line.
+fn parse(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect()
+}
@@ -0,0 +1,69 @@
+//! Executor of tests, results of progs come from a script instead of a guest.
+use crate::exec::{ExecError, ExecFuture, Executor};
+use crate::fault::Fault;
+use core::prog::Prog;
+use core::target::Target;
+use executor::ExecResult;
+
+type Script = Box<dyn FnMut(&Prog) -> Result<ExecResult, ExecError> + Send>;
+
+pub struct MockExecutor {
+    script: Script,
+    boots: usize,
+}
+
+impl MockExecutor {
+    /// Executor returning what `script` does for each executed prog.
+    pub fn new<F>(script: F) -> Self
+    where
+        F: FnMut(&Prog) -> Result<ExecResult, ExecError> + Send + 'static,
+    {
+        Self {
+            script: Box::new(script),
+            boots: 1,
+        }
+    }
+
+    /// Executor covering `blocks_of` each call of every prog.
+    pub fn covering() -> Self {
+        Self::new(|p| Ok(covered(p)))
+    }
+}
+
+/// Kernel text addresses of blocks call `fid` covers, the same wherever call is in prog.
+pub fn blocks_of(fid: usize) -> Vec<usize> {
+    (0..4)
+        .map(|i| 0xffff_ffff_8100_0000 + fid * 0x100 + i * 0x10)
+        .collect()
+}
+
+/// Result of `p` with each call covering its `blocks_of`.
+pub fn covered(p: &Prog) -> ExecResult {
+    ExecResult::Ok(p.calls.iter().map(|c| blocks_of(c.fid)).collect())
+}
+
+impl Executor for MockExecutor {
+    fn exec<'a>(
+        &'a mut self,
+        p: &'a Prog,
+        _t: &'a Target,
+    ) -> ExecFuture<'a, Result<ExecResult, ExecError>> {
+        let ret = (self.script)(p);
+        Box::pin(async move { ret })
+    }
+
+    fn restart(&mut self) -> ExecFuture<'_, ()> {
+        self.boots += 1;
+        Box::pin(async {})
+    }
+
+    fn boots(&self) -> usize {
+        self.boots
+    }
+
+    fn set_fault(&mut self, _fault: Option<Fault>) {}
+
+    fn fault_injected(&self) -> bool {
+        false
+    }
+}
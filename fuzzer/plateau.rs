@@ -0,0 +1,121 @@
+//! Escape from coverage plateau.
+//!
+//! Branch count is checked every window. Once fewer than `min_growth` new branches are
+//! found in a window, coverage has plateaued and jobs are biased toward generation, with
+//! `intensity` times more generated progs per mutated one, and mutate progs selected by
+//! novelty. Bias is relaxed once growth resumes, or after `MAX_WINDOWS` windows without
+//! recovery, then a new reseed only starts after coverage grows again.
+use crate::fuzzer::Fuzzer;
+use std::process::exit;
+use std::sync::atomic::Ordering;
+use tokio::sync::broadcast;
+use tokio::time::{delay_for, Duration};
+
+/// Windows a reseed lasts at most without recovering growth.
+const MAX_WINDOWS: usize = 3;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlateauConf {
+    /// Length of window growth is measured over in seconds, default 600
+    pub window: Option<u64>,
+    /// New branches per window below which coverage has plateaued, default 10
+    pub min_growth: Option<usize>,
+    /// Multiplier of gen_mut_ratio during reseed, default 10
+    pub intensity: Option<usize>,
+}
+
+impl PlateauConf {
+    pub fn check(&self) {
+        if self.window == Some(0) {
+            eprintln!("Config Error: plateau: window must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+        if self.min_growth == Some(0) {
+            eprintln!("Config Error: plateau: min_growth must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+        if self.intensity.is_some_and(|i| i < 2) {
+            eprintln!("Config Error: plateau: intensity must be at least 2");
+            exit(exitcode::CONFIG)
+        }
+    }
+
+    pub fn intensity(&self) -> usize {
+        self.intensity.unwrap_or(10)
+    }
+}
+
+/// Watch coverage growth of `fuzzer`, toggle its reseed bias until shutdown.
+pub async fn watch(fuzzer: Fuzzer, conf: PlateauConf, mut shutdown: broadcast::Receiver<()>) {
+    tokio::select! {
+        _ = shutdown.recv() => (),
+        _ = do_watch(&fuzzer, &conf) => (),
+    }
+}
+
+async fn do_watch(fuzzer: &Fuzzer, conf: &PlateauConf) {
+    let window = Duration::from_secs(conf.window.unwrap_or(600));
+    let min_growth = conf.min_growth.unwrap_or(10);
+    // epoch first, so a merge published in between is seen again by the next window.
+    let mut last_epoch = fuzzer.feedback.epoch();
+    let (_, mut last) = fuzzer.feedback.counts();
+    // reseed windows so far and branches when it started, None if not reseeding.
+    let mut reseed: Option<(usize, usize)> = None;
+    // a plateau that a reseed failed to escape doesn't start another one.
+    let mut armed = true;
+
+    loop {
+        delay_for(window).await;
+        let epoch = fuzzer.feedback.epoch();
+        let growth = if epoch == last_epoch {
+            // nothing merged during the window.
+            0
+        } else {
+            let (_, branches) = fuzzer.feedback.counts();
+            let growth = branches - last;
+            last = branches;
+            growth
+        };
+        last_epoch = epoch;
+        let branches = last;
+
+        match reseed.as_mut() {
+            None if growth < min_growth && armed && !fuzzer.warming_up().await => {
+                info!(
+                    "Reseed: coverage plateaued, {} new branch(es) in {}s, biasing toward generation",
+                    growth,
+                    window.as_secs()
+                );
+                fuzzer.reseeding.store(true, Ordering::Relaxed);
+                reseed = Some((0, branches));
+            }
+            None => {
+                if growth >= min_growth {
+                    armed = true;
+                }
+            }
+            Some((windows, start)) => {
+                *windows += 1;
+                if growth >= min_growth {
+                    info!(
+                        "Reseed: growth recovered after {}s, {} new branch(es) during reseed",
+                        *windows as u64 * window.as_secs(),
+                        branches - *start
+                    );
+                } else if *windows == MAX_WINDOWS {
+                    info!(
+                        "Reseed: growth not recovered after {}s, {} new branch(es) during reseed, back to normal",
+                        *windows as u64 * window.as_secs(),
+                        branches - *start
+                    );
+                    armed = false;
+                } else {
+                    continue;
+                }
+                fuzzer.reseeding.store(false, Ordering::Relaxed);
+                reseed = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! What produced a run: healer build, target, kernel and guest.
+//!
+//! Captured once the first guest is booted, as guest's `uname -a` and `/proc/version` are
+//! queried over ssh, and saved as `run_meta.json` in run dir. The part identifying the
+//! kernel and healer build is copied into every crash case, so a crash can still be tied
+//! to what produced it long after the run.
+use crate::exec::QemuExecutor;
+use crate::Config;
+use chrono::prelude::*;
+use chrono::DateTime;
+use std::path::Path;
+use tokio::fs::{read, write};
+
+/// Provenance saved with each crash.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Provenance {
+    /// Version of healer
+    pub healer_version: String,
+    /// Git hash healer was built from, empty if unknown
+    pub healer_git: String,
+    /// `os/arch` of guest
+    pub target: String,
+    /// Md5 of fots file, identifies revision of descriptions
+    pub fots_md5: String,
+    /// `uname -a` of guest
+    pub uname: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RunMeta {
+    #[serde(flatten)]
+    pub provenance: Provenance,
+    /// `/proc/version` of guest
+    pub proc_version: String,
+    pub kernel: String,
+    pub image: String,
+    /// Cargo features healer was built with
+    pub features: Vec<String>,
+    pub executor: ExecutorMeta,
+    pub start_time: DateTime<Local>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExecutorMeta {
+    pub concurrency: bool,
+    pub memleak_check: bool,
+    pub script_mode: bool,
+}
+
+impl RunMeta {
+    /// Capture provenance of run of `cfg`, querying guest of started `executor`.
+    /// Whatever fails to be captured is left empty with a warning.
+    pub async fn capture(cfg: &Config, executor: &QemuExecutor) -> Self {
+        let fots_md5 = match read(&cfg.fots_bin).await {
+            Ok(data) => format!("{:x}", md5::compute(data)),
+            Err(e) => {
+                warn!("Provenance: fail to read {}: {}", cfg.fots_bin.display(), e);
+                String::new()
+            }
+        };
+        let (uname, proc_version) = tokio::join!(
+            query(executor, "uname -a"),
+            query(executor, "cat /proc/version")
+        );
+        RunMeta {
+            provenance: Provenance {
+                healer_version: env!("CARGO_PKG_VERSION").to_string(),
+                healer_git: env!("HEALER_GIT_HASH").to_string(),
+                target: cfg.guest.target().to_string(),
+                fots_md5,
+                uname,
+            },
+            proc_version,
+            kernel: cfg.qemu.kernel.clone(),
+            image: cfg.qemu.image.clone(),
+            features: features(),
+            executor: ExecutorMeta {
+                concurrency: cfg.executor.concurrency,
+                memleak_check: cfg.executor.memleak_check,
+                script_mode: cfg.executor.script_mode,
+            },
+            start_time: Local::now(),
+        }
+    }
+
+    /// Save as `run_meta.json` in `run_dir`.
+    pub async fn persist(&self, run_dir: &Path) {
+        let path = run_dir.join("run_meta.json");
+        let meta = serde_json::to_string_pretty(self).unwrap();
+        write(&path, meta).await.unwrap_or_else(|e| {
+            exits!(
+                exitcode::IOERR,
+                "Fail to persist run meta to {} : {}",
+                path.display(),
+                e
+            )
+        });
+    }
+}
+
+async fn query(executor: &QemuExecutor, cmd: &str) -> String {
+    executor.query_guest(cmd).await.unwrap_or_else(|e| {
+        warn!("Provenance: fail to query guest: {}", e);
+        String::new()
+    })
+}
+
+fn features() -> Vec<String> {
+    let mut features = Vec::new();
+    if cfg!(feature = "mail") {
+        features.push(String::from("mail"));
+    }
+    if cfg!(feature = "otlp") {
+        features.push(String::from("otlp"));
+    }
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_meta_json() {
+        let meta = RunMeta {
+            provenance: Provenance {
+                healer_version: String::from("1.1.1"),
+                healer_git: String::from("4869b48c0ffe"),
+                target: String::from("linux/amd64"),
+                fots_md5: String::from("d41d8cd98f00b204e9800998ecf8427e"),
+                uname: String::from("Linux syzkaller 5.8.0 #1 SMP x86_64 GNU/Linux"),
+            },
+            proc_version: String::from("Linux version 5.8.0 (gcc 9.3.0) #1 SMP"),
+            kernel: String::from("./bzImage"),
+            image: String::from("./stretch.img"),
+            features: features(),
+            executor: ExecutorMeta {
+                concurrency: true,
+                memleak_check: false,
+                script_mode: false,
+            },
+            start_time: Local::now(),
+        };
+        let json = serde_json::to_value(&meta).unwrap();
+        let obj = json.as_object().unwrap();
+        for key in &[
+            "healer_version",
+            "healer_git",
+            "target",
+            "fots_md5",
+            "uname",
+            "proc_version",
+            "kernel",
+            "image",
+            "features",
+            "executor",
+            "start_time",
+        ] {
+            assert!(obj.contains_key(*key), "missing {}", key);
+        }
+
+        let text = serde_json::to_string_pretty(&meta).unwrap();
+        let back: RunMeta = serde_json::from_str(&text).unwrap();
+        assert_eq!(back.provenance, meta.provenance);
+        assert_eq!(back.proc_version, meta.proc_version);
+    }
+}
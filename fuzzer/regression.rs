@@ -0,0 +1,235 @@
+//! Regression check of crashes of a previous run against current kernel.
+//!
+//! Before fuzzing starts, progs of reproduced crashes of `run_dir`, or of every crash with
+//! `all`, are replayed in a vm booted just for this and torn down afterwards. A crash
+//! still fires if any attempt crashes with the same signature, is fixed if no attempt
+//! crashes, and crashes differently otherwise. Results are saved as `regressions.json` in
+//! run dir. Crash cases saved before progs were stored with them can't be replayed and
+//! are reported as skipped.
+use crate::exec::{Executor, QemuExecutor, RunError};
+use crate::fuzzer::Fuzzer;
+use crate::report::{signature_of, CrashedCase};
+use crate::resume::is_known;
+use crate::Config;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use tokio::fs::{read, read_dir, write};
+use tokio::time::Duration;
+
+/// Time limit of each replay.
+const PROG_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegressionConf {
+    /// Run dir whose crashes are replayed
+    pub run_dir: PathBuf,
+    /// Replay crashes that were never reproduced too, default false
+    pub all: Option<bool>,
+    /// Replays of each crash, default 3
+    pub attempts: Option<usize>,
+    /// Suppress crashes with signatures of fixed ones during fuzzing, default false
+    pub suppress_fixed: Option<bool>,
+}
+
+impl RegressionConf {
+    pub fn check(&self) {
+        if !self.run_dir.join("crashes").is_dir() {
+            eprintln!(
+                "Config Error: regression: no crashes dir in run dir {}",
+                self.run_dir.display()
+            );
+            exit(exitcode::CONFIG)
+        }
+        if self.attempts == Some(0) {
+            eprintln!("Config Error: regression: attempts must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    StillFires,
+    Fixed,
+    CrashesDifferently,
+    /// Prog not saved with crash, or contains calls unknown to current target
+    Skipped,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Regression {
+    /// File name of crash case in crashes dir of base run
+    pub case: String,
+    pub title: String,
+    pub verdict: Verdict,
+    /// Signatures of different crashes, if crashes differently
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub new_titles: Vec<String>,
+    /// Replays that crashed
+    pub crashed: usize,
+    pub attempts: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RegressionReport {
+    pub base_run: PathBuf,
+    pub kernel: String,
+    pub results: Vec<Regression>,
+}
+
+/// Verdict of crash with signature `title`, given signatures of crashes its replays hit.
+pub fn verdict(title: &str, hits: &[String]) -> Verdict {
+    if hits.iter().any(|t| t == title) {
+        Verdict::StillFires
+    } else if hits.is_empty() {
+        Verdict::Fixed
+    } else {
+        Verdict::CrashesDifferently
+    }
+}
+
+/// Replay crashes of `conf.run_dir` with `cfg`, save report to run dir of `fuzzer` and
+/// suppress fixed crashes if asked to.
+pub async fn check(conf: &RegressionConf, cfg: &Config, fuzzer: &mut Fuzzer) {
+    let cases = load_cases(&conf.run_dir, conf.all.unwrap_or(false)).await;
+    if cases.is_empty() {
+        info!(
+            "Regression: no crash of {} to replay",
+            conf.run_dir.display()
+        );
+        return;
+    }
+    info!("Regression: replaying {} crash(es) ...", cases.len());
+    let mut executor = QemuExecutor::new(cfg, 0);
+    executor.set_call_timeouts(fuzzer.call_timeouts.clone());
+    if let Err(e) = executor.start().await {
+        error!("Regression: {}, check skipped", e);
+        return;
+    }
+
+    let attempts = conf.attempts.unwrap_or(3);
+    let mut results = Vec::new();
+    for (name, case) in cases {
+        let title = signature_of(&case.crash.inner);
+        let p = match case.prog {
+            Some(p) if is_known(&p, &fuzzer.target) => p,
+            _ => {
+                results.push(Regression {
+                    case: name,
+                    title,
+                    verdict: Verdict::Skipped,
+                    new_titles: Vec::new(),
+                    crashed: 0,
+                    attempts: 0,
+                });
+                continue;
+            }
+        };
+        let mut hits = Vec::new();
+        for _ in 0..attempts {
+            executor.set_fault(case.fault);
+            if let Err(RunError::Crashed(crash)) =
+                executor.run(&p, &fuzzer.target, PROG_TIMEOUT).await
+            {
+                if crash.inner.is_empty() {
+                    hits.push(String::from("lost connection to guest"));
+                } else {
+                    hits.push(signature_of(&crash.inner));
+                }
+                if hits.last() == Some(&title) {
+                    break;
+                }
+            }
+        }
+        let verdict = verdict(&title, &hits);
+        info!("Regression: {}: {:?}", title, verdict);
+        let crashed = hits.len();
+        hits.sort_unstable();
+        hits.dedup();
+        hits.retain(|t| t != &title);
+        results.push(Regression {
+            case: name,
+            title,
+            verdict,
+            new_titles: if verdict == Verdict::CrashesDifferently {
+                hits
+            } else {
+                Vec::new()
+            },
+            crashed,
+            attempts,
+        });
+    }
+    drop(executor);
+
+    let count = |v| results.iter().filter(|r| r.verdict == v).count();
+    info!(
+        "Regression: {} still fire, {} fixed, {} crash differently, {} skipped",
+        count(Verdict::StillFires),
+        count(Verdict::Fixed),
+        count(Verdict::CrashesDifferently),
+        count(Verdict::Skipped)
+    );
+    if conf.suppress_fixed.unwrap_or(false) {
+        fuzzer.suppress_titles.extend(
+            results
+                .iter()
+                .filter(|r| r.verdict == Verdict::Fixed)
+                .map(|r| r.title.clone()),
+        );
+    }
+
+    let report = RegressionReport {
+        base_run: conf.run_dir.clone(),
+        kernel: cfg.qemu.kernel.clone(),
+        results,
+    };
+    let path = fuzzer.run_dir.join("regressions.json");
+    write(&path, serde_json::to_string_pretty(&report).unwrap())
+        .await
+        .unwrap_or_else(|e| warn!("Regression: fail to write {}: {}", path.display(), e));
+}
+
+/// Crash cases of run `dir` to replay, reproduced ones only unless `all`.
+async fn load_cases(dir: &Path, all: bool) -> Vec<(String, CrashedCase)> {
+    let path = dir.join("crashes");
+    let mut entries = match read_dir(&path).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Regression: fail to read crashes {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+    let mut cases = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let case = read(entry.path())
+            .await
+            .ok()
+            .and_then(|data| serde_json::from_slice::<CrashedCase>(&data).ok());
+        match case {
+            Some(case) if case.crash.inner.is_empty() => (),
+            Some(case) if all || case.repo => cases.push((name, case)),
+            Some(_) => (),
+            None => warn!("Regression: fail to load crash {}", entry.path().display()),
+        }
+    }
+    cases.sort_by(|a, b| a.0.cmp(&b.0));
+    cases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regression_verdicts() {
+        let title = "KASAN: use-after-free Read in tcp_v4_rcv";
+        assert_eq!(verdict(title, &[]), Verdict::Fixed);
+        let hits = [String::from("WARNING in __alloc_pages"), title.to_string()];
+        assert_eq!(verdict(title, &hits), Verdict::StillFires);
+        assert_eq!(verdict(title, &hits[..1]), Verdict::CrashesDifferently);
+    }
+}
@@ -0,0 +1,181 @@
+//! Relations between calls as JSON keyed by group and call names, to share them between
+//! machines running slightly different builds.
+//!
+//! Relation tables are indexed by position of calls in their group, which changes with
+//! descriptions. At exit every relation is written to `relations.json` of run dir, tagged
+//! with whether static analysis or fuzzing found it, and `relations` of config imports
+//! such a file at startup. Import is tolerant: relations of unknown groups or calls are
+//! skipped and reported once, duplicates are merged and fields other tools may add, e.g.
+//! a confidence, are ignored.
+use core::analyze::{static_analyze, RTable, Relation};
+use core::target::Target;
+use fots::types::GroupId;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct RelationsFile {
+    pub relations: Vec<NamedRelation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct NamedRelation {
+    pub group: String,
+    /// Call `impact` has impact on
+    pub call: String,
+    pub impact: String,
+    /// How relation was found, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// Inferred from descriptions, see `core::analyze::static_analyze`
+    Static,
+    /// Learned from progs during fuzzing
+    Learned,
+}
+
+/// Outcome of `import_json`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Imported {
+    /// Relations not in tables before
+    pub added: usize,
+    /// Relations tables already had
+    pub known: usize,
+    /// Entries repeating an earlier one
+    pub duplicates: usize,
+    /// `group:call` names not in target, sorted
+    pub unknown: Vec<String>,
+}
+
+/// Relations of `rt` as JSON.
+pub fn export_json(target: &Target, rt: &HashMap<GroupId, RTable>) -> String {
+    let statics = static_analyze(target);
+    let mut relations = Vec::new();
+    for g in target.iter_group() {
+        let r = match rt.get(&g.id) {
+            Some(r) => r,
+            None => continue,
+        };
+        let names = g.iter_fn().map(|f| &f.dec_name).collect::<Vec<_>>();
+        for ((i, j), rel) in r.indexed_iter() {
+            if *rel != Relation::Some {
+                continue;
+            }
+            let source = match statics.get(&g.id) {
+                Some(s) if s[(i, j)] == Relation::Some => Source::Static,
+                _ => Source::Learned,
+            };
+            relations.push(NamedRelation {
+                group: g.ident.clone(),
+                call: names[i].clone(),
+                impact: names[j].clone(),
+                source: Some(source),
+            });
+        }
+    }
+    serde_json::to_string_pretty(&RelationsFile { relations }).unwrap()
+}
+
+/// Add relations of JSON `json` to `rt`.
+pub fn import_json(
+    target: &Target,
+    rt: &mut HashMap<GroupId, RTable>,
+    json: &str,
+) -> Result<Imported, serde_json::Error> {
+    let file: RelationsFile = serde_json::from_str(json)?;
+    let groups = target
+        .iter_group()
+        .map(|g| (&g.ident[..], g))
+        .collect::<HashMap<_, _>>();
+    let mut imported = Imported::default();
+    let mut seen = HashSet::new();
+    let mut unknown = BTreeSet::new();
+    for rel in file.relations.iter() {
+        if !seen.insert((&rel.group, &rel.call, &rel.impact)) {
+            imported.duplicates += 1;
+            continue;
+        }
+        let g = match groups.get(&rel.group[..]) {
+            Some(g) => g,
+            None => {
+                unknown.insert(rel.group.clone());
+                continue;
+            }
+        };
+        let mut idx = |name: &str| {
+            let i = g.index_by_name(name);
+            if i.is_none() {
+                unknown.insert(format!("{}:{}", rel.group, name));
+            }
+            i
+        };
+        let (i, j) = match (idx(&rel.call), idx(&rel.impact)) {
+            (Some(i), Some(j)) => (i, j),
+            _ => continue,
+        };
+        let r = rt.entry(g.id).or_insert_with(|| RTable::new(g.fn_num()));
+        if r[(i, j)] == Relation::Some {
+            imported.known += 1;
+        } else {
+            r[(i, j)] = Relation::Some;
+            imported.added += 1;
+        }
+    }
+    imported.unknown = unknown.into_iter().collect();
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_import_by_name() {
+        let descs = "group T {\n fn a(n i32)\n fn b(n i32)\n fn c(n i32)\n}\n";
+        let t = Target::from(fots::parse_items(descs).unwrap());
+        let g = t.iter_group().next().unwrap();
+        let idx = |name| g.index_by_name(name).unwrap();
+        let mut rt = static_analyze(&t);
+        rt.get_mut(&g.id).unwrap()[(idx("b"), idx("a"))] = Relation::Some;
+        let json = export_json(&t, &rt);
+        let file: RelationsFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            file.relations,
+            vec![NamedRelation {
+                group: String::from("T"),
+                call: String::from("b"),
+                impact: String::from("a"),
+                source: Some(Source::Learned),
+            }]
+        );
+
+        // another build: c is gone, entries repeat and carry fields of other tools.
+        let json = r#"{"relations": [
+            {"group": "T", "call": "b", "impact": "a", "confidence": 0.9},
+            {"group": "T", "call": "b", "impact": "a"},
+            {"group": "T", "call": "c", "impact": "d"},
+            {"group": "U", "call": "a", "impact": "b"}
+        ]}"#;
+        let descs = "group T {\n fn a(n i32)\n fn b(n i32)\n fn d(n i32)\n}\n";
+        let t = Target::from(fots::parse_items(descs).unwrap());
+        let mut rt = static_analyze(&t);
+        let imported = import_json(&t, &mut rt, json).unwrap();
+        assert_eq!(
+            imported,
+            Imported {
+                added: 1,
+                known: 0,
+                duplicates: 1,
+                unknown: vec![String::from("T:c"), String::from("U")],
+            }
+        );
+        let g = t.iter_group().next().unwrap();
+        let (a, b) = (g.index_by_name("a").unwrap(), g.index_by_name("b").unwrap());
+        assert_eq!(rt[&g.id][(b, a)], Relation::Some);
+        assert_eq!(import_json(&t, &mut rt, json).unwrap().known, 1);
+        assert!(import_json(&t, &mut rt, "[]").is_err());
+    }
+}
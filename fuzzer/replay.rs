@@ -0,0 +1,304 @@
+//! Replay log of every executed prog, for replaying a campaign or mining it offline.
+//!
+//! Unlike corpus, which keeps only progs covering something new, every prog a job executes
+//! is logged with its outcome. Records are batched in memory and each batch is written as
+//! a frame: length of its data, then bincode of the records, raw deflate compressed. Logs
+//! are `replay/replay-<n>.log` in run dir, starting with `LOG_MAGIC`, and a log is rotated
+//! once it outgrows `rotate_mb`. A batch is lost if fuzzer dies before writing it, a frame
+//! cut off while writing ends `records` of the log with an error.
+use core::prog::Prog;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::Mutex;
+use std::time::Instant;
+
+const LOG_MAGIC: &[u8; 8] = b"HLRPLOG1";
+/// Records written at once.
+const BATCH: usize = 1024;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReplayConf {
+    /// Size of a log in MB before it's rotated, default 256
+    pub rotate_mb: Option<u64>,
+}
+
+impl ReplayConf {
+    pub fn check(&self) {
+        if self.rotate_mb == Some(0) {
+            eprintln!("Config Error: replay_log: rotate_mb must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+    }
+}
+
+/// An executed prog.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Record {
+    pub job: usize,
+    /// Milliseconds since fuzzing started
+    pub ms: u64,
+    pub prog: Prog,
+    pub outcome: ReplayOutcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum ReplayOutcome {
+    /// Executed, with blocks covered by all calls
+    Covered(usize),
+    /// Failed, with reason
+    Failed(String),
+    /// Kernel crashed, with first line of crash report
+    Crashed(String),
+    /// Guest or executor broke, with error
+    Infra(String),
+}
+
+/// Replay log shared by all jobs. Lock is never held across await point, so std mutex is
+/// used.
+pub struct ReplayLog {
+    dir: PathBuf,
+    rotate_bytes: u64,
+    start: Instant,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    batch: Vec<Record>,
+    /// Current log, its number and bytes written to it
+    log: Option<File>,
+    log_no: usize,
+    log_len: u64,
+}
+
+impl ReplayLog {
+    /// Log to `replay` dir of `run_dir`, exit if it can't be created.
+    pub fn new(conf: &ReplayConf, run_dir: &Path) -> Self {
+        let dir = run_dir.join("replay");
+        create_dir_all(&dir).unwrap_or_else(|e| {
+            exits!(
+                exitcode::IOERR,
+                "Fail to create replay log dir {}: {}",
+                dir.display(),
+                e
+            )
+        });
+        Self {
+            dir,
+            rotate_bytes: conf.rotate_mb.unwrap_or(256) * 1024 * 1024,
+            start: Instant::now(),
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Log `p` executed by `job` with `outcome`, writing the batch once it's full.
+    pub fn log(&self, job: usize, p: &Prog, outcome: ReplayOutcome) {
+        let record = Record {
+            job,
+            ms: self.start.elapsed().as_millis() as u64,
+            prog: p.clone(),
+            outcome,
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.batch.push(record);
+        if inner.batch.len() >= BATCH {
+            self.write_batch(&mut inner);
+        }
+    }
+
+    /// Write records batched so far.
+    pub fn flush(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.batch.is_empty() {
+            self.write_batch(&mut inner);
+        }
+    }
+
+    /// Failing to write drops the batch with a warning, fuzzing goes on.
+    fn write_batch(&self, inner: &mut Inner) {
+        let records = std::mem::take(&mut inner.batch);
+        if let Err(e) = self.try_write(inner, &records) {
+            warn!(
+                "Replay log: {} record(s) dropped, fail to write: {}",
+                records.len(),
+                e
+            );
+        }
+    }
+
+    fn try_write(&self, inner: &mut Inner, records: &[Record]) -> std::io::Result<()> {
+        let data = bincode::serialize(records)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&data)?;
+        let data = encoder.finish()?;
+
+        if inner.log.is_some() && inner.log_len >= self.rotate_bytes {
+            inner.log = None;
+            inner.log_no += 1;
+        }
+        if inner.log.is_none() {
+            let path = self.dir.join(format!("replay-{}.log", inner.log_no));
+            let mut log = OpenOptions::new()
+                .create(true)
+                .truncate(true)
+                .write(true)
+                .open(&path)?;
+            log.write_all(LOG_MAGIC)?;
+            inner.log = Some(log);
+            inner.log_len = LOG_MAGIC.len() as u64;
+        }
+        let log = inner.log.as_mut().unwrap();
+        log.write_all(&(data.len() as u32).to_le_bytes())?;
+        log.write_all(&data)?;
+        inner.log_len += 4 + data.len() as u64;
+        Ok(())
+    }
+}
+
+/// Logs of replay dir `dir` in order of writing.
+pub fn logs_of(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut logs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let no = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix("replay-")?.strip_suffix(".log"))
+            .and_then(|n| n.parse::<usize>().ok());
+        if let Some(no) = no {
+            logs.push((no, path));
+        }
+    }
+    logs.sort();
+    Ok(logs.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Records of log at `path`, in order of execution. Iteration ends at the first frame that
+/// can't be read, with its error.
+pub fn records(path: &Path) -> Result<Records, String> {
+    let mut log = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut magic = [0; LOG_MAGIC.len()];
+    if log.read_exact(&mut magic).is_err() || &magic != LOG_MAGIC {
+        return Err(format!("{}: not a replay log", path.display()));
+    }
+    Ok(Records {
+        log,
+        batch: Vec::new().into_iter(),
+        broken: false,
+    })
+}
+
+pub struct Records {
+    log: File,
+    batch: std::vec::IntoIter<Record>,
+    broken: bool,
+}
+
+impl Records {
+    /// Next frame, none at end of log.
+    fn next_batch(&mut self) -> Result<Option<Vec<Record>>, String> {
+        let mut len = [0; 4];
+        match self.log.read(&mut len[..1]) {
+            Ok(0) => return Ok(None),
+            Ok(_) => self
+                .log
+                .read_exact(&mut len[1..])
+                .map_err(|e| format!("broken frame: {}", e))?,
+            Err(e) => return Err(e.to_string()),
+        }
+        let mut data = vec![0; u32::from_le_bytes(len) as usize];
+        self.log
+            .read_exact(&mut data)
+            .map_err(|e| format!("broken frame: {}", e))?;
+        let mut decoded = Vec::new();
+        DeflateDecoder::new(&data[..])
+            .read_to_end(&mut decoded)
+            .map_err(|e| format!("broken frame: {}", e))?;
+        bincode::deserialize(&decoded)
+            .map(Some)
+            .map_err(|e| format!("broken frame: {}", e))
+    }
+}
+
+impl Iterator for Records {
+    type Item = Result<Record, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.batch.next() {
+                return Some(Ok(record));
+            }
+            if self.broken {
+                return None;
+            }
+            match self.next_batch() {
+                Ok(Some(batch)) => self.batch = batch.into_iter(),
+                Ok(None) => return None,
+                Err(e) => {
+                    self.broken = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::prog::Call;
+
+    #[test]
+    fn write_read_replay_log() {
+        let run_dir = std::env::temp_dir().join(format!("healer-replay-{}", std::process::id()));
+        let conf = ReplayConf { rotate_mb: None };
+        let log = ReplayLog {
+            rotate_bytes: 1,
+            ..ReplayLog::new(&conf, &run_dir)
+        };
+        let prog = |fid| {
+            let mut p = Prog::new(0);
+            p.add_call(Call::new(fid));
+            p
+        };
+        for fid in 0..BATCH + 1 {
+            log.log(fid % 2, &prog(fid), ReplayOutcome::Covered(fid));
+        }
+        log.log(
+            0,
+            &prog(0),
+            ReplayOutcome::Crashed(String::from("BUG: oops")),
+        );
+        log.flush();
+
+        let logs = logs_of(&run_dir.join("replay")).unwrap();
+        assert_eq!(logs.len(), 2);
+        let mut all = logs
+            .iter()
+            .flat_map(|l| records(l).unwrap())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(all.len(), BATCH + 2);
+        assert_eq!(all[7].prog, prog(7));
+        assert_eq!(all[7].job, 1);
+        assert_eq!(
+            all.pop().unwrap().outcome,
+            ReplayOutcome::Crashed(String::from("BUG: oops"))
+        );
+
+        // tail cut off while writing.
+        let data = std::fs::read(&logs[1]).unwrap();
+        std::fs::write(&logs[1], &data[..data.len() - 2]).unwrap();
+        let last = records(&logs[1]).unwrap().collect::<Vec<_>>();
+        assert_eq!(last.len(), 1);
+        assert!(last[0].is_err());
+        std::fs::remove_dir_all(&run_dir).unwrap();
+    }
+}
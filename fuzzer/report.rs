@@ -1,18 +1,29 @@
+use crate::fault::Fault;
 use crate::feedback::{Block, Branch};
 use crate::guest::Crash;
+use crate::hooks::CrashSummary;
 #[cfg(feature = "mail")]
 use crate::mail;
+use crate::provenance::Provenance;
+use crate::severity::{Severity, SeverityRule, SeverityTable};
+use crate::symbolize::Symbolizer;
+use crate::syz_crash;
+use crate::title;
 use chrono::prelude::*;
 use chrono::DateTime;
 use circular_queue::CircularQueue;
-use core::c::to_script;
+use core::c::{to_prog, to_script};
 use core::prog::Prog;
+use core::subsystem::classify_crash;
+use core::syz::encode;
 use core::target::Target;
 use executor::Reason;
 #[cfg(feature = "mail")]
 use lettre_email::EmailBuilder;
 use serde::Serialize;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::fs::write;
 use tokio::sync::Mutex;
@@ -23,11 +34,27 @@ pub struct TestCaseRecord {
     crash: Mutex<CircularQueue<CrashedCase>>,
 
     target: Arc<Target>,
+    run_dir: PathBuf,
+    symbolizer: Option<Symbolizer>,
     id_n: Mutex<usize>,
 
     normal_num: Mutex<usize>,
     failed_num: Mutex<usize>,
     crashed_num: Mutex<usize>,
+    deterministic_num: Mutex<usize>,
+    flaky_num: Mutex<usize>,
+    /// Hits of each crash signature, suppressed crashes included
+    crash_hits: Mutex<HashMap<String, usize>>,
+    /// Crashes of each subsystem, suppressed crashes included
+    crash_subsystems: Mutex<HashMap<&'static str, usize>>,
+    /// Crashes of each report class, suppressed crashes included
+    crash_classes: Mutex<HashMap<CrashClass, usize>>,
+    /// Copied into each crash case, set once first guest is booted
+    provenance: Mutex<Option<Provenance>>,
+    /// Hits of each crash title written in syzkaller's layout, None if it's disabled, see
+    /// `syz_crash`
+    syz_crashes: Option<Mutex<HashMap<String, usize>>>,
+    severity: SeverityTable,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -64,32 +91,286 @@ pub struct CrashedCase {
     pub meta: TestCase,
     pub p: String,
     pub repo: bool,
+    /// Re-executions of `p` that crashed again, zero attempts for suppressed crash
+    #[serde(default)]
+    pub repro: ReproScore,
     pub crash: Crash,
+    /// Kind of crash report
+    #[serde(default)]
+    pub class: CrashClass,
+    /// How urgent crash is when it's saved, see `severity`
+    #[serde(default)]
+    pub severity: Severity,
+    /// Subsystem crash is classified into, see `core::subsystem::classify_crash`
+    #[serde(default)]
+    pub subsystem: String,
+    /// Crash report symbolized by syz-symbolize
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbolized: Option<String>,
+    /// Why crash report couldn't be symbolized
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbolize_error: Option<String>,
+    /// Healer build and kernel crash is found with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// Maintainers and lists suggested for symbolized report, see `maintainers`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub maintainers: Vec<String>,
+    /// Fault injected when crash was found, see `fault`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fault: Option<Fault>,
+    /// Prog that crashed, for replaying it later, see `regression`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prog: Option<Prog>,
+}
+
+/// How many of the repro attempts of a crash reproduced it.
+#[derive(Deserialize, Serialize, Clone, Copy, Default, Debug)]
+pub struct ReproScore {
+    pub attempts: usize,
+    pub reproduced: usize,
+    /// Reproducibility measured after crash is reproduced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate: Option<ReproRate>,
+}
+
+/// Hits of extra executions of a reproduced crash prog.
+#[derive(Deserialize, Serialize, Clone, Copy, Default, Debug)]
+pub struct ReproRate {
+    pub attempts: usize,
+    pub hits: usize,
+    /// Rate is below `min_repro_rate`
+    pub unreliable: bool,
+}
+
+impl ReproRate {
+    /// Hits in percent.
+    pub fn percent(&self) -> f64 {
+        if self.attempts == 0 {
+            0.0
+        } else {
+            self.hits as f64 * 100.0 / self.attempts as f64
+        }
+    }
+}
+
+impl fmt::Display for ReproRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.0}% ({}/{}){}",
+            self.percent(),
+            self.hits,
+            self.attempts,
+            if self.unreliable { " unreliable" } else { "" }
+        )
+    }
+}
+
+impl ReproScore {
+    /// Every attempt reproduced the crash.
+    pub fn is_deterministic(&self) -> bool {
+        self.attempts != 0 && self.reproduced == self.attempts
+    }
+
+    /// Some but not all attempts reproduced the crash, likely a race.
+    pub fn is_flaky(&self) -> bool {
+        self.reproduced != 0 && self.reproduced < self.attempts
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashHits {
+    pub signature: String,
+    pub hits: usize,
+    /// Share of all crashes
+    pub rate: f64,
+}
+
+/// Kind of report a crash produced, sanitizer reports are triaged before generic ones.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Deserialize, Serialize,
+)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CrashClass {
+    Kasan,
+    Kmsan,
+    Kcsan,
+    Ubsan,
+    Bug,
+    Warning,
+    #[default]
+    Other,
+}
+
+impl fmt::Display for CrashClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CrashClass::Kasan => "KASAN",
+            CrashClass::Kmsan => "KMSAN",
+            CrashClass::Kcsan => "KCSAN",
+            CrashClass::Ubsan => "UBSAN",
+            CrashClass::Bug => "BUG",
+            CrashClass::Warning => "WARNING",
+            CrashClass::Other => "OTHER",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Class of a report title line, None if `l` is not a title.
+fn class_of_line(l: &str) -> Option<CrashClass> {
+    let class = if l.contains("BUG: KASAN:") {
+        CrashClass::Kasan
+    } else if l.contains("BUG: KMSAN:") {
+        CrashClass::Kmsan
+    } else if l.contains("BUG: KCSAN:") || l.starts_with("KCSAN:") {
+        CrashClass::Kcsan
+    } else if l.starts_with("UBSAN:") {
+        CrashClass::Ubsan
+    } else if l.starts_with("BUG:")
+        || l.starts_with("kernel BUG at")
+        || l.starts_with("general protection fault")
+        || l.starts_with("Oops:")
+    {
+        CrashClass::Bug
+    } else if l.starts_with("WARNING:") {
+        CrashClass::Warning
+    } else {
+        return None;
+    };
+    Some(class)
+}
+
+/// Title line of report and its class. Title is the first line recognized as a report
+/// title, or the first non-empty line of unrecognized reports.
+fn title_of_report(report: &str) -> (&str, CrashClass) {
+    let mut lines = report
+        .lines()
+        .map(|l| strip_log_prefix(l.trim()))
+        .filter(|l| !l.is_empty());
+    let first = lines.clone().next().unwrap_or("unknown");
+    lines
+        .find_map(|l| class_of_line(l).map(|c| (l, c)))
+        .unwrap_or((first, CrashClass::Other))
+}
+
+/// Strip `[  12.345678]` timestamp and `[  T123]` caller prefixes of console lines.
+fn strip_log_prefix(mut l: &str) -> &str {
+    while l.starts_with('[') {
+        match l.find(']') {
+            Some(i) => l = l[i + 1..].trim_start(),
+            None => break,
+        }
+    }
+    l
+}
+
+/// Class of crash report.
+pub fn classify(report: &str) -> CrashClass {
+    title_of_report(report).1
+}
+
+/// Whether console log of a guest that went down holds a kernel report, an oops, panic or
+/// sanitizer report, rather than only output of executor or of a dying vm.
+pub fn is_kernel_report(log: &str) -> bool {
+    log.lines().map(|l| strip_log_prefix(l.trim())).any(|l| {
+        class_of_line(l).is_some() || l.starts_with("Kernel panic") || l.contains("CRASH-MEMLEAK")
+    })
+}
+
+/// Report part of console `log`, from its title line on, or whole log if it has no title.
+pub fn report_of(log: &str) -> &str {
+    let mut start = 0;
+    for l in log.split_inclusive('\n') {
+        let line = strip_log_prefix(l.trim());
+        if class_of_line(line).is_some() || line.starts_with("Kernel panic") {
+            return &log[start..];
+        }
+        start += l.len();
+    }
+    log
+}
+
+/// Signature of crash report, its title normalized like syzkaller does, e.g.
+/// `KASAN: use-after-free Read in tcp_v4_rcv`, see `title`. Racing functions of KCSAN
+/// reports are sorted, since either side may be reported first.
+pub fn signature_of(report: &str) -> String {
+    let (title, class) = title_of_report(report);
+    let title = title::normalize(title, class, report);
+    let title = match (class, title.rfind(" in ")) {
+        (CrashClass::Kcsan, Some(i)) => {
+            let mut fns = title[i + 4..]
+                .split(" / ")
+                .map(str::trim)
+                .collect::<Vec<_>>();
+            fns.sort_unstable();
+            format!("{} in {}", &title[..i], fns.join(" / "))
+        }
+        _ => title.to_string(),
+    };
+    title.chars().take(120).collect()
+}
+
+/// Digest crashes are deduplicated by. Data races are inherently flaky and each report
+/// differs in values and tasks, so KCSAN reports are deduplicated by signature.
+pub fn digest_of(report: &str) -> md5::Digest {
+    if classify(report) == CrashClass::Kcsan {
+        md5::compute(signature_of(report))
+    } else {
+        md5::compute(report)
+    }
+}
+
+impl fmt::Display for ReproScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.reproduced, self.attempts)
+    }
 }
 
 #[allow(clippy::len_without_is_empty)]
 impl TestCaseRecord {
-    pub fn new(t: Arc<Target>) -> Self {
+    pub fn new(t: Arc<Target>, run_dir: PathBuf, symbolizer: Option<Symbolizer>) -> Self {
         Self {
             normal: Mutex::new(CircularQueue::with_capacity(1024 * 64)),
             failed: Mutex::new(CircularQueue::with_capacity(1024 * 64)),
             crash: Mutex::new(CircularQueue::with_capacity(1024)),
             target: t,
+            run_dir,
+            symbolizer,
 
             id_n: Mutex::new(0),
             normal_num: Mutex::new(0),
             failed_num: Mutex::new(0),
             crashed_num: Mutex::new(0),
+            deterministic_num: Mutex::new(0),
+            flaky_num: Mutex::new(0),
+            crash_hits: Mutex::new(HashMap::new()),
+            crash_subsystems: Mutex::new(HashMap::new()),
+            crash_classes: Mutex::new(HashMap::new()),
+            provenance: Mutex::new(None),
+            syz_crashes: None,
+            severity: SeverityTable::default(),
         }
     }
 
+    /// Rate severity of crashes with `rules` before default ones, see `severity`.
+    pub fn set_severity_rules(&mut self, rules: Vec<SeverityRule>) {
+        self.severity = SeverityTable::new(rules);
+    }
+
+    /// Also write crashes in syzkaller's layout, see `syz_crash`.
+    pub fn enable_syz_crashes(&mut self) {
+        self.syz_crashes = Some(Mutex::new(HashMap::new()));
+    }
+
     pub async fn insert_executed(
         &self,
         p: &Prog,
         blocks: &[Vec<Block>],
         branches: &[Vec<Branch>],
-        new_block: &HashSet<Block>,
-        new_branch: &HashSet<Branch>,
+        new_block: &[Block],
+        new_branch: &[Branch],
     ) {
         let block_num = blocks.iter().map(|blocks| blocks.len()).collect();
         let branch_num = branches.iter().map(|branches| branches.len()).collect();
@@ -119,21 +400,77 @@ impl TestCaseRecord {
         }
     }
 
-    pub async fn insert_crash(&self, p: Prog, crash: Crash, repo: bool) {
+    pub async fn set_provenance(&self, provenance: Provenance) {
+        let mut p = self.provenance.lock().await;
+        *p = Some(provenance);
+    }
+
+    pub async fn insert_crash(
+        &self,
+        p: Prog,
+        crash: Crash,
+        repro: ReproScore,
+        fault: Option<Fault>,
+    ) -> CrashSummary {
+        {
+            let mut crash_hits = self.crash_hits.lock().await;
+            *crash_hits.entry(signature_of(&crash.inner)).or_default() += 1;
+        }
+        let class = classify(&crash.inner);
+        {
+            let mut crash_classes = self.crash_classes.lock().await;
+            *crash_classes.entry(class).or_default() += 1;
+        }
+        let subsystem = classify_crash(&crash.inner);
+        {
+            let mut crash_subsystems = self.crash_subsystems.lock().await;
+            *crash_subsystems.entry(subsystem).or_default() += 1;
+        }
+        let severity = self.severity.of_crash(&signature_of(&crash.inner), &repro);
         let id = self.next_id().await;
-        let stmts = to_script(&p, &self.target);
+        let mut stmts = to_script(&p, &self.target).to_string();
+        if let Some(rate) = repro.rate.as_ref() {
+            stmts = format!("// repro rate: {}\n{}", rate, stmts);
+        }
+        if let Some(fault) = fault.as_ref() {
+            stmts = format!("// fault injection: {}\n{}", fault, stmts);
+        }
         let case = CrashedCase {
             meta: TestCase {
                 id,
                 title: self.title_of(&p, id),
                 test_time: Local::now(),
             },
-            p: stmts.to_string(),
+            p: stmts,
             crash,
-            repo,
+            subsystem: subsystem.to_string(),
+            class,
+            severity,
+            repo: repro.reproduced != 0,
+            repro,
+            symbolized: None,
+            symbolize_error: None,
+            provenance: self.provenance.lock().await.clone(),
+            maintainers: Vec::new(),
+            fault,
+            prog: Some(p),
         };
 
-        self.persist_crash_case(&case).await;
+        let path = self.persist_crash_case(&case).await;
+        self.persist_syz_crash(&case).await;
+        let summary = CrashSummary {
+            title: case.meta.title.clone(),
+            signature: signature_of(&case.crash.inner),
+            class,
+            subsystem: case.subsystem.clone(),
+            repro,
+            path: path.clone(),
+        };
+        if let Some(symbolizer) = self.symbolizer.as_ref() {
+            if !case.crash.inner.is_empty() {
+                symbolizer.queue(path);
+            }
+        }
 
         {
             let mut crashes = self.crash.lock().await;
@@ -143,6 +480,14 @@ impl TestCaseRecord {
             let mut crashed_num = self.crashed_num.lock().await;
             *crashed_num += 1;
         }
+        if repro.is_deterministic() {
+            let mut deterministic_num = self.deterministic_num.lock().await;
+            *deterministic_num += 1;
+        } else if repro.is_flaky() {
+            let mut flaky_num = self.flaky_num.lock().await;
+            *flaky_num += 1;
+        }
+        summary
     }
 
     pub async fn insert_failed(&self, p: Prog, reason: Reason) {
@@ -189,6 +534,118 @@ impl TestCaseRecord {
         )
     }
 
+    /// Top `n` crash signatures by hits, with their share of all crashes.
+    pub async fn top_crashes(&self, n: usize) -> Vec<CrashHits> {
+        let crash_hits = self.crash_hits.lock().await;
+        let total = crash_hits.values().sum::<usize>();
+        let mut top = crash_hits
+            .iter()
+            .map(|(signature, hits)| CrashHits {
+                signature: signature.clone(),
+                hits: *hits,
+                rate: *hits as f64 / total as f64,
+            })
+            .collect::<Vec<_>>();
+        top.sort_unstable_by(|a, b| b.hits.cmp(&a.hits).then(a.signature.cmp(&b.signature)));
+        top.truncate(n);
+        top
+    }
+
+    /// Number of distinct crash signatures.
+    pub async fn crash_titles(&self) -> usize {
+        self.crash_hits.lock().await.len()
+    }
+
+    /// Number of crashes of each subsystem, most crashed first.
+    pub async fn crashes_by_subsystem(&self) -> Vec<(&'static str, usize)> {
+        let crash_subsystems = self.crash_subsystems.lock().await;
+        let mut crashes = crash_subsystems
+            .iter()
+            .map(|(s, n)| (*s, *n))
+            .collect::<Vec<_>>();
+        crashes.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        crashes
+    }
+
+    /// Number of crashes of each report class, in triage order.
+    pub async fn crashes_by_class(&self) -> Vec<(CrashClass, usize)> {
+        let crash_classes = self.crash_classes.lock().await;
+        let mut crashes = crash_classes
+            .iter()
+            .map(|(c, n)| (*c, *n))
+            .collect::<Vec<_>>();
+        crashes.sort_unstable();
+        crashes
+    }
+
+    /// Number of deterministic and flaky crashes.
+    pub async fn repro_summary(&self) -> (usize, usize) {
+        tokio::join!(
+            async {
+                let deterministic_num = self.deterministic_num.lock().await;
+                *deterministic_num
+            },
+            async {
+                let flaky_num = self.flaky_num.lock().await;
+                *flaky_num
+            }
+        )
+    }
+
+    /// Hits of each crash signature, whether any recent case of it was reproduced and its
+    /// severity, most severe first, then most hit.
+    pub async fn crash_statuses(&self) -> Vec<(String, usize, bool, Severity)> {
+        let reproduced = {
+            let crashes = self.crash.lock().await;
+            crashes
+                .iter()
+                .filter(|c| c.repro.reproduced != 0)
+                .map(|c| signature_of(&c.crash.inner))
+                .collect::<HashSet<_>>()
+        };
+        let crash_hits = self.crash_hits.lock().await;
+        let mut statuses = crash_hits
+            .iter()
+            .map(|(s, hits)| {
+                let reproduced = reproduced.contains(s);
+                let severity = self.severity.classify(s, reproduced);
+                (s.clone(), *hits, reproduced, severity)
+            })
+            .collect::<Vec<_>>();
+        statuses.sort_unstable_by(|a, b| a.3.cmp(&b.3).then(b.1.cmp(&a.1)).then(a.0.cmp(&b.0)));
+        statuses
+    }
+
+    /// Progs of recent crashes that were reproduced.
+    pub async fn repro_progs(&self) -> Vec<Prog> {
+        let crashes = self.crash.lock().await;
+        crashes
+            .iter()
+            .filter(|c| c.repo)
+            .filter_map(|c| c.prog.clone())
+            .collect()
+    }
+
+    /// Repro rate of recent reproduced crashes, most reliable first.
+    pub async fn repro_rates(&self) -> Vec<(String, String, ReproRate)> {
+        let crashes = self.crash.lock().await;
+        let mut rates = crashes
+            .iter()
+            .filter_map(|c| {
+                c.repro
+                    .rate
+                    .map(|r| (c.meta.title.clone(), signature_of(&c.crash.inner), r))
+            })
+            .collect::<Vec<_>>();
+        rates.sort_by(|a, b| {
+            b.2.percent()
+                .partial_cmp(&a.2.percent())
+                .unwrap()
+                .then(a.0.cmp(&b.0))
+        });
+        rates
+    }
+
     async fn persist_normal_case(&self) {
         let cases = self.normal.lock().await;
         if cases.is_empty() {
@@ -196,14 +653,14 @@ impl TestCaseRecord {
         }
         let cases = cases.asc_iter().cloned().collect::<Vec<_>>();
 
-        let path = "./normal_case.json";
+        let path = self.run_dir.join("normal_case.json");
         let report = serde_json::to_string_pretty(&cases).unwrap();
 
         write(&path, report).await.unwrap_or_else(|e| {
             exits!(
                 exitcode::IOERR,
                 "Fail to persist normal test case to {} : {}",
-                path,
+                path.display(),
                 e
             )
         })
@@ -215,20 +672,25 @@ impl TestCaseRecord {
             return;
         }
         let cases = cases.asc_iter().cloned().collect::<Vec<_>>();
-        let path = "./failed_case.json";
+        let path = self.run_dir.join("failed_case.json");
         let report = serde_json::to_string_pretty(&cases).unwrap();
         write(&path, report).await.unwrap_or_else(|e| {
             exits!(
                 exitcode::IOERR,
                 "Fail to persist failed test case to {} : {}",
-                path,
+                path.display(),
                 e
             )
         })
     }
 
-    async fn persist_crash_case(&self, case: &CrashedCase) {
-        let path = format!("./crashes/{}", &case.meta.title);
+    /// Number of crash reports failed to be symbolized.
+    pub fn symbolize_failed(&self) -> usize {
+        self.symbolizer.as_ref().map_or(0, Symbolizer::failed)
+    }
+
+    async fn persist_crash_case(&self, case: &CrashedCase) -> PathBuf {
+        let path = self.run_dir.join("crashes").join(&case.meta.title);
         let crash = serde_json::to_string_pretty(case).unwrap();
 
         #[cfg(feature = "mail")]
@@ -242,17 +704,42 @@ impl TestCaseRecord {
         write(&path, crash).await.unwrap_or_else(|e| {
             exits!(
                 exitcode::IOERR,
-                "Fail to persist failed test case to {} : {}",
-                path,
+                "Fail to persist crashed test case to {} : {}",
+                path.display(),
                 e
             )
-        })
+        });
+        path
+    }
+
+    async fn persist_syz_crash(&self, case: &CrashedCase) {
+        let hits = match self.syz_crashes.as_ref() {
+            Some(hits) => hits,
+            None => return,
+        };
+        let title = signature_of(&case.crash.inner);
+        // held while writing, so hits of a title are numbered in order.
+        let mut hits = hits.lock().await;
+        let n = hits.entry(title.clone()).or_default();
+        let repro = case
+            .prog
+            .as_ref()
+            .filter(|_| case.repo)
+            .map(|p| (encode(p, &self.target), to_prog(p, &self.target)));
+        let crashes = self.run_dir.join("syz").join("crashes");
+        match syz_crash::save(&crashes, &title, *n, &case.crash.inner, repro).await {
+            Ok(_) => *n += 1,
+            Err(e) => warn!("Fail to write crash {} in syzkaller layout: {}", title, e),
+        }
     }
 
+    /// Title of case of `p`, also file name of crash case. Group and last call keep it
+    /// readable, content hash of `p` keeps cases of different progs apart even if ids
+    /// clash, e.g. records of several runs sharing a dir.
     fn title_of(&self, p: &Prog, id: usize) -> String {
         let group = String::from(self.target.group_name_of(p.gid));
         let f = String::from(&self.target.fn_of(p.calls.last().unwrap().fid).dec_name);
-        format!("{}_{}_{}", group, f, id)
+        format!("{}_{}_{}_{}", group, f, id, short_hash_of(p))
     }
 
     async fn next_id(&self) -> usize {
@@ -262,3 +749,168 @@ impl TestCaseRecord {
         next
     }
 }
+
+/// First 8 hex digits of md5 of `p`.
+fn short_hash_of(p: &Prog) -> String {
+    let digest = md5::compute(bincode::serialize(p).unwrap());
+    format!("{:x}", digest)[..8].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::bench::target;
+
+    const KASAN: &str =
+        "[   35.123456] ==================================================================
+[   35.123789] BUG: KASAN: use-after-free in tcp_v4_rcv+0x2f1/0x3a0
+[   35.124012] Read of size 8 at addr ffff888012345678 by task syz-executor/1234
+";
+    const KMSAN: &str = "BUG: KMSAN: uninit-value in __bpf_prog_run+0x1b2/0x2e0
+ __bpf_prog_run+0x1b2/0x2e0
+";
+    const KCSAN: &str = "==================================================================
+BUG: KCSAN: data-race in ext4_mark_iloc_dirty / ext4_do_update_inode
+
+write to 0xffff88800c0e3a10 of 4 bytes by task 3242 on cpu 1:
+";
+    const KCSAN_SWAPPED: &str =
+        "BUG: KCSAN: data-race in ext4_do_update_inode / ext4_mark_iloc_dirty
+
+read to 0xffff88800c0e3a10 of 4 bytes by task 3199 on cpu 0:
+";
+    const UBSAN: &str =
+        "================================================================================
+UBSAN: shift-out-of-bounds in net/sched/sch_api.c:1234:5
+shift exponent 32 is too large for 32-bit type 'int'
+";
+    const BUG: &str = "kernel BUG at fs/ext4/inode.c:2630!
+invalid opcode: 0000 [#1] SMP KASAN
+";
+    const WARNING: &str = "------------[ cut here ]------------
+WARNING: CPU: 0 PID: 3110 at mm/page_alloc.c:4900 __alloc_pages+0x3a1/0x4b0
+";
+
+    #[test]
+    fn classify_reports() {
+        assert_eq!(classify(KASAN), CrashClass::Kasan);
+        assert_eq!(classify(KMSAN), CrashClass::Kmsan);
+        assert_eq!(classify(KCSAN), CrashClass::Kcsan);
+        assert_eq!(classify(UBSAN), CrashClass::Ubsan);
+        assert_eq!(classify(BUG), CrashClass::Bug);
+        assert_eq!(classify(WARNING), CrashClass::Warning);
+        assert_eq!(classify("something odd\n"), CrashClass::Other);
+
+        assert_eq!(
+            signature_of(KASAN),
+            "KASAN: use-after-free Read in tcp_v4_rcv"
+        );
+        assert_eq!(signature_of(WARNING), "WARNING in __alloc_pages");
+        assert_eq!(signature_of("something odd\n"), "something odd");
+    }
+
+    #[test]
+    fn kernel_reports() {
+        for report in &[KASAN, KCSAN, UBSAN, BUG, WARNING] {
+            assert!(is_kernel_report(report), "report:\n{}", report);
+        }
+        assert!(is_kernel_report(
+            "[   12.000001] Kernel panic - not syncing: Fatal exception"
+        ));
+        assert!(!is_kernel_report(
+            "syz-executor[312]: segfault at 0 ip 00000000004012a4 sp 00007ffd error 4"
+        ));
+        assert!(!is_kernel_report(""));
+    }
+
+    #[test]
+    fn dedup_data_races() {
+        assert_eq!(signature_of(KCSAN), signature_of(KCSAN_SWAPPED));
+        assert_eq!(digest_of(KCSAN), digest_of(KCSAN_SWAPPED));
+        assert_ne!(digest_of(KASAN), digest_of(KMSAN));
+    }
+
+    #[test]
+    fn crash_files_of_distinct_progs() {
+        use core::prog::Call;
+        use std::env::temp_dir;
+        use std::process::id;
+
+        let t = Arc::new(target().0);
+        let sync = t.fn_by_name("sync").unwrap();
+        let read = t.fn_by_name("read").unwrap();
+        let mut p1 = Prog::new(sync.gid);
+        p1.add_call(Call::new(sync.id));
+        let mut p2 = Prog::new(sync.gid);
+        p2.add_call(Call::new(read.id));
+        p2.add_call(Call::new(sync.id));
+
+        let run_dir = temp_dir().join(format!("healer-report-{}", id()));
+        std::fs::create_dir_all(run_dir.join("crashes")).unwrap();
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            // same group, last call and id, as records of two runs sharing a dir have.
+            for p in [p1, p2] {
+                let record = TestCaseRecord::new(t.clone(), run_dir.clone(), None);
+                let crash = Crash {
+                    inner: KASAN.to_string(),
+                };
+                record
+                    .insert_crash(p, crash, ReproScore::default(), None)
+                    .await;
+            }
+        });
+        let files = std::fs::read_dir(run_dir.join("crashes")).unwrap().count();
+        std::fs::remove_dir_all(&run_dir).unwrap();
+        assert_eq!(files, 2);
+    }
+
+    #[test]
+    fn crashes_in_syz_layout() {
+        use core::prog::Call;
+        use std::env::temp_dir;
+        use std::fs::read_to_string;
+        use std::process::id;
+
+        let t = Arc::new(target().0);
+        let sync = t.fn_by_name("sync").unwrap();
+        let mut p = Prog::new(sync.gid);
+        p.add_call(Call::new(sync.id));
+
+        let run_dir = temp_dir().join(format!("healer-syz-crashes-{}", id()));
+        std::fs::create_dir_all(run_dir.join("crashes")).unwrap();
+        let mut record = TestCaseRecord::new(t, run_dir.clone(), None);
+        record.enable_syz_crashes();
+        let log = format!("[   34.000001] syz-executor: started\n{}", KASAN);
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            for reproduced in 0..2 {
+                let repro = ReproScore {
+                    attempts: 1,
+                    reproduced,
+                    rate: None,
+                };
+                let crash = Crash { inner: log.clone() };
+                record.insert_crash(p.clone(), crash, repro, None).await;
+            }
+        });
+        let title = "KASAN: use-after-free Read in tcp_v4_rcv";
+        let dir = syz_crash::dir_of(&run_dir.join("syz").join("crashes"), title);
+        let read = |f: &str| read_to_string(dir.join(f)).unwrap();
+        let files = (
+            read("description"),
+            read("log1"),
+            read("report0"),
+            read("repro.prog"),
+        );
+        let cprog = dir.join("repro.cprog").exists();
+        std::fs::remove_dir_all(&run_dir).unwrap();
+
+        assert!(dir.ends_with("b3638c2b760ae686a3f526a28da3925602b4bc93"));
+        assert_eq!(files.0, format!("{}\n", title));
+        assert_eq!(files.1, log);
+        assert_eq!(files.2, &KASAN[KASAN.find("[   35.123789]").unwrap()..]);
+        assert_eq!(files.3, "sync()\n");
+        assert!(cprog);
+    }
+}
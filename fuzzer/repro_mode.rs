@@ -0,0 +1,220 @@
+//! Repro mode: reproduce a crash seen in a console log after a run ended, from progs
+//! executed before it, e.g. a dumped corpus or crash cases. Suffixes of the history are
+//! executed first to find the prog crashing with the expected title, then single progs to
+//! find one crashing alone, which is minimized. The found prog goes through the same
+//! repro as crashes found by fuzzing and is saved to run dir.
+use crate::exec::{ExecError, Executor, QemuExecutor};
+use crate::exec_mode::load_progs;
+use crate::fuzzer::{Fuzzer, FuzzerDeps};
+use crate::guest::Crash;
+use crate::report::signature_of;
+use crate::work_dir;
+use crate::Config;
+use core::c::to_prog;
+use core::minimize::remove;
+use core::prog::Prog;
+use core::target::Target;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::Arc;
+
+/// Reproduce crash of `report` with progs of `files`, then exit, see module doc.
+pub async fn repro_mode(
+    cfg: &Config,
+    t: Arc<Target>,
+    run_dir: PathBuf,
+    report: &Path,
+    files: &[PathBuf],
+) -> ! {
+    let report = std::fs::read_to_string(report)
+        .unwrap_or_else(|e| exits!(exitcode::DATAERR, "{}: {}", report.display(), e));
+    let title = signature_of(&report);
+    let mut history = Vec::new();
+    for path in files.iter().flat_map(|f| prog_files(f)) {
+        let progs = load_progs(&path, &t)
+            .unwrap_or_else(|e| exits!(exitcode::DATAERR, "{}: {}", path.display(), e));
+        history.extend(progs);
+    }
+    if history.is_empty() {
+        exits!(exitcode::DATAERR, "No prog to reproduce with");
+    }
+    info!(
+        "repro: reproducing '{}' with {} prog(s)",
+        title,
+        history.len()
+    );
+
+    let mut executor = QemuExecutor::new(cfg, 0);
+    if let Err(e) = executor.start().await {
+        exits!(exitcode::UNAVAILABLE, "{}", e)
+    }
+    let (p, crash) = match reproduce(&mut executor, &t, &title, &history).await {
+        Some(found) => found,
+        None => {
+            println!("'{}' not reproduced", title);
+            work_dir::cleanup();
+            exit(exitcode::SOFTWARE)
+        }
+    };
+    println!("'{}' reproduced by:\n{}", title, to_prog(&p, &t));
+    let fuzzer = Fuzzer::new(FuzzerDeps::new(t, Vec::new(), cfg, run_dir.clone()));
+    if !fuzzer.repro_found(p, crash, &mut executor).await {
+        println!("repro rate is below min_repro_rate, repro is unreliable");
+    }
+    println!("crash saved to {}", run_dir.join("crashes").display());
+    work_dir::cleanup();
+    exit(exitcode::OK)
+}
+
+/// `path` itself, or files of it sorted by name if it's a dir.
+fn prog_files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    let mut files = std::fs::read_dir(path)
+        .unwrap_or_else(|e| exits!(exitcode::DATAERR, "{}: {}", path.display(), e))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+/// Prog of `history` crashing with `title`, minimized, and its crash. If no single prog
+/// crashes alone, the one crashing after progs before it is returned unminimized.
+pub(crate) async fn reproduce(
+    executor: &mut dyn Executor,
+    t: &Target,
+    title: &str,
+    history: &[Prog],
+) -> Option<(Prog, Crash)> {
+    // suffixes doubling in length, progs executed right before crash are most suspected.
+    let mut n = 1;
+    let (suspect, crash) = loop {
+        let start = history.len() - n;
+        if let Some((i, crash)) = crash_of(executor, t, title, &history[start..]).await {
+            info!("repro: reproduced by last {} prog(s)", n);
+            break (start + i, crash);
+        }
+        if n == history.len() {
+            return None;
+        }
+        n = (n * 2).min(history.len());
+    };
+
+    let singles = Some(suspect).into_iter().chain(
+        (history.len() - n..history.len())
+            .rev()
+            .filter(|i| *i != suspect),
+    );
+    let mut found = None;
+    for i in singles {
+        let p = &history[i];
+        if let Some((_, crash)) = crash_of(executor, t, title, std::slice::from_ref(p)).await {
+            found = Some((p.clone(), crash));
+            break;
+        }
+    }
+    let (mut p, mut crash) = match found {
+        Some(found) => found,
+        None => {
+            warn!("repro: no prog crashes alone, prog crashed after others is taken");
+            return Some((history[suspect].clone(), crash));
+        }
+    };
+
+    let mut i = 0;
+    while i + 1 < p.len() {
+        let mut q = p.clone();
+        if !remove(&mut q, i) {
+            i += 1;
+            continue;
+        }
+        match crash_of(executor, t, title, std::slice::from_ref(&q)).await {
+            Some((_, c)) => {
+                p = q;
+                crash = c;
+            }
+            None => i += 1,
+        }
+    }
+    Some((p, crash))
+}
+
+/// Execute `progs` in order, return index of the one crashing with `title` and its crash.
+/// Guest is restarted after any crash, which ends execution.
+async fn crash_of(
+    executor: &mut dyn Executor,
+    t: &Target,
+    title: &str,
+    progs: &[Prog],
+) -> Option<(usize, Crash)> {
+    for (i, p) in progs.iter().enumerate() {
+        match executor.exec(p, t).await {
+            Ok(_) => (),
+            Err(ExecError::KernelCrash(crash)) => {
+                executor.restart().await;
+                let got = signature_of(&crash.inner);
+                if got == title {
+                    return Some((i, crash));
+                }
+                info!("repro: crashed with another title '{}'", got);
+                return None;
+            }
+            Err(e) => {
+                if e.is_crash() {
+                    executor.restart().await;
+                    return None;
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{covered, MockExecutor};
+    use core::bench::target;
+    use core::gen::gen;
+
+    #[test]
+    fn reproduce_from_history() {
+        const KASAN: &str = "BUG: KASAN: use-after-free in tcp_v4_rcv+0x2f1/0x3a0
+Read of size 8 at addr ffff888012345678 by task executor/1234
+";
+        let (t, rt) = target();
+        let culprit = gen(&t, &rt, &Default::default());
+        // last call of culprit, called by no other prog, crashes.
+        let fid = culprit.calls.last().unwrap().fid;
+        let mut history = std::iter::repeat_with(|| gen(&t, &rt, &Default::default()))
+            .filter(|p| p.calls.iter().all(|c| c.fid != fid))
+            .take(7)
+            .collect::<Vec<_>>();
+        history.insert(2, culprit.clone());
+        let mut executor = MockExecutor::new(move |p| {
+            if p.calls.iter().any(|c| c.fid == fid) {
+                Err(ExecError::KernelCrash(Crash {
+                    inner: String::from(KASAN),
+                }))
+            } else {
+                Ok(covered(p))
+            }
+        });
+        let title = signature_of(KASAN);
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (p, crash) = reproduce(&mut executor, &t, &title, &history)
+                .await
+                .unwrap();
+            assert_eq!(p.calls.last().unwrap().fid, fid);
+            assert!(p.len() <= culprit.len());
+            assert_eq!(t.validate(&p), Ok(()));
+            assert_eq!(crash.inner, KASAN);
+            assert!(reproduce(&mut executor, &t, "WARNING in foo", &history)
+                .await
+                .is_none());
+        });
+    }
+}
@@ -0,0 +1,148 @@
+//! Continue a previous run from its run dir.
+//!
+//! Corpus of previous run is loaded as candidates, so every prog is executed and
+//! triaged against current kernel again. Relations and counters are restored from its
+//! fuzzer state, and its crashes become known ones, so they are not reported again.
+//! Crashes it left in its repro backlog are queued to be reproduced first.
+//! Any part that fails to load is skipped with a warning instead of aborting the run.
+use crate::backlog::RawCrash;
+use crate::fuzzer::Fuzzer;
+use crate::report::{digest_of, CrashedCase, ReproScore};
+use crate::state::FuzzerState;
+use core::prog::Prog;
+use core::target::Target;
+use std::path::Path;
+use tokio::fs::{read, read_dir};
+
+/// Corpus of run `dir`, progs containing calls unknown to `t` are dropped.
+pub async fn corpus_of(dir: &Path, t: &Target) -> Vec<Prog> {
+    let path = dir.join("corpus");
+    let corpus: Vec<Prog> = match read(&path)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|data| bincode::deserialize(&data).map_err(|e| e.to_string()))
+    {
+        Ok(corpus) => corpus,
+        Err(e) => {
+            warn!("Resume: fail to load corpus {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+    let n = corpus.len();
+    let corpus = corpus
+        .into_iter()
+        .filter(|p| is_known(p, t))
+        .collect::<Vec<_>>();
+    if n != corpus.len() {
+        warn!(
+            "Resume: {} prog(s) contain unknown calls, dropped",
+            n - corpus.len()
+        );
+    }
+    info!("Resume: imported {} prog(s)", corpus.len());
+    corpus
+}
+
+/// Restore fuzzer state of run `dir` to `fuzzer`.
+pub async fn restore_state(dir: &Path, fuzzer: &Fuzzer) {
+    let path = dir.join("fuzzer_state.json");
+    let state: FuzzerState = match read(&path)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|data| serde_json::from_slice(&data).map_err(|e| e.to_string()))
+    {
+        Ok(state) => state,
+        Err(e) => {
+            warn!(
+                "Resume: fail to load fuzzer state {}: {}",
+                path.display(),
+                e
+            );
+            return;
+        }
+    };
+    let relations = state.restore(fuzzer).await;
+    info!(
+        "Resume: imported {} relation(s), exec {}",
+        relations, state.exec
+    );
+}
+
+/// Mark crashes of run `dir` as known to `fuzzer`.
+pub async fn import_crashes(dir: &Path, fuzzer: &Fuzzer) {
+    let path = dir.join("crashes");
+    let mut entries = match read_dir(&path).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Resume: fail to read crashes {}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    let mut titles = 0;
+    let mut digests = fuzzer.crash_digests.lock().await;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let case = read(entry.path())
+            .await
+            .ok()
+            .and_then(|data| serde_json::from_slice::<CrashedCase>(&data).ok());
+        match case {
+            Some(case) if !case.crash.inner.is_empty() => {
+                if digests.insert(digest_of(&case.crash.inner)) {
+                    titles += 1;
+                }
+            }
+            _ => warn!("Resume: fail to load crash {}", entry.path().display()),
+        }
+    }
+    info!("Resume: imported {} known crash(es)", titles);
+}
+
+/// Queue crashes run `dir` left unreproduced to backlog of `fuzzer`, oldest first.
+pub async fn import_raw_crashes(dir: &Path, fuzzer: &Fuzzer) {
+    let path = dir.join("raw_crashes");
+    let mut entries = match read_dir(&path).await {
+        Ok(entries) => entries,
+        // runs before crash backlog have no raw crashes.
+        Err(_) => return,
+    };
+
+    let mut crashes = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let crash = read(entry.path())
+            .await
+            .ok()
+            .and_then(|data| serde_json::from_slice::<RawCrash>(&data).ok());
+        match crash {
+            Some(crash) if is_known(&crash.p, &fuzzer.target) => crashes.push(crash),
+            Some(_) => warn!(
+                "Resume: raw crash {} contains unknown calls, dropped",
+                entry.path().display()
+            ),
+            None => warn!("Resume: fail to load raw crash {}", entry.path().display()),
+        }
+    }
+    crashes.sort_by_key(|c| c.found_time);
+    let n = crashes.len();
+    for crash in crashes {
+        if let Some(dropped) = fuzzer.backlog.push(crash).await {
+            fuzzer
+                .record
+                .insert_crash(
+                    dropped.p,
+                    dropped.crash,
+                    ReproScore::default(),
+                    dropped.fault,
+                )
+                .await;
+        }
+    }
+    if n != 0 {
+        info!("Resume: queued {} unreproduced crash(es)", n);
+    }
+}
+
+/// `p` only contains calls of `t` and is valid for it, see `Target::validate`.
+pub(crate) fn is_known(p: &Prog, t: &Target) -> bool {
+    t.validate(p).is_ok()
+}
@@ -0,0 +1,220 @@
+//! Control of a running fuzzer by an external program.
+//!
+//! With `rpc` set, requests are served on unix socket `control.sock` of run dir, also
+//! reachable through `latest` of out dir. Each request is a json object on its own line,
+//! e.g. `{"cmd": "add-suppression", "arg": "WARNING in foo"}`, answered by a json line
+//! `{"ok": true, "result": ...}` or `{"ok": false, "error": "..."}`:
+//!
+//! - `status`: stats snapshot, as logged by sampler
+//! - `stop`: stop fuzzer gracefully, as SIGTERM does
+//! - `pause`, `resume`: jobs stop picking new progs, and resume
+//! - `add-suppression`: crashes matching regex `arg` are suppressed from now on
+//! - `dump-corpus`: write corpus to file `arg` of host, in the format of `corpus`
+//! - `list-crashes`: crash signatures with hits and whether they were reproduced
+//!
+//! Requests are served on a thread of their own, so a busy runtime doesn't delay them, and
+//! touch jobs only through state they share, e.g. flags checked before each cycle. A
+//! connection is dropped on a line longer than `MAX_LINE` or idle for `IDLE_SECS`, a
+//! malformed request only gets an error.
+use crate::fuzzer::Fuzzer;
+use crate::summary::CrashStatus;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use tokio::fs::{remove_file, write};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+use tokio::time::{delay_for, timeout, Duration};
+
+/// Socket of run dir requests are served on.
+pub const SOCKET: &str = "control.sock";
+/// Bytes of a request line at most.
+const MAX_LINE: u64 = 64 * 1024;
+/// Seconds a connection may wait before sending next request.
+const IDLE_SECS: u64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "cmd", content = "arg", rename_all = "kebab-case")]
+pub enum Request {
+    Status,
+    Stop,
+    Pause,
+    Resume,
+    AddSuppression(String),
+    DumpCorpus(PathBuf),
+    ListCrashes,
+}
+
+pub fn parse(line: &str) -> Result<Request, String> {
+    serde_json::from_str(line).map_err(|e| format!("bad request: {}", e))
+}
+
+/// Serve requests on a thread of its own until shutdown.
+pub fn spawn(fuzzer: Fuzzer, shutdown: broadcast::Receiver<()>) {
+    let ret = std::thread::Builder::new()
+        .name("healer-rpc".into())
+        .spawn(move || {
+            let rt = tokio::runtime::Builder::new()
+                .basic_scheduler()
+                .enable_all()
+                .build();
+            match rt {
+                Ok(mut rt) => rt.block_on(serve(fuzzer, shutdown)),
+                Err(e) => warn!("Rpc: fail to build runtime: {}", e),
+            }
+        });
+    if let Err(e) = ret {
+        warn!("Rpc: fail to spawn thread: {}", e);
+    }
+}
+
+async fn serve(fuzzer: Fuzzer, mut shutdown: broadcast::Receiver<()>) {
+    let path = fuzzer.run_dir.join(SOCKET);
+    let _ = remove_file(&path).await;
+    let mut listener = match UnixListener::bind(&path) {
+        Ok(l) => l,
+        Err(e) => {
+            warn!("Rpc: fail to bind {}: {}", path.display(), e);
+            return;
+        }
+    };
+    info!("Rpc: serving on {}", path.display());
+    let accept = async {
+        loop {
+            match listener.accept().await {
+                Ok((conn, _)) => {
+                    tokio::spawn(serve_conn(fuzzer.clone(), conn));
+                }
+                Err(e) => {
+                    warn!("Rpc: fail to accept: {}", e);
+                    delay_for(Duration::from_millis(200)).await;
+                }
+            }
+        }
+    };
+    tokio::select! {
+        _ = shutdown.recv() => (),
+        _ = accept => (),
+    }
+    let _ = remove_file(&path).await;
+}
+
+async fn serve_conn(fuzzer: Fuzzer, conn: UnixStream) {
+    let (rx, mut tx) = tokio::io::split(conn);
+    let mut rx = BufReader::new(rx.take(u64::MAX));
+    loop {
+        rx.get_mut().set_limit(MAX_LINE);
+        let mut line = String::new();
+        let n = match timeout(Duration::from_secs(IDLE_SECS), rx.read_line(&mut line)).await {
+            Ok(Ok(n)) => n,
+            _ => return,
+        };
+        if n == 0 {
+            return;
+        }
+        if !line.ends_with('\n') {
+            let resp = json!({"ok": false, "error": "request line too long"});
+            let _ = tx.write_all(format!("{}\n", resp).as_bytes()).await;
+            return;
+        }
+        let resp = match parse(line.trim()) {
+            Ok(req) => match handle(&fuzzer, req).await {
+                Ok(result) => json!({"ok": true, "result": result}),
+                Err(e) => json!({"ok": false, "error": e}),
+            },
+            Err(e) => json!({"ok": false, "error": e}),
+        };
+        if tx
+            .write_all(format!("{}\n", resp).as_bytes())
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+async fn handle(fuzzer: &Fuzzer, req: Request) -> Result<Value, String> {
+    info!("Rpc: {:?}", req);
+    match req {
+        Request::Status => {
+            let stats = fuzzer.stats().snapshot().await;
+            serde_json::to_value(stats).map_err(|e| e.to_string())
+        }
+        Request::Stop => {
+            fuzzer.stop_requested.store(true, Ordering::Relaxed);
+            Ok(Value::Null)
+        }
+        Request::Pause | Request::Resume => {
+            let pause = req == Request::Pause;
+            fuzzer.paused.store(pause, Ordering::Relaxed);
+            Ok(Value::Null)
+        }
+        Request::AddSuppression(s) => {
+            let re = Regex::new(&s).map_err(|e| format!("bad regex {}: {}", s, e))?;
+            fuzzer.suppressions.write().unwrap().push(re);
+            Ok(Value::Null)
+        }
+        Request::DumpCorpus(path) => dump_corpus(fuzzer, &path).await.map(Value::from),
+        Request::ListCrashes => {
+            let crashes = fuzzer
+                .record
+                .crash_statuses()
+                .await
+                .into_iter()
+                .map(|(title, hits, reproduced, severity)| CrashStatus {
+                    title,
+                    hits,
+                    reproduced: Some(reproduced),
+                    severity: Some(severity),
+                })
+                .collect::<Vec<_>>();
+            serde_json::to_value(crashes).map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Write corpus of `fuzzer` to `path`, return number of progs.
+async fn dump_corpus(fuzzer: &Fuzzer, path: &Path) -> Result<usize, String> {
+    let n = fuzzer.corpus.len().await;
+    let corpus = fuzzer.corpus.dump().await.map_err(|e| e.to_string())?;
+    write(path, corpus)
+        .await
+        .map_err(|e| format!("fail to write {}: {}", path.display(), e))?;
+    Ok(n)
+}
+
+/// Wait until stop is requested.
+pub async fn wait_stop(fuzzer: &Fuzzer) {
+    while !fuzzer.stop_requested.load(Ordering::Relaxed) {
+        delay_for(Duration::from_millis(200)).await;
+    }
+    warn!("Stop requested through rpc");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_requests() {
+        assert_eq!(parse(r#"{"cmd": "status"}"#), Ok(Request::Status));
+        assert_eq!(
+            parse(r#"{"cmd": "list-crashes"}"#),
+            Ok(Request::ListCrashes)
+        );
+        assert_eq!(
+            parse(r#"{"cmd": "add-suppression", "arg": "WARNING in foo"}"#),
+            Ok(Request::AddSuppression("WARNING in foo".to_string()))
+        );
+        assert_eq!(
+            parse(r#"{"cmd": "dump-corpus", "arg": "/tmp/corpus"}"#),
+            Ok(Request::DumpCorpus(PathBuf::from("/tmp/corpus")))
+        );
+        assert!(parse(r#"{"cmd": "reboot"}"#).is_err());
+        assert!(parse(r#"{"cmd": "add-suppression"}"#).is_err());
+        assert!(parse("status").is_err());
+    }
+}
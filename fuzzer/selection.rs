@@ -0,0 +1,146 @@
+//! Selection of corpus prog to mutate.
+//!
+//! Besides uniform random selection, novelty mode picks, among a few random samples of
+//! corpus, the prog most distinct from recently executed ones, which helps escaping from
+//! call sequences mutation keeps coming back to. Distinct degree of two progs is Jaccard
+//! distance of their call sets.
+//!
+//! With seed retirement, selection is weighted by `Corpus::weight_of`: random mode picks
+//! among a few random samples by weight, novelty mode scales novelty by it.
+use core::prog::Prog;
+use fots::types::FnId;
+use rand::prelude::*;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+/// Number of recently executed progs novelty is measured against.
+const RECENT_LEN: usize = 32;
+/// Number of corpus progs sampled for each novelty selection.
+const SAMPLES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelectionMode {
+    /// Uniform random
+    #[default]
+    Random,
+    /// Most distinct from recent executed progs
+    Novelty,
+    /// Half random, half novelty
+    Hybrid,
+}
+
+/// Call sets of recently executed progs, shared by all jobs.
+/// Lock is never held across await point, so std mutex is used.
+#[derive(Default)]
+pub struct Recent {
+    calls: Mutex<VecDeque<HashSet<FnId>>>,
+}
+
+impl Recent {
+    pub fn push(&self, p: &Prog) {
+        let mut calls = self.calls.lock().unwrap();
+        if calls.len() == RECENT_LEN {
+            calls.pop_front();
+        }
+        calls.push_back(calls_of(p));
+    }
+
+    /// Mean distinct degree of `p` from recent executed progs, 1.0 if nothing executed yet.
+    pub fn novelty_of(&self, p: &Prog) -> f64 {
+        let calls = self.calls.lock().unwrap();
+        if calls.is_empty() {
+            return 1.0;
+        }
+        let p_calls = calls_of(p);
+        calls
+            .iter()
+            .map(|c| distinct_degree(&p_calls, c))
+            .sum::<f64>()
+            / calls.len() as f64
+    }
+}
+
+/// Select a prog of non-empty `corpus` to mutate, weighted by `weight` if any.
+pub fn select<'a>(
+    corpus: &'a HashSet<Prog>,
+    mode: SelectionMode,
+    recent: &Recent,
+    weight: Option<&dyn Fn(&Prog) -> f64>,
+) -> &'a Prog {
+    let mut rng = thread_rng();
+    let novelty = match mode {
+        SelectionMode::Random => false,
+        SelectionMode::Novelty => true,
+        SelectionMode::Hybrid => rng.gen(),
+    };
+    let samples = match weight {
+        None if !novelty => return corpus.iter().choose(&mut rng).unwrap(),
+        _ => corpus.iter().choose_multiple(&mut rng, SAMPLES),
+    };
+    let weight = |p: &Prog| weight.map_or(1.0, |w| w(p));
+    if !novelty {
+        return samples.choose_weighted(&mut rng, |p| weight(p)).unwrap();
+    }
+    samples
+        .into_iter()
+        .map(|p| (p, recent.novelty_of(p) * weight(p)))
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        .map(|(p, _)| p)
+        .unwrap()
+}
+
+fn calls_of(p: &Prog) -> HashSet<FnId> {
+    p.calls.iter().map(|c| c.fid).collect()
+}
+
+/// Jaccard distance of two call sets, 0.0 for same calls and 1.0 for disjoint ones.
+fn distinct_degree(a: &HashSet<FnId>, b: &HashSet<FnId>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    1.0 - a.intersection(b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::selection::{select, Recent, SelectionMode};
+    use core::prog::{Call, Prog};
+    use std::collections::HashSet;
+
+    #[test]
+    fn novelty_select() {
+        let prog = |fids: &[usize]| {
+            let mut p = Prog::new(0);
+            for fid in fids {
+                p.add_call(Call::new(*fid));
+            }
+            p
+        };
+        let recent = Recent::default();
+        recent.push(&prog(&[1, 2]));
+        assert_eq!(recent.novelty_of(&prog(&[1, 2])), 0.0);
+        assert_eq!(recent.novelty_of(&prog(&[3])), 1.0);
+        assert!((recent.novelty_of(&prog(&[1, 3])) - 2.0 / 3.0).abs() < 1e-9);
+
+        let corpus = vec![prog(&[1, 2]), prog(&[1, 2, 3]), prog(&[4])]
+            .into_iter()
+            .collect::<HashSet<_>>();
+        assert_eq!(
+            select(&corpus, SelectionMode::Novelty, &recent, None),
+            &prog(&[4])
+        );
+        let retired = |p: &Prog| if p == &prog(&[4]) { 0.0 } else { 1.0 };
+        assert_ne!(
+            select(&corpus, SelectionMode::Novelty, &recent, Some(&retired)),
+            &prog(&[4])
+        );
+        for _ in 0..8 {
+            assert_ne!(
+                select(&corpus, SelectionMode::Random, &recent, Some(&retired)),
+                &prog(&[4])
+            );
+        }
+    }
+}
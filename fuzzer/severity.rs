@@ -0,0 +1,147 @@
+//! Quick triage of crashes by severity, so triagers start with the scariest bugs.
+//!
+//! Severity of a crash is taken from its signature by the first rule whose pattern the
+//! signature contains, `Low` if none does:
+//!
+//! | severity | patterns                                                         |
+//! |----------|------------------------------------------------------------------|
+//! | critical | `use-after-free`, `out-of-bounds`, `double-free`, `invalid-free` |
+//! | high     | `KMSAN:`, `BUG:`, `general protection fault`, `Kernel panic`     |
+//! | medium   | `UBSAN:`, `KCSAN:`, `WARNING`                                    |
+//! | low      | `INFO:`, e.g. hung tasks and stalls                              |
+//!
+//! A crash that was never reproduced is one level less severe. Rules of `severity` of
+//! config are tried before these, e.g. to raise a known-bad `WARNING in foo` to critical.
+use crate::report::ReproScore;
+use std::process::exit;
+
+/// Most severe first.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Deserialize, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Critical,
+    High,
+    Medium,
+    #[default]
+    Low,
+}
+
+impl Severity {
+    /// One level less severe, `Low` stays.
+    fn lower(self) -> Self {
+        match self {
+            Severity::Critical => Severity::High,
+            Severity::High => Severity::Medium,
+            Severity::Medium | Severity::Low => Severity::Low,
+        }
+    }
+}
+
+/// Crashes whose signature contains `pattern` are of `severity`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeverityRule {
+    pub pattern: String,
+    pub severity: Severity,
+}
+
+impl SeverityRule {
+    pub fn check(&self) {
+        if self.pattern.is_empty() {
+            eprintln!("Config Error: severity pattern must not be empty");
+            exit(exitcode::CONFIG)
+        }
+    }
+}
+
+const DEFAULT_RULES: [(&str, Severity); 12] = [
+    ("use-after-free", Severity::Critical),
+    ("out-of-bounds", Severity::Critical),
+    ("double-free", Severity::Critical),
+    ("invalid-free", Severity::Critical),
+    ("KMSAN:", Severity::High),
+    ("BUG:", Severity::High),
+    ("general protection fault", Severity::High),
+    ("Kernel panic", Severity::High),
+    ("UBSAN:", Severity::Medium),
+    ("KCSAN:", Severity::Medium),
+    ("WARNING", Severity::Medium),
+    ("INFO:", Severity::Low),
+];
+
+/// Rules of config followed by default ones.
+#[derive(Debug, Clone, Default)]
+pub struct SeverityTable {
+    rules: Vec<SeverityRule>,
+}
+
+impl SeverityTable {
+    pub fn new(rules: Vec<SeverityRule>) -> Self {
+        Self { rules }
+    }
+
+    /// Severity of crash `signature`, `reproduced` or not.
+    pub fn classify(&self, signature: &str, reproduced: bool) -> Severity {
+        let severity = self
+            .rules
+            .iter()
+            .map(|r| (&r.pattern[..], r.severity))
+            .chain(DEFAULT_RULES.iter().copied())
+            .find(|(pattern, _)| signature.contains(pattern))
+            .map(|(_, s)| s)
+            .unwrap_or_default();
+        if reproduced {
+            severity
+        } else {
+            severity.lower()
+        }
+    }
+
+    /// Severity of crash `signature` reproduced as `repro` says.
+    pub fn of_crash(&self, signature: &str, repro: &ReproScore) -> Severity {
+        self.classify(signature, repro.reproduced != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_by_report_type() {
+        let table = SeverityTable::default();
+        let cases = [
+            ("KASAN: use-after-free Read in foo", Severity::Critical),
+            ("KASAN: slab-out-of-bounds Write in bar", Severity::Critical),
+            ("KMSAN: uninit-value in baz", Severity::High),
+            (
+                "BUG: unable to handle kernel NULL pointer dereference in qux",
+                Severity::High,
+            ),
+            ("WARNING in quux", Severity::Medium),
+            ("INFO: task hung in corge", Severity::Low),
+            ("lost connection to test machine", Severity::Low),
+        ];
+        for (signature, severity) in cases.iter() {
+            assert_eq!(table.classify(signature, true), *severity, "{}", signature);
+        }
+        assert_eq!(
+            table.classify("KASAN: use-after-free Read in foo", false),
+            Severity::High
+        );
+        assert_eq!(
+            table.classify("INFO: task hung in corge", false),
+            Severity::Low
+        );
+
+        // rules of config go first.
+        let table = SeverityTable::new(vec![SeverityRule {
+            pattern: String::from("WARNING in quux"),
+            severity: Severity::Critical,
+        }]);
+        assert_eq!(table.classify("WARNING in quux", true), Severity::Critical);
+        assert_eq!(table.classify("WARNING in other", true), Severity::Medium);
+    }
+}
@@ -0,0 +1,78 @@
+//! Scheduling state of fuzzer, saved when fuzzer exits and restored with `fuzzer_state`.
+//!
+//! Captured: exec and trim counters, gen counter of each job, which decides when the job
+//! switches from generation to mutation, and relations between calls learned so far.
+//! Relations are saved by group and call name, so they survive recompiling descriptions.
+//!
+//! Not captured: random state. Generation and mutation draw from `thread_rng`, which is
+//! seeded by os per thread and jobs migrate between threads, so it can't be saved. Jobs
+//! also interleave by the speed of their guests. A resumed run continues with the same
+//! corpus, counters and relations, but doesn't replay the exact trajectory.
+use crate::fuzzer::Fuzzer;
+use core::analyze::Relation;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FuzzerState {
+    pub exec: usize,
+    pub trimmed: usize,
+    /// Gen counter of each job
+    pub gen_cnts: Vec<usize>,
+    /// Learned relations of each group, `(a, b)` means b has impact on a
+    pub relations: HashMap<String, Vec<(String, String)>>,
+}
+
+impl FuzzerState {
+    pub async fn save(fuzzer: &Fuzzer) -> Self {
+        let rt = fuzzer.rt.lock().await;
+        let mut relations = HashMap::new();
+        for (gid, r) in rt.iter() {
+            let g = &fuzzer.target.groups[gid];
+            let names = g.iter_fn().map(|f| &f.dec_name).collect::<Vec<_>>();
+            let pairs = r
+                .indexed_iter()
+                .filter(|(_, rel)| **rel == Relation::Some)
+                .map(|((i, j), _)| (names[i].clone(), names[j].clone()))
+                .collect();
+            relations.insert(g.ident.clone(), pairs);
+        }
+        Self {
+            exec: fuzzer.exec_cnt.load(Ordering::SeqCst),
+            trimmed: fuzzer.trimmed_cnt.load(Ordering::SeqCst),
+            gen_cnts: fuzzer
+                .gen_cnts
+                .iter()
+                .map(|c| c.load(Ordering::SeqCst))
+                .collect(),
+            relations,
+        }
+    }
+
+    /// Restore state to `fuzzer`, return number of relations restored.
+    /// Relations of unknown groups or calls are skipped, so are gen counters of jobs
+    /// beyond `vm_num`.
+    pub async fn restore(&self, fuzzer: &Fuzzer) -> usize {
+        fuzzer.exec_cnt.store(self.exec, Ordering::SeqCst);
+        fuzzer.trimmed_cnt.store(self.trimmed, Ordering::SeqCst);
+        for (c, n) in fuzzer.gen_cnts.iter().zip(self.gen_cnts.iter()) {
+            c.store(*n, Ordering::SeqCst);
+        }
+
+        let mut rt = fuzzer.rt.lock().await;
+        let mut restored = 0;
+        for g in fuzzer.target.iter_group() {
+            let (r, pairs) = match (rt.get_mut(&g.id), self.relations.get(&g.ident)) {
+                (Some(r), Some(pairs)) => (r, pairs),
+                _ => continue,
+            };
+            for (a, b) in pairs.iter() {
+                if let (Some(i), Some(j)) = (g.index_by_name(a), g.index_by_name(b)) {
+                    r[(i, j)] = Relation::Some;
+                    restored += 1;
+                }
+            }
+        }
+        restored
+    }
+}
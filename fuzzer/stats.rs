@@ -1,28 +1,65 @@
+use crate::backlog::CrashBacklog;
+use crate::call_timeout::{CallTimeouts, LearnedTimeout};
 use crate::corpus::Corpus;
+use crate::cross_check::CrossChecks;
+use crate::failures::Failures;
 use crate::feedback::FeedBack;
+use crate::growth::{fmt_secs, Growth, HORIZON_SECS};
+use crate::hooks::HookSender;
 #[cfg(feature = "mail")]
 use crate::mail;
-use crate::report::TestCaseRecord;
+use crate::report::{CrashClass, CrashHits, TestCaseRecord};
+use crate::summary;
+use crate::toggle::ActiveCalls;
 use crate::utils::queue::CQueue;
 #[cfg(feature = "mail")]
 use lettre_email::EmailBuilder;
 
 use circular_queue::CircularQueue;
+use core::analyze::LearnerKind;
 use core::prog::Prog;
+use core::target::Target;
+use rand::{thread_rng, Rng};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::process::exit;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::fs::write;
 use tokio::sync::broadcast;
 use tokio::time;
 use tokio::time::Duration;
 
+/// Number of crash signatures shown in stats.
+const TOP_CRASHES: usize = 5;
+
 pub struct StatSource {
     pub corpus: Arc<Corpus>,
     pub feedback: Arc<FeedBack>,
     pub candidates: Arc<CQueue<Prog>>,
     pub record: Arc<TestCaseRecord>,
+    pub backlog: Arc<CrashBacklog>,
+    pub active_calls: Arc<ActiveCalls>,
+    pub failures: Arc<Failures>,
     pub exec: Arc<AtomicUsize>,
+    pub max_execs: Option<usize>,
+    pub trimmed: Arc<AtomicUsize>,
+    pub truncated: Arc<AtomicUsize>,
+    pub spurious: Arc<AtomicUsize>,
+    pub reboots: Arc<AtomicUsize>,
+    pub sweep_execs: Arc<AtomicUsize>,
+    pub sweep_blocks: Arc<AtomicUsize>,
+    pub hub_sent: Arc<AtomicUsize>,
+    pub hub_recv: Arc<AtomicUsize>,
+    pub relation_learners: Vec<LearnerKind>,
+    pub relations_learned: Arc<Vec<AtomicUsize>>,
+    pub dict_corpus: Arc<AtomicUsize>,
+    pub cross_checks: Option<Arc<CrossChecks>>,
+    pub hooks: HookSender,
+    pub subsystems: Arc<SubsystemStats>,
+    pub call_timeouts: Arc<CallTimeouts>,
+    pub run_dir: PathBuf,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -31,20 +68,158 @@ pub struct Stats {
     pub blocks: usize,
     pub branches: usize,
     pub exec: usize,
+    /// Calls removed from progs before inserting to corpus
+    pub trimmed: usize,
+    /// Progs truncated to executor's max_calls before execution
+    pub truncated: usize,
+    /// Corpus progs quarantined for causing reboots without crash
+    pub quarantined: usize,
+    /// Post-reboot recalibration executions whose new coverage was taken as baseline
+    /// instead of being triaged
+    pub spurious_new: usize,
+    /// Executions of flag sweep and blocks they found new, see `Fuzzer::sweep`
+    pub sweep_execs: usize,
+    pub sweep_blocks: usize,
+    /// Progs sent to and fetched from syz-hub
+    pub hub_sent: usize,
+    pub hub_recv: usize,
+    /// Relations learned by each relation learner, see `core::analyze::learn`
+    pub relations_learned: Vec<(LearnerKind, usize)>,
+    /// Progs added to corpus with a value of `dictionaries`
+    pub dict_corpus: usize,
+    /// New corpus progs cross-checked with syz-execprog and those mismatching
+    pub cross_checked: usize,
+    pub cross_mismatched: usize,
     // pub gen:usize,
     // pub minimized:usize,
     pub candidates: usize,
     pub normal_case: usize,
     pub failed_case: usize,
     pub crashed_case: usize,
+    /// Per subsystem counters, sorted by exec
+    pub subsystems: Vec<SubsystemStat>,
+    /// Calls with learned timeout different from default one
+    pub call_timeouts: Vec<LearnedTimeout>,
+    /// Most hit crash signatures
+    pub top_crashes: Vec<CrashHits>,
+    /// Distinct crash signatures
+    pub crash_titles: usize,
+    /// Crash reports failed to be symbolized
+    pub symbolize_failed: usize,
+    /// Crashes of each subsystem, most crashed first
+    pub crash_subsystems: Vec<(&'static str, usize)>,
+    /// Crashes of each report class
+    pub crash_classes: Vec<(CrashClass, usize)>,
+    /// Crashes waiting to be reproduced
+    pub crash_backlog: usize,
+    /// Crashes saved without repro because backlog was full
+    pub backlog_dropped: usize,
+    /// Crashes out of repro budget, waiting to be revisited
+    pub crash_deferred: usize,
+    /// Crashes ever queued for repro, retained ones are those not dropped
+    pub crash_seen: usize,
+    /// Calls not disabled during run
+    pub active_calls: usize,
+    /// Executor failures in guest, not kernel crashes
+    pub guest_failures: usize,
+    /// Vm and connection failures
+    pub host_failures: usize,
+    /// Executor reconnects, part of host failures
+    pub reconnects: usize,
+    /// Guest reboots of all jobs
+    pub reboots: usize,
+    /// Seconds since a sample found new branches or a new crash title, see `growth`,
+    /// only set in samples of sampler
+    pub secs_since_cov_find: Option<u64>,
+    pub secs_since_new_crash: Option<u64>,
+    /// Branch growth in percent expected over next `growth::HORIZON_SECS`
+    pub projected_growth: Option<f64>,
+}
+
+/// Counters of each subsystem, shared by all fuzzing jobs.
+/// Subsystems are fixed once target is loaded, so no lock is needed.
+#[derive(Default)]
+pub struct SubsystemStats {
+    cnts: HashMap<&'static str, SubsystemCnt>,
+}
+
+#[derive(Default)]
+struct SubsystemCnt {
+    exec: AtomicUsize,
+    blocks: AtomicUsize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubsystemStat {
+    pub name: &'static str,
+    /// Calls of this subsystem executed
+    pub exec: usize,
+    /// New blocks found by calls of this subsystem
+    pub blocks: usize,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl SubsystemStats {
+    pub fn new(t: &Target) -> Self {
+        let cnts = t
+            .subsystems()
+            .into_iter()
+            .map(|(name, _)| (name, SubsystemCnt::default()))
+            .collect();
+        Self { cnts }
+    }
+
+    pub fn inc_exec(&self, subsystem: &str) {
+        self.cnts[subsystem].exec.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_blocks(&self, subsystem: &str, n: usize) {
+        self.cnts[subsystem].blocks.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Vec<SubsystemStat> {
+        let mut stats = self
+            .cnts
+            .iter()
+            .map(|(name, cnt)| SubsystemStat {
+                name,
+                exec: cnt.exec.load(Ordering::Relaxed),
+                blocks: cnt.blocks.load(Ordering::Relaxed),
+            })
+            .collect::<Vec<_>>();
+        stats.sort_unstable_by(|a, b| b.exec.cmp(&a.exec).then(a.name.cmp(b.name)));
+        stats
+    }
+}
+
+/// Top `n` subsystems by exec with share of total calls executed.
+fn subsystems_summary(stats: &[SubsystemStat], n: usize) -> String {
+    let total = stats.iter().map(|s| s.exec).sum::<usize>().max(1);
+    stats
+        .iter()
+        .take(n)
+        .filter(|s| s.exec != 0)
+        .map(|s| {
+            format!(
+                "{} {:.1}% (blocks {})",
+                s.name,
+                s.exec as f64 * 100.0 / total as f64,
+                s.blocks
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct SamplerConf {
     /// Duration for sampling, per second
     pub sample_interval: u64,
     /// Duration for report, per minites
     pub report_interval: u64,
+    /// Max percent each sample interval is randomly shortened or lengthened by, default 0.
+    /// Keeps samplers of many fuzzers on one host from reading stats at the same time.
+    pub jitter: Option<u64>,
 }
 
 impl Default for SamplerConf {
@@ -52,6 +227,7 @@ impl Default for SamplerConf {
         Self {
             sample_interval: 15,
             report_interval: 60,
+            jitter: None,
         }
     }
 }
@@ -67,12 +243,121 @@ impl SamplerConf {
                                     not longger than report interval");
             exit(exitcode::CONFIG)
         }
+        if self.jitter.unwrap_or(0) > 50 {
+            eprintln!(
+                "Config Error: invalid sample conf: jitter should not be bigger than 50 percent"
+            );
+            exit(exitcode::CONFIG)
+        }
+    }
+}
+
+impl StatSource {
+    /// Take a snapshot of all counters. Lock protected ones are read first and atomic
+    /// ones last: exec counter is bumped after a prog is fully analyzed, so the snapshot
+    /// never shows corpus or cases newer than its exec count. No lock is held across reads.
+    pub async fn snapshot(&self) -> Stats {
+        let (
+            corpus,
+            candidates,
+            (normal_case, failed_case, crashed_case),
+            top_crashes,
+            crash_titles,
+            crash_subsystems,
+            crash_classes,
+            crash_backlog,
+            crash_deferred,
+            active_calls,
+        ) = tokio::join!(
+            self.corpus.len(),
+            self.candidates.len(),
+            self.record.len(),
+            self.record.top_crashes(TOP_CRASHES),
+            self.record.crash_titles(),
+            self.record.crashes_by_subsystem(),
+            self.record.crashes_by_class(),
+            self.backlog.len(),
+            self.backlog.deferred(),
+            self.active_calls.len()
+        );
+        let (blocks, branches) = self.feedback.counts();
+        let backlog_dropped = self.backlog.dropped();
+        let crash_seen = self.backlog.seen();
+        let (guest_failures, host_failures) = self.failures.counts();
+        let reconnects = self.failures.reconnects();
+        let reboots = self.reboots.load(Ordering::Relaxed);
+        let subsystems = self.subsystems.snapshot();
+        let call_timeouts = self.call_timeouts.learned();
+        let symbolize_failed = self.record.symbolize_failed();
+        let trimmed = self.trimmed.load(Ordering::SeqCst);
+        let truncated = self.truncated.load(Ordering::Relaxed);
+        let spurious_new = self.spurious.load(Ordering::Relaxed);
+        let sweep_execs = self.sweep_execs.load(Ordering::Relaxed);
+        let sweep_blocks = self.sweep_blocks.load(Ordering::Relaxed);
+        let hub_sent = self.hub_sent.load(Ordering::Relaxed);
+        let hub_recv = self.hub_recv.load(Ordering::Relaxed);
+        let relations_learned = self
+            .relation_learners
+            .iter()
+            .zip(self.relations_learned.iter())
+            .map(|(l, n)| (*l, n.load(Ordering::Relaxed)))
+            .collect();
+        let dict_corpus = self.dict_corpus.load(Ordering::Relaxed);
+        let (cross_checked, cross_mismatched) = self
+            .cross_checks
+            .as_ref()
+            .map(|c| c.counts())
+            .unwrap_or_default();
+        let quarantined = self.corpus.quarantined_len();
+        let exec = self.exec.load(Ordering::SeqCst);
+        Stats {
+            exec,
+            trimmed,
+            truncated,
+            spurious_new,
+            sweep_execs,
+            sweep_blocks,
+            hub_sent,
+            hub_recv,
+            relations_learned,
+            dict_corpus,
+            cross_checked,
+            cross_mismatched,
+            quarantined,
+            corpus,
+            blocks,
+            branches,
+            candidates,
+            normal_case,
+            failed_case,
+            crashed_case,
+            subsystems,
+            call_timeouts,
+            top_crashes,
+            crash_titles,
+            symbolize_failed,
+            crash_subsystems,
+            crash_classes,
+            crash_backlog,
+            backlog_dropped,
+            crash_deferred,
+            crash_seen,
+            active_calls,
+            guest_failures,
+            host_failures,
+            reconnects,
+            reboots,
+            secs_since_cov_find: None,
+            secs_since_new_crash: None,
+            projected_growth: None,
+        }
     }
 }
 
 pub struct Sampler {
     pub source: StatSource,
     pub stats: CircularQueue<Stats>,
+    growth: Growth,
 }
 
 impl Sampler {
@@ -80,6 +365,7 @@ impl Sampler {
         Self {
             source,
             stats: CircularQueue::with_capacity(1024),
+            growth: Growth::new(Instant::now()),
         }
     }
     pub async fn sample(
@@ -91,11 +377,13 @@ impl Sampler {
             Some(SamplerConf {
                 sample_interval,
                 report_interval,
+                jitter,
             }) => (
                 Duration::new(*sample_interval, 0),
                 Duration::new(report_interval * 60, 0),
+                jitter.unwrap_or(0),
             ),
-            None => (Duration::new(15, 0), Duration::new(60 * 60, 0)),
+            None => (Duration::new(15, 0), Duration::new(60 * 60, 0), 0),
         };
         tokio::select! {
             _ = shutdown.recv() => (),
@@ -104,30 +392,102 @@ impl Sampler {
         self.persist().await;
     }
 
-    async fn do_sample(&mut self, (sample_interval, report_interval): (Duration, Duration)) {
+    async fn do_sample(
+        &mut self,
+        (sample_interval, report_interval, jitter): (Duration, Duration, u64),
+    ) {
         let mut last_report = Duration::new(0, 0);
         loop {
-            time::delay_for(sample_interval).await;
-            last_report += sample_interval;
-
-            let (corpus, (blocks, branches), candidates, (normal_case, failed_case, crashed_case)) = tokio::join!(
-                self.source.corpus.len(),
-                self.source.feedback.len(),
-                self.source.candidates.len(),
-                self.source.record.len()
-            );
-            let exec = self.source.exec.load(Ordering::SeqCst);
-
-            let stat = Stats {
-                exec,
-                corpus,
-                blocks,
-                branches,
-                candidates,
-                normal_case,
-                failed_case,
-                crashed_case,
+            let interval = jittered(sample_interval, jitter);
+            time::delay_for(interval).await;
+            last_report += interval;
+
+            let mut stat = self.source.snapshot().await;
+            self.growth.observe(&mut stat, Instant::now());
+            let summary = subsystems_summary(&stat.subsystems, 5);
+            let timeouts_summary = stat
+                .call_timeouts
+                .iter()
+                .take(5)
+                .map(|t| format!("{} {}ms", t.name, t.timeout))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let crashes_summary = stat
+                .top_crashes
+                .iter()
+                .map(|c| format!("{:.0}% ({}) {}", c.rate * 100.0, c.hits, c.signature))
+                .collect::<Vec<_>>()
+                .join("; ");
+            let crash_subsystems_summary = stat
+                .crash_subsystems
+                .iter()
+                .map(|(s, n)| format!("{} {}", s, n))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let crash_classes_summary = stat
+                .crash_classes
+                .iter()
+                .map(|(c, n)| format!("{} {}", c, n))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let exec = match self.source.max_execs {
+                Some(n) => format!("{} / {}", group_digits(stat.exec), group_digits(n)),
+                None => stat.exec.to_string(),
             };
+            let mut info = format!(
+                "exec {}, blocks {}, branches {}, failed {}, crashed {}, trimmed {}",
+                exec, stat.blocks, stat.branches, stat.failed_case, stat.crashed_case, stat.trimmed
+            );
+            info += &growth_summary(&stat);
+            if stat.truncated != 0 {
+                info += &format!(", truncated {}", stat.truncated);
+            }
+            if stat.quarantined != 0 {
+                info += &format!(", quarantined {}", stat.quarantined);
+            }
+            if stat.spurious_new != 0 {
+                info += &format!(", spurious new {}", stat.spurious_new);
+            }
+            if stat.sweep_execs != 0 {
+                info += &format!(
+                    ", sweep exec {} new blocks {}",
+                    stat.sweep_execs, stat.sweep_blocks
+                );
+            }
+            if stat.hub_sent != 0 || stat.hub_recv != 0 {
+                info += &format!(", hub sent {} recv {}", stat.hub_sent, stat.hub_recv);
+            }
+            if stat.dict_corpus != 0 {
+                info += &format!(", dict progs {}", stat.dict_corpus);
+            }
+            if stat.cross_checked != 0 {
+                info += &format!(
+                    ", cross-checked {} mismatched {}",
+                    stat.cross_checked, stat.cross_mismatched
+                );
+            }
+            if stat.symbolize_failed != 0 {
+                info += &format!(", symbolize failed {}", stat.symbolize_failed);
+            }
+            if stat.crash_backlog != 0 || stat.backlog_dropped != 0 || stat.crash_deferred != 0 {
+                info += &format!(
+                    ", repro backlog {} (seen {}, dropped {}, deferred {})",
+                    stat.crash_backlog, stat.crash_seen, stat.backlog_dropped, stat.crash_deferred
+                );
+            }
+            if stat.guest_failures != 0 || stat.host_failures != 0 {
+                info += &format!(
+                    ", failures guest {} host {}",
+                    stat.guest_failures, stat.host_failures
+                );
+            }
+            if stat.active_calls != self.source.active_calls.total() {
+                info += &format!(
+                    ", calls active {}/{}",
+                    stat.active_calls,
+                    self.source.active_calls.total()
+                );
+            }
 
             if report_interval <= last_report {
                 #[cfg(feature = "mail")]
@@ -135,11 +495,25 @@ impl Sampler {
                 last_report = Duration::new(0, 0);
             }
 
+            self.source.hooks.stats(|| stat.clone());
+            summary::update(&stat);
             self.stats.push(stat);
-            info!(
-                "exec {}, blocks {}, branches {}, failed {}, crashed {}",
-                exec, blocks, branches, failed_case, crashed_case
-            );
+            info!("{}", info);
+            if !summary.is_empty() {
+                info!("subsystems: {}", summary);
+            }
+            if !timeouts_summary.is_empty() {
+                info!("call timeouts: {}", timeouts_summary);
+            }
+            if !crashes_summary.is_empty() {
+                info!("top crashes: {}", crashes_summary);
+            }
+            if !crash_subsystems_summary.is_empty() {
+                info!("crashes by subsystem: {}", crash_subsystems_summary);
+            }
+            if !crash_classes_summary.is_empty() {
+                info!("crashes by class: {}", crash_classes_summary);
+            }
         }
     }
 
@@ -149,10 +523,15 @@ impl Sampler {
         }
 
         let stats = self.stats.asc_iter().cloned().collect::<Vec<_>>();
-        let path = "./stats.json";
+        let path = self.source.run_dir.join("stats.json");
         let stats = serde_json::to_string_pretty(&stats).unwrap();
         write(&path, stats).await.unwrap_or_else(|e| {
-            exits!(exitcode::IOERR, "Fail to persist stats to {} : {}", path, e)
+            exits!(
+                exitcode::IOERR,
+                "Fail to persist stats to {} : {}",
+                path.display(),
+                e
+            )
         })
     }
 
@@ -165,3 +544,45 @@ impl Sampler {
         mail::send(email).await
     }
 }
+
+/// Time since last finds and projected growth of a sample.
+fn growth_summary(stat: &Stats) -> String {
+    let mut summary = String::new();
+    if let Some(secs) = stat.secs_since_cov_find {
+        summary += &format!(", last cov find {} ago", fmt_secs(secs));
+    }
+    if let Some(secs) = stat.secs_since_new_crash {
+        summary += &format!(", last new crash {} ago", fmt_secs(secs));
+    }
+    if let Some(p) = stat.projected_growth {
+        let hours = HORIZON_SECS / 3600;
+        if p < 1.0 {
+            summary += &format!(", <1% coverage growth expected in next {}h", hours);
+        } else {
+            summary += &format!(", ~{:.0}% coverage growth expected in next {}h", p, hours);
+        }
+    }
+    summary
+}
+
+/// `3400000` -> `3 400 000`
+fn group_digits(n: usize) -> String {
+    let digits = n.to_string();
+    let mut groups = digits
+        .as_bytes()
+        .rchunks(3)
+        .map(|g| std::str::from_utf8(g).unwrap())
+        .collect::<Vec<_>>();
+    groups.reverse();
+    groups.join(" ")
+}
+
+/// `interval` randomly shortened or lengthened by at most `jitter` percent.
+fn jittered(interval: Duration, jitter: u64) -> Duration {
+    if jitter == 0 {
+        return interval;
+    }
+    let ms = interval.as_millis() as u64;
+    let delta = ms * jitter / 100;
+    Duration::from_millis(thread_rng().gen_range(ms - delta, ms + delta + 1))
+}
@@ -0,0 +1,285 @@
+//! Machine-readable summary of how a run ended.
+//!
+//! Every way out of fuzzing, signals, budgets, watchdog and fatal errors of `exits!`, writes
+//! `summary.json` to run dir and exits with the code of its end reason, so automation
+//! doesn't need to parse logs. Fields are only added within a `SCHEMA_VERSION`, removing or
+//! changing one bumps it. A fatal end has no chance to gather fresh stats, its summary is
+//! built from the last stats sample.
+use crate::severity::Severity;
+use crate::stats::Stats;
+use chrono::prelude::*;
+use core::analyze::LearnerKind;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndReason {
+    /// SIGINT or SIGTERM received
+    Interrupted,
+    /// `max_execs` reached
+    ExecBudget,
+    /// `max_crashes` reached
+    CrashBudget,
+    /// Unrecoverable error, e.g. host out of resources or vms failed to boot
+    Fatal,
+    /// No execution for `stall_secs`, see `watchdog`
+    Stalled,
+    /// Stop requested through `rpc`
+    Requested,
+}
+
+impl EndReason {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            EndReason::Interrupted | EndReason::ExecBudget | EndReason::Requested => 0,
+            EndReason::CrashBudget => 2,
+            EndReason::Fatal => 3,
+            EndReason::Stalled => 4,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub schema_version: u32,
+    pub end_reason: EndReason,
+    pub exit_code: i32,
+    /// Error a fatal end was caused by
+    pub message: Option<String>,
+    pub start_time: DateTime<Local>,
+    pub end_time: DateTime<Local>,
+    pub duration_secs: i64,
+    pub exec: ExecSummary,
+    pub coverage: CoverageSummary,
+    pub corpus: CorpusSummary,
+    /// Most severe first, then most hit, see `severity`
+    pub crashes: Vec<CrashStatus>,
+    pub infra: InfraSummary,
+    /// Relations learned by each relation learner, comparable across runs
+    pub relations: Vec<RelationSummary>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ExecSummary {
+    pub total: usize,
+    pub per_sec: f64,
+    pub normal: usize,
+    pub failed: usize,
+    pub crashed: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CoverageSummary {
+    pub blocks: usize,
+    pub branches: usize,
+}
+
+/// Size of corpus and each queue of progs
+#[derive(Debug, Default, Serialize)]
+pub struct CorpusSummary {
+    pub size: usize,
+    pub candidates: usize,
+    pub quarantined: usize,
+    pub trimmed_calls: usize,
+    pub crash_backlog: usize,
+    pub crash_deferred: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrashStatus {
+    pub title: String,
+    pub hits: usize,
+    /// Whether any recent case of it reproduced, unknown for a fatal end
+    pub reproduced: Option<bool>,
+    /// Unknown for a fatal end too, as it depends on reproduction
+    pub severity: Option<Severity>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelationSummary {
+    pub learner: LearnerKind,
+    pub learned: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct InfraSummary {
+    pub reboots: usize,
+    pub reconnects: usize,
+    pub guest_failures: usize,
+    pub host_failures: usize,
+    pub symbolize_failed: usize,
+}
+
+struct Run {
+    run_dir: PathBuf,
+    start: DateTime<Local>,
+    /// Latest stats sample
+    last: Option<Stats>,
+}
+
+lazy_static! {
+    static ref RUN: Mutex<Option<Run>> = Mutex::new(None);
+}
+
+// a thread panicked holding the lock doesn't stop others from writing summary.
+fn run() -> MutexGuard<'static, Option<Run>> {
+    RUN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Start of fuzzing run writing its summary to `run_dir`. Fatal errors before it, e.g.
+/// config errors, keep their own exit codes and write no summary.
+pub fn register(run_dir: PathBuf) {
+    *run() = Some(Run {
+        run_dir,
+        start: Local::now(),
+        last: None,
+    });
+}
+
+/// Keep `stats` as latest sample, a fatal end is summarized from it.
+pub fn update(stats: &Stats) {
+    if let Some(run) = run().as_mut() {
+        run.last = Some(stats.clone());
+    }
+}
+
+/// Write summary of run ended for `reason`, with fresh `stats` and `crashes`, return exit
+/// code. Only the first end of a run is written.
+pub fn write(reason: EndReason, stats: &Stats, crashes: Vec<CrashStatus>) -> i32 {
+    finish(reason, None, Some(stats), Some(crashes))
+}
+
+/// Write summary of a fatal end with error `message` if a run is going, return exit code
+/// of process, `code` if no run is going. Called by `exits!`.
+pub fn fatal(code: i32, message: &str) -> i32 {
+    let last = match run().as_ref() {
+        Some(run) => run.last.clone(),
+        None => return code,
+    };
+    finish(EndReason::Fatal, Some(message), last.as_ref(), None)
+}
+
+fn finish(
+    reason: EndReason,
+    message: Option<&str>,
+    stats: Option<&Stats>,
+    crashes: Option<Vec<CrashStatus>>,
+) -> i32 {
+    let run = match run().take() {
+        Some(run) => run,
+        None => return reason.exit_code(),
+    };
+    let summary = Summary::new(&run, reason, message, stats, crashes);
+    let path = run.run_dir.join("summary.json");
+    // never `exits!` here, it would come back.
+    if let Err(e) = std::fs::write(&path, serde_json::to_string_pretty(&summary).unwrap()) {
+        eprintln!("Fail to write summary to {}: {}", path.display(), e);
+    }
+    summary.exit_code
+}
+
+impl Summary {
+    fn new(
+        run: &Run,
+        reason: EndReason,
+        message: Option<&str>,
+        stats: Option<&Stats>,
+        crashes: Option<Vec<CrashStatus>>,
+    ) -> Self {
+        let end_time = Local::now();
+        let duration_secs = (end_time - run.start).num_seconds();
+        let crashes = crashes.unwrap_or_else(|| {
+            stats
+                .map(|s| {
+                    s.top_crashes
+                        .iter()
+                        .map(|c| CrashStatus {
+                            title: c.signature.clone(),
+                            hits: c.hits,
+                            reproduced: None,
+                            severity: None,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        });
+        let mut summary = Summary {
+            schema_version: SCHEMA_VERSION,
+            end_reason: reason,
+            exit_code: reason.exit_code(),
+            message: message.map(String::from),
+            start_time: run.start,
+            end_time,
+            duration_secs,
+            exec: ExecSummary::default(),
+            coverage: CoverageSummary::default(),
+            corpus: CorpusSummary::default(),
+            crashes,
+            infra: InfraSummary::default(),
+            relations: Vec::new(),
+        };
+        if let Some(s) = stats {
+            summary.exec = ExecSummary {
+                total: s.exec,
+                per_sec: s.exec as f64 / duration_secs.max(1) as f64,
+                normal: s.normal_case,
+                failed: s.failed_case,
+                crashed: s.crashed_case,
+            };
+            summary.coverage = CoverageSummary {
+                blocks: s.blocks,
+                branches: s.branches,
+            };
+            summary.corpus = CorpusSummary {
+                size: s.corpus,
+                candidates: s.candidates,
+                quarantined: s.quarantined,
+                trimmed_calls: s.trimmed,
+                crash_backlog: s.crash_backlog,
+                crash_deferred: s.crash_deferred,
+            };
+            summary.infra = InfraSummary {
+                reboots: s.reboots,
+                reconnects: s.reconnects,
+                guest_failures: s.guest_failures,
+                host_failures: s.host_failures,
+                symbolize_failed: s.symbolize_failed,
+            };
+            summary.relations = s
+                .relations_learned
+                .iter()
+                .map(|(learner, learned)| RelationSummary {
+                    learner: *learner,
+                    learned: *learned,
+                })
+                .collect();
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_schema() {
+        // no run is registered in tests, fatal errors keep their codes.
+        assert_eq!(fatal(exitcode::CONFIG, "Config Error"), exitcode::CONFIG);
+        let run = Run {
+            run_dir: PathBuf::new(),
+            start: Local::now(),
+            last: None,
+        };
+        let summary = Summary::new(&run, EndReason::CrashBudget, None, None, None);
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert_eq!(json["end_reason"], "crash_budget");
+        assert_eq!(json["exit_code"], 2);
+        assert_eq!(json["exec"]["total"], 0);
+        assert!(json["crashes"].as_array().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+//! Symbolization of crash reports with syz-symbolize.
+//!
+//! Crash case is always saved with raw report first, then queued here, so detecting and
+//! saving a crash never waits on symbolization. A single task takes queued cases one by
+//! one. Reports with bare addresses are resolved with the kernel symbol table loaded once
+//! at startup if it can resolve all of them, see `symbols`; otherwise syz-symbolize is run
+//! with a timeout and retried once. Symbolized report or the error is written back to the saved
+//! crash case, together with maintainers suggested for it if kernel source is given, see
+//! `maintainers`. Cases still queued when fuzzer exits keep their raw report only.
+use crate::guest::GuestConf;
+use crate::maintainers::Maintainers;
+use crate::report::CrashedCase;
+use crate::symbols::SymbolTable;
+use crate::work_dir;
+use core::subsystem::{classify_crash, OTHER};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::fs::{read, remove_file, write};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio::time::{timeout, Duration, Instant};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SymbolizeConf {
+    /// Path of syz-symbolize
+    pub bin: PathBuf,
+    /// Dir of kernel build containing vmlinux
+    pub kernel_obj: PathBuf,
+    /// Time limit of symbolizing a report in seconds, default 60
+    pub timeout: Option<u64>,
+    /// Kernel source tree, maintainers of crashes are suggested with its get_maintainer.pl
+    pub kernel_src_dir: Option<PathBuf>,
+}
+
+impl SymbolizeConf {
+    pub fn check(&self) {
+        if !self.bin.is_file() {
+            eprintln!(
+                "Config Error: symbolize: bin {} is invalid",
+                self.bin.display()
+            );
+            exit(exitcode::CONFIG)
+        }
+        if !self.kernel_obj.join("vmlinux").is_file() {
+            eprintln!(
+                "Config Error: symbolize: no vmlinux in kernel_obj {}",
+                self.kernel_obj.display()
+            );
+            exit(exitcode::CONFIG)
+        }
+        if self.timeout == Some(0) {
+            eprintln!("Config Error: symbolize: timeout must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+        if let Some(src) = self.kernel_src_dir.as_ref() {
+            if !src.is_dir() {
+                eprintln!(
+                    "Config Error: symbolize: kernel_src_dir {} is invalid",
+                    src.display()
+                );
+                exit(exitcode::CONFIG)
+            }
+        }
+    }
+}
+
+pub struct Symbolizer {
+    queue: mpsc::UnboundedSender<PathBuf>,
+    failed: Arc<AtomicUsize>,
+}
+
+impl Symbolizer {
+    /// Spawn symbolization task of crashes of `guest`.
+    pub fn spawn(conf: SymbolizeConf, guest: &GuestConf) -> Self {
+        let (queue, cases) = mpsc::unbounded_channel();
+        let failed = Arc::new(AtomicUsize::new(0));
+        let args = vec![
+            OsString::from("-os"),
+            OsString::from(&guest.os),
+            OsString::from("-arch"),
+            OsString::from(&guest.arch),
+            OsString::from("-kernel_obj"),
+            conf.kernel_obj.clone().into_os_string(),
+        ];
+        let timeout = Duration::from_secs(conf.timeout.unwrap_or(60));
+        let symbols = match SymbolTable::load(&conf.kernel_obj) {
+            Ok(symbols) => {
+                info!("Symbolize: loaded {} kernel symbol(s)", symbols.len());
+                Some(Arc::new(symbols))
+            }
+            Err(e) => {
+                warn!("Symbolize: {}, every report goes to syz-symbolize", e);
+                None
+            }
+        };
+        let maintainers = conf
+            .kernel_src_dir
+            .map(|src| Maintainers::new(src, timeout));
+        let symbolize = Symbolize {
+            bin: conf.bin,
+            args,
+            limit: timeout,
+            symbols,
+            maintainers,
+        };
+        tokio::spawn(run(symbolize, cases, failed.clone()));
+        Self { queue, failed }
+    }
+
+    /// Queue crash case saved at `path`.
+    pub fn queue(&self, path: PathBuf) {
+        if let Err(e) = self.queue.send(path) {
+            warn!("Symbolize: task exited, {} not queued", e.0.display());
+        }
+    }
+
+    /// Number of reports failed to be symbolized.
+    pub fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+struct Symbolize {
+    bin: PathBuf,
+    args: Vec<OsString>,
+    limit: Duration,
+    symbols: Option<Arc<SymbolTable>>,
+    maintainers: Option<Maintainers>,
+}
+
+async fn run(
+    mut s: Symbolize,
+    mut cases: mpsc::UnboundedReceiver<PathBuf>,
+    failed: Arc<AtomicUsize>,
+) {
+    while let Some(path) = cases.recv().await {
+        let ret = match load(&path).await {
+            Ok(mut case) => {
+                let now = Instant::now();
+                let in_process = s
+                    .symbols
+                    .as_ref()
+                    .and_then(|symbols| symbols.annotate(&case.crash.inner));
+                let (ret, by) = match in_process {
+                    Some(report) => (Ok(report), "symbol table"),
+                    None => (
+                        symbolize_case(&s.bin, &s.args, s.limit, &path, &case).await,
+                        "syz-symbolize",
+                    ),
+                };
+                info!(
+                    "Symbolize: {} by {} in {}ms",
+                    path.display(),
+                    by,
+                    now.elapsed().as_millis()
+                );
+                match ret {
+                    Ok(ref report) => {
+                        // symbolized frames have source paths, more precise than names.
+                        let subsystem = classify_crash(report);
+                        if subsystem != OTHER {
+                            case.subsystem = subsystem.to_string();
+                        }
+                        case.symbolized = Some(report.clone());
+                        if let Some(maintainers) = s.maintainers.as_mut() {
+                            case.maintainers = maintainers.suggest(report).await;
+                        }
+                    }
+                    Err(ref e) => case.symbolize_error = Some(e.clone()),
+                }
+                store(&path, &case).await.and(ret.map(drop))
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = ret {
+            failed.fetch_add(1, Ordering::Relaxed);
+            warn!("Symbolize: fail to symbolize {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Symbolize report of `case`, retry once on failure. Raw report is written to scratch dir
+/// of run for syz-symbolize and removed after, crashes dir only holds crash cases.
+async fn symbolize_case(
+    bin: &Path,
+    args: &[OsString],
+    limit: Duration,
+    path: &Path,
+    case: &CrashedCase,
+) -> Result<String, String> {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".report");
+    let report_path = work_dir::run_dir().join(name);
+    write(&report_path, &case.crash.inner)
+        .await
+        .map_err(|e| format!("fail to write {}: {}", report_path.display(), e))?;
+
+    let ret = match symbolize(bin, args, limit, &report_path).await {
+        Ok(report) => Ok(report),
+        Err(e) => {
+            info!("Symbolize: {}: {}, retrying", path.display(), e);
+            symbolize(bin, args, limit, &report_path).await
+        }
+    };
+    if let Err(e) = remove_file(&report_path).await {
+        warn!("Symbolize: fail to remove {}: {}", report_path.display(), e);
+    }
+    ret
+}
+
+async fn symbolize(
+    bin: &Path,
+    args: &[OsString],
+    limit: Duration,
+    report: &Path,
+) -> Result<String, String> {
+    let output = Command::new(bin)
+        .args(args)
+        .arg(report)
+        .kill_on_drop(true)
+        .output();
+    match timeout(limit, output).await {
+        Err(_) => Err(format!("timed out after {}s", limit.as_secs())),
+        Ok(Err(e)) => Err(format!("fail to spawn {}: {}", bin.display(), e)),
+        Ok(Ok(output)) if !output.status.success() => Err(format!(
+            "{}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Ok(Ok(output)) => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+    }
+}
+
+async fn load(path: &Path) -> Result<CrashedCase, String> {
+    let data = read(path)
+        .await
+        .map_err(|e| format!("fail to read case: {}", e))?;
+    serde_json::from_slice(&data).map_err(|e| format!("fail to parse case: {}", e))
+}
+
+async fn store(path: &Path, case: &CrashedCase) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(case).unwrap();
+    write(path, data)
+        .await
+        .map_err(|e| format!("fail to update case: {}", e))
+}
@@ -0,0 +1,130 @@
+//! Kernel symbol table for symbolizing crash reports in process.
+//!
+//! Text symbols of `System.map` in kernel build dir are loaded once at startup and shared.
+//! Bare kernel text addresses in a report, e.g. `RIP: 0010:0xffffffff81234567` or
+//! `[<ffffffff81234567>]`, are annotated with function and offset. Reports without such
+//! addresses already name their functions, and addresses beyond the table, e.g. of
+//! modules, can't be resolved; only these reports need syz-symbolize.
+use regex::Regex;
+use std::path::Path;
+
+lazy_static! {
+    static ref TEXT_ADDR: Regex = Regex::new(r"\b(?:0x)?(ffffffff[0-9a-f]{8})\b").unwrap();
+}
+
+pub struct SymbolTable {
+    /// Text symbols sorted by address
+    syms: Vec<(u64, Box<str>)>,
+    /// End of kernel image, addresses from it on are not resolved
+    end: u64,
+}
+
+impl SymbolTable {
+    /// Load `System.map` of kernel build dir `kernel_obj`.
+    pub fn load(kernel_obj: &Path) -> Result<Self, String> {
+        let path = kernel_obj.join("System.map");
+        let map = std::fs::read_to_string(&path)
+            .map_err(|e| format!("fail to read {}: {}", path.display(), e))?;
+        Self::parse(&map).ok_or_else(|| format!("no text symbol in {}", path.display()))
+    }
+
+    /// Parse lines like `ffffffff81000000 T _stext` of System.map.
+    pub fn parse(map: &str) -> Option<Self> {
+        let mut syms = Vec::new();
+        let mut end = 0;
+        for l in map.lines() {
+            let mut fields = l.split_whitespace();
+            let (addr, kind, name) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(addr), Some(kind), Some(name)) => (addr, kind, name),
+                _ => continue,
+            };
+            let addr = match u64::from_str_radix(addr, 16) {
+                Ok(addr) => addr,
+                Err(_) => continue,
+            };
+            end = end.max(addr);
+            if matches!(kind, "T" | "t" | "W" | "w") {
+                syms.push((addr, Box::from(name)));
+            }
+        }
+        if syms.is_empty() {
+            return None;
+        }
+        syms.sort_unstable_by_key(|(addr, _)| *addr);
+        Some(Self { syms, end })
+    }
+
+    pub fn len(&self) -> usize {
+        self.syms.len()
+    }
+
+    /// Function `addr` falls in and offset in it.
+    pub fn resolve(&self, addr: u64) -> Option<(&str, u64)> {
+        if addr >= self.end {
+            return None;
+        }
+        let i = match self.syms.binary_search_by_key(&addr, |(a, _)| *a) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let (start, name) = &self.syms[i];
+        Some((name, addr - start))
+    }
+
+    /// Annotate bare text addresses of `report` with function and offset. None if there's
+    /// no such address or any of them can't be resolved.
+    pub fn annotate(&self, report: &str) -> Option<String> {
+        let mut resolved = 0;
+        let mut complete = true;
+        let annotated = TEXT_ADDR.replace_all(report, |c: &regex::Captures| {
+            let addr = u64::from_str_radix(&c[1], 16).unwrap();
+            match self.resolve(addr) {
+                Some((f, off)) => {
+                    resolved += 1;
+                    format!("{} ({}+{:#x})", &c[0], f, off)
+                }
+                None => {
+                    complete = false;
+                    c[0].to_string()
+                }
+            }
+        });
+        if resolved != 0 && complete {
+            Some(annotated.into_owned())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SymbolTable;
+
+    const MAP: &str = "0000000000000000 A VDSO32_PRELINK
+ffffffff81000000 T _stext
+ffffffff81000000 T startup_64
+ffffffff81234000 T tcp_v4_rcv
+ffffffff81234400 t tcp_v4_fill_cb
+ffffffff82000000 D init_task
+ffffffff83000000 B _end
+";
+
+    #[test]
+    fn annotate_report() {
+        let t = SymbolTable::parse(MAP).unwrap();
+        assert_eq!(t.len(), 4);
+        assert_eq!(t.resolve(0xffffffff81234010), Some(("tcp_v4_rcv", 0x10)));
+        assert_eq!(t.resolve(0xffffffff81234400), Some(("tcp_v4_fill_cb", 0)));
+        assert_eq!(t.resolve(0xffffffffa0001000), None);
+
+        let r = t.annotate("RIP: 0010:0xffffffff81234010\n [<ffffffff81234404>]");
+        assert_eq!(
+            r.as_deref(),
+            Some("RIP: 0010:0xffffffff81234010 (tcp_v4_rcv+0x10)\n [<ffffffff81234404 (tcp_v4_fill_cb+0x4)>]")
+        );
+        assert!(t.annotate("RIP: 0010:0xffffffffa0001000").is_none());
+        assert!(t.annotate("RIP: 0010:tcp_v4_rcv+0x10/0x400").is_none());
+    }
+}
@@ -0,0 +1,44 @@
+//! Crashes in syzkaller's layout, for tools reading crashes of a syz-manager workdir.
+//!
+//! With `syz_crash_layout`, each crash is also written to `syz/crashes/<hash>/` of run dir,
+//! `<hash>` being hex SHA-1 of its title, like syz-manager does. The dir holds `description`,
+//! the title on a single line, `logN` and `reportN`, console log and report of the Nth hit
+//! numbered from 0, and `repro.prog` and `repro.cprog`, syzkaller and C prog of the first
+//! reproduced hit. Title is the signature of report, normalized like syzkaller's. Like
+//! syz-manager, logs of at most `MAX_LOGS` hits of a title are kept.
+use crate::report::report_of;
+use crate::syzdb::sha1_hex;
+use std::path::{Path, PathBuf};
+use tokio::fs::{create_dir_all, metadata, write};
+
+pub const MAX_LOGS: usize = 100;
+
+/// Dir of crashes with `title` under `crashes`.
+pub fn dir_of(crashes: &Path, title: &str) -> PathBuf {
+    crashes.join(sha1_hex(title.as_bytes()))
+}
+
+/// Write `n`th hit of crash `title` with console `log` to `crashes`, with `repro`, syzkaller
+/// and C prog, if it was reproduced.
+pub async fn save(
+    crashes: &Path,
+    title: &str,
+    n: usize,
+    log: &str,
+    repro: Option<(String, String)>,
+) -> std::io::Result<PathBuf> {
+    let dir = dir_of(crashes, title);
+    create_dir_all(&dir).await?;
+    write(dir.join("description"), format!("{}\n", title)).await?;
+    if n < MAX_LOGS {
+        write(dir.join(format!("log{}", n)), log).await?;
+        write(dir.join(format!("report{}", n)), report_of(log)).await?;
+    }
+    if let Some((prog, cprog)) = repro {
+        if metadata(dir.join("repro.prog")).await.is_err() {
+            write(dir.join("repro.prog"), prog).await?;
+            write(dir.join("repro.cprog"), cprog).await?;
+        }
+    }
+    Ok(dir)
+}
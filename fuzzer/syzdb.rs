@@ -0,0 +1,343 @@
+//! Seed corpus from a syzkaller corpus.db, and pack corpus into one.
+//!
+//! corpus.db is syzkaller's own append-only file rather than a BoltDB: a header of magic
+//! and version, then records of key, seq and raw deflate compressed value. A later record
+//! of a key replaces earlier ones, a record of deleted seq removes it. Values are progs in
+//! syzkaller's text format, decoded by `core::syz`. A broken tail, e.g. of a manager killed
+//! while writing, keeps records before it as syzkaller does. Records whose value doesn't
+//! decompress or decode are skipped and counted, calls renamed by other syzkaller versions
+//! or missing in healer's descriptions end up there.
+//!
+//! `pack` goes the other way, for continuing a campaign with syz-manager: each prog is
+//! encoded by `core::syz` and keyed by SHA-1 of its text, as syz-db keys records.
+use core::prog::Prog;
+use core::syz::{decode, encode};
+use core::target::Target;
+use flate2::bufread::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use tokio::fs::read;
+
+const DB_MAGIC: u32 = 0xbaddb;
+const REC_MAGIC: u32 = 0xfee1bad;
+const SEQ_DELETED: u64 = u64::MAX;
+/// Version of packed corpus. The oldest one, so syz-manager minimizes and smashes progs
+/// itself, as it does with corpus of older syzkaller.
+const PACK_VERSION: u64 = 0;
+
+#[derive(Debug, Default)]
+struct SyzDb {
+    version: u64,
+    /// Live records sorted by key
+    records: Vec<(String, Vec<u8>)>,
+    /// Records skipped for broken values
+    corrupted: usize,
+    /// Whether reading stopped at a broken record
+    truncated: bool,
+}
+
+/// Progs of syzkaller corpus.db at `path` decodable for `t`.
+pub async fn seeds_of(path: &Path, t: &Target) -> Vec<Prog> {
+    let data = read(path)
+        .await
+        .unwrap_or_else(|e| exits!(exitcode::IOERR, "{}: {}", path.display(), e));
+    let db =
+        parse(&data).unwrap_or_else(|e| exits!(exitcode::DATAERR, "{}: {}", path.display(), e));
+    if db.truncated {
+        warn!(
+            "Syz corpus: {} is truncated, records before broken one are kept",
+            path.display()
+        );
+    }
+
+    let mut first_err = None;
+    let seeds = db
+        .records
+        .iter()
+        .filter_map(|(key, val)| {
+            let p = std::str::from_utf8(val)
+                .map_err(|_| String::from("non utf-8 prog"))
+                .and_then(|text| decode(text, t));
+            p.map_err(|e| first_err.get_or_insert_with(|| format!("{}: {}", key, e)))
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    info!(
+        "Syz corpus: version {}, {} record(s), {} decoded",
+        db.version,
+        db.records.len() + db.corrupted,
+        seeds.len()
+    );
+    if db.corrupted != 0 {
+        warn!("Syz corpus: {} corrupted record(s) skipped", db.corrupted);
+    }
+    if let Some(e) = first_err {
+        warn!(
+            "Syz corpus: {} undecodable prog(s) skipped, e.g. {}",
+            db.records.len() - seeds.len(),
+            e
+        );
+    }
+    seeds
+}
+
+/// corpus.db of `progs` of target `t`, duplicates packed once.
+pub fn pack(progs: &[Prog], t: &Target) -> Vec<u8> {
+    let mut records = progs
+        .iter()
+        .map(|p| {
+            let text = encode(p, t);
+            (sha1_hex(text.as_bytes()), text)
+        })
+        .collect::<Vec<_>>();
+    records.sort();
+    records.dedup_by(|a, b| a.0 == b.0);
+
+    let mut db = DB_MAGIC.to_le_bytes().to_vec();
+    db.extend(&PACK_VERSION.to_le_bytes());
+    for (seq, (key, val)) in records.iter().enumerate() {
+        db.extend(&REC_MAGIC.to_le_bytes());
+        db.extend(&(key.len() as u32).to_le_bytes());
+        db.extend(key.as_bytes());
+        db.extend(&(seq as u64).to_le_bytes());
+        db.extend(&(val.len() as u32).to_le_bytes());
+        let mut e = DeflateEncoder::new(db, Compression::default());
+        e.write_all(val.as_bytes()).unwrap();
+        db = e.finish().unwrap();
+    }
+    db
+}
+
+/// Hex SHA-1 of `data`.
+pub(crate) fn sha1_hex(data: &[u8]) -> String {
+    let mut h: [u32; 5] = [
+        0x6745_2301,
+        0xefcd_ab89,
+        0x98ba_dcfe,
+        0x1032_5476,
+        0xc3d2_e1f0,
+    ];
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend(&(data.len() as u64 * 8).to_be_bytes());
+    for block in msg.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (w, b) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *w = u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, w) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let tmp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*w);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = tmp;
+        }
+        for (h, v) in h.iter_mut().zip(&[a, b, c, d, e]) {
+            *h = h.wrapping_add(*v);
+        }
+    }
+    h.iter().map(|v| format!("{:08x}", v)).collect()
+}
+
+/// Parse corpus.db `data`, only a bad header fails.
+fn parse(mut data: &[u8]) -> Result<SyzDb, String> {
+    let magic = take_u32(&mut data).ok_or("no header")?;
+    if magic != DB_MAGIC {
+        return Err(format!("bad magic {:#x}, not a syzkaller db", magic));
+    }
+    let mut db = SyzDb {
+        version: take_u64(&mut data).ok_or("no header")?,
+        ..Default::default()
+    };
+    let mut records = HashMap::new();
+    while !data.is_empty() {
+        match take_record(&mut data) {
+            Some((key, Some(Some(val)))) => {
+                records.insert(key, val);
+            }
+            Some((key, Some(None))) => {
+                records.remove(&key);
+            }
+            // value decompressed to other length than recorded.
+            Some((key, None)) => {
+                records.remove(&key);
+                db.corrupted += 1;
+            }
+            None => {
+                db.corrupted += 1;
+                db.truncated = true;
+                break;
+            }
+        }
+    }
+    db.records = records.into_iter().collect();
+    db.records.sort();
+    Ok(db)
+}
+
+/// Key and value of next record, none value if it's broken, none inner value if it's
+/// deleted. None if record can't be read, data after it can't be found then.
+#[allow(clippy::type_complexity)]
+fn take_record(data: &mut &[u8]) -> Option<(String, Option<Option<Vec<u8>>>)> {
+    if take_u32(data)? != REC_MAGIC {
+        return None;
+    }
+    let key_len = take_u32(data)? as usize;
+    let key = String::from_utf8_lossy(data.get(..key_len)?).into_owned();
+    *data = &data[key_len..];
+    let seq = take_u64(data)?;
+    if seq == SEQ_DELETED {
+        return Some((key, Some(None)));
+    }
+    let val_len = take_u32(data)? as usize;
+    if val_len == 0 {
+        return Some((key, Some(Some(Vec::new()))));
+    }
+    // stream has no length of its own, decoder stops right after it.
+    let mut val = Vec::new();
+    let mut decoder = DeflateDecoder::new(*data);
+    decoder.read_to_end(&mut val).ok()?;
+    *data = decoder.into_inner();
+    if val.len() == val_len {
+        Some((key, Some(Some(val))))
+    } else {
+        Some((key, None))
+    }
+}
+
+fn take_u32(data: &mut &[u8]) -> Option<u32> {
+    let bytes = data.get(..4)?;
+    *data = &data[4..];
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn take_u64(data: &mut &[u8]) -> Option<u64> {
+    let lo = take_u32(data)? as u64;
+    let hi = take_u32(data)? as u64;
+    Some(hi << 32 | lo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::bench::target;
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn record(db: &mut Vec<u8>, key: &str, val: Option<&str>, seq: u64) {
+        db.extend(&REC_MAGIC.to_le_bytes());
+        db.extend(&(key.len() as u32).to_le_bytes());
+        db.extend(key.as_bytes());
+        db.extend(&seq.to_le_bytes());
+        if let Some(val) = val {
+            db.extend(&(val.len() as u32).to_le_bytes());
+            let mut e = DeflateEncoder::new(Vec::new(), Compression::best());
+            e.write_all(val.as_bytes()).unwrap();
+            db.extend(e.finish().unwrap());
+        }
+    }
+
+    #[test]
+    fn parse_syz_db() {
+        let mut db = DB_MAGIC.to_le_bytes().to_vec();
+        db.extend(&4u64.to_le_bytes());
+        record(&mut db, "a", Some("close(0x1)"), 0);
+        record(&mut db, "b", Some("no_such_call()"), 1);
+        record(&mut db, "c", Some("close(0x2)"), 2);
+        record(&mut db, "a", None, SEQ_DELETED);
+        record(&mut db, "c", Some("close(0x3)"), 3);
+        record(&mut db, "d", Some("close(0x4)"), 4);
+        // tail of a record cut off while writing.
+        db.truncate(db.len() - 3);
+
+        let parsed = parse(&db).unwrap();
+        assert_eq!(parsed.version, 4);
+        assert!(parsed.truncated);
+        assert_eq!(parsed.corrupted, 1);
+        let vals = parsed
+            .records
+            .iter()
+            .map(|(k, v)| (&k[..], std::str::from_utf8(v).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(vals, vec![("b", "no_such_call()"), ("c", "close(0x3)")]);
+        assert!(parse(b"not a db").is_err());
+
+        let (t, _) = target();
+        let path = std::env::temp_dir().join(format!("healer-syzdb-{}", std::process::id()));
+        std::fs::write(&path, &db).unwrap();
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let seeds = rt.block_on(seeds_of(&path, &t));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(seeds.len(), 1);
+        assert_eq!(t.fn_of(seeds[0].calls[0].fid).dec_name, "close");
+    }
+
+    #[test]
+    fn pack_then_import() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            sha1_hex(&[b'a'; 1000]),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+
+        let (t, rt) = target();
+        // healer has calls of the same name, e.g. on different fds, text can't tell them
+        // apart.
+        let unique = |p: &Prog| {
+            p.calls.iter().all(|c| {
+                let f = t.fn_of(c.fid);
+                t.fn_by_name(&f.dec_name).map(|f| f.id) == Some(c.fid)
+            })
+        };
+        let progs = std::iter::repeat_with(|| core::gen::gen(&t, &rt, &Default::default()))
+            .take(64)
+            .filter(unique)
+            .collect::<Vec<_>>();
+
+        let packed = pack(&progs, &t);
+        // duplicates are packed once.
+        assert_eq!(
+            pack(&[progs[0].clone(), progs[0].clone()], &t).len(),
+            pack(&progs[..1], &t).len()
+        );
+        let db = parse(&packed).unwrap();
+        assert_eq!(db.version, PACK_VERSION);
+        assert!(!db.truncated && db.corrupted == 0);
+        let mut texts = progs.iter().map(|p| encode(p, &t)).collect::<Vec<_>>();
+        texts.sort();
+        texts.dedup();
+        assert_eq!(db.records.len(), texts.len());
+
+        let path = std::env::temp_dir().join(format!("healer-syzdb-pack-{}", std::process::id()));
+        std::fs::write(&path, &packed).unwrap();
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let seeds = rt.block_on(seeds_of(&path, &t));
+        std::fs::remove_file(&path).unwrap();
+        let mut imported = seeds.iter().map(|p| encode(p, &t)).collect::<Vec<_>>();
+        imported.sort();
+        assert_eq!(imported, texts);
+    }
+}
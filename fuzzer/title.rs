@@ -0,0 +1,170 @@
+//! Crash titles compatible with syzkaller's.
+//!
+//! The same bug should get the same title on every run and the one syzbot reports for it,
+//! so titles are normalized the way syzkaller does: `BUG: ` prefix of sanitizer reports is
+//! dropped, access type of KASAN reports is taken from report body, titles locating the
+//! crash by source line or address get the first frame outside error reporting instead,
+//! and addresses, offsets, source paths and line numbers, compiler suffixes of functions
+//! and qualifiers such as `(corrupted)` are stripped.
+use crate::report::CrashClass;
+use core::subsystem::crash_site;
+use regex::Regex;
+
+lazy_static! {
+    static ref OFFSET: Regex = Regex::new(r"\+0x[0-9a-f]+(?:/0x[0-9a-f]+)?").unwrap();
+    static ref ADDR: Regex = Regex::new(r"\b0x[0-9a-f]+\b|\b[0-9a-f]{16}\b").unwrap();
+    static ref FRAME_PATH: Regex = Regex::new(r"( in [\w.]+) \S+\.[chS]\b\S*").unwrap();
+    static ref LINE: Regex = Regex::new(r"(\.[chS]):\d+(?::\d+)?").unwrap();
+    static ref SUFFIX: Regex =
+        Regex::new(r"\.(?:isra|constprop|part|cold|llvm)(?:\.[0-9]+)*\b").unwrap();
+    static ref QUALIFIER: Regex =
+        Regex::new(r"\s*\((?:corrupted|tainted)\)|\s*(?:Not tainted|Tainted: [A-Z ]+)").unwrap();
+    static ref KASAN_ACCESS: Regex = Regex::new(r"\b(Read|Write) of size \d+").unwrap();
+    static ref WARNING_AT: Regex = Regex::new(r"^WARNING: CPU: \d+ PID: \d+ at \S+ (\S+)").unwrap();
+}
+
+/// Normalized title of `report`, whose title line `title` is of `class`.
+pub fn normalize(title: &str, class: CrashClass, report: &str) -> String {
+    let site = || crash_site(report);
+    let title = match class {
+        CrashClass::Kasan | CrashClass::Kmsan | CrashClass::Kcsan | CrashClass::Ubsan => {
+            let title = title.trim_start_matches("BUG: ");
+            match title.find(" in ") {
+                Some(i) if class == CrashClass::Kasan => match KASAN_ACCESS.captures(report) {
+                    Some(access) => format!("{} {}{}", &title[..i], &access[1], &title[i..]),
+                    None => title.to_string(),
+                },
+                // UBSAN locates the crash by source line.
+                Some(i) if class == CrashClass::Ubsan && title[i + 4..].contains('/') => {
+                    match site() {
+                        Some(f) => format!("{} in {}", &title[..i], f),
+                        None => title.to_string(),
+                    }
+                }
+                _ => title.to_string(),
+            }
+        }
+        CrashClass::Warning => match WARNING_AT.captures(title) {
+            Some(f) => format!("WARNING in {}", &f[1]),
+            None => title.to_string(),
+        },
+        CrashClass::Bug if title.contains(" in ") => title.to_string(),
+        CrashClass::Bug => {
+            // e.g. `kernel BUG at fs/ext4/inode.c:2630!` or `BUG: kernel NULL pointer
+            // dereference, address: 0000000000000008`.
+            // Bare head is a fine title for the latter, not for the former.
+            let head = ["kernel BUG", "general protection fault", "Oops"]
+                .iter()
+                .find(|h| title.starts_with(*h));
+            match (site(), head) {
+                (Some(f), Some(head)) => format!("{} in {}", head, f),
+                (None, Some(_)) => title.to_string(),
+                (f, None) => {
+                    let head = title.split(',').next().unwrap();
+                    let head = head.split(" for address").next().unwrap();
+                    match f {
+                        Some(f) => format!("{} in {}", head, f),
+                        None => head.to_string(),
+                    }
+                }
+            }
+        }
+        CrashClass::Other => title.to_string(),
+    };
+
+    let title = OFFSET.replace_all(&title, "");
+    let title = SUFFIX.replace_all(&title, "");
+    let title = FRAME_PATH.replace_all(&title, "$1");
+    let title = LINE.replace_all(&title, "$1");
+    let title = ADDR.replace_all(&title, "");
+    let title = QUALIFIER.replace_all(&title, "");
+    title.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::signature_of;
+
+    /// Raw reports and their expected titles.
+    const REPORTS: &[(&str, &str)] = &[
+        (
+            "[   35.123456] ==================================================================
+[   35.123789] BUG: KASAN: use-after-free in tcp_v4_rcv+0x2f1/0x3a0
+[   35.124012] Read of size 8 at addr ffff888012345678 by task syz-executor/1234",
+            "KASAN: use-after-free Read in tcp_v4_rcv",
+        ),
+        (
+            "BUG: KASAN: slab-out-of-bounds in skb_put.cold+0x10/0x20 net/core/skbuff.c:1900
+Write of size 4 at addr ffff88801a2b3c4d by task syz-executor/77",
+            "KASAN: slab-out-of-bounds Write in skb_put",
+        ),
+        (
+            "BUG: KMSAN: uninit-value in __bpf_prog_run+0x1b2/0x2e0
+ __bpf_prog_run+0x1b2/0x2e0",
+            "KMSAN: uninit-value in __bpf_prog_run",
+        ),
+        (
+            "BUG: KCSAN: data-race in ext4_mark_iloc_dirty / ext4_do_update_inode",
+            "KCSAN: data-race in ext4_do_update_inode / ext4_mark_iloc_dirty",
+        ),
+        (
+            "UBSAN: shift-out-of-bounds in net/sched/sch_api.c:1234:5
+shift exponent 32 is too large for 32-bit type 'int'
+Call Trace:
+ __dump_stack lib/dump_stack.c:77 [inline]
+ dump_stack+0x107/0x163 lib/dump_stack.c:118
+ ubsan_epilogue+0xb/0x5a lib/ubsan.c:148
+ __ubsan_handle_shift_out_of_bounds.cold+0xb1/0x181 lib/ubsan.c:395
+ tc_modify_qdisc+0x1a2/0x1c00 net/sched/sch_api.c:1234
+",
+            "UBSAN: shift-out-of-bounds in tc_modify_qdisc",
+        ),
+        (
+            "UBSAN: array-index-out-of-bounds in drivers/tty/vt/keyboard.c:1250:3",
+            "UBSAN: array-index-out-of-bounds in drivers/tty/vt/keyboard.c",
+        ),
+        (
+            "------------[ cut here ]------------
+WARNING: CPU: 0 PID: 3110 at mm/page_alloc.c:4900 __alloc_pages+0x3a1/0x4b0",
+            "WARNING in __alloc_pages",
+        ),
+        (
+            "WARNING: ODEBUG bug in __do_softirq (tainted)",
+            "WARNING: ODEBUG bug in __do_softirq",
+        ),
+        (
+            "kernel BUG at fs/ext4/inode.c:2630!
+invalid opcode: 0000 [#1] SMP KASAN
+RIP: 0010:ext4_writepages+0x1234/0x2000 fs/ext4/inode.c:2630",
+            "kernel BUG in ext4_writepages",
+        ),
+        (
+            "general protection fault, probably for non-canonical address 0xdffffc0000000001: 0000 [#1] PREEMPT SMP KASAN
+CPU: 1 PID: 8610 Comm: syz-executor Not tainted 5.10.0 #1
+RIP: 0010:nla_put+0x3a/0x80 lib/nlattr.c:1000",
+            "general protection fault in nla_put",
+        ),
+        (
+            "BUG: kernel NULL pointer dereference, address: 0000000000000008
+RIP: 0010:sock_sendmsg.isra.0+0x12/0x40",
+            "BUG: kernel NULL pointer dereference in sock_sendmsg",
+        ),
+        (
+            "BUG: unable to handle page fault for address: ffffc90000a3f000
+RIP: 0010:memcpy_erms+0x6/0x10 arch/x86/lib/memcpy_64.S:55",
+            "BUG: unable to handle page fault in memcpy_erms",
+        ),
+        (
+            "BUG: unable to handle page fault for address: ffffc90000a3f000",
+            "BUG: unable to handle page fault",
+        ),
+        ("something odd\n", "something odd"),
+    ];
+
+    #[test]
+    fn normalize_titles() {
+        for (report, expected) in REPORTS {
+            assert_eq!(signature_of(report), *expected, "report:\n{}", report);
+        }
+    }
+}
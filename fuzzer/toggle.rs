@@ -0,0 +1,211 @@
+//! Disabling and enabling calls during a run.
+//!
+//! On SIGUSR1, `toggle_calls` in run dir is read and applied. Each line is `disable <call>`
+//! or `enable <call>`, where call can be a glob pattern as in call list files, `#` starts a
+//! comment. Disabled calls are no longer generated and progs containing them are skipped,
+//! enabling calls undoes that. Calls removed by `disabled_calls` at startup are not part of
+//! target, so they can't be enabled. A change leaving nothing to generate is rejected.
+use crate::calls;
+use crate::fuzzer::Fuzzer;
+use core::target::Target;
+use fots::types::FnId;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::fs::read_to_string;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast;
+use tokio::sync::Mutex;
+
+/// Command file of run dir applied on SIGUSR1.
+pub const TOGGLE_FILE: &str = "toggle_calls";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Toggle {
+    Disable(String),
+    Enable(String),
+}
+
+/// Parse content of command file.
+pub fn parse(content: &str) -> Result<Vec<Toggle>, String> {
+    let mut toggles = Vec::new();
+    for (n, l) in content.lines().enumerate() {
+        let l = l.split('#').next().unwrap().trim();
+        if l.is_empty() {
+            continue;
+        }
+        let toggle = match l.split_once(char::is_whitespace) {
+            Some(("disable", call)) => Toggle::Disable(call.trim().to_string()),
+            Some(("enable", call)) => Toggle::Enable(call.trim().to_string()),
+            _ => {
+                return Err(format!(
+                    "line {}: expect `disable <call>` or `enable <call>`",
+                    n + 1
+                ))
+            }
+        };
+        toggles.push(toggle);
+    }
+    Ok(toggles)
+}
+
+/// Calls of target disabled during run, shared by all fuzzing jobs.
+pub struct ActiveCalls {
+    total: usize,
+    /// Replaced, never mutated in place, jobs keep refs to the set they last saw.
+    disabled: Mutex<Arc<HashSet<FnId>>>,
+    /// Bumped on each change, jobs refresh their ref to disabled calls once it moves.
+    version: AtomicUsize,
+}
+
+impl ActiveCalls {
+    pub fn new(target: &Target) -> Self {
+        Self {
+            total: target.fns.len(),
+            disabled: Mutex::new(Arc::new(HashSet::new())),
+            version: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Number of calls not disabled.
+    pub async fn len(&self) -> usize {
+        self.total - self.disabled.lock().await.len()
+    }
+
+    pub fn version(&self) -> usize {
+        self.version.load(Ordering::Acquire)
+    }
+
+    pub async fn disabled(&self) -> Arc<HashSet<FnId>> {
+        Arc::clone(&*self.disabled.lock().await)
+    }
+
+    /// Apply `toggles` in order. Change is rejected if no group of `target` would have an
+    /// enabled call, or, with `required` calls, an enabled required call.
+    pub async fn apply(
+        &self,
+        target: &Target,
+        required: &HashSet<FnId>,
+        toggles: &[Toggle],
+    ) -> Result<(), String> {
+        let mut disabled = self.disabled.lock().await;
+        let mut next = HashSet::clone(&disabled);
+        let names = target
+            .groups
+            .values()
+            .flat_map(|g| g.fns.iter().map(|f| &f.dec_name[..]));
+        let names = names.collect::<Vec<_>>();
+        for toggle in toggles {
+            let (pattern, disable) = match toggle {
+                Toggle::Disable(p) => (p, true),
+                Toggle::Enable(p) => (p, false),
+            };
+            let expansion = calls::expand(std::slice::from_ref(pattern), names.iter().copied());
+            if !expansion.unmatched.is_empty() {
+                warn!("Toggle calls: `{}` matches no call of target", pattern);
+            }
+            let mut n = 0;
+            for f in expansion
+                .matched
+                .iter()
+                .filter_map(|n| target.fn_by_name(n))
+            {
+                let changed = if disable {
+                    next.insert(f.id)
+                } else {
+                    next.remove(&f.id)
+                };
+                if changed {
+                    n += 1;
+                }
+            }
+            let action = if disable { "disabled" } else { "enabled" };
+            info!("Toggle calls: `{}` {} {} call(s)", pattern, action, n);
+        }
+
+        let usable = target.groups.values().any(|g| {
+            g.fns
+                .iter()
+                .any(|f| !next.contains(&f.id) && (required.is_empty() || required.contains(&f.id)))
+        });
+        if !usable {
+            return Err(String::from("no enabled call would be left to generate"));
+        }
+        if next != **disabled {
+            *disabled = Arc::new(next);
+            self.version.fetch_add(1, Ordering::Release);
+        }
+        Ok(())
+    }
+}
+
+/// Apply command file of run dir of `fuzzer` on each SIGUSR1 until shutdown.
+pub async fn watch(fuzzer: Fuzzer, mut shutdown: broadcast::Receiver<()>) {
+    let mut usr1 = match signal(SignalKind::user_defined1()) {
+        Ok(usr1) => usr1,
+        Err(e) => {
+            warn!(
+                "Fail to set up SIGUSR1 handler, calls can't be toggled: {}",
+                e
+            );
+            return;
+        }
+    };
+    let path = fuzzer.run_dir.join(TOGGLE_FILE);
+    loop {
+        tokio::select! {
+            _ = shutdown.recv() => return,
+            sig = usr1.recv() => if sig.is_none() {
+                return;
+            }
+        }
+        let toggles = read_to_string(&path)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|content| parse(&content));
+        let ret = match toggles {
+            Ok(toggles) => {
+                fuzzer
+                    .active_calls
+                    .apply(&fuzzer.target, &fuzzer.conf.required_calls, &toggles)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        match ret {
+            Ok(()) => info!(
+                "Toggle calls: {} of {} call(s) active",
+                fuzzer.active_calls.len().await,
+                fuzzer.active_calls.total()
+            ),
+            Err(e) => warn!(
+                "Fail to toggle calls from {}: {}, nothing changed",
+                path.display(),
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_toggles() {
+        let content = "# noisy ones\ndisable socket$netlink*\n\nenable  open  # back\n";
+        assert_eq!(
+            parse(content),
+            Ok(vec![
+                Toggle::Disable(String::from("socket$netlink*")),
+                Toggle::Enable(String::from("open")),
+            ])
+        );
+        assert!(parse("drop open").is_err());
+        assert!(parse("disable").is_err());
+    }
+}
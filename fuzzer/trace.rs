@@ -0,0 +1,122 @@
+//! Spans of fuzzing loop phases exported with OTLP, built with `otlp` feature.
+//!
+//! Each prog goes through generate or mutate, execute, ingest-coverage and, for progs with
+//! new coverage, cull (trim calls that don't contribute to it). Every span carries job id,
+//! spans of execute also carry prog length. Export is over OTLP/HTTP from sdk's own
+//! thread, so it doesn't depend on fuzzer's runtime. Without the feature, or when `otlp`
+//! section is absent, spans are no-op.
+#[cfg(feature = "otlp")]
+use opentelemetry::trace::{Span as _, Tracer, TracerProvider as _};
+#[cfg(feature = "otlp")]
+use opentelemetry::KeyValue;
+#[cfg(feature = "otlp")]
+use opentelemetry_otlp::WithExportConfig;
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+#[cfg(feature = "otlp")]
+use std::process::exit;
+#[cfg(feature = "otlp")]
+use std::sync::Mutex;
+
+#[cfg(feature = "otlp")]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OtlpConf {
+    /// OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`
+    pub endpoint: String,
+    /// Service name of spans, default healer
+    pub service_name: Option<String>,
+}
+
+#[cfg(feature = "otlp")]
+impl OtlpConf {
+    pub fn check(&self) {
+        if !self.endpoint.starts_with("http://") && !self.endpoint.starts_with("https://") {
+            eprintln!(
+                "Config Error: otlp: endpoint \"{}\" is not a http(s) url",
+                self.endpoint
+            );
+            exit(exitcode::CONFIG)
+        }
+    }
+}
+
+#[cfg(feature = "otlp")]
+lazy_static! {
+    static ref PROVIDER: Mutex<Option<(SdkTracerProvider, SdkTracer)>> = Mutex::new(None);
+}
+
+/// Start exporting spans to `conf.endpoint`.
+#[cfg(feature = "otlp")]
+pub fn init(conf: &OtlpConf) {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(conf.endpoint.clone())
+        .build()
+        .unwrap_or_else(|e| exits!(exitcode::SOFTWARE, "Fail to build otlp exporter: {}", e));
+    let mut resource = opentelemetry_sdk::Resource::builder().with_service_name(
+        conf.service_name
+            .clone()
+            .unwrap_or_else(|| String::from("healer")),
+    );
+    let mut buf = [0u8; 256];
+    if let Ok(host) = nix::unistd::gethostname(&mut buf) {
+        resource = resource.with_attribute(KeyValue::new(
+            "host.name",
+            host.to_string_lossy().into_owned(),
+        ));
+    }
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build();
+    let tracer = provider.tracer("healer");
+    *PROVIDER.lock().unwrap() = Some((provider, tracer));
+    info!("Exporting traces to {}", conf.endpoint);
+}
+
+/// Flush pending spans and stop exporting.
+pub fn shutdown() {
+    #[cfg(feature = "otlp")]
+    if let Some((provider, _)) = PROVIDER.lock().unwrap().take() {
+        if let Err(e) = provider.shutdown() {
+            warn!("Fail to flush traces: {}", e);
+        }
+    }
+}
+
+/// Span of a phase, ended on drop.
+pub struct Span {
+    #[cfg(feature = "otlp")]
+    inner: Option<opentelemetry_sdk::trace::Span>,
+}
+
+/// Start span of `phase` of fuzzing job `job`.
+pub fn span(phase: &'static str, job: usize) -> Span {
+    #[cfg(feature = "otlp")]
+    {
+        let inner = PROVIDER.lock().unwrap().as_ref().map(|(_, tracer)| {
+            let mut span = tracer.start(phase);
+            span.set_attribute(KeyValue::new("job", job as i64));
+            span
+        });
+        Span { inner }
+    }
+    #[cfg(not(feature = "otlp"))]
+    {
+        let _ = (phase, job);
+        Span {}
+    }
+}
+
+impl Span {
+    /// Record number of calls of prog handled in this phase.
+    pub fn set_prog_len(&mut self, len: usize) {
+        #[cfg(feature = "otlp")]
+        if let Some(span) = self.inner.as_mut() {
+            span.set_attribute(KeyValue::new("prog.len", len as i64));
+        }
+        #[cfg(not(feature = "otlp"))]
+        let _ = len;
+    }
+}
@@ -0,0 +1,48 @@
+/// Shell-style glob matching over call names.
+///
+/// `*` matches any sequence of chars, `?` matches exactly one char, every other
+/// char (including `$` and `@`) is matched literally.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let p = pattern.chars().collect::<Vec<_>>();
+    let n = name.chars().collect::<Vec<_>>();
+
+    let (mut pi, mut ni) = (0, 0);
+    // position of last '*' in pattern and the name index it was tried at.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ni));
+            pi += 1;
+        } else if let Some((star, matched)) = backtrack {
+            pi = star + 1;
+            ni = matched + 1;
+            backtrack = Some((star, ni));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::glob::glob_match;
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("socket@inet*", "socket@inet_udplite"));
+        assert!(!glob_match("socket@inet*", "socket@kcm"));
+        assert!(!glob_match("socket$inet*", "socket@inet_udplite"));
+        assert!(glob_match("ioctl@KVM_*", "ioctl@KVM_CREATE_VM"));
+        assert!(!glob_match("ioctl@KVM_*", "ioctl"));
+        assert!(glob_match("open*", "open@special_file"));
+        assert!(glob_match("*at", "openat"));
+        assert!(glob_match("dup?", "dup2"));
+        assert!(!glob_match("dup?", "dup"));
+        assert!(glob_match("read", "read"));
+        assert!(!glob_match("read", "readv"));
+    }
+}
@@ -174,8 +174,11 @@ macro_rules! exits {
 		::std::process::exit($code)
 	};
 
+	// a fatal error during fuzzing ends the run, see `summary`.
 	( $code :expr, $fmt:expr $( , $arg:expr )* ) => {{
-        eprintln!($fmt $( , $arg )*);
-		::std::process::exit($code)
+        let msg = format!($fmt $( , $arg )*);
+        eprintln!("{}", msg);
+		$crate::work_dir::keep();
+		::std::process::exit($crate::summary::fatal($code, &msg))
 	}};
 }
@@ -0,0 +1,78 @@
+//! Detect stall of whole campaign.
+//!
+//! Total executions of all jobs are checked periodically. Once they haven't moved for
+//! `stall_secs`, every job is wedged, e.g. host ran out of file descriptors, and a loud
+//! error is logged instead of the fuzzer sitting there silently. With `exit`, fuzzer
+//! persists what it has and exits as stalled, so CI can tell it from other failures, see
+//! `summary`.
+use crate::fuzzer::Fuzzer;
+use std::process::exit;
+use std::sync::atomic::Ordering;
+use tokio::time::{delay_for, Duration, Instant};
+
+/// Checks per `stall_secs`, bounding how late a stall is detected.
+const CHECKS: u32 = 10;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchdogConf {
+    /// Seconds without any execution of any job before campaign is stalled, default 600
+    pub stall_secs: Option<u64>,
+    /// Stop fuzzer once stalled instead of only logging, default false
+    pub exit: Option<bool>,
+}
+
+impl WatchdogConf {
+    pub fn check(&self) {
+        if self.stall_secs == Some(0) {
+            eprintln!("Config Error: watchdog: stall_secs must be bigger than 0");
+            exit(exitcode::CONFIG)
+        }
+    }
+}
+
+/// Watch executions of `fuzzer`, return once campaign stalled if `conf.exit` is set,
+/// otherwise never.
+pub async fn watch(fuzzer: &Fuzzer, conf: &WatchdogConf) {
+    let stall = Duration::from_secs(conf.stall_secs.unwrap_or(600));
+    let mut last = fuzzer.exec_cnt.load(Ordering::Relaxed);
+    let mut since = Instant::now();
+    let mut stalled = false;
+
+    loop {
+        delay_for(stall / CHECKS).await;
+        let exec = fuzzer.exec_cnt.load(Ordering::Relaxed);
+        // a paused campaign isn't stalled, see `rpc`.
+        if fuzzer.paused.load(Ordering::Relaxed) {
+            last = exec;
+            since = Instant::now();
+            continue;
+        }
+        if exec != last {
+            if stalled {
+                warn!(
+                    "Watchdog: executions resumed after {}s",
+                    since.elapsed().as_secs()
+                );
+                stalled = false;
+            }
+            last = exec;
+            since = Instant::now();
+            continue;
+        }
+        // jobs stop on their own once budget is used up.
+        if stalled || fuzzer.budget_exhausted() || since.elapsed() < stall {
+            continue;
+        }
+        error!(
+            "Watchdog: ========== STALLED ========== no execution of any job in {}s, stuck at {} \
+             exec(s), check host resources (e.g. open files, memory), vms and executors",
+            since.elapsed().as_secs(),
+            exec
+        );
+        if conf.exit.unwrap_or(false) {
+            return;
+        }
+        stalled = true;
+    }
+}
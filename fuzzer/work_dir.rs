@@ -0,0 +1,96 @@
+//! Host side scratch files.
+//!
+//! Each run gets its own `healer-<pid>-<run name>` dir under `work_dir`, system temp dir by
+//! default, so concurrent runs never share files, and each vm gets a `vm-<job>` dir in it.
+//! Qemu keeps `-snapshot` overlays of the image there through `TMPDIR` and the script
+//! executor writes its cases there, raw crash reports are handed to syz-symbolize from there. The dir of a run is removed once it ends normally, a
+//! fatal error leaves it in place and logs where it is for inspection.
+use crate::Config;
+use std::path::{Path, PathBuf};
+use std::process::{exit, id};
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref SCRATCH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Exit if `dir` can't be created or written to.
+pub fn check(dir: &Path) {
+    let probe = dir.join(format!(".healer-probe-{}", id()));
+    let writable = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&probe, b""))
+        .and_then(|_| std::fs::remove_file(&probe));
+    if let Err(e) = writable {
+        eprintln!(
+            "Config Error: work_dir {} is not writable: {}",
+            dir.display(),
+            e
+        );
+        exit(exitcode::CONFIG)
+    }
+}
+
+/// Create scratch dir of run `run_name` and return it.
+pub fn init(cfg: &Config, run_name: &str) -> PathBuf {
+    let dir = cfg
+        .work_dir
+        .clone()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("healer-{}-{}", id(), run_name));
+    std::fs::create_dir_all(&dir).unwrap_or_else(|e| {
+        exits!(
+            exitcode::IOERR,
+            "Fail to create work dir {}: {}",
+            dir.display(),
+            e
+        )
+    });
+    *SCRATCH.lock().unwrap() = Some(dir.clone());
+    dir
+}
+
+/// Scratch dir of run, created on first use. Falls back to system temp dir before `init`,
+/// e.g. in tests.
+pub fn run_dir() -> PathBuf {
+    let dir = SCRATCH
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| std::env::temp_dir().join(format!("healer-{}", id())));
+    create(dir)
+}
+
+/// Scratch dir of vm of `job`, created on first use.
+pub fn vm_dir(job: usize) -> PathBuf {
+    create(run_dir().join(format!("vm-{}", job)))
+}
+
+fn create(dir: PathBuf) -> PathBuf {
+    std::fs::create_dir_all(&dir).unwrap_or_else(|e| {
+        exits!(
+            exitcode::IOERR,
+            "Fail to create work dir {}: {}",
+            dir.display(),
+            e
+        )
+    });
+    dir
+}
+
+/// Remove scratch dir of run after a normal end.
+pub fn cleanup() {
+    if let Some(dir) = SCRATCH.lock().unwrap().take() {
+        if let Err(e) = std::fs::remove_dir_all(&dir) {
+            warn!("Fail to remove work dir {}: {}", dir.display(), e);
+        }
+    }
+}
+
+/// Keep scratch dir of run after an abnormal end, logging where it is. Called by `exits!`.
+pub fn keep() {
+    // a thread panicked holding the lock doesn't stop others from exiting.
+    let scratch = SCRATCH.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(dir) = scratch {
+        error!("Scratch files are kept in {} for inspection", dir.display());
+    }
+}
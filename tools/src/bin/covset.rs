@@ -0,0 +1,40 @@
+use fuzzer::covset::{compare, SetOp};
+use std::path::PathBuf;
+use std::process::exit;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "Covset",
+    about = "Compare two saved coverage sets, e.g. what run b covered that run a didn't"
+)]
+struct Settings {
+    /// File of hex PCs, or dir of such files
+    #[structopt(short = "a")]
+    a: PathBuf,
+    /// File of hex PCs, or dir of such files
+    #[structopt(short = "b")]
+    b: PathBuf,
+    /// PCs printed: union, intersection or difference, i.e. those of a missing in b
+    #[structopt(long, default_value = "difference")]
+    op: SetOp,
+    /// Kernel build dir, System.map of it symbolizes printed PCs
+    #[structopt(short = "k", long)]
+    kernel_obj: Option<PathBuf>,
+}
+
+fn main() {
+    let settings = Settings::from_args();
+    match compare(
+        &settings.a,
+        &settings.b,
+        settings.op,
+        settings.kernel_obj.as_deref(),
+    ) {
+        Ok(out) => print!("{}", out),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(exitcode::DATAERR)
+        }
+    }
+}
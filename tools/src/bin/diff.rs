@@ -0,0 +1,32 @@
+use core::diff::diff;
+use std::path::PathBuf;
+use std::process::exit;
+use structopt::StructOpt;
+use tools::load_target;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "Diff",
+    about = "Report calls and resources changed between two fots targets"
+)]
+struct Settings {
+    /// Old fots target
+    #[structopt(long, short = "o")]
+    old: PathBuf,
+    /// New fots target
+    #[structopt(long, short = "n")]
+    new: PathBuf,
+}
+
+fn main() {
+    let settings = Settings::from_args();
+
+    let old = load_target(&settings.old);
+    let new = load_target(&settings.new);
+
+    let d = diff(&old, &new);
+    print!("{}", d);
+    if !d.is_empty() {
+        exit(1)
+    }
+}
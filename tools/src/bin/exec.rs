@@ -38,6 +38,7 @@ fn main() {
     let conf = Config {
         memleak_check: settings.memleak_check,
         concurrency: settings.concurrency,
+        fault_injection: false,
     };
     match fork_exec(p, &target, &conf) {
         ExecResult::Ok(covs) => {
@@ -0,0 +1,54 @@
+use core::c::to_prog;
+use fuzzer::replay::{logs_of, records, ReplayOutcome};
+use std::path::PathBuf;
+use std::process::exit;
+use structopt::StructOpt;
+use tools::load_target;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "Replay", about = "Print progs of replay log of a run in order")]
+struct Settings {
+    /// Fots target of the run
+    #[structopt(short = "t", long)]
+    items: PathBuf,
+    /// Replay dir of the run, or a single log of it
+    #[structopt(short = "l", long)]
+    log: PathBuf,
+    /// Only print crashed progs
+    #[structopt(long)]
+    crashed: bool,
+}
+
+fn main() {
+    let settings = Settings::from_args();
+    let target = load_target(&settings.items);
+    let logs = if settings.log.is_dir() {
+        logs_of(&settings.log).unwrap_or_else(|e| {
+            eprintln!("Fail to read {:?}: {}", &settings.log, e);
+            exit(exitcode::NOINPUT)
+        })
+    } else {
+        vec![settings.log.clone()]
+    };
+
+    for log in logs {
+        let records = records(&log).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(exitcode::DATAERR)
+        });
+        for record in records {
+            let record = record.unwrap_or_else(|e| {
+                eprintln!("{:?}: {}", log, e);
+                exit(exitcode::DATAERR)
+            });
+            if settings.crashed && !matches!(record.outcome, ReplayOutcome::Crashed(_)) {
+                continue;
+            }
+            println!(
+                "// job-{} +{}ms {:?}",
+                record.job, record.ms, record.outcome
+            );
+            println!("{}", to_prog(&record.prog, &target));
+        }
+    }
+}
@@ -149,6 +149,7 @@ fn report_crash(crash: &CrashedCase) -> String {
     writeln!(buf, "# {}", crash.meta.title).unwrap();
     writeln!(buf, "**Id**:   {}</br>", crash.meta.id).unwrap();
     writeln!(buf, "**Repo**: {}</br>", crash.repo).unwrap();
+    writeln!(buf, "**Repro Score**: {}</br>", crash.repro).unwrap();
     writeln!(buf, "**Test Time**: {}</br>", crash.meta.test_time).unwrap();
     writeln!(buf, "## Prog").unwrap();
     writeln!(buf, "``` c").unwrap();
@@ -0,0 +1,48 @@
+use core::prog::Prog;
+use fuzzer::syzdb::pack;
+use std::fs::{read, write};
+use std::path::PathBuf;
+use std::process::exit;
+use structopt::StructOpt;
+use tools::load_target;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "Syzdb",
+    about = "Pack corpus of a run into a syzkaller corpus.db"
+)]
+struct Settings {
+    /// Fots target of the run
+    #[structopt(short = "t", long)]
+    items: PathBuf,
+    /// Run dir holding the corpus
+    #[structopt(short = "d", long)]
+    run_dir: PathBuf,
+    /// Output corpus.db, default corpus.db of run dir
+    #[structopt(short = "o", long)]
+    out: Option<PathBuf>,
+}
+
+fn main() {
+    let settings = Settings::from_args();
+    let target = load_target(&settings.items);
+    let corpus = settings.run_dir.join("corpus");
+    let data = read(&corpus).unwrap_or_else(|e| {
+        eprintln!("Fail to read {:?}: {}", corpus, e);
+        exit(exitcode::NOINPUT)
+    });
+    let progs: Vec<Prog> = bincode::deserialize(&data).unwrap_or_else(|e| {
+        eprintln!("Fail to deserialize {:?}: {}", corpus, e);
+        exit(exitcode::DATAERR)
+    });
+
+    let out = settings
+        .out
+        .clone()
+        .unwrap_or_else(|| settings.run_dir.join("corpus.db"));
+    write(&out, pack(&progs, &target)).unwrap_or_else(|e| {
+        eprintln!("Fail to write {:?}: {}", out, e);
+        exit(exitcode::IOERR)
+    });
+    println!("{} prog(s) packed into {:?}", progs.len(), out);
+}